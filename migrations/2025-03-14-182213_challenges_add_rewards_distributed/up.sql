@@ -0,0 +1 @@
+ALTER TABLE challenges ADD COLUMN rewards_distributed BOOL DEFAULT FALSE NOT NULL
@@ -0,0 +1,11 @@
+CREATE TABLE submission_aggregates (
+  id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  miner_id INT NOT NULL,
+  day DATE NOT NULL,
+  count INT UNSIGNED NOT NULL,
+  max_difficulty TINYINT NOT NULL,
+  sum_difficulty BIGINT UNSIGNED NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP NOT NULL,
+  UNIQUE KEY miner_id_day (miner_id, day)
+)
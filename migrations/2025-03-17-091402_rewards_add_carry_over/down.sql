@@ -0,0 +1 @@
+ALTER TABLE rewards DROP COLUMN carry_over
@@ -0,0 +1 @@
+ALTER TABLE rewards ADD COLUMN carry_over BIGINT UNSIGNED DEFAULT 0 NOT NULL
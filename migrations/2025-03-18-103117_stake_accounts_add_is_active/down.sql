@@ -0,0 +1 @@
+ALTER TABLE stake_accounts DROP COLUMN is_active
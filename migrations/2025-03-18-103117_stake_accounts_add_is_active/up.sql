@@ -0,0 +1 @@
+ALTER TABLE stake_accounts ADD COLUMN is_active BOOL DEFAULT TRUE NOT NULL
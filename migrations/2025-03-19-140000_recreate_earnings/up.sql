@@ -0,0 +1,11 @@
+CREATE TABLE earnings (
+  id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  miner_id INT NOT NULL,
+  pool_id INT NOT NULL,
+  challenge_id INT NOT NULL,
+  amount BIGINT UNSIGNED DEFAULT 0 NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP NOT NULL,
+  INDEX miner_id_created_at (miner_id, created_at),
+  INDEX pool_id_created_at (pool_id, created_at)
+)
@@ -0,0 +1 @@
+ALTER TABLE challenges ADD COLUMN needs_review BOOL DEFAULT FALSE NOT NULL
@@ -0,0 +1,9 @@
+CREATE TABLE balance_adjustments (
+  id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  miner_id INT NOT NULL,
+  delta BIGINT NOT NULL,
+  reason VARCHAR(255) NOT NULL,
+  admin VARCHAR(64) NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  INDEX miner_id_created_at (miner_id, created_at)
+)
@@ -0,0 +1 @@
+ALTER TABLE submissions_2 DROP INDEX idx_submissions_2_miner_challenge_nonce
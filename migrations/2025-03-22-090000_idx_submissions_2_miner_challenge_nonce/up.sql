@@ -0,0 +1 @@
+CREATE INDEX idx_submissions_2_miner_challenge_nonce ON submissions_2 (miner_id, challenge_id, nonce)
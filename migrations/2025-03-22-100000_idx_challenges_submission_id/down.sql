@@ -0,0 +1 @@
+ALTER TABLE challenges DROP INDEX idx_challenges_submission_id
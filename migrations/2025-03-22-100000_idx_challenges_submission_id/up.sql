@@ -0,0 +1 @@
+CREATE INDEX idx_challenges_submission_id ON challenges (submission_id)
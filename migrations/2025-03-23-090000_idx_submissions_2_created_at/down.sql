@@ -0,0 +1 @@
+ALTER TABLE submissions_2 DROP INDEX idx_submissions_2_created_at_miner_id
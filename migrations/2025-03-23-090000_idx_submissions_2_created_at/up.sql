@@ -0,0 +1 @@
+CREATE INDEX idx_submissions_2_created_at_miner_id ON submissions_2 (created_at, miner_id)
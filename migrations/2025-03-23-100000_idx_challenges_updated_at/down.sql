@@ -0,0 +1 @@
+ALTER TABLE challenges DROP INDEX idx_challenges_updated_at
@@ -0,0 +1 @@
+CREATE INDEX idx_challenges_updated_at ON challenges (updated_at)
@@ -1,7 +1,10 @@
-use deadpool_diesel::mysql::{Manager, Pool};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use deadpool_diesel::mysql::{Manager, Pool, PoolError};
 use diesel::{
-    insert_into, sql_types::{BigInt, Binary, Bool, Integer, Nullable, Text, Unsigned}, Connection, MysqlConnection, RunQueryDsl
+    insert_into, sql_types::{BigInt, Binary, Bool, Integer, Nullable, Text, Unsigned}, Connection, MysqlConnection, OptionalExtension, RunQueryDsl
 };
+use serde::Serialize;
 use tokio::time::Instant;
 use tracing::{error, info};
 
@@ -14,28 +17,340 @@ pub enum AppDatabaseError {
     FailedToInsertRow,
     InteractionFailed,
     QueryFailed,
+    QueryTimedOut,
+    InvalidInput(String),
+    PoolNotInitialized,
+    PoolBuildFailed(String),
+}
+
+enum SignupError {
+    PoolNotInitialized,
+    Query(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for SignupError {
+    fn from(e: diesel::result::Error) -> Self {
+        SignupError::Query(e)
+    }
+}
+
+enum AdjustBalanceError {
+    OutOfRange(u64),
+    Query(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for AdjustBalanceError {
+    fn from(e: diesel::result::Error) -> Self {
+        AdjustBalanceError::Query(e)
+    }
+}
+
+enum StakeRewardsError {
+    Overflow(i64),
+    Query(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for StakeRewardsError {
+    fn from(e: diesel::result::Error) -> Self {
+        StakeRewardsError::Query(e)
+    }
+}
+
+// 10,000 ORE, far above any single distribution
+const MAX_STAKE_REWARD_DELTA: u64 = 1_000_000_000_000_000;
+
+const POOL_CACHE_TTL: Duration = Duration::from_secs(5);
+
+const DEFAULT_REWARDS_UPDATE_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_REWARDS_UPDATE_BATCH_SIZE: usize = 1000;
+
+const DEFAULT_CONNECTION_RETRIES: u32 = 3;
+
+const CONNECTION_RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+const STAKE_PDA_QUERY_CHUNK_SIZE: usize = 500;
+
+const MINER_ID_QUERY_CHUNK_SIZE: usize = 500;
+
+const SUBMISSION_INSERT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IntegrityReport {
+    pub orphaned_rewards: u64,
+    pub anomalous_balances: u64,
+    pub duplicate_challenges: u64,
+    pub challenges_missing_rewards: u64,
+}
+
+impl IntegrityReport {
+    /// Anomalies that point at money or challenge identity being wrong, as opposed to leftover rows.
+    pub fn critical(&self) -> u64 {
+        self.anomalous_balances + self.duplicate_challenges
+    }
+
+    pub fn total(&self) -> u64 {
+        self.critical() + self.orphaned_rewards + self.challenges_missing_rewards
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistributionOutcome {
+    AlreadyDistributed,
+    Distributed { best_submission_id: Option<i32> },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DbPoolTimeouts {
+    pub wait: Duration,
+    pub create: Duration,
+    pub recycle: Duration,
+}
+
+impl Default for DbPoolTimeouts {
+    fn default() -> Self {
+        DbPoolTimeouts {
+            wait: Duration::from_secs(5),
+            create: Duration::from_secs(5),
+            recycle: Duration::from_secs(5),
+        }
+    }
 }
 
 pub struct AppDatabase {
     connection_pool: Pool,
+    pool_cache: Mutex<PoolCache>,
+    rewards_update_lock_timeout: Duration,
+    rewards_update_batch_size: usize,
+    connection_retries: u32,
+    query_metrics: QueryMetrics,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryLatency {
+    pub query: &'static str,
+    pub count: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Call count and latency per AppDatabase method, including time spent waiting for a connection.
+#[derive(Default)]
+pub struct QueryMetrics {
+    queries: Mutex<HashMap<&'static str, QueryLatency>>,
+}
+
+impl QueryMetrics {
+    fn time(&self, query: &'static str) -> QueryTimer<'_> {
+        QueryTimer {
+            metrics: self,
+            query,
+            started: Instant::now(),
+        }
+    }
+
+    fn record(&self, query: &'static str, elapsed: Duration) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        if let Ok(mut queries) = self.queries.lock() {
+            let entry = queries.entry(query).or_insert(QueryLatency {
+                query,
+                count: 0,
+                total_ms: 0,
+                max_ms: 0,
+            });
+            entry.count += 1;
+            entry.total_ms = entry.total_ms.saturating_add(elapsed_ms);
+            entry.max_ms = entry.max_ms.max(elapsed_ms);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<QueryLatency> {
+        let mut snapshot: Vec<QueryLatency> = match self.queries.lock() {
+            Ok(queries) => queries.values().cloned().collect(),
+            Err(_) => Vec::new(),
+        };
+        snapshot.sort_by_key(|q| q.query);
+        snapshot
+    }
+}
+
+struct QueryTimer<'a> {
+    metrics: &'a QueryMetrics,
+    query: &'static str,
+    started: Instant,
+}
+
+impl Drop for QueryTimer<'_> {
+    fn drop(&mut self) {
+        self.metrics.record(self.query, self.started.elapsed());
+    }
+}
+
+/// Pool rows by authority pubkey. Every invalidation bumps the generation, so a lookup that
+/// started before an update can't put back the row it read.
+#[derive(Default)]
+struct PoolCache {
+    entries: HashMap<String, (Instant, models::Pool)>,
+    generation: u64,
+}
+
+impl PoolCache {
+    fn get(&self, authority_pubkey: &str, ttl: Duration) -> Option<models::Pool> {
+        self.entries
+            .get(authority_pubkey)
+            .filter(|(cached_at, _)| cached_at.elapsed() < ttl)
+            .map(|(_, pool)| pool.clone())
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn insert(&mut self, authority_pubkey: String, pool: models::Pool, fetched_at_generation: u64) {
+        if fetched_at_generation == self.generation {
+            self.entries.insert(authority_pubkey, (Instant::now(), pool));
+        }
+    }
+
+    fn invalidate(&mut self, authority_pubkey: &str) {
+        self.generation += 1;
+        self.entries.remove(authority_pubkey);
+    }
+}
+
+impl Drop for AppDatabase {
+    fn drop(&mut self) {
+        info!(target: "server_log", "Closing database pool. Final status: {:?}", self.connection_pool.status());
+    }
 }
 
 impl AppDatabase {
     pub fn new(url: String) -> Self {
+        Self::new_with_config(url, None, DbPoolTimeouts::default().wait)
+            .expect("Failed to build database connection pool")
+    }
+
+    /// Once all `max_size` connections are checked out, callers wait up to `acquire_timeout`
+    /// for one to be returned, then fail with `FailedToGetConnectionFromPool` after any retries.
+    /// Without a `max_size` the pool keeps deadpool's default of four connections per cpu.
+    pub fn new_with_config(url: String, max_size: Option<usize>, acquire_timeout: Duration) -> Result<Self, AppDatabaseError> {
+        Self::new_with_timeouts(url, max_size, DbPoolTimeouts {
+            wait: acquire_timeout,
+            ..DbPoolTimeouts::default()
+        })
+    }
+
+    /// Bounded timeouts make `get()` fail with `FailedToGetConnectionFromPool` instead of waiting forever.
+    pub fn new_with_timeouts(url: String, max_size: Option<usize>, timeouts: DbPoolTimeouts) -> Result<Self, AppDatabaseError> {
         let manager = Manager::new(url, deadpool_diesel::Runtime::Tokio1);
 
-        let pool = Pool::builder(manager).build().unwrap();
+        let mut builder = Pool::builder(manager)
+            .runtime(deadpool_diesel::Runtime::Tokio1)
+            .wait_timeout(Some(timeouts.wait))
+            .create_timeout(Some(timeouts.create))
+            .recycle_timeout(Some(timeouts.recycle));
+        if let Some(max_size) = max_size {
+            builder = builder.max_size(max_size.max(1));
+        }
+        let pool = builder
+            .build()
+            .map_err(|e| AppDatabaseError::PoolBuildFailed(e.to_string()))?;
 
-        AppDatabase {
+        Ok(AppDatabase {
             connection_pool: pool,
+            pool_cache: Mutex::new(PoolCache::default()),
+            rewards_update_lock_timeout: DEFAULT_REWARDS_UPDATE_LOCK_TIMEOUT,
+            rewards_update_batch_size: DEFAULT_REWARDS_UPDATE_BATCH_SIZE,
+            connection_retries: DEFAULT_CONNECTION_RETRIES,
+            query_metrics: QueryMetrics::default(),
+        })
+    }
+
+    /// Caps how long the bulk rewards updates wait on row locks before failing with `QueryTimedOut`.
+    pub fn with_rewards_update_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.rewards_update_lock_timeout = timeout;
+        self
+    }
+
+    /// Number of miners credited per bulk rewards UPDATE statement.
+    pub fn with_rewards_update_batch_size(mut self, batch_size: usize) -> Self {
+        self.rewards_update_batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Extra attempts to get a pooled connection, backing off exponentially from 50ms. Only
+    /// failures to open a connection are retried; a pool wait timeout fails right away.
+    pub fn with_connection_retries(mut self, retries: u32) -> Self {
+        self.connection_retries = retries;
+        self
+    }
+
+    pub fn metrics_snapshot(&self) -> Vec<QueryLatency> {
+        self.query_metrics.snapshot()
+    }
+
+    async fn get_conn(&self) -> Result<deadpool_diesel::mysql::Object, AppDatabaseError> {
+        let mut attempt = 0;
+        loop {
+            match self.connection_pool.get().await {
+                Ok(conn) => return Ok(conn),
+                Err(e) => {
+                    // a timeout already spent the pool's wait_timeout, retrying would stack on it
+                    if attempt >= self.connection_retries || !matches!(e, PoolError::Backend(_)) {
+                        error!(target: "server_log", "Failed to get db connection after {} attempts: {:?}", attempt + 1, e);
+                        return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+                    }
+                    let delay = CONNECTION_RETRY_BASE_DELAY * 2u32.saturating_pow(attempt);
+                    tracing::warn!(target: "server_log", "Failed to get db connection ({:?}), retry {} of {} in {}ms", e, attempt + 1, self.connection_retries, delay.as_millis());
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
         }
     }
 
+    /// Closes the pool. Tasks that still hold a handle get an error instead of a connection.
+    pub fn close(&self) {
+        self.connection_pool.close();
+    }
+
+    /// Runs `SELECT 1` on a pooled connection. Unlike other methods it does not retry
+    /// connection acquisition, so a readiness probe sees an exhausted pool right away.
+    pub async fn health_check(&self) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("health_check");
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT 1").execute(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(_) => {
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "health check query error: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "health check interaction error: {:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn get_challenge_by_challenge(
         &self,
         challenge: Vec<u8>,
     ) -> Result<models::Challenge, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_challenge_by_challenge");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("SELECT id, pool_id, submission_id, challenge, rewards_earned FROM challenges WHERE challenges.challenge = ?")
                 .bind::<Binary, _>(challenge)
@@ -66,11 +381,45 @@ impl AppDatabase {
         &self,
         miner_pubkey: String,
     ) -> Result<models::Reward, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_miner_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT r.id, r.balance, r.miner_id FROM miners m JOIN rewards r ON m.id = r.miner_id WHERE m.pubkey = ?")
+                .bind::<Text, _>(miner_pubkey)
+                .get_result::<models::Reward>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn find_miner_rewards(
+        &self,
+        miner_pubkey: String,
+    ) -> Result<Option<models::Reward>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("find_miner_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("SELECT r.id, r.balance, r.miner_id FROM miners m JOIN rewards r ON m.id = r.miner_id WHERE m.pubkey = ?")
                 .bind::<Text, _>(miner_pubkey)
                 .get_result::<models::Reward>(conn)
+                .optional()
             }).await;
 
             match res {
@@ -97,29 +446,26 @@ impl AppDatabase {
         &self,
         rewards: Vec<models::UpdateReward>,
     ) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("update_rewards");
+        if rewards.is_empty() {
+            return Ok(());
+        }
+
         let id = uuid::Uuid::new_v4();
         let instant = Instant::now();
         tracing::info!(target: "server_log", "{} - Getting db pool connection.", id);
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        if let Ok(db_conn) = self.get_conn().await {
             tracing::info!(target: "server_log", "{} - Got db pool connection in {}ms.", id, instant.elapsed().as_millis());
+            let lock_timeout = self.rewards_update_lock_timeout;
+            let batch_size = self.rewards_update_batch_size;
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    let query = diesel::sql_query(
-                        "UPDATE rewards SET balance = balance + CASE miner_id ".to_string() +
-                        &rewards
-                            .iter()
-                            .map(|r| format!("WHEN {} THEN {}", r.miner_id, r.balance))
-                            .collect::<Vec<_>>()
-                            .join(" ") +
-                        " END WHERE miner_id IN (" +
-                        &rewards
-                            .iter()
-                            .map(|r| r.miner_id.to_string())
-                            .collect::<Vec<_>>()
-                            .join(",") +
-                        ")"
-                    );
-                    query.execute(conn)
+                    with_lock_wait_timeout(conn, lock_timeout, |conn| conn.transaction::<(), diesel::result::Error, _>(|conn| {
+                        for batch in rewards.chunks(batch_size) {
+                            update_rewards_query(batch).execute(conn)?;
+                        }
+                        Ok(())
+                    }))
                 })
                 .await;
 
@@ -129,6 +475,10 @@ impl AppDatabase {
                     Ok(_query) => {
                         return Ok(());
                     }
+                    Err(e) if is_lock_wait_timeout(&e) => {
+                        error!(target: "server_log", "update rewards timed out waiting for locks: {:?}", e);
+                        return Err(AppDatabaseError::QueryTimedOut);
+                    }
                     Err(e) => {
                         error!(target: "server_log", "update rewards query error: {:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
@@ -144,12 +494,148 @@ impl AppDatabase {
         };
     }
 
+    pub async fn is_challenge_rewards_distributed(
+        &self,
+        challenge_id: i32,
+    ) -> Result<bool, AppDatabaseError> {
+        let _timer = self.query_metrics.time("is_challenge_rewards_distributed");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT rewards_distributed FROM challenges WHERE id = ?")
+                .bind::<Integer, _>(challenge_id)
+                .get_result::<models::ChallengeRewardsDistributed>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query.rewards_distributed);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    /// Marks the challenge as distributed, stores its submissions, credits miner rewards with their
+    /// earnings and records the challenge reward and best submission, all in one transaction.
+    /// Nothing is written if the challenge was already distributed.
+    pub async fn distribute_challenge_rewards(
+        &self,
+        pool_id: i32,
+        challenge_id: i32,
+        rewards: Vec<models::UpdateReward>,
+        submissions: Vec<models::InsertSubmission>,
+        best_nonce: u64,
+        challenge_rewards: u64,
+        min_reward_credit: u64,
+    ) -> Result<DistributionOutcome, AppDatabaseError> {
+        let _timer = self.query_metrics.time("distribute_challenge_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
+            let lock_timeout = self.rewards_update_lock_timeout;
+            let batch_size = self.rewards_update_batch_size;
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    with_lock_wait_timeout(conn, lock_timeout, |conn| conn.transaction::<DistributionOutcome, diesel::result::Error, _>(|conn| {
+                        let marked = diesel::sql_query("UPDATE challenges SET rewards_distributed = TRUE WHERE id = ? AND rewards_distributed = FALSE")
+                            .bind::<Integer, _>(challenge_id)
+                            .execute(conn)?;
+
+                        if marked == 0 {
+                            return Ok(DistributionOutcome::AlreadyDistributed);
+                        }
+
+                        for batch in submissions.chunks(SUBMISSION_INSERT_BATCH_SIZE) {
+                            insert_into(crate::schema::submissions_2::dsl::submissions_2)
+                                .values(batch)
+                                .execute(conn)?;
+                        }
+
+                        // read under lock so the earnings match what the update below credits
+                        let mut carry_overs = HashMap::with_capacity(rewards.len());
+                        for batch in rewards.chunks(MINER_ID_QUERY_CHUNK_SIZE) {
+                            let query = diesel::sql_query(format!(
+                                "SELECT miner_id, carry_over FROM rewards WHERE miner_id IN ({}) FOR UPDATE",
+                                vec!["?"; batch.len()].join(","),
+                            ))
+                            .into_boxed::<diesel::mysql::Mysql>();
+                            let rows = bind_rewards_ids(query, batch).load::<models::RewardCarryOver>(conn)?;
+                            carry_overs.extend(rows.into_iter().map(|r| (r.miner_id, r.carry_over)));
+                        }
+                        let earnings = credited_earnings(&rewards, &carry_overs, min_reward_credit, pool_id, challenge_id);
+
+                        for batch in rewards.chunks(batch_size) {
+                            if min_reward_credit > 0 {
+                                update_rewards_with_carry_over_query(batch, min_reward_credit).execute(conn)?;
+                            } else {
+                                update_rewards_query(batch).execute(conn)?;
+                            }
+                        }
+
+                        for batch in earnings.chunks(SUBMISSION_INSERT_BATCH_SIZE) {
+                            insert_into(crate::schema::earnings::dsl::earnings)
+                                .values(batch)
+                                .execute(conn)?;
+                        }
+
+                        let best_submission = diesel::sql_query("SELECT id FROM submissions_2 WHERE challenge_id = ? AND nonce = ? ORDER BY id DESC LIMIT 1")
+                            .bind::<Integer, _>(challenge_id)
+                            .bind::<Unsigned<BigInt>, _>(best_nonce)
+                            .get_result::<SubmissionWithId>(conn)
+                            .optional()?;
+                        let best_submission_id = best_submission.map(|s| s.id);
+
+                        diesel::sql_query("UPDATE challenges SET rewards_earned = ?, submission_id = ? WHERE id = ?")
+                            .bind::<Nullable<Unsigned<BigInt>>, _>(Some(challenge_rewards))
+                            .bind::<Nullable<Integer>, _>(best_submission_id)
+                            .bind::<Integer, _>(challenge_id)
+                            .execute(conn)?;
+
+                        Ok(DistributionOutcome::Distributed { best_submission_id })
+                    }))
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(distributed) => {
+                        return Ok(distributed);
+                    }
+                    Err(e) if is_lock_wait_timeout(&e) => {
+                        error!(target: "server_log", "distribute challenge rewards timed out waiting for locks: {:?}", e);
+                        return Err(AppDatabaseError::QueryTimedOut);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "distribute challenge rewards query error: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "distribute challenge rewards interaction error: {:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn decrease_miner_reward(
         &self,
         miner_id: i32,
         rewards_to_decrease: u64,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("decrease_miner_reward");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query("UPDATE rewards SET balance = balance - ? WHERE miner_id = ?")
@@ -179,24 +665,58 @@ impl AppDatabase {
         };
     }
 
-    pub async fn get_submission_id_with_nonce(&self, nonce: u64) -> Result<i64, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    /// Applies a manual correction to a miner's rewards balance and records it in
+    /// balance_adjustments. Returns the new balance.
+    pub async fn adjust_miner_balance(
+        &self,
+        miner_id: i32,
+        delta: i64,
+        reason: String,
+        admin: String,
+    ) -> Result<u64, AppDatabaseError> {
+        let _timer = self.query_metrics.time("adjust_miner_balance");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query(
-                        "SELECT id FROM submissions_2 WHERE submissions_2.nonce = ? ORDER BY id DESC",
-                    )
-                    .bind::<Unsigned<BigInt>, _>(nonce)
-                    .get_result::<SubmissionWithId>(conn)
+                    conn.transaction::<u64, AdjustBalanceError, _>(|conn| {
+                        let reward: models::Reward = diesel::sql_query("SELECT id, balance, miner_id FROM rewards WHERE miner_id = ? FOR UPDATE")
+                            .bind::<Integer, _>(miner_id)
+                            .get_result(conn)?;
+
+                        let new_balance = if delta >= 0 {
+                            reward.balance.checked_add(delta as u64)
+                        } else {
+                            reward.balance.checked_sub(delta.unsigned_abs())
+                        }
+                        .ok_or(AdjustBalanceError::OutOfRange(reward.balance))?;
+
+                        diesel::sql_query("UPDATE rewards SET balance = ? WHERE id = ?")
+                            .bind::<Unsigned<BigInt>, _>(new_balance)
+                            .bind::<Integer, _>(reward.id)
+                            .execute(conn)?;
+
+                        diesel::sql_query("INSERT INTO balance_adjustments (miner_id, delta, reason, admin) VALUES (?, ?, ?, ?)")
+                            .bind::<Integer, _>(miner_id)
+                            .bind::<BigInt, _>(delta)
+                            .bind::<Text, _>(&reason)
+                            .bind::<Text, _>(&admin)
+                            .execute(conn)?;
+
+                        Ok(new_balance)
+                    })
                 })
                 .await;
 
             match res {
                 Ok(interaction) => match interaction {
-                    Ok(query) => {
-                        return Ok(query.id as i64);
+                    Ok(new_balance) => {
+                        return Ok(new_balance);
                     }
-                    Err(e) => {
+                    Err(AdjustBalanceError::OutOfRange(balance)) => {
+                        error!(target: "server_log", "Rejected balance adjustment of {} for miner id {}: balance is {}", delta, miner_id, balance);
+                        return Err(AppDatabaseError::InvalidInput(format!("adjustment of {} would take balance {} out of range", delta, balance)));
+                    }
+                    Err(AdjustBalanceError::Query(e)) => {
                         error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
@@ -211,28 +731,43 @@ impl AppDatabase {
         };
     }
 
-    pub async fn update_challenge_rewards(
+    /// Flags a challenge for manual review, recording its reported reward and submissions without
+    /// crediting anyone. A challenge that is already flagged is left as is and counts as success.
+    pub async fn flag_challenge_for_review(
         &self,
-        challenge: Vec<u8>,
-        submission_id: i64,
+        challenge_id: i32,
         rewards: u64,
+        submissions: Vec<models::InsertSubmission>,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("flag_challenge_for_review");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("UPDATE challenges SET rewards_earned = ?, submission_id = ? WHERE challenge = ?")
-                .bind::<Nullable<Unsigned<BigInt>>, _>(Some(rewards))
-                .bind::<Nullable<BigInt>, _>(submission_id)
-                .bind::<Binary, _>(challenge)
-                .execute(conn)
+                conn.transaction::<usize, diesel::result::Error, _>(|conn| {
+                    let flagged = diesel::sql_query("UPDATE challenges SET needs_review = TRUE, rewards_earned = ? WHERE id = ? AND needs_review = FALSE")
+                        .bind::<Nullable<Unsigned<BigInt>>, _>(Some(rewards))
+                        .bind::<Integer, _>(challenge_id)
+                        .execute(conn)?;
+
+                    if flagged == 0 {
+                        return Ok(0);
+                    }
+
+                    for batch in submissions.chunks(SUBMISSION_INSERT_BATCH_SIZE) {
+                        insert_into(crate::schema::submissions_2::dsl::submissions_2)
+                            .values(batch)
+                            .execute(conn)?;
+                    }
+                    Ok(flagged)
+                })
             }).await;
 
             match res {
                 Ok(interaction) => match interaction {
-                    Ok(query) => {
-                        if query != 1 {
-                            return Err(AppDatabaseError::FailedToUpdateRow);
-                        }
-                        info!(target: "server_log", "Updated challenge rewards!");
+                    Ok(0) => {
+                        info!(target: "server_log", "Challenge {} was already flagged for review.", challenge_id);
+                        return Ok(());
+                    }
+                    Ok(_query) => {
                         return Ok(());
                     }
                     Err(e) => {
@@ -254,7 +789,12 @@ impl AppDatabase {
         &self,
         challenge: models::InsertChallenge,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("add_new_challenge");
+        if challenge.challenge.len() != 32 {
+            error!(target: "server_log", "add_new_challenge: challenge must be 32 bytes, got {}", challenge.challenge.len());
+            return Err(AppDatabaseError::InvalidInput(format!("challenge must be 32 bytes, got {}", challenge.challenge.len())));
+        }
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("INSERT INTO challenges (pool_id, challenge, rewards_earned) VALUES (?, ?, ?)")
                 .bind::<Integer, _>(challenge.pool_id)
@@ -286,11 +826,30 @@ impl AppDatabase {
         };
     }
 
+    fn invalidate_pool_cache(&self, pool_authority_pubkey: &str) {
+        if let Ok(mut cache) = self.pool_cache.lock() {
+            cache.invalidate(pool_authority_pubkey);
+        }
+    }
+
+    /// Served from a short-lived cache; pool updates through this handle evict the entry.
     pub async fn get_pool_by_authority_pubkey(
         &self,
         pool_pubkey: String,
     ) -> Result<models::Pool, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_pool_by_authority_pubkey");
+        let generation = match self.pool_cache.lock() {
+            Ok(cache) => {
+                if let Some(pool) = cache.get(&pool_pubkey, POOL_CACHE_TTL) {
+                    return Ok(pool);
+                }
+                cache.generation()
+            }
+            Err(_) => u64::MAX,
+        };
+
+        if let Ok(db_conn) = self.get_conn().await {
+            let cache_key = pool_pubkey.clone();
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("SELECT id, proof_pubkey, authority_pubkey, total_rewards, claimed_rewards FROM pools WHERE pools.authority_pubkey = ?")
                 .bind::<Text, _>(pool_pubkey)
@@ -300,8 +859,14 @@ impl AppDatabase {
             match res {
                 Ok(interaction) => match interaction {
                     Ok(query) => {
+                        if let Ok(mut cache) = self.pool_cache.lock() {
+                            cache.insert(cache_key, query.clone(), generation);
+                        }
                         return Ok(query);
                     }
+                    Err(diesel::result::Error::NotFound) => {
+                        return Err(AppDatabaseError::PoolNotInitialized);
+                    }
                     Err(e) => {
                         error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
@@ -322,7 +887,8 @@ impl AppDatabase {
         authority_pubkey: String,
         proof_pubkey: String,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("add_new_pool");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query(
@@ -362,13 +928,16 @@ impl AppDatabase {
         pool_authority_pubkey: String,
         earned_rewards: u64,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("update_pool_rewards");
+        let cache_key = pool_authority_pubkey.clone();
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("UPDATE pools SET total_rewards = total_rewards + ? WHERE authority_pubkey = ?")
                 .bind::<Unsigned<BigInt>, _>(earned_rewards)
                 .bind::<Text, _>(pool_authority_pubkey)
                 .execute(conn)
             }).await;
+            self.invalidate_pool_cache(&cache_key);
 
             match res {
                 Ok(interaction) => match interaction {
@@ -399,13 +968,16 @@ impl AppDatabase {
         pool_authority_pubkey: String,
         claimed_rewards: u64,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("update_pool_claimed");
+        let cache_key = pool_authority_pubkey.clone();
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("UPDATE pools SET claimed_rewards = claimed_rewards + ? WHERE authority_pubkey = ?")
                 .bind::<Unsigned<BigInt>, _>(claimed_rewards)
                 .bind::<Text, _>(pool_authority_pubkey)
                 .execute(conn)
             }).await;
+            self.invalidate_pool_cache(&cache_key);
 
             match res {
                 Ok(interaction) => match interaction {
@@ -434,7 +1006,8 @@ impl AppDatabase {
         &self,
         miner_pubkey: String,
     ) -> Result<Miner, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_miner_by_pubkey_str");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query(
@@ -464,8 +1037,62 @@ impl AppDatabase {
         };
     }
 
+    /// Returns the number of miners whose enabled flag changed.
+    pub async fn set_miners_enabled(
+        &self,
+        miner_ids: Vec<i32>,
+        enabled: bool,
+    ) -> Result<u64, AppDatabaseError> {
+        let _timer = self.query_metrics.time("set_miners_enabled");
+        if miner_ids.is_empty() {
+            return Ok(0);
+        }
+
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    conn.transaction::<u64, diesel::result::Error, _>(|conn| {
+                        let mut updated = 0;
+                        for chunk in miner_ids.chunks(MINER_ID_QUERY_CHUNK_SIZE) {
+                            let mut query = diesel::sql_query(format!(
+                                "UPDATE miners SET enabled = ? WHERE id IN ({})",
+                                vec!["?"; chunk.len()].join(",")
+                            ))
+                            .into_boxed::<diesel::mysql::Mysql>()
+                            .bind::<Bool, _>(enabled);
+                            for miner_id in chunk {
+                                query = query.bind::<Integer, _>(*miner_id);
+                            }
+                            updated += query.execute(conn)? as u64;
+                        }
+                        Ok(updated)
+                    })
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn add_new_claim(&self, claim: models::InsertClaim) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("add_new_claim");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
                 diesel::sql_query("INSERT INTO claims (miner_id, pool_id, txn_id, amount) VALUES (?, ?, ?, ?)")
                 .bind::<Integer, _>(claim.miner_id)
@@ -495,18 +1122,58 @@ impl AppDatabase {
         };
     }
 
+    pub async fn add_new_claims_batch(
+        &self,
+        claims: Vec<models::InsertClaim>,
+    ) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("add_new_claims_batch");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    insert_into(crate::schema::claims::dsl::claims)
+                        .values(&claims)
+                        .execute(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        info!(target: "server_log", "Claims inserted: {}", query);
+                        if query == 0 {
+                            return Err(AppDatabaseError::FailedToInsertRow);
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn get_last_claim(
         &self,
         miner_id: i32,
-    ) -> Result<models::LastClaim, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    ) -> Result<Option<models::LastClaim>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_last_claim");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query(
-                        "SELECT created_at FROM claims WHERE miner_id = ? ORDER BY id DESC",
+                        "SELECT created_at FROM claims WHERE miner_id = ? ORDER BY id DESC LIMIT 1",
                     )
                     .bind::<Integer, _>(miner_id)
                     .get_result::<models::LastClaim>(conn)
+                    .optional()
                 })
                 .await;
 
@@ -531,7 +1198,8 @@ impl AppDatabase {
     }
 
     pub async fn add_new_txn(&self, txn: models::InsertTxn) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("add_new_txn");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query(
@@ -565,7 +1233,8 @@ impl AppDatabase {
     }
 
     pub async fn get_txn_by_sig(&self, sig: String) -> Result<models::TxnId, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_txn_by_sig");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query("SELECT id FROM txns WHERE signature = ?")
@@ -594,45 +1263,13 @@ impl AppDatabase {
         };
     }
 
-    // pub async fn add_new_earning(
-    //     &self,
-    //     earning: models::InsertEarning,
-    // ) -> Result<(), AppDatabaseError> {
-    //     if let Ok(db_conn) = self.connection_pool.get().await {
-    //         let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-    //             diesel::sql_query("INSERT INTO earnings (miner_id, pool_id, challenge_id, amount) VALUES (?, ?, ?, ?)")
-    //             .bind::<Integer, _>(earning.miner_id)
-    //             .bind::<Integer, _>(earning.pool_id)
-    //             .bind::<Integer, _>(earning.challenge_id)
-    //             .bind::<Unsigned<BigInt>, _>(earning.amount)
-    //             .execute(conn)
-    //         }).await;
-
-    //         match res {
-    //             Ok(interaction) => match interaction {
-    //                 Ok(_query) => {
-    //                     return Ok(());
-    //                 }
-    //                 Err(e) => {
-    //                     error!(target: "server_log", "{:?}", e);
-    //                     return Err(AppDatabaseError::QueryFailed);
-    //                 }
-    //             },
-    //             Err(e) => {
-    //                 error!(target: "server_log", "{:?}", e);
-    //                 return Err(AppDatabaseError::InteractionFailed);
-    //             }
-    //         }
-    //     } else {
-    //         return Err(AppDatabaseError::FailedToGetConnectionFromPool);
-    //     };
-    // }
-
+    /// Returns the number of rows inserted, which callers should compare against the batch length.
     pub async fn add_new_submissions_batch(
         &self,
         submissions: Vec<models::InsertSubmission>,
-    ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    ) -> Result<usize, AppDatabaseError> {
+        let _timer = self.query_metrics.time("add_new_submissions_batch");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     insert_into(crate::schema::submissions_2::dsl::submissions_2)
@@ -648,7 +1285,7 @@ impl AppDatabase {
                         if query == 0 {
                             return Err(AppDatabaseError::FailedToInsertRow);
                         }
-                        return Ok(());
+                        return Ok(query);
                     }
                     Err(e) => {
                         error!(target: "server_log", "{:?}", e);
@@ -670,12 +1307,20 @@ impl AppDatabase {
         user_pubkey: String,
         pool_authority_pubkey: String,
     ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("signup_user_transaction");
+        if let Ok(db_conn) = self.get_conn().await {
             let user_pk = user_pubkey.clone();
+            let pool_authority = pool_authority_pubkey.clone();
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     let user_pubkey = user_pk;
-                    conn.transaction(|conn| {
+                    conn.transaction::<usize, SignupError, _>(|conn| {
+                        let pool: models::Pool = diesel::sql_query("SELECT id, proof_pubkey, authority_pubkey, total_rewards, claimed_rewards FROM pools WHERE pools.authority_pubkey = ?")
+                            .bind::<Text, _>(&pool_authority_pubkey)
+                            .get_result(conn)
+                            .optional()?
+                            .ok_or(SignupError::PoolNotInitialized)?;
+
                         diesel::sql_query("INSERT INTO miners (pubkey, enabled) VALUES (?, ?)")
                             .bind::<Text, _>(&user_pubkey)
                             .bind::<Bool, _>(true)
@@ -685,14 +1330,10 @@ impl AppDatabase {
                             .bind::<Text, _>(&user_pubkey)
                             .get_result(conn)?;
 
-                        let pool: models::Pool = diesel::sql_query("SELECT id, proof_pubkey, authority_pubkey, total_rewards, claimed_rewards FROM pools WHERE pools.authority_pubkey = ?")
-                            .bind::<Text, _>(&pool_authority_pubkey)
-                            .get_result(conn)?;
-
-                        diesel::sql_query("INSERT INTO rewards (miner_id, pool_id) VALUES (?, ?)")
+                        Ok(diesel::sql_query("INSERT INTO rewards (miner_id, pool_id) VALUES (?, ?)")
                             .bind::<Integer, _>(miner.id)
                             .bind::<Integer, _>(pool.id)
-                            .execute(conn)
+                            .execute(conn)?)
                     })
                 })
                 .await;
@@ -707,7 +1348,11 @@ impl AppDatabase {
                         info!(target: "server_log", "Successfully inserted signup for pubkey: {}", user_pubkey);
                         return Ok(());
                     }
-                    Err(e) => {
+                    Err(SignupError::PoolNotInitialized) => {
+                        error!(target: "server_log", "Signup for {} failed: pool {} is not initialized", user_pubkey, pool_authority);
+                        return Err(AppDatabaseError::PoolNotInitialized);
+                    }
+                    Err(SignupError::Query(e)) => {
                         error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
@@ -727,7 +1372,8 @@ impl AppDatabase {
         pool_id: i32,
         last_id: i32,
     ) -> Result<Vec<StakeAccount>, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_stake_accounts");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.id > ? ORDER BY s.id ASC LIMIT 500")
@@ -764,10 +1410,11 @@ impl AppDatabase {
         last_id: i32,
         minimum_balance: u64,
     ) -> Result<Vec<StakeAccount>, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_staker_accounts_for_mint");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.mint_pubkey = ? AND s.id > ? AND s.staked_balance >= ? ORDER BY s.id ASC LIMIT 500")
+                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.mint_pubkey = ? AND s.id > ? AND s.staked_balance >= ? AND s.is_active = TRUE ORDER BY s.id ASC LIMIT 500")
                         .bind::<Integer, _>(pool_id)
                         .bind::<Text, _>(mint_pubkey)
                         .bind::<Integer, _>(last_id)
@@ -782,12 +1429,668 @@ impl AppDatabase {
                         return Ok(query);
                     }
                     Err(e) => {
-                        error!("{:?}", e);
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_accounts(
+        &self,
+        last_id: i32,
+    ) -> Result<Vec<Miner>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_miner_accounts");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT * FROM miners m WHERE m.id > ? ORDER BY m.id ASC LIMIT 500")
+                        .bind::<Integer, _>(last_id)
+                        .load::<Miner>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn add_new_stake_accounts_batch(
+        &self,
+        new_stake_accounts: Vec<models::InsertStakeAccount>,
+    ) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("add_new_stake_accounts_batch");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    insert_into(crate::schema::stake_accounts::dsl::stake_accounts)
+                        .values(&new_stake_accounts)
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        info!(target: "server_log", "New Stake Accounts inserted: {}", query);
+                        if query == 0 {
+                            return Err(AppDatabaseError::FailedToInsertRow);
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_stake_accounts_staked_balance(
+        &self,
+        stake_accts: Vec<models::UpdateStakeAccount>,
+    ) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("update_stake_accounts_staked_balance");
+        if stake_accts.is_empty() {
+            return Ok(());
+        }
+
+        let id = uuid::Uuid::new_v4();
+        let instant = Instant::now();
+        tracing::info!(target: "server_log", "{} - Getting db pool connection.", id);
+        if let Ok(db_conn) = self.get_conn().await {
+            tracing::info!(target: "server_log", "{} - Got db pool connection in {}ms.", id, instant.elapsed().as_millis());
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    conn.transaction::<(), diesel::result::Error, _>(|conn| {
+                        for batch in stake_accts.chunks(STAKE_PDA_QUERY_CHUNK_SIZE) {
+                            let mut query = diesel::sql_query(format!(
+                                "UPDATE stake_accounts SET staked_balance = CASE stake_pda {} END, is_active = staked_balance > 0 WHERE stake_pda IN ({})",
+                                vec!["WHEN ? THEN ?"; batch.len()].join(" "),
+                                vec!["?"; batch.len()].join(","),
+                            ))
+                            .into_boxed::<diesel::mysql::Mysql>();
+                            for sa in batch {
+                                query = query
+                                    .bind::<Text, _>(sa.stake_pda.clone())
+                                    .bind::<Unsigned<BigInt>, _>(sa.staked_balance);
+                            }
+                            for sa in batch {
+                                query = query.bind::<Text, _>(sa.stake_pda.clone());
+                            }
+                            query.execute(conn)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .await;
+
+            match res {
+
+                Ok(interaction) => match interaction {
+                    Ok(_query) => {
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "update stake_account query error: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "update stake_account interaction error: {:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_active_stake_accounts(
+        &self,
+        pool_id: i32,
+        last_id: i32,
+    ) -> Result<Vec<StakeAccount>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_active_stake_accounts");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.id > ? AND s.is_active = TRUE ORDER BY s.id ASC LIMIT 500")
+                        .bind::<Integer, _>(pool_id)
+                        .bind::<Integer, _>(last_id)
+                        .load::<StakeAccount>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_stake_accounts_by_pdas(
+        &self,
+        pdas: Vec<String>,
+    ) -> Result<Vec<StakeAccount>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_stake_accounts_by_pdas");
+        if pdas.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    let mut accounts = Vec::with_capacity(pdas.len());
+                    for chunk in pdas.chunks(STAKE_PDA_QUERY_CHUNK_SIZE) {
+                        let placeholders = vec!["?"; chunk.len()].join(",");
+                        let mut query = diesel::sql_query(format!(
+                            "SELECT * FROM stake_accounts WHERE stake_pda IN ({}) ORDER BY id ASC",
+                            placeholders
+                        ))
+                        .into_boxed::<diesel::mysql::Mysql>();
+                        for stake_pda in chunk {
+                            query = query.bind::<Text, _>(stake_pda.clone());
+                        }
+                        accounts.extend(query.load::<StakeAccount>(conn)?);
+                    }
+                    Ok::<Vec<StakeAccount>, diesel::result::Error>(accounts)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn deactivate_stake_accounts(
+        &self,
+        stake_pdas: Vec<String>,
+    ) -> Result<usize, AppDatabaseError> {
+        let _timer = self.query_metrics.time("deactivate_stake_accounts");
+        if stake_pdas.is_empty() {
+            return Ok(0);
+        }
+
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    let placeholders = vec!["?"; stake_pdas.len()].join(",");
+                    let mut query = diesel::sql_query(format!(
+                        "UPDATE stake_accounts SET staked_balance = 0, is_active = FALSE WHERE is_active = TRUE AND stake_pda IN ({})",
+                        placeholders
+                    ))
+                    .into_boxed::<diesel::mysql::Mysql>();
+                    for stake_pda in stake_pdas {
+                        query = query.bind::<Text, _>(stake_pda);
+                    }
+                    query.execute(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_stake_accounts_rewards(
+        &self,
+        stake_accts: Vec<models::UpdateStakeAccountRewards>,
+    ) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("update_stake_accounts_rewards");
+        if stake_accts.is_empty() {
+            return Ok(());
+        }
+
+        check_stake_reward_deltas(&stake_accts)?;
+
+        let id = uuid::Uuid::new_v4();
+        let instant = Instant::now();
+        tracing::info!(target: "server_log", "{} - Getting db pool connection.", id);
+        if let Ok(db_conn) = self.get_conn().await {
+            tracing::info!(target: "server_log", "{} - Got db pool connection in {}ms.", id, instant.elapsed().as_millis());
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    conn.transaction::<(), StakeRewardsError, _>(|conn| {
+                        for batch in stake_accts.chunks(STAKE_PDA_QUERY_CHUNK_SIZE) {
+                            // MySQL rejects unsigned overflow only in strict mode, so check explicitly
+                            let mut overflow_check = diesel::sql_query(format!(
+                                "SELECT COUNT(*) AS count FROM stake_accounts WHERE {}",
+                                vec!["(stake_pda = ? AND mint_pubkey = ? AND (rewards_balance > ? OR total_rewards_earned > ?))"; batch.len()].join(" OR "),
+                            ))
+                            .into_boxed::<diesel::mysql::Mysql>();
+                            for sa in batch {
+                                let headroom = u64::MAX - sa.rewards_balance;
+                                overflow_check = overflow_check
+                                    .bind::<Text, _>(sa.stake_pda.clone())
+                                    .bind::<Text, _>(sa.mint_pubkey.clone())
+                                    .bind::<Unsigned<BigInt>, _>(headroom)
+                                    .bind::<Unsigned<BigInt>, _>(headroom);
+                            }
+                            let overflowing = overflow_check.get_result::<models::RowCount>(conn)?.count;
+                            if overflowing > 0 {
+                                return Err(StakeRewardsError::Overflow(overflowing));
+                            }
+
+                            let delta = format!("CASE {} ELSE 0 END", vec!["WHEN stake_pda = ? AND mint_pubkey = ? THEN ?"; batch.len()].join(" "));
+                            let mut query = diesel::sql_query(format!(
+                                "UPDATE stake_accounts SET rewards_balance = rewards_balance + {delta}, total_rewards_earned = total_rewards_earned + {delta} WHERE stake_pda IN ({ids})",
+                                delta = delta,
+                                ids = vec!["?"; batch.len()].join(","),
+                            ))
+                            .into_boxed::<diesel::mysql::Mysql>();
+                            for _ in 0..2 {
+                                for sa in batch {
+                                    query = query
+                                        .bind::<Text, _>(sa.stake_pda.clone())
+                                        .bind::<Text, _>(sa.mint_pubkey.clone())
+                                        .bind::<Unsigned<BigInt>, _>(sa.rewards_balance);
+                                }
+                            }
+                            for sa in batch {
+                                query = query.bind::<Text, _>(sa.stake_pda.clone());
+                            }
+                            query.execute(conn)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .await;
+
+            match res {
+
+                Ok(interaction) => match interaction {
+                    Ok(_query) => {
+                        return Ok(());
+                    }
+                    Err(StakeRewardsError::Overflow(overflowing)) => {
+                        error!(target: "server_log", "Rejecting stake rewards batch, {} stake accounts would overflow their rewards columns", overflowing);
+                        return Err(AppDatabaseError::InvalidInput(format!("{} stake accounts would overflow their rewards columns", overflowing)));
+                    }
+                    Err(StakeRewardsError::Query(e)) => {
+                        error!(target: "server_log", "update stake_account query error: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "update stake_account interaction error: {:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_stake_account_for_staker(
+        &self,
+        pool_id: i32,
+        staker_pubkey: String,
+        mint: String,
+    ) -> Result<StakeAccount, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_stake_account_for_staker");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? AND s.mint_pubkey = ? ORDER BY s.id ASC LIMIT 1")
+                        .bind::<Integer, _>(pool_id)
+                        .bind::<Text, _>(staker_pubkey)
+                        .bind::<Text, _>(mint)
+                        .get_result::<StakeAccount>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn find_stake_account_for_staker(
+        &self,
+        pool_id: i32,
+        staker_pubkey: String,
+        mint: String,
+    ) -> Result<Option<StakeAccount>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("find_stake_account_for_staker");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? AND s.mint_pubkey = ? ORDER BY s.id ASC LIMIT 1")
+                .bind::<Integer, _>(pool_id)
+                .bind::<Text, _>(staker_pubkey)
+                .bind::<Text, _>(mint)
+                .get_result::<StakeAccount>(conn)
+                .optional()
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_stake_accounts_for_staker(
+        &self,
+        pool_id: i32,
+        staker_pubkey: String,
+    ) -> Result<Vec<StakeAccount>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_stake_accounts_for_staker");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? ORDER BY s.id ASC")
+                        .bind::<Integer, _>(pool_id)
+                        .bind::<Text, _>(staker_pubkey)
+                        .load::<StakeAccount>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    /// A staker has one row per mint in each pool, so lookups are scoped to `pool_id`.
+    pub async fn get_staker_rewards(
+        &self,
+        pool_id: i32,
+        staker_pubkey: String,
+        mint: String,
+    ) -> Result<models::StakeAccount, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_staker_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT s.* FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? AND s.mint_pubkey = ? ORDER BY s.id ASC LIMIT 1")
+                .bind::<Integer, _>(pool_id)
+                .bind::<Text, _>(staker_pubkey)
+                .bind::<Text, _>(mint)
+                .get_result::<models::StakeAccount>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn find_staker_rewards(
+        &self,
+        pool_id: i32,
+        staker_pubkey: String,
+        mint: String,
+    ) -> Result<Option<models::StakeAccount>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("find_staker_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT s.* FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? AND s.mint_pubkey = ? ORDER BY s.id ASC LIMIT 1")
+                .bind::<Integer, _>(pool_id)
+                .bind::<Text, _>(staker_pubkey)
+                .bind::<Text, _>(mint)
+                .get_result::<models::StakeAccount>(conn)
+                .optional()
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn decrease_stakers_rewards(
+        &self,
+        staker_id: i32,
+        rewards_to_decrease: u64,
+    ) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("decrease_stakers_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("UPDATE stake_accounts SET rewards_balance = rewards_balance - ? WHERE id = ?")
+                        .bind::<Unsigned<BigInt>, _>(rewards_to_decrease)
+                        .bind::<Integer, _>(staker_id)
+                        .execute(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(_query) => {
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+     
+    pub async fn delete_old_submissions(&self) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("delete_old_submissions");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("DELETE FROM submissions WHERE created_at < NOW() - INTERVAL 7 DAY LIMIT 100000")
+                        .execute(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(_query) => {
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    /// Rolls submissions older than `older_than_days` into submission_aggregates and deletes them.
+    /// Rows a challenge points at through `challenges.submission_id` are kept.
+    pub async fn compact_old_submissions(
+        &self,
+        older_than_days: u32,
+        batch_size: u32,
+    ) -> Result<usize, AppDatabaseError> {
+        let _timer = self.query_metrics.time("compact_old_submissions");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    conn.transaction::<usize, diesel::result::Error, _>(|conn| {
+                        let max_id = diesel::sql_query("SELECT MAX(id) AS id FROM (SELECT s.id FROM submissions_2 s WHERE s.created_at < UTC_DATE() - INTERVAL ? DAY AND NOT EXISTS (SELECT 1 FROM challenges c WHERE c.submission_id = s.id) ORDER BY s.id ASC LIMIT ?) s")
+                            .bind::<Unsigned<Integer>, _>(older_than_days)
+                            .bind::<Unsigned<Integer>, _>(batch_size)
+                            .get_result::<models::MaxSubmissionId>(conn)?;
+
+                        let max_id = match max_id.id {
+                            Some(id) => id,
+                            None => return Ok(0),
+                        };
+
+                        diesel::sql_query("INSERT INTO submission_aggregates (miner_id, day, count, max_difficulty, sum_difficulty) SELECT miner_id, DATE(created_at), COUNT(*), MAX(difficulty), SUM(difficulty) FROM submissions_2 s WHERE s.id <= ? AND NOT EXISTS (SELECT 1 FROM challenges c WHERE c.submission_id = s.id) GROUP BY miner_id, DATE(created_at) ON DUPLICATE KEY UPDATE count = count + VALUES(count), max_difficulty = GREATEST(max_difficulty, VALUES(max_difficulty)), sum_difficulty = sum_difficulty + VALUES(sum_difficulty)")
+                            .bind::<Integer, _>(max_id)
+                            .execute(conn)?;
+
+                        diesel::sql_query("DELETE s FROM submissions_2 s WHERE s.id <= ? AND NOT EXISTS (SELECT 1 FROM challenges c WHERE c.submission_id = s.id)")
+                            .bind::<Integer, _>(max_id)
+                            .execute(conn)
+                    })
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
                 },
                 Err(e) => {
-                    error!("{:?}", e);
+                    error!(target: "server_log", "{:?}", e);
                     return Err(AppDatabaseError::InteractionFailed);
                 }
             }
@@ -796,16 +2099,19 @@ impl AppDatabase {
         };
     }
 
-    pub async fn get_miner_accounts(
+    pub async fn get_claimable_miner_rewards(
         &self,
+        min_balance: u64,
         last_id: i32,
-    ) -> Result<Vec<Miner>, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    ) -> Result<Vec<models::ClaimableMinerReward>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_claimable_miner_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query("SELECT * FROM miners m WHERE m.id > ? ORDER BY m.id ASC LIMIT 500")
+                    diesel::sql_query("SELECT r.id, r.miner_id, m.pubkey, r.balance FROM rewards r JOIN miners m ON r.miner_id = m.id WHERE r.id > ? AND r.balance >= ? AND m.enabled = TRUE ORDER BY r.id ASC LIMIT 500")
                         .bind::<Integer, _>(last_id)
-                        .load::<Miner>(conn)
+                        .bind::<Unsigned<BigInt>, _>(min_balance)
+                        .load::<models::ClaimableMinerReward>(conn)
                 })
                 .await;
 
@@ -815,12 +2121,12 @@ impl AppDatabase {
                         return Ok(query);
                     }
                     Err(e) => {
-                        error!("{:?}", e);
+                        error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
                 },
                 Err(e) => {
-                    error!("{:?}", e);
+                    error!(target: "server_log", "{:?}", e);
                     return Err(AppDatabaseError::InteractionFailed);
                 }
             }
@@ -829,28 +2135,20 @@ impl AppDatabase {
         };
     }
 
-    pub async fn add_new_stake_accounts_batch(
-        &self,
-        new_stake_accounts: Vec<models::InsertStakeAccount>,
-    ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    pub async fn legacy_submissions_table_exists(&self) -> Result<bool, AppDatabaseError> {
+        let _timer = self.query_metrics.time("legacy_submissions_table_exists");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    insert_into(crate::schema::stake_accounts::dsl::stake_accounts)
-                        .values(&new_stake_accounts)
-                        .on_conflict_do_nothing()
-                        .execute(conn)
+                    diesel::sql_query("SELECT COUNT(*) AS count FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = 'submissions'")
+                        .get_result::<models::RowCount>(conn)
                 })
                 .await;
 
             match res {
                 Ok(interaction) => match interaction {
                     Ok(query) => {
-                        info!(target: "server_log", "New Stake Accounts inserted: {}", query);
-                        if query == 0 {
-                            return Err(AppDatabaseError::FailedToInsertRow);
-                        }
-                        return Ok(());
+                        return Ok(query.count > 0);
                     }
                     Err(e) => {
                         error!(target: "server_log", "{:?}", e);
@@ -867,49 +2165,28 @@ impl AppDatabase {
         };
     }
 
-    pub async fn update_stake_accounts_staked_balance(
-        &self,
-        stake_accts: Vec<models::UpdateStakeAccount>,
-    ) -> Result<(), AppDatabaseError> {
-        let id = uuid::Uuid::new_v4();
-        let instant = Instant::now();
-        tracing::info!(target: "server_log", "{} - Getting db pool connection.", id);
-        if let Ok(db_conn) = self.connection_pool.get().await {
-            tracing::info!(target: "server_log", "{} - Got db pool connection in {}ms.", id, instant.elapsed().as_millis());
+    pub async fn get_legacy_submissions_id_range(&self) -> Result<(Option<i64>, Option<i64>), AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_legacy_submissions_id_range");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    let query = diesel::sql_query(
-                        "UPDATE stake_accounts SET staked_balance = CASE ".to_string() +
-                        &stake_accts
-                            .iter()
-                            .map(|sa| format!("WHEN stake_pda = '{}' THEN {}", sa.stake_pda, sa.staked_balance))
-                            .collect::<Vec<_>>()
-                            .join(" ") +
-                        " END WHERE stake_pda IN (" +
-                        &stake_accts
-                            .iter()
-                            .map(|sa| format!("'{}'", sa.stake_pda.clone()))
-                            .collect::<Vec<_>>()
-                            .join(",") +
-                        ")"
-                    );
-                    query.execute(conn)
+                    diesel::sql_query("SELECT MIN(id) AS min_id, MAX(id) AS max_id FROM submissions")
+                        .get_result::<models::IdRange>(conn)
                 })
                 .await;
 
             match res {
-
                 Ok(interaction) => match interaction {
-                    Ok(_query) => {
-                        return Ok(());
+                    Ok(query) => {
+                        return Ok((query.min_id, query.max_id));
                     }
                     Err(e) => {
-                        error!(target: "server_log", "update stake_account query error: {:?}", e);
+                        error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
                 },
                 Err(e) => {
-                    error!(target: "server_log", "update stake_account interaction error: {:?}", e);
+                    error!(target: "server_log", "{:?}", e);
                     return Err(AppDatabaseError::InteractionFailed);
                 }
             }
@@ -918,55 +2195,30 @@ impl AppDatabase {
         };
     }
 
-    pub async fn update_stake_accounts_rewards(
-        &self,
-        stake_accts: Vec<models::UpdateStakeAccountRewards>,
-    ) -> Result<(), AppDatabaseError> {
-        let id = uuid::Uuid::new_v4();
-        let instant = Instant::now();
-        tracing::info!(target: "server_log", "{} - Getting db pool connection.", id);
-        if let Ok(db_conn) = self.connection_pool.get().await {
-            tracing::info!(target: "server_log", "{} - Got db pool connection in {}ms.", id, instant.elapsed().as_millis());
+    pub async fn copy_legacy_submissions(&self, from_id: i64, to_id: i64) -> Result<usize, AppDatabaseError> {
+        let _timer = self.query_metrics.time("copy_legacy_submissions");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    let query = diesel::sql_query(
-                        "UPDATE stake_accounts SET rewards_balance = CASE ".to_string() +
-                        &stake_accts
-                            .iter()
-                            .map(|sa| format!("WHEN stake_pda = '{}' THEN rewards_balance + {}", sa.stake_pda, sa.rewards_balance))
-                            .collect::<Vec<_>>()
-                            .join(" ") +
-                        " END, total_rewards_earned = CASE " +
-                        &stake_accts
-                            .iter()
-                            .map(|sa| format!("WHEN stake_pda = '{}' THEN total_rewards_earned + {}", sa.stake_pda, sa.rewards_balance))
-                            .collect::<Vec<_>>()
-                            .join(" ") +
-                        " END WHERE stake_pda IN (" +
-                        &stake_accts
-                            .iter()
-                            .map(|sa| format!("'{}'", sa.stake_pda.clone()))
-                            .collect::<Vec<_>>()
-                            .join(",") +
-                        ")"
-                    );
-                    query.execute(conn)
+                    diesel::sql_query("INSERT INTO submissions_2 (miner_id, challenge_id, difficulty, nonce, created_at, updated_at) SELECT s.miner_id, s.challenge_id, s.difficulty, s.nonce, s.created_at, s.updated_at FROM submissions s WHERE s.id >= ? AND s.id <= ? AND NOT EXISTS (SELECT 1 FROM submissions_2 s2 WHERE s2.miner_id = s.miner_id AND s2.challenge_id = s.challenge_id AND s2.nonce = s.nonce)")
+                        .bind::<BigInt, _>(from_id)
+                        .bind::<BigInt, _>(to_id)
+                        .execute(conn)
                 })
                 .await;
 
             match res {
-
                 Ok(interaction) => match interaction {
-                    Ok(_query) => {
-                        return Ok(());
+                    Ok(query) => {
+                        return Ok(query);
                     }
                     Err(e) => {
-                        error!(target: "server_log", "update stake_account query error: {:?}", e);
+                        error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
                 },
                 Err(e) => {
-                    error!(target: "server_log", "update stake_account interaction error: {:?}", e);
+                    error!(target: "server_log", "{:?}", e);
                     return Err(AppDatabaseError::InteractionFailed);
                 }
             }
@@ -975,35 +2227,28 @@ impl AppDatabase {
         };
     }
 
-    pub async fn get_stake_account_for_staker(
-        &self,
-        pool_id: i32,
-        staker_pubkey: String,
-        mint: String,
-    ) -> Result<StakeAccount, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    pub async fn count_uncopied_legacy_submissions(&self) -> Result<i64, AppDatabaseError> {
+        let _timer = self.query_metrics.time("count_uncopied_legacy_submissions");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? AND s.mint_pubkey = ? ORDER BY s.id ASC LIMIT 1")
-                        .bind::<Integer, _>(pool_id)
-                        .bind::<Text, _>(staker_pubkey)
-                        .bind::<Text, _>(mint)
-                        .get_result::<StakeAccount>(conn)
+                    diesel::sql_query("SELECT COUNT(*) AS count FROM submissions s WHERE NOT EXISTS (SELECT 1 FROM submissions_2 s2 WHERE s2.miner_id = s.miner_id AND s2.challenge_id = s.challenge_id AND s2.nonce = s.nonce)")
+                        .get_result::<models::RowCount>(conn)
                 })
                 .await;
 
             match res {
                 Ok(interaction) => match interaction {
                     Ok(query) => {
-                        return Ok(query);
+                        return Ok(query.count);
                     }
                     Err(e) => {
-                        error!("{:?}", e);
+                        error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
                 },
                 Err(e) => {
-                    error!("{:?}", e);
+                    error!(target: "server_log", "{:?}", e);
                     return Err(AppDatabaseError::InteractionFailed);
                 }
             }
@@ -1012,33 +2257,28 @@ impl AppDatabase {
         };
     }
 
-    pub async fn get_stake_accounts_for_staker(
-        &self,
-        pool_id: i32,
-        staker_pubkey: String,
-    ) -> Result<Vec<StakeAccount>, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    pub async fn drop_legacy_submissions_table(&self) -> Result<(), AppDatabaseError> {
+        let _timer = self.query_metrics.time("drop_legacy_submissions_table");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query("SELECT * FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? ORDER BY s.id ASC")
-                        .bind::<Integer, _>(pool_id)
-                        .bind::<Text, _>(staker_pubkey)
-                        .load::<StakeAccount>(conn)
+                    diesel::sql_query("DROP TABLE IF EXISTS submissions")
+                        .execute(conn)
                 })
                 .await;
 
             match res {
                 Ok(interaction) => match interaction {
-                    Ok(query) => {
-                        return Ok(query);
+                    Ok(_query) => {
+                        return Ok(());
                     }
                     Err(e) => {
-                        error!("{:?}", e);
+                        error!(target: "server_log", "{:?}", e);
                         return Err(AppDatabaseError::QueryFailed);
                     }
                 },
                 Err(e) => {
-                    error!("{:?}", e);
+                    error!(target: "server_log", "{:?}", e);
                     return Err(AppDatabaseError::InteractionFailed);
                 }
             }
@@ -1047,23 +2287,20 @@ impl AppDatabase {
         };
     }
 
-    pub async fn get_staker_rewards(
-        &self,
-        staker_pubkey: String,
-        mint: String,
-    ) -> Result<models::StakeAccount, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
-            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("SELECT s.* FROM stake_accounts s WHERE s.staker_pubkey = ? AND s.mint_pubkey = ?")
-                .bind::<Text, _>(staker_pubkey)
-                .bind::<Text, _>(mint)
-                .get_result::<models::StakeAccount>(conn)
-            }).await;
+    pub async fn find_orphaned_rewards(&self) -> Result<Vec<i32>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("find_orphaned_rewards");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT r.id FROM rewards r LEFT JOIN miners m ON r.miner_id = m.id WHERE m.id IS NULL ORDER BY r.id ASC")
+                        .load::<models::RowId>(conn)
+                })
+                .await;
 
             match res {
                 Ok(interaction) => match interaction {
                     Ok(query) => {
-                        return Ok(query);
+                        return Ok(query.into_iter().map(|r| r.id).collect());
                     }
                     Err(e) => {
                         error!(target: "server_log", "{:?}", e);
@@ -1080,25 +2317,29 @@ impl AppDatabase {
         };
     }
 
-    pub async fn decrease_stakers_rewards(
-        &self,
-        staker_id: i32,
-        rewards_to_decrease: u64,
-    ) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+    pub async fn run_integrity_checks(&self) -> Result<IntegrityReport, AppDatabaseError> {
+        let _timer = self.query_metrics.time("run_integrity_checks");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
-                .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query("UPDATE stake_accounts SET rewards_balance = rewards_balance - ? WHERE id = ?")
-                        .bind::<Unsigned<BigInt>, _>(rewards_to_decrease)
-                        .bind::<Integer, _>(staker_id)
-                        .execute(conn)
+                .interact(move |conn: &mut MysqlConnection| -> Result<IntegrityReport, diesel::result::Error> {
+                    let count = |conn: &mut MysqlConnection, query: &str| {
+                        diesel::sql_query(query)
+                            .get_result::<models::RowCount>(conn)
+                            .map(|r| r.count as u64)
+                    };
+                    Ok(IntegrityReport {
+                        orphaned_rewards: count(conn, "SELECT COUNT(*) as count FROM rewards r LEFT JOIN miners m ON r.miner_id = m.id WHERE m.id IS NULL")?,
+                        anomalous_balances: count(conn, "SELECT COUNT(*) as count FROM rewards r JOIN pools p ON r.pool_id = p.id WHERE r.balance + p.claimed_rewards > p.total_rewards")?,
+                        duplicate_challenges: count(conn, "SELECT COUNT(*) as count FROM (SELECT c.pool_id FROM challenges c GROUP BY c.pool_id, c.challenge HAVING COUNT(*) > 1) d")?,
+                        challenges_missing_rewards: count(conn, "SELECT COUNT(*) as count FROM challenges c WHERE c.submission_id IS NOT NULL AND c.rewards_earned IS NULL AND c.created_at < NOW() - INTERVAL 10 MINUTE")?,
+                    })
                 })
                 .await;
 
             match res {
                 Ok(interaction) => match interaction {
-                    Ok(_query) => {
-                        return Ok(());
+                    Ok(report) => {
+                        return Ok(report);
                     }
                     Err(e) => {
                         error!(target: "server_log", "{:?}", e);
@@ -1114,20 +2355,34 @@ impl AppDatabase {
             return Err(AppDatabaseError::FailedToGetConnectionFromPool);
         };
     }
-     
-    pub async fn delete_old_submissions(&self) -> Result<(), AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+
+    /// Deletes the given rewards rows, skipping any whose miner has since reappeared.
+    pub async fn delete_orphaned_rewards(&self, reward_ids: Vec<i32>) -> Result<usize, AppDatabaseError> {
+        let _timer = self.query_metrics.time("delete_orphaned_rewards");
+        if reward_ids.is_empty() {
+            return Ok(0);
+        }
+
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
-                    diesel::sql_query("DELETE FROM submissions WHERE created_at < NOW() - INTERVAL 7 DAY LIMIT 100000")
-                        .execute(conn)
+                    let placeholders = vec!["?"; reward_ids.len()].join(",");
+                    let mut query = diesel::sql_query(format!(
+                        "DELETE r FROM rewards r LEFT JOIN miners m ON r.miner_id = m.id WHERE m.id IS NULL AND r.id IN ({})",
+                        placeholders
+                    ))
+                    .into_boxed::<diesel::mysql::Mysql>();
+                    for id in reward_ids {
+                        query = query.bind::<Integer, _>(id);
+                    }
+                    query.execute(conn)
                 })
                 .await;
 
             match res {
                 Ok(interaction) => match interaction {
-                    Ok(_query) => {
-                        return Ok(());
+                    Ok(query) => {
+                        return Ok(query);
                     }
                     Err(e) => {
                         error!(target: "server_log", "{:?}", e);
@@ -1148,7 +2403,8 @@ impl AppDatabase {
         &self,
         last_id: i32,
     ) -> Result<Vec<Reward>, AppDatabaseError> {
-        if let Ok(db_conn) = self.connection_pool.get().await {
+        let _timer = self.query_metrics.time("get_miner_reward_accounts");
+        if let Ok(db_conn) = self.get_conn().await {
             let res = db_conn
                 .interact(move |conn: &mut MysqlConnection| {
                     diesel::sql_query("SELECT * FROM rewards r WHERE r.id > ? ORDER BY r.id ASC LIMIT 500")
@@ -1176,4 +2432,504 @@ impl AppDatabase {
             return Err(AppDatabaseError::FailedToGetConnectionFromPool);
         };
     }
+
+    /// Returns (pool_id, miner unpaid, staker unpaid) for every pool with rewards or stake accounts.
+    pub async fn get_unpaid_by_pool(&self) -> Result<Vec<(i32, u64, u64)>, AppDatabaseError> {
+        let _timer = self.query_metrics.time("get_unpaid_by_pool");
+        if let Ok(db_conn) = self.get_conn().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query(
+                        "SELECT pool_id, CAST(SUM(miner_unpaid) AS UNSIGNED) AS miner_unpaid, CAST(SUM(staker_unpaid) AS UNSIGNED) AS staker_unpaid FROM (\
+                            SELECT pool_id, SUM(balance) AS miner_unpaid, 0 AS staker_unpaid FROM rewards GROUP BY pool_id \
+                            UNION ALL \
+                            SELECT pool_id, 0 AS miner_unpaid, SUM(rewards_balance) AS staker_unpaid FROM stake_accounts GROUP BY pool_id\
+                        ) unpaid GROUP BY pool_id ORDER BY pool_id ASC"
+                    )
+                    .load::<models::PoolUnpaidTotals>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query
+                            .into_iter()
+                            .map(|r| (r.pool_id, r.miner_unpaid, r.staker_unpaid))
+                            .collect());
+                    }
+                    Err(e) => {
+                        error!(target: "server_log", "{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!(target: "server_log", "{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+}
+
+fn check_stake_reward_deltas(stake_accts: &[models::UpdateStakeAccountRewards]) -> Result<(), AppDatabaseError> {
+    if let Some(sa) = stake_accts.iter().find(|sa| sa.rewards_balance > MAX_STAKE_REWARD_DELTA) {
+        error!(target: "server_log", "Rejecting stake rewards batch, delta {} for stake_pda {} exceeds max of {}", sa.rewards_balance, sa.stake_pda, MAX_STAKE_REWARD_DELTA);
+        return Err(AppDatabaseError::InvalidInput(format!("rewards delta {} for {} exceeds max of {}", sa.rewards_balance, sa.stake_pda, MAX_STAKE_REWARD_DELTA)));
+    }
+    Ok(())
+}
+
+type BoxedRewardsQuery = diesel::query_builder::BoxedSqlQuery<'static, diesel::mysql::Mysql, diesel::query_builder::SqlQuery>;
+
+fn bind_rewards_delta(mut query: BoxedRewardsQuery, rewards: &[models::UpdateReward]) -> BoxedRewardsQuery {
+    for r in rewards {
+        query = query
+            .bind::<Integer, _>(r.miner_id)
+            .bind::<Unsigned<BigInt>, _>(r.balance);
+    }
+    query
+}
+
+fn bind_rewards_ids(mut query: BoxedRewardsQuery, rewards: &[models::UpdateReward]) -> BoxedRewardsQuery {
+    for r in rewards {
+        query = query.bind::<Integer, _>(r.miner_id);
+    }
+    query
+}
+
+fn rewards_delta_sql(len: usize) -> String {
+    format!("CASE miner_id {} END", vec!["WHEN ? THEN ?"; len].join(" "))
+}
+
+fn update_rewards_query(rewards: &[models::UpdateReward]) -> BoxedRewardsQuery {
+    let query = diesel::sql_query(format!(
+        "UPDATE rewards SET balance = balance + {} WHERE miner_id IN ({})",
+        rewards_delta_sql(rewards.len()),
+        vec!["?"; rewards.len()].join(","),
+    ))
+    .into_boxed::<diesel::mysql::Mysql>();
+    let query = bind_rewards_delta(query, rewards);
+    bind_rewards_ids(query, rewards)
+}
+
+// Rewards that would leave a miner below min_reward_credit are held in carry_over
+// and moved into balance once the accumulated amount reaches the threshold.
+fn update_rewards_with_carry_over_query(rewards: &[models::UpdateReward], min_reward_credit: u64) -> BoxedRewardsQuery {
+    let delta = rewards_delta_sql(rewards.len());
+    let query = diesel::sql_query(format!(
+        "UPDATE rewards SET balance = balance + IF(carry_over + {delta} >= ?, carry_over + {delta}, 0), carry_over = IF(carry_over + {delta} >= ?, 0, carry_over + {delta}) WHERE miner_id IN ({ids})",
+        delta = delta,
+        ids = vec!["?"; rewards.len()].join(","),
+    ))
+    .into_boxed::<diesel::mysql::Mysql>();
+    let query = bind_rewards_delta(query, rewards).bind::<Unsigned<BigInt>, _>(min_reward_credit);
+    let query = bind_rewards_delta(query, rewards);
+    let query = bind_rewards_delta(query, rewards).bind::<Unsigned<BigInt>, _>(min_reward_credit);
+    let query = bind_rewards_delta(query, rewards);
+    bind_rewards_ids(query, rewards)
+}
+
+/// The earnings a distribution records: what each miner's balance is actually credited. With
+/// `min_reward_credit` set, a reward that stays below the threshold is carried over and earns
+/// nothing yet, and one that crosses it earns the carried amount along with it. Miners without a
+/// rewards row are not credited by the update and get no earning.
+pub fn credited_earnings(
+    rewards: &[models::UpdateReward],
+    carry_overs: &HashMap<i32, u64>,
+    min_reward_credit: u64,
+    pool_id: i32,
+    challenge_id: i32,
+) -> Vec<models::InsertEarning> {
+    rewards
+        .iter()
+        .filter_map(|reward| {
+            let carry_over = *carry_overs.get(&reward.miner_id)?;
+            let amount = if min_reward_credit > 0 {
+                let pending = carry_over.saturating_add(reward.balance);
+                if pending >= min_reward_credit { pending } else { 0 }
+            } else {
+                reward.balance
+            };
+            if amount == 0 {
+                return None;
+            }
+            Some(models::InsertEarning {
+                miner_id: reward.miner_id,
+                pool_id,
+                challenge_id,
+                amount,
+            })
+        })
+        .collect()
+}
+
+/// Runs `f` with InnoDB's session lock wait timeout lowered, restoring the server default afterwards.
+/// `max_execution_time` only applies to SELECTs, so lock waits are what bound a stuck UPDATE.
+fn with_lock_wait_timeout<T>(
+    conn: &mut MysqlConnection,
+    timeout: Duration,
+    f: impl FnOnce(&mut MysqlConnection) -> Result<T, diesel::result::Error>,
+) -> Result<T, diesel::result::Error> {
+    diesel::sql_query(format!(
+        "SET SESSION innodb_lock_wait_timeout = {}",
+        timeout.as_secs().max(1)
+    ))
+    .execute(conn)?;
+    let res = f(conn);
+    if let Err(e) = diesel::sql_query("SET SESSION innodb_lock_wait_timeout = DEFAULT").execute(conn) {
+        error!(target: "server_log", "Failed to reset innodb_lock_wait_timeout: {:?}", e);
+    }
+    res
+}
+
+fn is_lock_wait_timeout(e: &diesel::result::Error) -> bool {
+    match e {
+        diesel::result::Error::DatabaseError(_, info) => info.message().contains("Lock wait timeout exceeded"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use diesel::{
+        mysql::Mysql,
+        sql_types::{Date, TinyInt},
+        QueryableByName,
+    };
+
+    use super::*;
+
+    // The tests below need a migrated MySQL database and run one at a time:
+    // TEST_DATABASE_URL=mysql://... cargo test -- --ignored --test-threads=1
+    pub(crate) fn test_database_url() -> String {
+        std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set")
+    }
+
+    fn test_database() -> AppDatabase {
+        AppDatabase::new(test_database_url())
+    }
+
+    pub(crate) async fn execute(pool: &Pool, query: String) {
+        pool.get()
+            .await
+            .unwrap()
+            .interact(move |conn: &mut MysqlConnection| diesel::sql_query(query).execute(conn))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    pub(crate) async fn load<T>(pool: &Pool, query: String) -> Vec<T>
+    where
+        T: QueryableByName<Mysql> + Send + 'static,
+    {
+        pool.get()
+            .await
+            .unwrap()
+            .interact(move |conn: &mut MysqlConnection| diesel::sql_query(query).load::<T>(conn))
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[derive(QueryableByName)]
+    pub(crate) struct TestMiner {
+        #[diesel(sql_type = Integer)]
+        pub id: i32,
+        #[diesel(sql_type = Text)]
+        pub pubkey: String,
+    }
+
+    pub(crate) async fn insert_miner(pool: &Pool) -> TestMiner {
+        let pubkey = uuid::Uuid::new_v4().simple().to_string();
+        execute(pool, format!("INSERT INTO miners (pubkey, enabled) VALUES ('{}', TRUE)", pubkey)).await;
+        load::<TestMiner>(pool, format!("SELECT id, pubkey FROM miners WHERE pubkey = '{}'", pubkey))
+            .await
+            .remove(0)
+    }
+
+    pub(crate) async fn count(pool: &Pool, query: String) -> i64 {
+        load::<models::RowCount>(pool, query).await[0].count
+    }
+
+    #[derive(QueryableByName)]
+    struct Aggregate {
+        #[diesel(sql_type = Date)]
+        day: chrono::NaiveDate,
+        #[diesel(sql_type = Unsigned<Integer>)]
+        count: u32,
+        #[diesel(sql_type = TinyInt)]
+        max_difficulty: i8,
+        #[diesel(sql_type = Unsigned<BigInt>)]
+        sum_difficulty: u64,
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn compaction_rolls_old_submissions_into_daily_aggregates() {
+        let db = test_database();
+        let pool = &db.connection_pool;
+        let miner = insert_miner(pool).await;
+        execute(pool, format!(
+            "INSERT INTO submissions_2 (miner_id, challenge_id, difficulty, nonce, created_at) VALUES \
+             ({m}, 1, 10, 1, UTC_DATE() - INTERVAL 40 DAY), \
+             ({m}, 1, 20, 2, UTC_DATE() - INTERVAL 40 DAY + INTERVAL 1 HOUR), \
+             ({m}, 2, 15, 3, UTC_DATE() - INTERVAL 39 DAY), \
+             ({m}, 3, 25, 4, NOW())",
+            m = miner.id
+        )).await;
+
+        while db.compact_old_submissions(30, 1_000).await.unwrap() > 0 {}
+
+        let aggregates = load::<Aggregate>(pool, format!(
+            "SELECT day, count, max_difficulty, sum_difficulty FROM submission_aggregates WHERE miner_id = {} ORDER BY day ASC",
+            miner.id
+        )).await;
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(aggregates[1].day - aggregates[0].day, chrono::Duration::days(1));
+        assert_eq!((aggregates[0].count, aggregates[0].max_difficulty, aggregates[0].sum_difficulty), (2, 20, 30));
+        assert_eq!((aggregates[1].count, aggregates[1].max_difficulty, aggregates[1].sum_difficulty), (1, 15, 15));
+
+        // only the recent submission is left as a raw row
+        let remaining = count(pool, format!("SELECT COUNT(*) AS count FROM submissions_2 WHERE miner_id = {}", miner.id)).await;
+        assert_eq!(remaining, 1);
+
+        execute(pool, format!("DELETE FROM submissions_2 WHERE miner_id = {}", miner.id)).await;
+        execute(pool, format!("DELETE FROM submission_aggregates WHERE miner_id = {}", miner.id)).await;
+        execute(pool, format!("DELETE FROM miners WHERE id = {}", miner.id)).await;
+    }
+
+    #[derive(QueryableByName)]
+    struct LegacyId {
+        #[diesel(sql_type = BigInt)]
+        id: i64,
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn legacy_submissions_are_copied_once() {
+        let db = test_database();
+        let pool = &db.connection_pool;
+        assert!(!db.legacy_submissions_table_exists().await.unwrap());
+        execute(pool, "CREATE TABLE submissions (\
+            id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY, \
+            miner_id INT NOT NULL, \
+            challenge_id INT NOT NULL, \
+            difficulty TINYINT NOT NULL, \
+            nonce BIGINT UNSIGNED NOT NULL, \
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL, \
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP NOT NULL)".to_string()).await;
+        let miner = insert_miner(pool).await;
+        execute(pool, format!(
+            "INSERT INTO submissions (miner_id, challenge_id, difficulty, nonce) VALUES ({m}, 1, 10, 1), ({m}, 1, 12, 2), ({m}, 2, 14, 3)",
+            m = miner.id
+        )).await;
+        let ids = load::<LegacyId>(pool, "SELECT id FROM submissions ORDER BY id ASC".to_string()).await;
+        let (from, to) = (ids[0].id, ids[ids.len() - 1].id);
+        assert_eq!(db.get_legacy_submissions_id_range().await.unwrap(), (Some(from), Some(to)));
+
+        assert_eq!(db.copy_legacy_submissions(from, to).await.unwrap(), 3);
+        // a rerun over the same range must not duplicate anything
+        assert_eq!(db.copy_legacy_submissions(from, to).await.unwrap(), 0);
+        assert_eq!(db.count_uncopied_legacy_submissions().await.unwrap(), 0);
+        let copied = count(pool, format!("SELECT COUNT(*) AS count FROM submissions_2 WHERE miner_id = {}", miner.id)).await;
+        assert_eq!(copied, 3);
+
+        db.drop_legacy_submissions_table().await.unwrap();
+        assert!(!db.legacy_submissions_table_exists().await.unwrap());
+        execute(pool, format!("DELETE FROM submissions_2 WHERE miner_id = {}", miner.id)).await;
+        execute(pool, format!("DELETE FROM miners WHERE id = {}", miner.id)).await;
+    }
+
+    fn stake_rewards(deltas: &[u64]) -> Vec<models::UpdateStakeAccountRewards> {
+        deltas
+            .iter()
+            .enumerate()
+            .map(|(i, delta)| models::UpdateStakeAccountRewards {
+                stake_pda: format!("stake{}", i),
+                rewards_balance: *delta,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn implausible_stake_reward_deltas_are_rejected() {
+        assert!(check_stake_reward_deltas(&stake_rewards(&[1, MAX_STAKE_REWARD_DELTA])).is_ok());
+        assert!(matches!(
+            check_stake_reward_deltas(&stake_rewards(&[1, MAX_STAKE_REWARD_DELTA + 1])),
+            Err(AppDatabaseError::InvalidInput(_))
+        ));
+    }
+
+    #[derive(QueryableByName)]
+    struct TestId {
+        #[diesel(sql_type = Integer)]
+        id: i32,
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn integrity_check_counts_each_kind_of_anomaly() {
+        let db = test_database();
+        let pool = &db.connection_pool;
+        let before = db.run_integrity_checks().await.unwrap();
+
+        // a rewards row whose miner is gone
+        let gone = insert_miner(pool).await;
+        execute(pool, format!("DELETE FROM miners WHERE id = {}", gone.id)).await;
+        execute(pool, format!("INSERT INTO rewards (miner_id, pool_id, balance) VALUES ({}, 1, 0)", gone.id)).await;
+
+        // a balance larger than anything the pool ever earned
+        let proof_pubkey = uuid::Uuid::new_v4().simple().to_string();
+        execute(pool, format!(
+            "INSERT INTO pools (proof_pubkey, authority_pubkey, total_rewards, claimed_rewards) VALUES ('{}', '{}', 0, 0)",
+            proof_pubkey, proof_pubkey
+        )).await;
+        let pool_id = load::<TestId>(pool, format!("SELECT id FROM pools WHERE proof_pubkey = '{}'", proof_pubkey)).await[0].id;
+        let miner = insert_miner(pool).await;
+        execute(pool, format!("INSERT INTO rewards (miner_id, pool_id, balance) VALUES ({}, {}, 1)", miner.id, pool_id)).await;
+
+        // a mined challenge that never had its rewards recorded
+        let challenge = "CONCAT(UNHEX(REPLACE(UUID(), '-', '')), UNHEX(REPLACE(UUID(), '-', '')))";
+        execute(pool, format!(
+            "INSERT INTO challenges (pool_id, submission_id, challenge, created_at) VALUES ({}, 1, {}, NOW() - INTERVAL 1 HOUR)",
+            pool_id, challenge
+        )).await;
+
+        let after = db.run_integrity_checks().await.unwrap();
+        assert_eq!(after.orphaned_rewards, before.orphaned_rewards + 1);
+        assert_eq!(after.anomalous_balances, before.anomalous_balances + 1);
+        assert_eq!(after.challenges_missing_rewards, before.challenges_missing_rewards + 1);
+        // uc_challenges keeps duplicates out entirely, so that count cannot move
+        assert_eq!(after.duplicate_challenges, before.duplicate_challenges);
+
+        execute(pool, format!("DELETE FROM challenges WHERE pool_id = {}", pool_id)).await;
+        execute(pool, format!("DELETE FROM rewards WHERE miner_id IN ({}, {})", gone.id, miner.id)).await;
+        execute(pool, format!("DELETE FROM miners WHERE id = {}", miner.id)).await;
+        execute(pool, format!("DELETE FROM pools WHERE id = {}", pool_id)).await;
+    }
+
+    fn reward(miner_id: i32, balance: u64) -> models::UpdateReward {
+        models::UpdateReward { miner_id, balance }
+    }
+
+    fn pool(total_rewards: u64) -> models::Pool {
+        models::Pool {
+            id: 1,
+            proof_pubkey: "proof".to_string(),
+            authority_pubkey: "authority".to_string(),
+            total_rewards,
+            claimed_rewards: 0,
+        }
+    }
+
+    #[test]
+    fn pool_cache_serves_hits_until_invalidated() {
+        let mut cache = PoolCache::default();
+        let generation = cache.generation();
+        cache.insert("authority".to_string(), pool(10), generation);
+
+        assert_eq!(cache.get("authority", POOL_CACHE_TTL).map(|p| p.total_rewards), Some(10));
+        assert!(cache.get("authority", Duration::ZERO).is_none());
+
+        cache.invalidate("authority");
+        assert!(cache.get("authority", POOL_CACHE_TTL).is_none());
+    }
+
+    #[test]
+    fn pool_cache_drops_rows_read_before_an_update() {
+        let mut cache = PoolCache::default();
+        let generation = cache.generation();
+
+        // an update lands while the lookup is still waiting on the database
+        cache.invalidate("authority");
+        cache.insert("authority".to_string(), pool(10), generation);
+        assert!(cache.get("authority", POOL_CACHE_TTL).is_none());
+
+        let generation = cache.generation();
+        cache.insert("authority".to_string(), pool(20), generation);
+        assert_eq!(cache.get("authority", POOL_CACHE_TTL).map(|p| p.total_rewards), Some(20));
+    }
+
+    #[test]
+    fn single_earning_records_the_full_reward_without_a_threshold() {
+        let carry_overs = HashMap::from([(1, 0)]);
+        let earnings = credited_earnings(&[reward(1, 500)], &carry_overs, 0, 7, 42);
+
+        assert_eq!(earnings.len(), 1);
+        assert_eq!(earnings[0].miner_id, 1);
+        assert_eq!(earnings[0].pool_id, 7);
+        assert_eq!(earnings[0].challenge_id, 42);
+        assert_eq!(earnings[0].amount, 500);
+    }
+
+    #[test]
+    fn single_earning_below_the_threshold_is_carried_not_recorded() {
+        let carry_overs = HashMap::from([(1, 100)]);
+        let earnings = credited_earnings(&[reward(1, 200)], &carry_overs, 1_000, 7, 42);
+
+        assert!(earnings.is_empty());
+    }
+
+    #[test]
+    fn batch_earnings_record_only_credited_amounts() {
+        let carry_overs = HashMap::from([(1, 0), (2, 900), (3, 0)]);
+        let rewards = [reward(1, 200), reward(2, 200), reward(3, 1_500), reward(4, 5_000)];
+        let earnings = credited_earnings(&rewards, &carry_overs, 1_000, 7, 42);
+
+        let amounts: Vec<(i32, u64)> = earnings.iter().map(|e| (e.miner_id, e.amount)).collect();
+        assert_eq!(amounts, vec![(2, 1_100), (3, 1_500)]);
+    }
+
+    #[derive(QueryableByName)]
+    struct TestBalance {
+        #[diesel(sql_type = Unsigned<BigInt>)]
+        balance: u64,
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn distributing_a_challenge_twice_is_a_no_op() {
+        let db = test_database();
+        let pool = &db.connection_pool;
+        // a pool id no real pool uses, so the challenge can be found again by it
+        let pool_id = 1_000_000 + rand::random::<u16>() as i32;
+        let miner = insert_miner(pool).await;
+        execute(pool, format!("INSERT INTO rewards (miner_id, pool_id, balance) VALUES ({}, {}, 0)", miner.id, pool_id)).await;
+        execute(pool, format!(
+            "INSERT INTO challenges (pool_id, challenge) VALUES ({}, CONCAT(UNHEX(REPLACE(UUID(), '-', '')), UNHEX(REPLACE(UUID(), '-', ''))))",
+            pool_id
+        )).await;
+        let challenge_id = load::<TestId>(pool, format!("SELECT id FROM challenges WHERE pool_id = {}", pool_id)).await[0].id;
+        assert!(!db.is_challenge_rewards_distributed(challenge_id).await.unwrap());
+
+        let distribute = || db.distribute_challenge_rewards(
+            pool_id,
+            challenge_id,
+            vec![models::UpdateReward { miner_id: miner.id, balance: 100 }],
+            vec![models::InsertSubmission { miner_id: miner.id, challenge_id, nonce: 7, difficulty: 20 }],
+            7,
+            100,
+            0,
+        );
+        assert!(matches!(distribute().await.unwrap(), DistributionOutcome::Distributed { best_submission_id: Some(_) }));
+        assert!(db.is_challenge_rewards_distributed(challenge_id).await.unwrap());
+        assert_eq!(distribute().await.unwrap(), DistributionOutcome::AlreadyDistributed);
+
+        let balance = load::<TestBalance>(pool, format!("SELECT balance FROM rewards WHERE miner_id = {}", miner.id)).await[0].balance;
+        assert_eq!(balance, 100);
+        let submissions = count(pool, format!("SELECT COUNT(*) AS count FROM submissions_2 WHERE challenge_id = {}", challenge_id)).await;
+        assert_eq!(submissions, 1);
+        let earnings = count(pool, format!("SELECT COUNT(*) AS count FROM earnings WHERE challenge_id = {}", challenge_id)).await;
+        assert_eq!(earnings, 1);
+
+        execute(pool, format!("DELETE FROM earnings WHERE challenge_id = {}", challenge_id)).await;
+        execute(pool, format!("DELETE FROM submissions_2 WHERE challenge_id = {}", challenge_id)).await;
+        execute(pool, format!("DELETE FROM challenges WHERE id = {}", challenge_id)).await;
+        execute(pool, format!("DELETE FROM rewards WHERE miner_id = {}", miner.id)).await;
+        execute(pool, format!("DELETE FROM miners WHERE id = {}", miner.id)).await;
+    }
 }
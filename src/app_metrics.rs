@@ -1,7 +1,196 @@
+use std::collections::BTreeMap;
+
 use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::RwLock;
 
 use crate::ore_utils::{MineEventWithBoosts, MineEventWithGlobalBoosts};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Gauge => "gauge",
+            MetricKind::Counter => "counter",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricSample {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl MetricSample {
+    pub fn gauge(name: &'static str, help: &'static str, value: f64) -> Self {
+        Self { name, help, kind: MetricKind::Gauge, labels: Vec::new(), value }
+    }
+
+    pub fn counter(name: &'static str, help: &'static str, value: f64) -> Self {
+        Self { name, help, kind: MetricKind::Counter, labels: Vec::new(), value }
+    }
+
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.labels.push((key, value.into()));
+        self
+    }
+}
+
+/// Renders samples in the Prometheus text format. Samples of one metric must be adjacent.
+pub fn render_prometheus(samples: &[MetricSample]) -> String {
+    let mut out = String::new();
+    let mut last_name = None;
+    for sample in samples {
+        if last_name != Some(sample.name) {
+            out.push_str(&format!("# HELP {} {}\n", sample.name, sample.help));
+            out.push_str(&format!("# TYPE {} {}\n", sample.name, sample.kind.as_str()));
+            last_name = Some(sample.name);
+        }
+        if sample.labels.is_empty() {
+            out.push_str(&format!("{} {}\n", sample.name, sample.value));
+        } else {
+            let labels: Vec<String> = sample
+                .labels
+                .iter()
+                .map(|(key, value)| {
+                    let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                    format!("{}=\"{}\"", key, value)
+                })
+                .collect();
+            out.push_str(&format!("{}{{{}}} {}\n", sample.name, labels.join(","), sample.value));
+        }
+    }
+    out
+}
+
+/// Renders samples as JSON or Prometheus text and returns the body with its content type.
+pub fn render_metrics(mut samples: Vec<MetricSample>, wants_json: bool) -> (&'static str, String) {
+    samples.sort_by(|a, b| a.name.cmp(b.name));
+    if wants_json {
+        ("application/json", serde_json::to_string(&samples).unwrap_or_else(|_| "[]".to_string()))
+    } else {
+        ("text/plain; version=0.0.4", render_prometheus(&samples))
+    }
+}
+
+/// Latest value of every metric recorded from `AppMetricsEvent`s, served by `/metrics`.
+#[derive(Default)]
+pub struct MetricsRegistry {
+    samples: RwLock<BTreeMap<(&'static str, Vec<(&'static str, String)>), MetricSample>>,
+}
+
+impl MetricsRegistry {
+    /// Gauges replace the previous value, counters add to it.
+    pub async fn record(&self, sample: MetricSample) {
+        let mut samples = self.samples.write().await;
+        let key = (sample.name, sample.labels.clone());
+        match samples.get_mut(&key) {
+            Some(existing) if sample.kind == MetricKind::Counter => existing.value += sample.value,
+            _ => {
+                samples.insert(key, sample);
+            }
+        }
+    }
+
+    /// Samples ordered by name, so each metric's series are adjacent.
+    pub async fn snapshot(&self) -> Vec<MetricSample> {
+        self.samples.read().await.values().cloned().collect()
+    }
+
+    pub async fn record_event(&self, event: &AppMetricsEvent) {
+        for sample in event_samples(event) {
+            self.record(sample).await;
+        }
+    }
+}
+
+fn event_samples(event: &AppMetricsEvent) -> Vec<MetricSample> {
+    match event {
+        AppMetricsEvent::MineEvent(AppMetricsMineEvent::V1(data)) => vec![
+            MetricSample::gauge("ore_pool_proof_balance", "Pool proof balance after the last mine", data.balance as f64),
+            MetricSample::gauge("ore_pool_mine_difficulty", "Difficulty of the last mined hash", data.difficulty as f64),
+            MetricSample::gauge("ore_pool_mine_reward", "Reward of the last mine", data.reward as f64),
+            MetricSample::counter("ore_pool_mines_total", "Landed mine transactions", 1.0),
+        ],
+        AppMetricsEvent::MineEvent(AppMetricsMineEvent::V2(data)) => vec![
+            MetricSample::gauge("ore_pool_proof_balance", "Pool proof balance after the last mine", data.balance as f64),
+            MetricSample::gauge("ore_pool_mine_difficulty", "Difficulty of the last mined hash", data.difficulty as f64),
+            MetricSample::gauge("ore_pool_mine_reward", "Reward of the last mine", data.net_reward as f64),
+            MetricSample::counter("ore_pool_mines_total", "Landed mine transactions", 1.0),
+        ],
+        AppMetricsEvent::ClaimEvent(_) => vec![],
+        AppMetricsEvent::ProcessingClaimsEvent(data) => vec![
+            MetricSample::gauge("ore_pool_claims_queue_length", "Claims waiting to be processed", data.claims_queue_length as f64),
+            MetricSample::gauge("ore_pool_claims_in_flight", "Claims sent and awaiting confirmation", data.in_flight_claims as f64),
+        ],
+        AppMetricsEvent::RouteEvent(data) => vec![
+            MetricSample::counter("ore_pool_http_requests_total", "Handled http requests", 1.0)
+                .label("route", data.route.clone())
+                .label("method", data.method.clone())
+                .label("status", data.status_code.to_string()),
+        ],
+        AppMetricsEvent::MineCycleEvent(data) => {
+            let mut samples = vec![
+                MetricSample::gauge("ore_pool_mine_cycle_phase_ms", "Duration of each phase of the last mine cycle", data.observe_to_build_ms as f64).label("phase", "build"),
+                MetricSample::gauge("ore_pool_mine_cycle_phase_ms", "Duration of each phase of the last mine cycle", data.build_to_send_ms as f64).label("phase", "send"),
+                MetricSample::gauge("ore_pool_mine_cycle_phase_ms", "Duration of each phase of the last mine cycle", data.send_to_confirm_ms as f64).label("phase", "confirm"),
+            ];
+            if let Some(phase) = data.failed_phase {
+                samples.push(MetricSample::counter("ore_pool_mine_cycle_failures_total", "Mine cycles that failed, by phase", 1.0).label("phase", phase.as_str()));
+            }
+            samples
+        }
+        AppMetricsEvent::RpcFetchFailureEvent(data) => vec![
+            MetricSample::counter("ore_pool_rpc_fetch_failures_total", "Failed proof, config and bus fetches", 1.0)
+                .label("kind", if data.total_failure { "total" } else { "partial" }),
+            MetricSample::gauge("ore_pool_rpc_missing_busses", "Busses missing from the last failed fetch", data.missing_busses as f64),
+        ],
+        AppMetricsEvent::RpcHealthEvent(data) => {
+            let mut samples = vec![
+                MetricSample::gauge("ore_pool_rpc_consecutive_failures", "Consecutive failed RPC health checks", data.consecutive_failures as f64),
+            ];
+            if let Some(secs) = data.secs_since_success {
+                samples.push(MetricSample::gauge("ore_pool_rpc_secs_since_success", "Seconds since the last successful RPC health check", secs as f64));
+            }
+            samples
+        }
+        AppMetricsEvent::ClaimAmountMismatchEvent(data) => vec![
+            MetricSample::counter("ore_pool_claim_amount_mismatches_total", "Claims whose transfer did not match the claimed amount", 1.0)
+                .label("kind", data.claim_kind),
+        ],
+        AppMetricsEvent::ProofBalanceDecreaseEvent(data) => vec![
+            MetricSample::counter("ore_pool_unexplained_balance_decreases_total", "Proof balance decreases not matched by recorded claims", 1.0),
+            MetricSample::gauge("ore_pool_unexplained_balance_decrease", "Size of the last unexplained proof balance decrease", data.unexplained_decrease as f64),
+        ],
+        AppMetricsEvent::RpcCallsEvent(data) => vec![
+            MetricSample::gauge("ore_pool_rpc_calls_per_second", "RPC calls per second by method", data.calls_per_sec)
+                .label("method", data.method.clone()),
+        ],
+        AppMetricsEvent::RewardRemainderEvent(data) => vec![
+            MetricSample::gauge("ore_pool_reward_remainder", "Rounding remainder of the last reward distribution", data.remainder as f64),
+        ],
+        AppMetricsEvent::SubmissionBufferFlushEvent(data) => vec![
+            MetricSample::counter("ore_pool_submission_buffer_forced_flushes_total", "Submission buffer flushes forced by the cap", data.forced_flushes as f64),
+        ],
+        AppMetricsEvent::ClientVersionsEvent(data) => vec![
+            MetricSample::gauge("ore_pool_client_connections", "Connected clients by version", data.connections as f64)
+                .label("miner_version", data.miner_version.clone())
+                .label("drillx_version", data.drillx_version.clone())
+                .label("capabilities", data.capabilities.to_string()),
+        ],
+    }
+}
+
 #[derive(Debug)]
 pub enum AppMetricsError {
     FailedToSendMetrics(String),
@@ -18,6 +207,7 @@ pub struct MetricsClaimEventData {
 #[derive(Debug)]
 pub struct MetricsProcessingClaimsEventData {
     pub claims_queue_length: usize,
+    pub in_flight_claims: usize,
 }
 
 #[derive(Debug)]
@@ -31,6 +221,89 @@ pub struct MetricsRouteEventData {
     pub ts_ns: u128,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum MineCyclePhase {
+    Observe,
+    Build,
+    Send,
+    Confirm,
+}
+
+impl MineCyclePhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MineCyclePhase::Observe => "observe",
+            MineCyclePhase::Build => "build",
+            MineCyclePhase::Send => "send",
+            MineCyclePhase::Confirm => "confirm",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MetricsMineCycleEventData {
+    pub observe_to_build_ms: u128,
+    pub build_to_send_ms: u128,
+    pub send_to_confirm_ms: u128,
+    pub failed_phase: Option<MineCyclePhase>,
+}
+
+#[derive(Debug)]
+pub struct MetricsRpcFetchFailureEventData {
+    pub total_failure: bool,
+    pub proof_ok: bool,
+    pub config_ok: bool,
+    pub busses_ok: bool,
+    pub missing_busses: usize,
+}
+
+#[derive(Debug)]
+pub struct MetricsRpcHealthEventData {
+    pub consecutive_failures: u64,
+    pub secs_since_success: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct MetricsClaimAmountMismatchEventData {
+    pub claim_kind: &'static str,
+    pub expected_amount: u64,
+    pub transferred_amount: u64,
+}
+
+#[derive(Debug)]
+pub struct MetricsProofBalanceDecreaseEventData {
+    pub unexplained_decrease: u64,
+    pub balance: u64,
+}
+
+#[derive(Debug)]
+pub struct MetricsRpcCallsEventData {
+    pub method: String,
+    pub calls_per_sec: f64,
+}
+
+#[derive(Debug)]
+pub struct MetricsRewardRemainderEventData {
+    pub challenge_id: i32,
+    pub remainder: u64,
+    pub recipients: usize,
+}
+
+#[derive(Debug)]
+pub struct MetricsSubmissionBufferFlushEventData {
+    pub challenge_id: i32,
+    pub forced_flushes: u32,
+    pub submissions: usize,
+}
+
+#[derive(Debug)]
+pub struct MetricsClientVersionsEventData {
+    pub miner_version: String,
+    pub drillx_version: String,
+    pub capabilities: u8,
+    pub connections: u64,
+}
+
 #[derive(Debug)]
  pub enum AppMetricsMineEvent {
     V1(MineEventWithBoosts),
@@ -43,6 +316,15 @@ pub enum AppMetricsEvent {
     ClaimEvent(MetricsClaimEventData),
     ProcessingClaimsEvent(MetricsProcessingClaimsEventData),
     RouteEvent(MetricsRouteEventData),
+    MineCycleEvent(MetricsMineCycleEventData),
+    RpcFetchFailureEvent(MetricsRpcFetchFailureEventData),
+    RpcHealthEvent(MetricsRpcHealthEventData),
+    ClaimAmountMismatchEvent(MetricsClaimAmountMismatchEventData),
+    ProofBalanceDecreaseEvent(MetricsProofBalanceDecreaseEventData),
+    RpcCallsEvent(MetricsRpcCallsEventData),
+    RewardRemainderEvent(MetricsRewardRemainderEventData),
+    SubmissionBufferFlushEvent(MetricsSubmissionBufferFlushEventData),
+    ClientVersionsEvent(MetricsClientVersionsEventData),
 }
 
 pub struct AppMetrics {
@@ -97,3 +379,45 @@ impl AppMetrics {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn registry_metrics_render_in_both_formats() {
+        let registry = MetricsRegistry::default();
+        let event = AppMetricsEvent::RpcCallsEvent(MetricsRpcCallsEventData {
+            method: "getAccountInfo".to_string(),
+            calls_per_sec: 2.5,
+        });
+        registry.record_event(&event).await;
+        let samples = registry.snapshot().await;
+
+        let (content_type, body) = render_metrics(samples.clone(), false);
+        assert!(content_type.starts_with("text/plain"));
+        assert!(body.contains("# TYPE ore_pool_rpc_calls_per_second gauge"));
+        assert!(body.contains("ore_pool_rpc_calls_per_second{method=\"getAccountInfo\"} 2.5"));
+
+        let (content_type, body) = render_metrics(samples, true);
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json[0]["name"], "ore_pool_rpc_calls_per_second");
+        assert_eq!(json[0]["value"], 2.5);
+    }
+
+    #[tokio::test]
+    async fn counters_accumulate_and_gauges_replace() {
+        let registry = MetricsRegistry::default();
+        registry.record(MetricSample::counter("requests_total", "requests", 1.0)).await;
+        registry.record(MetricSample::counter("requests_total", "requests", 3.0)).await;
+        registry.record(MetricSample::gauge("queue_length", "queue", 5.0)).await;
+        registry.record(MetricSample::gauge("queue_length", "queue", 2.0)).await;
+
+        let samples = registry.snapshot().await;
+        assert_eq!(samples.len(), 2);
+        let value_of = |name: &str| samples.iter().find(|s| s.name == name).map(|s| s.value);
+        assert_eq!(value_of("requests_total"), Some(4.0));
+        assert_eq!(value_of("queue_length"), Some(2.0));
+    }
+}
@@ -1,27 +1,73 @@
 use deadpool_diesel::mysql::{Manager, Pool};
-use diesel::{sql_types::Text, MysqlConnection, RunQueryDsl};
+use chrono::NaiveDateTime;
+use diesel::{sql_types::{Integer, Text, Timestamp, Unsigned}, MysqlConnection, OptionalExtension, RunQueryDsl};
+use serde::Deserialize;
 use tracing::error;
 
 use crate::{
-    app_database::AppDatabaseError, models, ChallengeWithDifficulty, Submission,
+    app_database::{AppDatabaseError, DbPoolTimeouts}, models, ChallengeWithDifficulty, Submission,
     SubmissionWithPubkey, Txn,
 };
 
+const MAX_TOP_MINERS_LIMIT: u32 = 100;
+const MAX_RECENT_CLAIMS_LIMIT: u32 = 500;
+pub const MAX_RECENT_SUBMISSIONS_PER_MINER: u32 = 20;
+const RECENT_SUBMISSIONS_MINERS_PAGE: u32 = 1_000;
+const MAX_EARNINGS_HISTORY_BUCKETS: u32 = 720;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EarningsInterval {
+    Hour,
+    Day,
+}
+
+impl EarningsInterval {
+    fn bucket_format(&self) -> &'static str {
+        match self {
+            EarningsInterval::Hour => "%Y-%m-%d %H:00:00",
+            EarningsInterval::Day => "%Y-%m-%d 00:00:00",
+        }
+    }
+
+    pub fn max_days(&self) -> u32 {
+        match self {
+            EarningsInterval::Hour => MAX_EARNINGS_HISTORY_BUCKETS / 24,
+            EarningsInterval::Day => MAX_EARNINGS_HISTORY_BUCKETS,
+        }
+    }
+}
+
 pub struct AppRRDatabase {
     connection_pool: Pool,
 }
 
 impl AppRRDatabase {
     pub fn new(url: String) -> Self {
+        Self::new_with_timeouts(url, DbPoolTimeouts::default())
+    }
+
+    pub fn new_with_timeouts(url: String, timeouts: DbPoolTimeouts) -> Self {
         let manager = Manager::new(url, deadpool_diesel::Runtime::Tokio1);
 
-        let pool = Pool::builder(manager).build().unwrap();
+        let pool = Pool::builder(manager)
+            .runtime(deadpool_diesel::Runtime::Tokio1)
+            .wait_timeout(Some(timeouts.wait))
+            .create_timeout(Some(timeouts.create))
+            .recycle_timeout(Some(timeouts.recycle))
+            .build()
+            .unwrap();
 
         AppRRDatabase {
             connection_pool: pool,
         }
     }
 
+    /// Closes the pool. Tasks that still hold a handle get an error instead of a connection.
+    pub fn close(&self) {
+        self.connection_pool.close();
+    }
+
     pub async fn get_miner_rewards(
         &self,
         miner_pubkey: String,
@@ -53,6 +99,105 @@ impl AppRRDatabase {
         };
     }
 
+    pub async fn find_miner_rewards(
+        &self,
+        miner_pubkey: String,
+    ) -> Result<Option<models::Reward>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT r.id, r.balance, r.miner_id FROM miners m JOIN rewards r ON m.id = r.miner_id WHERE m.pubkey = ?")
+                .bind::<Text, _>(miner_pubkey)
+                .get_result::<models::Reward>(conn)
+                .optional()
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("find_miner_rewards: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_rank(
+        &self,
+        pool_id: i32,
+        miner_id: i32,
+    ) -> Result<Option<i64>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT CAST((SELECT COUNT(*) FROM rewards r2 WHERE r2.pool_id = r.pool_id AND r2.balance > r.balance) + 1 AS SIGNED) AS miner_rank FROM rewards r WHERE r.pool_id = ? AND r.miner_id = ?")
+                .bind::<Integer, _>(pool_id)
+                .bind::<Integer, _>(miner_id)
+                .get_result::<models::MinerRank>(conn)
+                .optional()
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query.map(|r| r.miner_rank));
+                    }
+                    Err(e) => {
+                        error!("get_miner_rank: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_staker_rewards_by_mint(
+        &self,
+        pool_id: i32,
+        staker_pubkey: String,
+    ) -> Result<Vec<models::StakerMintRewards>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT s.mint_pubkey, CAST(SUM(s.staked_balance) AS UNSIGNED) AS staked_balance, CAST(SUM(s.rewards_balance) AS UNSIGNED) AS rewards_balance, CAST(SUM(s.total_rewards_earned) AS UNSIGNED) AS total_rewards_earned FROM stake_accounts s WHERE s.pool_id = ? AND s.staker_pubkey = ? GROUP BY s.mint_pubkey ORDER BY s.mint_pubkey")
+                .bind::<Integer, _>(pool_id)
+                .bind::<Text, _>(staker_pubkey)
+                .load::<models::StakerMintRewards>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("get_staker_rewards_by_mint: {:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn get_last_challenge_submissions(
         &self,
     ) -> Result<Vec<SubmissionWithPubkey>, AppDatabaseError> {
@@ -85,6 +230,265 @@ impl AppRRDatabase {
         };
     }
 
+    /// The `per_miner` newest submissions of the next `RECENT_SUBMISSIONS_MINERS_PAGE` miners
+    /// after `after_miner_id` that were active in the last day, ordered by miner id.
+    pub async fn get_recent_submissions_per_miner(
+        &self,
+        per_miner: u32,
+        after_miner_id: i32,
+    ) -> Result<Vec<SubmissionWithPubkey>, AppDatabaseError> {
+        let per_miner = per_miner.clamp(1, MAX_RECENT_SUBMISSIONS_PER_MINER);
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT r.id, r.miner_id, r.challenge_id, r.nonce, r.difficulty, r.created_at, m.pubkey FROM (SELECT s.id, s.miner_id, s.challenge_id, s.nonce, s.difficulty, s.created_at, ROW_NUMBER() OVER (PARTITION BY s.miner_id ORDER BY s.id DESC) AS rn FROM submissions_2 s JOIN (SELECT DISTINCT miner_id FROM submissions_2 WHERE created_at >= NOW() - INTERVAL 1 DAY AND miner_id > ? ORDER BY miner_id ASC LIMIT ?) p ON s.miner_id = p.miner_id WHERE s.created_at >= NOW() - INTERVAL 1 DAY) r JOIN miners m ON r.miner_id = m.id WHERE r.rn <= ? ORDER BY r.miner_id ASC, r.id DESC")
+                        .bind::<Integer, _>(after_miner_id)
+                        .bind::<Unsigned<Integer>, _>(RECENT_SUBMISSIONS_MINERS_PAGE)
+                        .bind::<Unsigned<Integer>, _>(per_miner)
+                        .load::<SubmissionWithPubkey>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_submission_aggregates(
+        &self,
+        pubkey: String,
+    ) -> Result<Vec<models::SubmissionAggregate>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT sa.miner_id, sa.day, sa.count, sa.max_difficulty, sa.sum_difficulty FROM submission_aggregates sa JOIN miners m ON sa.miner_id = m.id WHERE m.pubkey = ? ORDER BY sa.day DESC LIMIT 365")
+                        .bind::<Text, _>(pubkey)
+                        .load::<models::SubmissionAggregate>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_submission_stats(
+        &self,
+        miner_id: i32,
+    ) -> Result<models::MinerSubmissionStats, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT COUNT(*) as count, MAX(s.difficulty) as max_difficulty FROM submissions_2 s WHERE s.miner_id = ? AND s.created_at >= NOW() - INTERVAL 1 DAY")
+                        .bind::<Integer, _>(miner_id)
+                        .get_result::<models::MinerSubmissionStats>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_top_miners_by_difficulty(
+        &self,
+        since: NaiveDateTime,
+        limit: u32,
+    ) -> Result<Vec<(String, u64)>, AppDatabaseError> {
+        let limit = limit.clamp(1, MAX_TOP_MINERS_LIMIT);
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT m.pubkey, CAST(SUM(s.difficulty) AS UNSIGNED) as total_difficulty FROM submissions_2 s JOIN miners m ON s.miner_id = m.id WHERE s.created_at >= ? GROUP BY m.id, m.pubkey ORDER BY total_difficulty DESC LIMIT ?")
+                        .bind::<Timestamp, _>(since)
+                        .bind::<Unsigned<Integer>, _>(limit)
+                        .load::<models::MinerDifficultyTotal>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query.into_iter().map(|t| (t.pubkey, t.total_difficulty)).collect());
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_earnings_history(
+        &self,
+        pubkey: String,
+        interval: EarningsInterval,
+        since: NaiveDateTime,
+    ) -> Result<Vec<models::EarningsBucket>, AppDatabaseError> {
+        let query = format!(
+            "SELECT TIMESTAMP(DATE_FORMAT(e.created_at, '{}')) as bucket_start, CAST(SUM(e.amount) AS UNSIGNED) as amount FROM earnings e JOIN miners m ON e.miner_id = m.id WHERE m.pubkey = ? AND e.created_at >= ? GROUP BY bucket_start ORDER BY bucket_start ASC LIMIT ?",
+            interval.bucket_format()
+        );
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query(query)
+                        .bind::<Text, _>(pubkey)
+                        .bind::<Timestamp, _>(since)
+                        .bind::<Unsigned<Integer>, _>(MAX_EARNINGS_HISTORY_BUCKETS)
+                        .load::<models::EarningsBucket>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_pool_earnings_history(
+        &self,
+        pool_id: i32,
+        interval: EarningsInterval,
+        since: NaiveDateTime,
+    ) -> Result<Vec<models::EarningsBucket>, AppDatabaseError> {
+        let query = format!(
+            "SELECT TIMESTAMP(DATE_FORMAT(e.created_at, '{}')) as bucket_start, CAST(SUM(e.amount) AS UNSIGNED) as amount FROM earnings e WHERE e.pool_id = ? AND e.created_at >= ? GROUP BY bucket_start ORDER BY bucket_start ASC LIMIT ?",
+            interval.bucket_format()
+        );
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query(query)
+                        .bind::<Integer, _>(pool_id)
+                        .bind::<Timestamp, _>(since)
+                        .bind::<Unsigned<Integer>, _>(MAX_EARNINGS_HISTORY_BUCKETS)
+                        .load::<models::EarningsBucket>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_recent_claims(
+        &self,
+        pool_id: i32,
+        limit: u32,
+    ) -> Result<Vec<models::ClaimWithMiner>, AppDatabaseError> {
+        let limit = limit.clamp(1, MAX_RECENT_CLAIMS_LIMIT);
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT m.pubkey, c.amount, t.signature, c.created_at FROM claims c JOIN miners m ON c.miner_id = m.id JOIN txns t ON c.txn_id = t.id WHERE c.pool_id = ? ORDER BY c.created_at DESC, c.id DESC LIMIT ?")
+                        .bind::<Integer, _>(pool_id)
+                        .bind::<Unsigned<Integer>, _>(limit)
+                        .load::<models::ClaimWithMiner>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn get_miner_submissions(
         &self,
         pubkey: String,
@@ -148,6 +552,37 @@ impl AppRRDatabase {
         };
     }
 
+    /// Challenges updated at or after `since`, so rewards written after a challenge was first cached are picked up.
+    pub async fn get_challenges_since(&self, since: NaiveDateTime) -> Result<Vec<ChallengeWithDifficulty>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn
+                .interact(move |conn: &mut MysqlConnection| {
+                    diesel::sql_query("SELECT c.id, c.rewards_earned, c.updated_at, s.difficulty FROM challenges c JOIN submissions_2 s ON c.submission_id = s.id WHERE c.submission_id IS NOT NULL AND c.updated_at >= ? ORDER BY c.updated_at ASC LIMIT 1440")
+                        .bind::<Timestamp, _>(since)
+                        .load::<ChallengeWithDifficulty>(conn)
+                })
+                .await;
+
+            match res {
+                Ok(interaction) => match interaction {
+                    Ok(query) => {
+                        return Ok(query);
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(AppDatabaseError::QueryFailed);
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn get_pool_by_authority_pubkey(
         &self,
         pool_pubkey: String,
@@ -244,3 +679,60 @@ impl AppRRDatabase {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::app_database::tests::{execute, insert_miner, test_database_url};
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn top_miners_only_count_submissions_inside_the_window() {
+        let db = AppRRDatabase::new(test_database_url());
+        let pool = &db.connection_pool;
+        let (a, b, c) = (insert_miner(pool).await, insert_miner(pool).await, insert_miner(pool).await);
+        // dated far ahead so rows from other runs never fall inside the window
+        execute(pool, format!(
+            "INSERT INTO submissions_2 (miner_id, challenge_id, difficulty, nonce, created_at) VALUES \
+             ({a}, 1, 20, 1, '2099-01-01 00:00:00'), ({a}, 2, 22, 2, '2099-01-01 00:01:00'), \
+             ({b}, 1, 30, 3, '2099-01-01 00:00:00'), \
+             ({c}, 1, 25, 4, '2099-01-01 00:00:00'), ({c}, 0, 60, 5, '2098-12-31 23:00:00')",
+            a = a.id, b = b.id, c = c.id
+        )).await;
+
+        let since = NaiveDate::from_ymd_opt(2099, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let top = db.get_top_miners_by_difficulty(since, 10).await.unwrap();
+        assert_eq!(top, vec![(a.pubkey.clone(), 42), (b.pubkey.clone(), 30), (c.pubkey.clone(), 25)]);
+        assert_eq!(db.get_top_miners_by_difficulty(since, 1).await.unwrap(), vec![(a.pubkey, 42)]);
+
+        let ids = format!("{}, {}, {}", a.id, b.id, c.id);
+        execute(pool, format!("DELETE FROM submissions_2 WHERE miner_id IN ({})", ids)).await;
+        execute(pool, format!("DELETE FROM miners WHERE id IN ({})", ids)).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn miner_rank_counts_richer_miners_in_the_pool() {
+        let db = AppRRDatabase::new(test_database_url());
+        let pool = &db.connection_pool;
+        // a pool id no real pool uses, so only these rows are ranked
+        let pool_id = 1_000_000 + rand::random::<u16>() as i32;
+        let (a, b, c) = (insert_miner(pool).await, insert_miner(pool).await, insert_miner(pool).await);
+        let unranked = insert_miner(pool).await;
+        execute(pool, format!(
+            "INSERT INTO rewards (miner_id, pool_id, balance) VALUES ({}, {p}, 300), ({}, {p}, 200), ({}, {p}, 100)",
+            a.id, b.id, c.id, p = pool_id
+        )).await;
+
+        assert_eq!(db.get_miner_rank(pool_id, a.id).await.unwrap(), Some(1));
+        assert_eq!(db.get_miner_rank(pool_id, b.id).await.unwrap(), Some(2));
+        assert_eq!(db.get_miner_rank(pool_id, c.id).await.unwrap(), Some(3));
+        assert_eq!(db.get_miner_rank(pool_id, unranked.id).await.unwrap(), None);
+
+        let ids = format!("{}, {}, {}, {}", a.id, b.id, c.id, unranked.id);
+        execute(pool, format!("DELETE FROM rewards WHERE miner_id IN ({})", ids)).await;
+        execute(pool, format!("DELETE FROM miners WHERE id IN ({})", ids)).await;
+    }
+}
@@ -1,14 +1,14 @@
-use ore_api::consts::{CONFIG_ADDRESS, BUS_ADDRESSES};
+use ore_api::consts::BUS_ADDRESSES;
 use ore_api::state::{Proof, Config};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::account::ReadableAccount;
 use solana_sdk::pubkey::Pubkey;
 use steel::AccountDeserialize;
 
-use crate::ore_utils::{get_proof_pda, proof_pubkey};
+use crate::ore_utils::{get_proof_pda, proof_pubkey, ParsePolicy, ProgramIds};
 
-pub async fn get_proof(client: &RpcClient, authority: Pubkey) -> Result<Proof, String> {
-    let proof_address = crate::ore_utils::get_proof_pda(authority);
+pub async fn get_proof(ids: &ProgramIds, client: &RpcClient, authority: Pubkey) -> Result<Proof, String> {
+    let proof_address = get_proof_pda(ids, authority);
     let data = client.get_account_data(&proof_address).await;
     match data {
         Ok(data) => {
@@ -24,100 +24,76 @@ pub async fn get_proof(client: &RpcClient, authority: Pubkey) -> Result<Proof, S
 }
 
 pub async fn get_proof_and_config_with_busses(
+    ids: &ProgramIds,
     client: &RpcClient,
     authority: Pubkey,
+    parse_policy: ParsePolicy,
 ) -> (
     Result<Proof, ()>,
     Result<ore_api::state::Config, ()>,
     Result<Vec<Result<ore_api::state::Bus, ()>>, ()>,
 ) {
-    let account_pubkeys = vec![
-        get_proof_pda(authority),
-        CONFIG_ADDRESS,
-        BUS_ADDRESSES[0],
-        BUS_ADDRESSES[1],
-        BUS_ADDRESSES[2],
-        BUS_ADDRESSES[3],
-        BUS_ADDRESSES[4],
-        BUS_ADDRESSES[5],
-        BUS_ADDRESSES[6],
-        BUS_ADDRESSES[7],
+    let mut account_pubkeys = vec![
+        get_proof_pda(ids, authority),
+        ids.config_address(),
     ];
+    account_pubkeys.extend((0..BUS_ADDRESSES.len()).map(|bus| ids.bus_address(bus)));
     let datas = client.get_multiple_accounts(&account_pubkeys).await;
     if let Ok(datas) = datas {
-        let proof = if let Some(data) = &datas[0] {
-            Ok(*Proof::try_from_bytes(data.data()).expect("Failed to parse treasury account"))
-        } else {
-            Err(())
+        // each account is parsed independently so one bad account doesn't discard the rest,
+        // unless the parse policy says to fail fast
+        let mut abandoned = false;
+        let mut check = |parsed: Result<(), ()>, label: &str| {
+            if parsed.is_err() && parse_policy.on_parse_failure(label).is_err() {
+                abandoned = true;
+            }
         };
 
-        let treasury_config = if let Some(data) = &datas[1] {
-            Ok(*ore_api::state::Config::try_from_bytes(data.data())
-                .expect("Failed to parse config account"))
-        } else {
-            Err(())
-        };
-        let bus_1 = if let Some(data) = &datas[2] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus1 account"))
-        } else {
-            Err(())
-        };
-        let bus_2 = if let Some(data) = &datas[3] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus2 account"))
-        } else {
-            Err(())
-        };
-        let bus_3 = if let Some(data) = &datas[4] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus3 account"))
-        } else {
-            Err(())
-        };
-        let bus_4 = if let Some(data) = &datas[5] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus4 account"))
-        } else {
-            Err(())
-        };
-        let bus_5 = if let Some(data) = &datas[6] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus5 account"))
-        } else {
-            Err(())
-        };
-        let bus_6 = if let Some(data) = &datas[7] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus6 account"))
-        } else {
-            Err(())
+        let proof = match &datas[0] {
+            Some(data) => Proof::try_from_bytes(data.data()).map(|p| *p).map_err(|_| ()),
+            None => Err(()),
         };
-        let bus_7 = if let Some(data) = &datas[8] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus7 account"))
-        } else {
-            Err(())
-        };
-        let bus_8 = if let Some(data) = &datas[9] {
-            Ok(*ore_api::state::Bus::try_from_bytes(data.data())
-                .expect("Failed to parse bus1 account"))
-        } else {
-            Err(())
+        if datas[0].is_some() {
+            check(proof.map(|_| ()), "proof");
+        }
+
+        let treasury_config = match &datas[1] {
+            Some(data) => ore_api::state::Config::try_from_bytes(data.data()).map(|c| *c).map_err(|_| ()),
+            None => Err(()),
         };
+        if datas[1].is_some() {
+            check(treasury_config.map(|_| ()), "config");
+        }
+
+        let busses = datas[2..]
+            .iter()
+            .enumerate()
+            .map(|(i, data)| match data {
+                Some(data) => {
+                    let bus = ore_api::state::Bus::try_from_bytes(data.data()).map(|b| *b).map_err(|_| ());
+                    check(bus.map(|_| ()), &format!("bus{}", i + 1));
+                    bus
+                }
+                None => Err(()),
+            })
+            .collect::<Vec<_>>();
+
+        if abandoned {
+            return (Err(()), Err(()), Err(()));
+        }
 
         (
             proof,
             treasury_config,
-            Ok(vec![bus_1, bus_2, bus_3, bus_4, bus_5, bus_6, bus_7, bus_8]),
+            Ok(busses),
         )
     } else {
         (Err(()), Err(()), Err(()))
     }
 }
 
-pub async fn get_original_proof(client: &RpcClient, authority: Pubkey) -> Result<Proof, String> {
-    let proof_address = proof_pubkey(authority);
+pub async fn get_original_proof(ids: &ProgramIds, client: &RpcClient, authority: Pubkey) -> Result<Proof, String> {
+    let proof_address = proof_pubkey(ids, authority);
     let data = client.get_account_data(&proof_address).await;
     match data {
         Ok(data) => {
@@ -133,8 +109,8 @@ pub async fn get_original_proof(client: &RpcClient, authority: Pubkey) -> Result
 }
 
 
-pub async fn get_config(client: &RpcClient) -> Result<ore_api::state::Config, String> {
-    let data = client.get_account_data(&CONFIG_ADDRESS).await;
+pub async fn get_config(ids: &ProgramIds, client: &RpcClient) -> Result<ore_api::state::Config, String> {
+    let data = client.get_account_data(&ids.config_address()).await;
     match data {
         Ok(data) => {
             let config = Config::try_from_bytes(&data);
@@ -1,37 +1,38 @@
 use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
-    ops::{ControlFlow, Div},
+    ops::{ControlFlow, Div, Range},
     path::Path,
     str::FromStr,
-    sync::Arc,
+    sync::{atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering}, Arc},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use app_metrics::MetricsRouteEventData;
-use ore_miner_delegation::{pda::{delegated_boost_pda, managed_proof_pda}, state::DelegatedBoost, utils::AccountDeserializeV1};
+use app_metrics::{render_metrics, MetricSample, MetricsRegistry, MetricsRouteEventData};
+use ore_miner_delegation::{pda::delegated_boost_pda, state::DelegatedBoost, utils::AccountDeserializeV1};
 use solana_account_decoder::UiAccountEncoding;
 use steel::AccountDeserialize as _;
 use systems::{
-    claim_system::claim_system, client_message_handler_system::client_message_handler_system,
+    claim_system::{beneficiary_ata_exists, claim_system, ClaimReservations, InFlightClaims, CLAIM_CONFIRM_TIMEOUT_SECS}, client_message_handler_system::client_message_handler_system,
+    client_submissions_handler::write_superseded_submissions,
     handle_ready_clients_system::handle_ready_clients_system,
     pong_tracking_system::pong_tracking_system, proof_tracking_system::proof_tracking_system,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 use crate::{
-    app_metrics::AppMetricsEvent, global_boost_util::{get_original_proof, get_proof}, ore_utils::{get_managed_proof_token_ata, get_proof_pda, get_rotate_ix, proof_pubkey}, systems::{app_metrics_system::metrics_system, cache_update_system::cache_update_system, message_text_all_clients_system::message_text_all_clients_system, pool_mine_success_system::pool_mine_success_system, pool_submission_system::pool_submission_system}
+    app_metrics::AppMetricsEvent, nonce_allocator::{Clock, NonceAllocator, SystemClock}, rpc_budget::{RpcBudget, RpcPriority}, global_boost_util::{get_original_proof, get_proof}, ore_utils::{get_managed_proof_pda, get_managed_proof_token_ata, retarget_ix, BusSelectionPolicy, MineEventLayout, ParsePolicy, ProgramIds, get_proof_pda, get_rotate_ix, proof_pubkey}, systems::{app_metrics_system::{metrics_registry_system, metrics_system}, cache_update_system::cache_update_system, message_text_all_clients_system::message_text_all_clients_system, pool_mine_success_system::pool_mine_success_system, pool_submission_system::pool_submission_system, submission_compaction_system::submission_compaction_system, claim_scheduler_system::claim_scheduler_system, proof_balance_watchdog_system::proof_balance_watchdog_system, janitor_system::janitor_system, rpc_call_metrics_system::rpc_call_metrics_system, client_versions_metrics_system::client_versions_metrics_system}
 };
 
 use self::models::*;
-use app_database::{AppDatabase, AppDatabaseError};
-use app_rr_database::AppRRDatabase;
+use app_database::{AppDatabase, AppDatabaseError, DbPoolTimeouts};
+use app_rr_database::{AppRRDatabase, EarningsInterval};
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message, WebSocket},
         ConnectInfo, Query, State, WebSocketUpgrade,
     },
-    http::{Method, Response, StatusCode},
+    http::{header, HeaderMap, Method, Response, StatusCode},
     response::IntoResponse,
     routing::{get, post},
     Extension, Json, Router,
@@ -42,7 +43,7 @@ use clap::{Parser, Subcommand};
 use drillx::Solution;
 use futures::{stream::SplitSink, SinkExt, StreamExt};
 use ore_utils::{
-    get_delegated_boost_account, get_delegated_boost_account_v2, get_delegated_stake_account, get_ore_mint, get_register_ix, ORE_TOKEN_DECIMALS
+    get_delegated_boost_account, get_delegated_boost_account_v2, get_delegated_stake_account, is_known_boost_mint, verify_pool_pdas, DelegatedBoostError, get_ore_mint, get_register_ix, from_ui_amount, ORE_TOKEN_DECIMALS
 };
 use routes::{get_challenges, get_latest_mine_txn, get_pool_balance};
 use serde::{Deserialize, Serialize};
@@ -59,7 +60,7 @@ use spl_associated_token_account::{
     get_associated_token_address, instruction::create_associated_token_account,
 };
 use tokio::{
-    sync::{mpsc::UnboundedSender, Mutex, RwLock},
+    sync::{broadcast::{self, error::RecvError}, mpsc::{self, error::TrySendError, UnboundedSender}, Mutex, RwLock},
     time::Instant,
 };
 use tower_http::{
@@ -79,6 +80,8 @@ mod systems;
 mod scripts;
 mod app_metrics;
 mod global_boost_util;
+mod rpc_budget;
+mod nonce_allocator;
 
 const MIN_DIFF: u32 = 12;
 const MIN_HASHPOWER: u64 = 80; // difficulty 12
@@ -90,6 +93,11 @@ const ORE_ISC_BOOST_MINT: &str = "meUwDp23AaxhiNKaQCyJ2EAF2T4oe1gSkEkGXSRVdZb";
 
 const CLAIM_MINIMUM: u64 = 5_000;
 
+const SHUTDOWN_DRAIN_TIMEOUT_SECS: u64 = 120;
+
+// a claim can wait out its full confirmation timeout after being sent, plus time to settle
+const CLAIM_DRAIN_TIMEOUT_SECS: u64 = CLAIM_CONFIRM_TIMEOUT_SECS + SHUTDOWN_DRAIN_TIMEOUT_SECS;
+
 #[derive(Clone)]
 enum ClientVersion {
     V1,
@@ -101,9 +109,155 @@ struct AppClientConnection {
     pubkey: Pubkey,
     miner_id: i32,
     client_version: ClientVersion,
+    cores: u32,
+    connected_at: Instant,
+    valid_submissions: Arc<AtomicU64>,
+    out_of_range_nonces: Arc<AtomicU64>,
+    subscriptions: Arc<AtomicU8>,
+    capabilities: ClientCapabilities,
     socket: Arc<Mutex<SplitSink<WebSocket, Message>>>,
 }
 
+const CAPABILITY_BOOSTS: u8 = 1 << 0;
+const CAPABILITY_COMPRESSION: u8 = 1 << 1;
+const CAPABILITY_SUBSCRIPTIONS: u8 = 1 << 2;
+const MAX_CLIENT_VERSION_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct ClientCapabilities {
+    miner_version: String,
+    drillx_version: String,
+    flags: u8,
+}
+
+impl ClientCapabilities {
+    fn from_handshake(miner_version: Option<&str>, drillx_version: Option<&str>, capabilities: Option<&str>) -> Self {
+        let flags = capabilities
+            .unwrap_or_default()
+            .split(',')
+            .fold(0, |mask, capability| {
+                mask | match capability.trim() {
+                    "boosts" => CAPABILITY_BOOSTS,
+                    "compression" => CAPABILITY_COMPRESSION,
+                    "subscriptions" => CAPABILITY_SUBSCRIPTIONS,
+                    _ => 0,
+                }
+            });
+        ClientCapabilities {
+            miner_version: sanitize_client_version(miner_version),
+            drillx_version: sanitize_client_version(drillx_version),
+            flags,
+        }
+    }
+
+    fn supports_subscriptions(&self) -> bool {
+        self.flags & CAPABILITY_SUBSCRIPTIONS != 0
+    }
+
+    /// Clients that don't advertise subscriptions keep getting every message, as before.
+    fn default_subscriptions(&self) -> u8 {
+        if self.supports_subscriptions() {
+            CHANNEL_WORK
+        } else {
+            CHANNEL_ALL
+        }
+    }
+}
+
+/// Parses `name:token` pairs separated by commas into a token -> name map. Malformed pairs are skipped.
+fn parse_admin_tokens(value: &str) -> HashMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (name, token) = pair.split_once(':')?;
+            let (name, token) = (name.trim(), token.trim());
+            if name.is_empty() || name.chars().count() > 64 || token.is_empty() {
+                return None;
+            }
+            Some((token.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+// versions end up as metrics tags, so only allow a small charset
+fn sanitize_client_version(version: Option<&str>) -> String {
+    match version {
+        Some(v)
+            if !v.is_empty()
+                && v.len() <= MAX_CLIENT_VERSION_LEN
+                && v.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_') =>
+        {
+            v.to_string()
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
+impl AppClientConnection {
+    fn is_subscribed(&self, channel: u8) -> bool {
+        self.subscriptions.load(Ordering::Relaxed) & channel != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ClaimDecrementOrder {
+    DecrementBeforeSend,
+    DecrementAfterConfirm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ClaimAtaPayer {
+    /// The pool wallet pays rent for the beneficiary's token account and deducts a fee from the claim.
+    Pool,
+    /// The beneficiary must create their own token account before claiming.
+    Miner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum DuplicateConnectionPolicy {
+    AllowMultiple,
+    RejectNew,
+    KickOldest,
+}
+
+// websocket message channels; work is always delivered and get_stats is always answered
+const CHANNEL_WORK: u8 = 1 << 0;
+const CHANNEL_REWARDS: u8 = 1 << 1;
+const CHANNEL_STATS: u8 = 1 << 2;
+const CHANNEL_STATUS: u8 = 1 << 3;
+const CHANNEL_ANNOUNCEMENTS: u8 = 1 << 4;
+const CHANNEL_ALL: u8 = CHANNEL_WORK | CHANNEL_REWARDS | CHANNEL_STATS | CHANNEL_STATUS | CHANNEL_ANNOUNCEMENTS;
+
+fn parse_subscription_channels(channels: &[String]) -> u8 {
+    channels.iter().fold(CHANNEL_WORK, |mask, channel| {
+        mask | match channel.as_str() {
+            "rewards" => CHANNEL_REWARDS,
+            "stats" => CHANNEL_STATS,
+            "status" => CHANNEL_STATUS,
+            "announcements" => CHANNEL_ANNOUNCEMENTS,
+            _ => 0,
+        }
+    })
+}
+
+const STATS_REQUEST_MIN_INTERVAL_SECS: u64 = 10;
+
+#[derive(Deserialize)]
+struct ClientTextRequest {
+    r#type: String,
+    #[serde(default)]
+    channels: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ClientStatsReply {
+    r#type: &'static str,
+    connected_secs: u64,
+    session_valid_submissions: u64,
+    submissions_24h: i64,
+    max_difficulty_24h: Option<i8>,
+}
+
 #[derive(Clone)]
 struct WalletExtension {
     miner_wallet: Arc<Keypair>,
@@ -113,6 +267,144 @@ struct WalletExtension {
 struct AppState {
     sockets: HashMap<SocketAddr, AppClientConnection>,
     paused: bool,
+    retained_sessions: HashMap<String, RetainedSession>,
+    lingering_sessions: HashMap<Pubkey, RetainedSession>,
+    disconnect_linger: Duration,
+    reward_router: RewardRouter,
+}
+
+/// Delivers staker reward notifications only to the connections of the staker they belong to.
+struct RewardRouter {
+    capacity: usize,
+    subscribers: HashMap<String, HashMap<SocketAddr, RewardSubscriber>>,
+}
+
+struct RewardSubscriber {
+    sender: mpsc::Sender<MessageInternalReward>,
+    missed: Arc<AtomicU64>,
+}
+
+impl RewardRouter {
+    fn new(capacity: usize) -> Self {
+        RewardRouter {
+            capacity: capacity.max(1),
+            subscribers: HashMap::new(),
+        }
+    }
+
+    /// Returns the connection's reward queue and the count of rewards dropped while it was full.
+    fn subscribe(&mut self, staker_pubkey: String, addr: SocketAddr) -> (mpsc::Receiver<MessageInternalReward>, Arc<AtomicU64>) {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        let missed = Arc::new(AtomicU64::new(0));
+        self.subscribers
+            .entry(staker_pubkey)
+            .or_default()
+            .insert(addr, RewardSubscriber { sender, missed: missed.clone() });
+        (receiver, missed)
+    }
+
+    fn unsubscribe(&mut self, staker_pubkey: &str, addr: &SocketAddr) {
+        if let Some(connections) = self.subscribers.get_mut(staker_pubkey) {
+            connections.remove(addr);
+            if connections.is_empty() {
+                self.subscribers.remove(staker_pubkey);
+            }
+        }
+    }
+
+    fn route(&self, reward: &MessageInternalReward) {
+        if let Some(connections) = self.subscribers.get(&reward.staker_pubkey) {
+            for subscriber in connections.values() {
+                if let Err(TrySendError::Full(_)) = subscriber.sender.try_send(reward.clone()) {
+                    subscriber.missed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+const RESUME_TOKEN_TTL_SECS: u64 = 120;
+const MAX_RETAINED_SESSIONS: usize = 10_000;
+
+struct RetainedSession {
+    pubkey: Pubkey,
+    connected_at: Instant,
+    valid_submissions: u64,
+    // waiting for work when it disconnected, so a reconnect is handed work without a new ready
+    ready: bool,
+    retained_at: Instant,
+}
+
+impl AppState {
+    fn total_cores(&self) -> u64 {
+        self.sockets.values().map(|c| c.cores as u64).sum()
+    }
+
+    fn purge_expired_sessions(&mut self) {
+        let ttl = Duration::from_secs(RESUME_TOKEN_TTL_SECS);
+        self.retained_sessions.retain(|_, s| s.retained_at.elapsed() < ttl);
+    }
+
+    fn retain_session(&mut self, token: String, session: RetainedSession) {
+        self.purge_expired_sessions();
+        if self.retained_sessions.len() >= MAX_RETAINED_SESSIONS {
+            let oldest = self
+                .retained_sessions
+                .iter()
+                .min_by_key(|(_, s)| s.retained_at)
+                .map(|(t, _)| t.clone());
+            if let Some(oldest) = oldest {
+                self.retained_sessions.remove(&oldest);
+            }
+        }
+        self.retained_sessions.insert(token, session);
+    }
+
+    fn take_retained_session(&mut self, token: &str, pubkey: Pubkey) -> Option<RetainedSession> {
+        let session = self.retained_sessions.remove(token)?;
+        if session.pubkey != pubkey
+            || session.retained_at.elapsed() >= Duration::from_secs(RESUME_TOKEN_TTL_SECS)
+        {
+            return None;
+        }
+        Some(session)
+    }
+
+    fn linger_session(&mut self, session: RetainedSession) {
+        if self.disconnect_linger.is_zero() {
+            return;
+        }
+        self.lingering_sessions.insert(session.pubkey, session);
+    }
+
+    fn take_lingering_session(&mut self, pubkey: Pubkey) -> Option<RetainedSession> {
+        let session = self.lingering_sessions.remove(&pubkey)?;
+        if session.retained_at.elapsed() >= self.disconnect_linger {
+            return None;
+        }
+        Some(session)
+    }
+
+    /// Drops sessions whose linger window has passed and returns their pubkeys, so the rest of
+    /// their state can be released.
+    fn purge_lingering_sessions(&mut self) -> Vec<Pubkey> {
+        let linger = self.disconnect_linger;
+        let expired: Vec<Pubkey> = self
+            .lingering_sessions
+            .values()
+            .filter(|s| s.retained_at.elapsed() >= linger)
+            .map(|s| s.pubkey)
+            .collect();
+        for pubkey in expired.iter() {
+            self.lingering_sessions.remove(pubkey);
+        }
+        expired
+    }
+
+    // sessions only linger while their pubkey has no socket and leave on reconnect or purge
+    fn lingering_count(&self) -> usize {
+        self.lingering_sessions.len()
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -127,6 +419,8 @@ struct ClaimCooldownItem {
     last_processed: Instant,
 }
 
+const CLAIM_COOLDOWN_SECS: u64 = 86_400 * 2;
+
 struct ClaimsQueue {
     queue: RwLock<HashMap<(Pubkey, Option<Pubkey>), ClaimsQueueItem>>,
     claim_cooldown: RwLock<HashMap<Pubkey, ClaimCooldownItem>>
@@ -138,6 +432,23 @@ struct SubmissionWindow {
 
 pub struct MessageInternalAllClients {
     text: String,
+    channel: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageInternalReward {
+    staker_pubkey: String,
+    challenge_id: i32,
+    mint: String,
+    amount: u64,
+}
+
+const MAX_ANNOUNCEMENT_LEN: usize = 500;
+const ANNOUNCEMENT_MIN_INTERVAL_SECS: u64 = 30;
+
+struct Announcements {
+    all_clients_sender: UnboundedSender<MessageInternalAllClients>,
+    last_sent_at: Mutex<Option<Instant>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -145,6 +456,7 @@ pub struct InternalMessageSubmission {
     miner_id: i32,
     supplied_diff: u32,
     supplied_nonce: u64,
+    supplied_digest: [u8; 16],
     hashpower: u64,
 }
 
@@ -160,6 +472,7 @@ pub struct MessageInternalMineSuccess {
     ore_config: Option<ore_api::state::Config>,
     multiplier: f64,
     submissions: HashMap<Pubkey, InternalMessageSubmission>,
+    superseded_submissions: Vec<InternalMessageSubmission>,
     global_boosts_active: bool,
 }
 
@@ -173,12 +486,144 @@ pub enum ClientMessage {
     Mining(SocketAddr),
     Pong(SocketAddr),
     BestSolution(SocketAddr, Solution, Pubkey),
+    GetStats(SocketAddr),
+    Subscribe(SocketAddr, u8),
 }
 
 pub struct EpochHashes {
     challenge: [u8; 32],
     best_hash: BestHash,
     submissions: HashMap<Pubkey, InternalMessageSubmission>,
+    // keyed by nonce so a resubmitted share is only stored once
+    superseded_submissions: HashMap<u64, InternalMessageSubmission>,
+    finalized: bool,
+    previous: Option<PreviousEpoch>,
+    challenge_feed: Arc<ChallengeFeed>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChallengeEvent {
+    NewChallenge {
+        challenge: String,
+        cutoff_at: i64,
+    },
+    BestSubmission {
+        challenge: String,
+        difficulty: u32,
+    },
+}
+
+pub struct ChallengeFeed {
+    events: broadcast::Sender<ChallengeEvent>,
+    subscribers: AtomicUsize,
+}
+
+struct ChallengeFeedSubscription(Arc<ChallengeFeed>);
+
+impl Drop for ChallengeFeedSubscription {
+    fn drop(&mut self) {
+        self.0.subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct PreviousEpoch {
+    challenge: [u8; 32],
+    submissions: HashMap<Pubkey, InternalMessageSubmission>,
+    superseded_submissions: HashMap<u64, InternalMessageSubmission>,
+    nonce_ranges: HashMap<Pubkey, Vec<Range<u64>>>,
+    rotated_at: Instant,
+    finalized: bool,
+}
+
+impl EpochHashes {
+    pub fn rotate(&mut self, challenge: [u8; 32], cutoff_at: i64, nonce_ranges: HashMap<Pubkey, Vec<Range<u64>>>) {
+        self.previous = Some(PreviousEpoch {
+            challenge: self.challenge,
+            submissions: std::mem::take(&mut self.submissions),
+            superseded_submissions: std::mem::take(&mut self.superseded_submissions),
+            nonce_ranges,
+            rotated_at: Instant::now(),
+            finalized: self.finalized,
+        });
+        self.challenge = challenge;
+        self.best_hash.solution = None;
+        self.best_hash.difficulty = 0;
+        self.finalized = false;
+        let _ = self.challenge_feed.events.send(ChallengeEvent::NewChallenge {
+            challenge: BASE64_STANDARD.encode(challenge),
+            cutoff_at,
+        });
+    }
+
+    /// Records a new best solution for the current challenge if it beats the existing one.
+    pub fn offer_best(&mut self, solution: Solution, difficulty: u32) -> bool {
+        if difficulty <= self.best_hash.difficulty {
+            return false;
+        }
+        self.best_hash.difficulty = difficulty;
+        self.best_hash.solution = Some(solution);
+        let _ = self.challenge_feed.events.send(ChallengeEvent::BestSubmission {
+            challenge: BASE64_STANDARD.encode(self.challenge),
+            difficulty,
+        });
+        true
+    }
+
+    /// Returns the submissions to distribute for a challenge, marking them finalized
+    /// so no further grace submissions are credited to it.
+    pub fn finalize_submissions(&mut self, challenge: [u8; 32]) -> Option<HashMap<Pubkey, InternalMessageSubmission>> {
+        if self.challenge == challenge && !self.finalized {
+            self.finalized = true;
+            return Some(self.submissions.clone());
+        }
+        if let Some(previous) = self.previous.as_mut() {
+            if previous.challenge == challenge && !previous.finalized {
+                previous.finalized = true;
+                return Some(previous.submissions.clone());
+            }
+        }
+        None
+    }
+
+    /// Buffers a non-best share for the current challenge, ignoring a nonce that is already
+    /// buffered. Once `cap` shares are buffered (`submission_buffer_max`) they are handed back so
+    /// the caller can write them out ahead of distribution.
+    pub fn push_superseded(&mut self, submission: InternalMessageSubmission, cap: usize) -> Option<Vec<InternalMessageSubmission>> {
+        self.superseded_submissions.entry(submission.supplied_nonce).or_insert(submission);
+        if self.superseded_submissions.len() >= cap.max(1) {
+            return Some(std::mem::take(&mut self.superseded_submissions).into_values().collect());
+        }
+        None
+    }
+
+    /// Takes every buffered non-best share, grouped by challenge, so they can be written out
+    /// on shutdown.
+    pub fn drain_superseded(&mut self) -> Vec<([u8; 32], Vec<InternalMessageSubmission>)> {
+        let mut drained = Vec::new();
+        if let Some(previous) = self.previous.as_mut() {
+            if !previous.superseded_submissions.is_empty() {
+                drained.push((previous.challenge, std::mem::take(&mut previous.superseded_submissions).into_values().collect()));
+            }
+        }
+        if !self.superseded_submissions.is_empty() {
+            drained.push((self.challenge, std::mem::take(&mut self.superseded_submissions).into_values().collect()));
+        }
+        drained
+    }
+
+    /// Takes the non-best shares kept for a challenge when all submissions are stored.
+    pub fn take_superseded_submissions(&mut self, challenge: [u8; 32]) -> Vec<InternalMessageSubmission> {
+        if self.challenge == challenge {
+            return std::mem::take(&mut self.superseded_submissions).into_values().collect();
+        }
+        if let Some(previous) = self.previous.as_mut() {
+            if previous.challenge == challenge {
+                return std::mem::take(&mut previous.superseded_submissions).into_values().collect();
+            }
+        }
+        Vec::new()
+    }
 }
 
 pub struct BestHash {
@@ -188,11 +633,49 @@ pub struct BestHash {
 
 pub struct Config {
     password: String,
+    // admin token -> admin name, the name is recorded in audit trails
+    admin_tokens: HashMap<String, String>,
     pool_id: i32,
     stats_enabled: bool,
     signup_fee: f64,
     commissions_pubkey: String,
     commissions_miner_id: i32,
+    submissions_compaction_age_days: u32,
+    submission_grace_ms: u64,
+    bus_selection_policy: BusSelectionPolicy,
+    scheduled_claims_interval_secs: u64,
+    scheduled_claims_threshold: u64,
+    scheduled_claims_cooldown_secs: i64,
+    scheduled_claims_batch_size: usize,
+    reserve_amount: u64,
+    min_reward_credit: u64,
+    max_ws_message_size: usize,
+    max_in_flight_claims: usize,
+    nonce_range_warn_only: bool,
+    store_all_submissions: bool,
+    distribution_retry_max: u32,
+    distribution_retry_max_secs: u64,
+    duplicate_connection_policy: DuplicateConnectionPolicy,
+    janitor_interval_secs: u64,
+    boost_fallback: bool,
+    clock_offset_secs: i64,
+    nonce_seed: Option<u64>,
+    reward_broadcast_capacity: usize,
+    mine_event_layout: MineEventLayout,
+    submission_buffer_max: usize,
+    staker_reward_share_bps: u16,
+    max_blockhash_age_secs: u64,
+    recent_submissions_per_miner: usize,
+    distribution_queue_capacity: usize,
+    disconnect_linger_secs: u64,
+    max_plausible_challenge_reward: u64,
+    account_parse_policy: ParsePolicy,
+    program_ids: ProgramIds,
+    challenge_feed_max_subscribers: usize,
+    challenge_feed_token: Option<String>,
+    claim_decrement_order: ClaimDecrementOrder,
+    claim_ata_payer: ClaimAtaPayer,
+    health_max_staleness_secs: u64,
 }
 
 #[derive(Clone)]
@@ -204,9 +687,17 @@ pub struct BoostMultiplierCache {
 #[derive(Clone)]
 pub struct LastChallengeSubmissionsCache {
     item: Vec<SubmissionWithPubkey>,
+    recent_by_miner: HashMap<String, Vec<RecentSubmission>>,
     last_updated_at: Instant,
 }
 
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RecentSubmission {
+    challenge_id: i32,
+    nonce: u64,
+    difficulty: i8,
+}
+
 #[derive(Clone)]
 pub struct ChallengesCache {
     item: Vec<ChallengeWithDifficulty>,
@@ -219,6 +710,75 @@ pub struct LatestBlockhashCache {
     last_updated_at: Instant,
 }
 
+#[derive(Clone, Serialize)]
+pub struct ChallengeTopEarner {
+    pubkey: String,
+    earned: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct LastChallengeRewards {
+    challenge_id: i32,
+    total_rewards: u64,
+    miner_count: usize,
+    top_earners: Vec<ChallengeTopEarner>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BusBalance {
+    index: usize,
+    rewards: u64,
+    theoretical_rewards: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BussesData {
+    busses: Vec<BusBalance>,
+    best_bus: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct BussesCache {
+    item: BussesData,
+    last_updated_at: Instant,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RpcHealth {
+    last_success_at: Option<u64>,
+    consecutive_failures: u64,
+}
+
+impl RpcHealth {
+    pub fn record_success(&mut self) {
+        self.last_success_at = Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_secs(),
+        );
+        self.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+    }
+
+    pub fn secs_since_success(&self) -> Option<u64> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        self.last_success_at.map(|ts| now.saturating_sub(ts))
+    }
+}
+
+#[derive(Clone)]
+pub struct LastChallengeRewardsCache {
+    item: Option<LastChallengeRewards>,
+    last_updated_at: Instant,
+}
+
 mod ore_utils;
 
 #[derive(Parser, Debug)]
@@ -264,6 +824,394 @@ struct ServeArgs {
         help = "Migrate balance from original proof to delegate stake managed proof"
     )]
     migrate: bool,
+    #[arg(
+        long,
+        value_name = "days",
+        help = "Age in days after which raw submissions are compacted into daily aggregates",
+        default_value = "7",
+        global = true
+    )]
+    submissions_compaction_age_days: u32,
+    #[arg(
+        long,
+        value_name = "milliseconds",
+        help = "Grace period after a challenge rotation during which submissions for the previous challenge are still credited",
+        default_value = "3000",
+        global = true
+    )]
+    submission_grace_ms: u64,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "policy",
+        help = "How the bus is picked for mine transactions",
+        default_value = "max-current",
+        global = true
+    )]
+    bus_selection: BusSelectionPolicy,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Interval between scheduled batch claims for eligible miners, 0 disables scheduled claims",
+        default_value = "0",
+        global = true
+    )]
+    scheduled_claims_interval: u64,
+    #[arg(
+        long,
+        value_name = "ORE",
+        help = "Minimum miner rewards balance in ORE to be included in scheduled claims",
+        default_value = "1",
+        global = true
+    )]
+    scheduled_claims_threshold: f64,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Minimum time since a miner's last claim before they are included in scheduled claims",
+        default_value = "1800",
+        global = true
+    )]
+    scheduled_claims_cooldown: i64,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Maximum number of miners paid in one scheduled claims transaction",
+        default_value = "8",
+        global = true
+    )]
+    scheduled_claims_batch_size: usize,
+    #[arg(
+        long,
+        value_name = "ORE",
+        help = "ORE kept unclaimed in the pool's delegated stake account; claims that would dip below it are rejected",
+        default_value = "0",
+        global = true
+    )]
+    reserve_amount: f64,
+    #[arg(
+        long,
+        value_name = "ORE",
+        help = "Per-challenge miner rewards are held back until the accumulated amount in ORE reaches this value",
+        default_value = "0",
+        global = true
+    )]
+    min_reward_credit: f64,
+    #[arg(
+        long,
+        value_name = "pubkey",
+        help = "Override the ORE program id used for PDA derivation and instructions, e.g. for a devnet deployment",
+        global = true
+    )]
+    ore_program_id: Option<Pubkey>,
+    #[arg(
+        long,
+        value_name = "pubkey",
+        help = "Override the miner delegation program id used for PDA derivation and instructions",
+        global = true
+    )]
+    delegation_program_id: Option<Pubkey>,
+    #[arg(
+        long,
+        value_name = "milliseconds",
+        help = "Max time to wait for a free database connection before failing",
+        default_value = "5000",
+        global = true
+    )]
+    db_pool_wait_timeout_ms: u64,
+    #[arg(
+        long,
+        value_name = "connections",
+        help = "Max number of open connections in the primary database pool, defaults to four per cpu",
+        global = true
+    )]
+    db_pool_max_size: Option<usize>,
+    #[arg(
+        long,
+        value_name = "milliseconds",
+        help = "Max time to wait when opening a new database connection",
+        default_value = "5000",
+        global = true
+    )]
+    db_pool_create_timeout_ms: u64,
+    #[arg(
+        long,
+        value_name = "milliseconds",
+        help = "Max time to wait when recycling a database connection",
+        default_value = "5000",
+        global = true
+    )]
+    db_pool_recycle_timeout_ms: u64,
+    #[arg(
+        long,
+        value_name = "bytes",
+        help = "Max size of an inbound websocket message, larger messages close the connection",
+        default_value = "4096",
+        global = true
+    )]
+    max_ws_message_size: usize,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Max number of claim transactions submitted or awaiting confirmation at once",
+        default_value = "10",
+        global = true
+    )]
+    max_in_flight_claims: usize,
+    #[arg(
+        long,
+        action,
+        help = "Only log submissions with nonces outside the assigned range instead of rejecting them",
+        global = true
+    )]
+    nonce_range_warn_only: bool,
+    #[arg(
+        long,
+        action,
+        help = "Store every valid share in submissions instead of only each miner's best share per challenge",
+        global = true
+    )]
+    store_all_submissions: bool,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Seconds the bulk rewards updates may wait on row locks before failing",
+        default_value = "30",
+        global = true
+    )]
+    rewards_update_lock_timeout_secs: u64,
+    #[arg(
+        long,
+        value_name = "rows",
+        help = "Miners credited per bulk rewards UPDATE statement",
+        default_value = "1000",
+        global = true
+    )]
+    rewards_update_batch_size: usize,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Times to retry opening a database connection, with exponential backoff from 50ms. Pool wait timeouts are not retried",
+        default_value = "3",
+        global = true
+    )]
+    db_connection_retries: u32,
+    #[arg(
+        long,
+        action,
+        help = "Run database integrity checks at startup and log a summary",
+        global = true
+    )]
+    integrity_check_on_start: bool,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Refuse to start when the startup integrity check finds more critical anomalies than this",
+        global = true
+    )]
+    integrity_check_max_critical: Option<u64>,
+    #[arg(
+        long,
+        action,
+        help = "Delete rewards rows whose miner no longer exists when the startup integrity check finds any",
+        global = true
+    )]
+    integrity_check_delete_orphans: bool,
+    #[arg(
+        long,
+        action,
+        help = "Refuse to start if the pool proof account does not exist instead of registering a new one",
+        global = true
+    )]
+    require_pool_proof: bool,
+    #[arg(
+        long,
+        value_name = "retries",
+        help = "Total DB retries a reward distribution cycle may use before its rewards commit; later writes retry until they land",
+        default_value = "120",
+        global = true
+    )]
+    distribution_retry_max: u32,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Total seconds a reward distribution cycle may spend retrying before its rewards commit",
+        default_value = "300",
+        global = true
+    )]
+    distribution_retry_max_secs: u64,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "policy",
+        help = "How to handle a new connection for a pubkey that is already connected",
+        default_value = "allow-multiple",
+        global = true
+    )]
+    duplicate_connection_policy: DuplicateConnectionPolicy,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Interval between sweeps of expired in-memory state",
+        default_value = "300",
+        global = true
+    )]
+    janitor_interval_secs: u64,
+    #[arg(
+        long,
+        value_name = "calls",
+        help = "RPC calls per second budget; stats calls are deferred near the limit (0 disables)",
+        default_value = "0",
+        global = true
+    )]
+    rpc_max_calls_per_sec: u32,
+    #[arg(
+        long,
+        action,
+        help = "Send a plain mine instruction when the boosted one fails simulation on a boost account and the plain one simulates cleanly",
+        global = true
+    )]
+    boost_fallback: bool,
+    #[arg(
+        long,
+        value_name = "seconds",
+        allow_hyphen_values = true,
+        help = "Seconds added to the local clock when computing challenge cutoffs, to correct for skew against chain time.",
+        default_value = "0",
+        global = true
+    )]
+    clock_offset_secs: i64,
+    #[arg(
+        long,
+        value_name = "seed",
+        help = "Seed for the RNG picking each challenge's starting nonce, so nonce range assignment is reproducible. Random if unset.",
+        global = true
+    )]
+    nonce_seed: Option<u64>,
+    #[arg(
+        long,
+        value_name = "messages",
+        help = "Capacity of each client's reward notification queue. Clients lagging further than this behind are sent a resync hint.",
+        default_value = "1024",
+        global = true
+    )]
+    reward_broadcast_capacity: usize,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "layout",
+        help = "Mine event layout returned by the delegation program. auto picks whichever layout's reward fields are consistent.",
+        default_value = "global-boosts",
+        global = true
+    )]
+    mine_event_layout: MineEventLayout,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Non-best shares buffered in memory per challenge with --store-all-submissions before they are written to the database early.",
+        default_value = "5000",
+        global = true
+    )]
+    submission_buffer_max: usize,
+    #[arg(
+        long,
+        value_name = "basis points",
+        value_parser = clap::value_parser!(u16).range(0..=10_000),
+        help = "Share of each challenge reward, in basis points, paid to boost stakers when global boosts are not active. The rest after commissions goes to miners.",
+        default_value = "4400",
+        global = true
+    )]
+    staker_reward_share_bps: u16,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Sign mine transactions with the cached blockhash and hold them (for at most this long, then count a failed attempt) while it is older than this, since the RPC is likely unhealthy. 0 disables the check and fetches a fresh blockhash per attempt.",
+        default_value = "30",
+        global = true
+    )]
+    max_blockhash_age_secs: u64,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Recent submissions kept per miner in the stats cache (at most 20). 0 disables.",
+        default_value = "3",
+        global = true
+    )]
+    recent_submissions_per_miner: usize,
+    #[arg(
+        long,
+        value_name = "challenges",
+        help = "Maximum number of landed challenges waiting for reward distribution. Distributions run one at a time; when full, new challenges wait for room.",
+        default_value = "16",
+        global = true
+    )]
+    distribution_queue_capacity: usize,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Seconds a disconnected miner's session is kept for an automatic reconnect before it is dropped. 0 disables.",
+        default_value = "15",
+        global = true
+    )]
+    disconnect_linger_secs: u64,
+    #[arg(
+        long,
+        value_name = "ORE",
+        help = "Largest reward in ORE a single landed challenge may report. Larger rewards are not distributed and the challenge is flagged for review. 0 disables.",
+        default_value = "10",
+        global = true
+    )]
+    max_plausible_challenge_reward: f64,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "policy",
+        help = "How to treat accounts that fail to parse in batch fetches: skip-and-log, fail-fast (abort the fetch) or skip-silently.",
+        default_value = "skip-and-log",
+        global = true
+    )]
+    account_parse_policy: ParsePolicy,
+    #[arg(
+        long,
+        value_name = "count",
+        help = "Maximum number of concurrent /ws/challenges dashboard subscribers.",
+        default_value = "100",
+        global = true
+    )]
+    challenge_feed_max_subscribers: usize,
+    #[arg(
+        long,
+        value_name = "token",
+        help = "If set, /ws/challenges subscribers must pass this value as the token query parameter.",
+        global = true
+    )]
+    challenge_feed_token: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "order",
+        help = "When a miner claim debits the database balance: decrement-after-confirm (only once the claim is finalized) or decrement-before-send (optimistic, credited back if the claim fails).",
+        default_value = "decrement-after-confirm",
+        global = true
+    )]
+    claim_decrement_order: ClaimDecrementOrder,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "payer",
+        help = "Who pays to create a claim beneficiary's ORE token account. With miner, claims to a wallet without one are rejected.",
+        default_value = "pool",
+        global = true
+    )]
+    claim_ata_payer: ClaimAtaPayer,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Oldest RPC success or cache refresh /health accepts before reporting the subsystem unhealthy.",
+        default_value = "60",
+        global = true
+    )]
+    health_max_staleness_secs: u64,
 }
 
 
@@ -273,9 +1221,27 @@ enum Commands {
     #[command(about = "Serve the pool webserver for mining.")]
     Serve(ServeArgs),
     #[command(about = "Manually run the update for stake accounts balances from on-chain")]
-    UpdateStakeAccounts,
+    UpdateStakeAccounts(ScriptArgs),
     #[command(about = "Start the db submissions cleanup script.")]
     DbSubmissionsCleanup,
+    #[command(about = "Copy rows from the legacy submissions table into submissions_2 and drop it once verified.")]
+    MigrateLegacySubmissions,
+    #[command(about = "Mark stake accounts with no on-chain delegated balance as inactive.")]
+    ReconcileStakeAccounts(ScriptArgs),
+    #[command(about = "Print unpaid miner and staker rewards per pool, in base units.")]
+    UnpaidByPool,
+}
+
+#[derive(Parser, Debug)]
+struct ScriptArgs {
+    #[arg(
+        long,
+        value_enum,
+        value_name = "policy",
+        help = "How to treat on-chain accounts that fail to parse: skip-and-log, fail-fast (abort the script) or skip-silently.",
+        default_value = "skip-and-log"
+    )]
+    account_parse_policy: ParsePolicy,
 }
 
 #[tokio::main]
@@ -317,20 +1283,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Serve(args) => {
             serve(args).await
         }
-        Commands::UpdateStakeAccounts => {
-            scripts::update_stake_accounts().await
+        Commands::UpdateStakeAccounts(args) => {
+            scripts::update_stake_accounts(args.account_parse_policy).await
         }
         Commands::DbSubmissionsCleanup => {
             scripts::db_submissions_cleanup().await
         }
+        Commands::MigrateLegacySubmissions => {
+            scripts::migrate_legacy_submissions().await
+        }
+        Commands::ReconcileStakeAccounts(args) => {
+            scripts::reconcile_stake_accounts(args.account_parse_policy).await
+        }
+        Commands::UnpaidByPool => {
+            scripts::unpaid_by_pool().await
+        }
     }
 }
 
 async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let default_program_ids = ProgramIds::default();
+    let program_ids = ProgramIds {
+        ore: args.ore_program_id.unwrap_or(default_program_ids.ore),
+        delegation: args.delegation_program_id.unwrap_or(default_program_ids.delegation),
+    };
+    if program_ids != default_program_ids {
+        info!(target: "server_log", "Using program id overrides: {:?}", program_ids);
+    }
+
     // load envs
-    let wallet_path_str = std::env::var("WALLET_PATH").expect("WALLET_PATH must be set.");
-    let fee_wallet_path_str =
-        std::env::var("FEE_WALLET_PATH").expect("FEE_WALLET_PATH must be set.");
     let rpc_url = std::env::var("RPC_URL").expect("RPC_URL must be set.");
     let rpc_2_url = match std::env::var("RPC_2_URL") {
         Ok(url) => {
@@ -343,6 +1324,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     };
     let rpc_ws_url = std::env::var("RPC_WS_URL").expect("RPC_WS_URL must be set.");
     let password = std::env::var("PASSWORD").expect("PASSWORD must be set.");
+    let admin_tokens = parse_admin_tokens(&std::env::var("ADMIN_TOKENS").unwrap_or_default());
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
     let database_rr_url = std::env::var("DATABASE_RR_URL").expect("DATABASE_RR_URL must be set.");
     let commission_env = std::env::var("COMMISSION_PUBKEY").expect("COMMISSION_PUBKEY must be set.");
@@ -415,7 +1397,8 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let (metrics_message_sender, metrics_message_receiver) =
         tokio::sync::mpsc::unbounded_channel::<AppMetricsEvent>();
 
-    if has_metrics_envs {
+    let influx_sender = if has_metrics_envs {
+        let (influx_sender, influx_receiver) = tokio::sync::mpsc::unbounded_channel::<AppMetricsEvent>();
         tokio::spawn(async move {
             metrics_system(
                 metrics_url,
@@ -423,14 +1406,34 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
                 metrics_org,
                 metrics_bucket,
                 metrics_host,
-                metrics_message_receiver,
+                influx_receiver,
             )
             .await;
         });
-    }
+        Some(influx_sender)
+    } else {
+        None
+    };
 
-    let app_database = Arc::new(AppDatabase::new(database_url));
-    let app_rr_database = Arc::new(AppRRDatabase::new(database_rr_url));
+    let metrics_registry = Arc::new(MetricsRegistry::default());
+    let app_metrics_registry = metrics_registry.clone();
+    tokio::spawn(async move {
+        metrics_registry_system(metrics_message_receiver, app_metrics_registry, influx_sender).await;
+    });
+
+    let db_pool_timeouts = DbPoolTimeouts {
+        wait: Duration::from_millis(args.db_pool_wait_timeout_ms),
+        create: Duration::from_millis(args.db_pool_create_timeout_ms),
+        recycle: Duration::from_millis(args.db_pool_recycle_timeout_ms),
+    };
+    let app_database = Arc::new(
+        AppDatabase::new_with_timeouts(database_url, args.db_pool_max_size, db_pool_timeouts)
+            .map_err(|e| format!("Failed to build database connection pool: {:?}", e))?
+            .with_rewards_update_lock_timeout(Duration::from_secs(args.rewards_update_lock_timeout_secs))
+            .with_rewards_update_batch_size(args.rewards_update_batch_size)
+            .with_connection_retries(args.db_connection_retries),
+    );
+    let app_rr_database = Arc::new(AppRRDatabase::new_with_timeouts(database_rr_url, db_pool_timeouts));
 
     let priority_fee = Arc::new(args.priority_fee);
     let jito_tip = Arc::new(args.jito_tip);
@@ -442,6 +1445,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
 
     let app_cache_last_challenge_submissions: Arc<RwLock<LastChallengeSubmissionsCache>> = Arc::new(RwLock::new(LastChallengeSubmissionsCache {
         item: vec![],
+        recent_by_miner: HashMap::new(),
         last_updated_at: Instant::now(),
     }));
 
@@ -450,28 +1454,42 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         last_updated_at: Instant::now(),
     }));
 
-    // load wallet
-    let wallet_path = Path::new(&wallet_path_str);
-
-    if !wallet_path.exists() {
-        tracing::error!(target: "server_log", "Failed to load wallet at: {}", wallet_path_str);
-        return Err("Failed to find wallet path.".into());
-    }
+    let app_cache_busses: Arc<RwLock<BussesCache>> = Arc::new(RwLock::new(BussesCache {
+        item: BussesData {
+            busses: vec![],
+            best_bus: None,
+        },
+        last_updated_at: Instant::now(),
+    }));
 
-    let wallet = read_keypair_file(wallet_path)
-        .expect("Failed to load keypair from file: {wallet_path_str}");
-    info!(target: "server_log", "loaded wallet {}", wallet.pubkey().to_string());
+    let app_rpc_health: Arc<RwLock<RpcHealth>> = Arc::new(RwLock::new(RpcHealth {
+        last_success_at: None,
+        consecutive_failures: 0,
+    }));
 
-    let wallet_path = Path::new(&fee_wallet_path_str);
+    let app_cache_last_challenge_rewards: Arc<RwLock<LastChallengeRewardsCache>> = Arc::new(RwLock::new(LastChallengeRewardsCache {
+        item: None,
+        last_updated_at: Instant::now(),
+    }));
 
-    if !wallet_path.exists() {
-        tracing::error!(target: "server_log", "Failed to load fee wallet at: {}", fee_wallet_path_str);
-        return Err("Failed to find fee wallet path.".into());
-    }
+    // load wallet
+    let wallet = match load_keypair_from_env("WALLET") {
+        Ok(kp) => kp,
+        Err(e) => {
+            tracing::error!(target: "server_log", "Failed to load wallet: {}", e);
+            return Err(e.into());
+        }
+    };
+    info!(target: "server_log", "loaded wallet {}", wallet.pubkey().to_string());
 
-    let fee_wallet = read_keypair_file(wallet_path)
-        .expect("Failed to load keypair from file: {wallet_path_str}");
-    info!(target: "server_log", "loaded fee wallet {}", wallet.pubkey().to_string());
+    let fee_wallet = match load_keypair_from_env("FEE_WALLET") {
+        Ok(kp) => kp,
+        Err(e) => {
+            tracing::error!(target: "server_log", "Failed to load fee wallet: {}", e);
+            return Err(e.into());
+        }
+    };
+    info!(target: "server_log", "loaded fee wallet {}", fee_wallet.pubkey().to_string());
 
     info!(target: "server_log", "establishing rpc connection...");
     let rpc_client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
@@ -515,14 +1533,37 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         return Err("Sol balance is too low!".into());
     }
 
-    let proof = if let Ok(loaded_proof) = get_proof(&rpc_client, wallet.pubkey()).await {
+    info!(target: "server_log", "Verifying pool accounts for authority {}", wallet.pubkey().to_string());
+    match verify_pool_pdas(&program_ids, &rpc_client, wallet.pubkey()).await {
+        Ok(pdas) => {
+            for pda in pdas.iter() {
+                if pda.exists {
+                    info!(target: "server_log", "  {} {}: found", pda.label, pda.address.to_string());
+                } else {
+                    tracing::warn!(target: "server_log", "  {} {}: missing", pda.label, pda.address.to_string());
+                }
+            }
+            let proof_exists = pdas.iter().any(|pda| pda.label == "proof" && pda.exists);
+            if !proof_exists && args.require_pool_proof {
+                return Err(format!("Pool proof account is missing for authority {}. Check the wallet and program ids.", wallet.pubkey().to_string()).into());
+            }
+        }
+        Err(e) => {
+            error!(target: "server_log", "{}", e);
+            if args.require_pool_proof {
+                return Err(e.into());
+            }
+        }
+    }
+
+    let proof = if let Ok(loaded_proof) = get_proof(&program_ids, &rpc_client, wallet.pubkey()).await {
         info!(target: "server_log", "LOADED PROOF: \n{:?}", loaded_proof);
         loaded_proof
     } else {
         error!(target: "server_log", "Failed to load proof.");
         info!(target: "server_log", "Creating proof account...");
 
-        let ix = get_register_ix(wallet.pubkey());
+        let ix = get_register_ix(&program_ids, wallet.pubkey());
 
         if let Ok((hash, _slot)) = rpc_client
             .get_latest_blockhash_with_commitment(rpc_client.commitment())
@@ -545,7 +1586,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
                 return Err("Failed to create proof account".into());
             }
         }
-        let proof = if let Ok(loaded_proof) = get_proof(&rpc_client, wallet.pubkey()).await {
+        let proof = if let Ok(loaded_proof) = get_proof(&program_ids, &rpc_client, wallet.pubkey()).await {
             loaded_proof
         } else {
             return Err("Failed to get newly created proof".into());
@@ -554,17 +1595,23 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     };
 
     info!(target: "server_log", "Validating miners delegate stake account is created");
-    match get_delegated_stake_account(&rpc_client, wallet.pubkey(), wallet.pubkey()).await {
+    match get_delegated_stake_account(&program_ids, &rpc_client, wallet.pubkey(), wallet.pubkey()).await {
         Ok(data) => {
             info!(target: "server_log", "Miner Delegated Stake Account: {:?}", data);
             info!(target: "server_log", "Miner delegate stake account already created.");
         }
         Err(_) => {
             info!(target: "server_log", "Creating miner delegate stake account");
-            let ix = ore_miner_delegation::instruction::init_delegate_stake(
-                wallet.pubkey(),
-                wallet.pubkey(),
+            let ix = retarget_ix(
+                ore_miner_delegation::instruction::init_delegate_stake(
+                    wallet.pubkey(),
+                    wallet.pubkey(),
+                    wallet.pubkey(),
+                ),
+                &program_ids,
                 wallet.pubkey(),
+                Some(wallet.pubkey()),
+                None,
             );
 
             let mut tx = Transaction::new_with_payer(&[ix], Some(&wallet.pubkey()));
@@ -597,12 +1644,9 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     }
 
     info!(target: "server_log", "Validating managed proof token account is created");
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", wallet.pubkey().as_ref()],
-        &ore_miner_delegation::id(),
-    );
+    let managed_proof = get_managed_proof_pda(&program_ids, wallet.pubkey());
 
-    let managed_proof_token_account_addr = get_managed_proof_token_ata(wallet.pubkey());
+    let managed_proof_token_account_addr = get_managed_proof_token_ata(&program_ids, wallet.pubkey());
     match rpc_client
         .get_token_account_balance(&managed_proof_token_account_addr)
         .await
@@ -614,7 +1658,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
             info!(target: "server_log", "Creating managed proof token account");
             let ix = create_associated_token_account(
                 &wallet.pubkey(),
-                &managed_proof.0,
+                &managed_proof,
                 &ore_api::consts::MINT_ADDRESS,
                 &spl_token::id(),
             );
@@ -664,7 +1708,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     if args.migrate {
         info!(target: "server_log", "Checking original proof, and token account balances for migration.");
         let original_proof =
-            if let Ok(loaded_proof) = get_original_proof(&rpc_client, wallet.pubkey()).await {
+            if let Ok(loaded_proof) = get_original_proof(&program_ids, &rpc_client, wallet.pubkey()).await {
                 loaded_proof
             } else {
                 panic!("Failed to get original proof!");
@@ -672,6 +1716,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         if original_proof.balance > 0 || token_balance > 0 {
             info!(target: "server_log", "Proof balance has {} tokens. Miner ORE token account has {} tokens.\nMigrating...", original_proof.balance, token_balance);
             if let Err(e) = proof_migration::migrate(
+                &program_ids,
                 &rpc_client,
                 &wallet,
                 original_proof.balance,
@@ -699,9 +1744,9 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         Err(AppDatabaseError::FailedToGetConnectionFromPool) => {
             panic!("Failed to get database pool connection");
         }
-        Err(_) => {
+        Err(AppDatabaseError::PoolNotInitialized) => {
             info!(target: "server_log", "Pool missing from database. Inserting...");
-            let proof_pubkey = get_proof_pda(wallet.pubkey());
+            let proof_pubkey = get_proof_pda(&program_ids, wallet.pubkey());
             let result = app_database
                 .add_new_pool(wallet.pubkey().to_string(), proof_pubkey.to_string())
                 .await;
@@ -710,6 +1755,45 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
                 panic!("Failed to create pool in database");
             }
         }
+        Err(e) => {
+            panic!("Failed to validate pool in database: {:?}", e);
+        }
+    }
+
+    if args.integrity_check_on_start {
+        info!(target: "server_log", "Running database integrity checks");
+        match app_database.run_integrity_checks().await {
+            Ok(report) => {
+                if report.total() > 0 {
+                    tracing::warn!(target: "server_log", "Integrity check found anomalies: {:?}", report);
+                } else {
+                    info!(target: "server_log", "Integrity check passed.");
+                }
+                if report.orphaned_rewards > 0 && args.integrity_check_delete_orphans {
+                    match app_database.find_orphaned_rewards().await {
+                        Ok(reward_ids) => match app_database.delete_orphaned_rewards(reward_ids).await {
+                            Ok(deleted) => {
+                                info!(target: "server_log", "Deleted {} orphaned rewards rows.", deleted);
+                            }
+                            Err(e) => {
+                                error!(target: "server_log", "Failed to delete orphaned rewards: {:?}", e);
+                            }
+                        },
+                        Err(e) => {
+                            error!(target: "server_log", "Failed to find orphaned rewards: {:?}", e);
+                        }
+                    }
+                }
+                if let Some(max_critical) = args.integrity_check_max_critical {
+                    if report.critical() > max_critical {
+                        panic!("Integrity check found {} critical anomalies, more than the allowed {}", report.critical(), max_critical);
+                    }
+                }
+            }
+            Err(e) => {
+                error!(target: "server_log", "Failed to run integrity checks: {:?}", e);
+            }
+        }
     }
 
 
@@ -775,15 +1859,65 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let scheduled_claims_threshold = from_ui_amount(args.scheduled_claims_threshold, ORE_TOKEN_DECIMALS)
+        .unwrap_or_else(|e| panic!("Invalid scheduled claims threshold: {}", e));
+    let reserve_amount = from_ui_amount(args.reserve_amount, ORE_TOKEN_DECIMALS)
+        .unwrap_or_else(|e| panic!("Invalid reserve amount: {}", e));
+    let min_reward_credit = from_ui_amount(args.min_reward_credit, ORE_TOKEN_DECIMALS)
+        .unwrap_or_else(|e| panic!("Invalid min reward credit: {}", e));
+    let max_plausible_challenge_reward = from_ui_amount(args.max_plausible_challenge_reward, ORE_TOKEN_DECIMALS)
+        .unwrap_or_else(|e| panic!("Invalid max plausible challenge reward: {}", e));
+
     let config = Arc::new(Config {
         password,
+        admin_tokens,
         pool_id: db_pool.id,
         stats_enabled: args.stats,
         signup_fee: args.signup_fee,
         commissions_pubkey: commission_pubkey.to_string(),
         commissions_miner_id: commission_miner_id,
+        submissions_compaction_age_days: args.submissions_compaction_age_days,
+        submission_grace_ms: args.submission_grace_ms,
+        bus_selection_policy: args.bus_selection,
+        scheduled_claims_interval_secs: args.scheduled_claims_interval,
+        scheduled_claims_threshold,
+        scheduled_claims_cooldown_secs: args.scheduled_claims_cooldown,
+        scheduled_claims_batch_size: args.scheduled_claims_batch_size,
+        reserve_amount,
+        min_reward_credit,
+        max_ws_message_size: args.max_ws_message_size,
+        max_in_flight_claims: args.max_in_flight_claims,
+        nonce_range_warn_only: args.nonce_range_warn_only,
+        store_all_submissions: args.store_all_submissions,
+        distribution_retry_max: args.distribution_retry_max,
+        distribution_retry_max_secs: args.distribution_retry_max_secs,
+        duplicate_connection_policy: args.duplicate_connection_policy,
+        janitor_interval_secs: args.janitor_interval_secs,
+        boost_fallback: args.boost_fallback,
+        clock_offset_secs: args.clock_offset_secs,
+        nonce_seed: args.nonce_seed,
+        reward_broadcast_capacity: args.reward_broadcast_capacity,
+        mine_event_layout: args.mine_event_layout,
+        submission_buffer_max: args.submission_buffer_max,
+        staker_reward_share_bps: args.staker_reward_share_bps,
+        max_blockhash_age_secs: args.max_blockhash_age_secs,
+        recent_submissions_per_miner: args.recent_submissions_per_miner,
+        distribution_queue_capacity: args.distribution_queue_capacity,
+        disconnect_linger_secs: args.disconnect_linger_secs,
+        max_plausible_challenge_reward,
+        account_parse_policy: args.account_parse_policy,
+        program_ids,
+        challenge_feed_max_subscribers: args.challenge_feed_max_subscribers,
+        challenge_feed_token: args.challenge_feed_token.clone(),
+        claim_decrement_order: args.claim_decrement_order,
+        claim_ata_payer: args.claim_ata_payer,
+        health_max_staleness_secs: args.health_max_staleness_secs,
     });
 
+    let challenge_feed = Arc::new(ChallengeFeed {
+        events: broadcast::channel(64).0,
+        subscribers: AtomicUsize::new(0),
+    });
     let epoch_hashes = Arc::new(RwLock::new(EpochHashes {
         challenge: proof.challenge,
         best_hash: BestHash {
@@ -791,6 +1925,10 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
             difficulty: 0,
         },
         submissions: HashMap::new(),
+        superseded_submissions: HashMap::new(),
+        finalized: false,
+        previous: None,
+        challenge_feed: challenge_feed.clone(),
     }));
 
     let wallet_extension = Arc::new(WalletExtension {
@@ -798,13 +1936,17 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         fee_wallet: Arc::new(fee_wallet),
     });
     let proof_ext = Arc::new(Mutex::new(proof));
-    let nonce_ext = Arc::new(Mutex::new(0u64));
+    let nonce_ext = Arc::new(Mutex::new(NonceAllocator::new(config.nonce_seed)));
 
     let client_nonce_ranges = Arc::new(RwLock::new(HashMap::new()));
 
     let shared_state = Arc::new(RwLock::new(AppState {
         sockets: HashMap::new(),
         paused: false,
+        retained_sessions: HashMap::new(),
+        lingering_sessions: HashMap::new(),
+        disconnect_linger: Duration::from_secs(config.disconnect_linger_secs),
+        reward_router: RewardRouter::new(config.reward_broadcast_capacity),
     }));
     let ready_clients = Arc::new(Mutex::new(HashSet::new()));
 
@@ -820,23 +1962,43 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let submission_window = Arc::new(RwLock::new(SubmissionWindow { closed: false }));
 
     let rpc_client = Arc::new(rpc_client);
+    let rpc_budget = Arc::new(RpcBudget::new(args.rpc_max_calls_per_sec));
     let rpc_2_client = Arc::new(rpc_2_client);
     let jito_client = Arc::new(jito_client);
 
     let last_challenge = Arc::new(Mutex::new([0u8; 32]));
 
+    let (shutdown_sender, shutdown_receiver) = tokio::sync::watch::channel(false);
+    // the submission system stops first so nothing new is queued for the draining systems
+    let (submission_shutdown_sender, submission_shutdown_receiver) = tokio::sync::watch::channel(false);
+
+    // shared by the claim system and the claim scheduler so they never pay the same miner
+    // or spend the same on-chain balance at once
+    let in_flight_claims: InFlightClaims = Arc::new(std::sync::Mutex::new(HashSet::new()));
+    let claim_reservations: ClaimReservations = Arc::new(std::sync::Mutex::new(0));
+
     let app_rpc_client = rpc_client.clone();
+    let app_rpc_budget = rpc_budget.clone();
     let app_wallet = wallet_extension.clone();
     let app_claims_queue = claims_queue.clone();
     let app_app_database = app_database.clone();
     let app_metrics = metrics_message_sender.clone();
-    tokio::spawn(async move {
+    let app_in_flight_claims = in_flight_claims.clone();
+    let app_claim_reservations = claim_reservations.clone();
+    let app_shutdown = shutdown_receiver.clone();
+    let app_config = config.clone();
+    let claim_system_handle = tokio::spawn(async move {
         claim_system(
             app_claims_queue,
             app_rpc_client,
+            app_rpc_budget,
             app_wallet.miner_wallet.clone(),
             app_app_database,
             app_metrics,
+            app_in_flight_claims,
+            app_claim_reservations,
+            app_shutdown,
+            app_config,
         )
         .await;
     });
@@ -848,16 +2010,26 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let boost_multiplier_cache = app_cache_boost_multiplier.clone();
     let challenges_cache = app_cache_challenges.clone();
     let latest_blockhash_cache = app_cache_latest_blockhash_cache.clone();
+    let busses_cache = app_cache_busses.clone();
+    let rpc_health = app_rpc_health.clone();
     let app_app_rr_database = app_rr_database.clone();
-    tokio::spawn(async move {
+    let app_wallet = wallet_extension.clone();
+    let app_metrics = metrics_message_sender.clone();
+    let app_rpc_budget = rpc_budget.clone();
+    let cache_update_system_handle = tokio::spawn(async move {
         cache_update_system(
             app_config,
             app_rpc_client,
             app_app_rr_database,
+            app_wallet,
             boost_multiplier_cache,
             last_challenge_cache,
             challenges_cache,
-            latest_blockhash_cache
+            latest_blockhash_cache,
+            busses_cache,
+            rpc_health,
+            app_metrics,
+            app_rpc_budget,
         )
         .await;
     });
@@ -877,6 +2049,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     tokio::spawn(async move {
         proof_tracking_system(
             ws_url,
+            program_ids,
             app_wallet.miner_wallet.clone(),
             app_proof,
             app_last_challenge
@@ -894,6 +2067,10 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let app_state = shared_state.clone();
     let app_pongs = pongs.clone();
     let app_submission_window = submission_window.clone();
+    let app_config = config.clone();
+    let app_app_rr_database = app_rr_database.clone();
+    let app_app_database = app_database.clone();
+    let app_metrics = metrics_message_sender.clone();
     tokio::spawn(async move {
         client_message_handler_system(
             client_message_receiver,
@@ -904,6 +2081,10 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
             app_state,
             app_pongs,
             app_submission_window,
+            app_config,
+            app_app_rr_database,
+            app_app_database,
+            app_metrics,
         )
         .await;
     });
@@ -916,6 +2097,7 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let app_client_nonce_ranges = client_nonce_ranges.clone();
     let app_ready_clients = ready_clients.clone();
     let app_submission_window = submission_window.clone();
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock { offset_secs: config.clock_offset_secs });
     tokio::spawn(async move {
         handle_ready_clients_system(
             app_shared_state,
@@ -925,12 +2107,13 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
             app_nonce,
             app_client_nonce_ranges,
             app_submission_window,
+            clock,
         )
         .await;
     });
 
     let (mine_success_sender, mine_success_receiver) =
-        tokio::sync::mpsc::unbounded_channel::<MessageInternalMineSuccess>();
+        tokio::sync::mpsc::channel::<MessageInternalMineSuccess>(config.distribution_queue_capacity.max(1));
 
     let (all_clients_sender, all_clients_receiver) =
         tokio::sync::mpsc::unbounded_channel::<MessageInternalAllClients>();
@@ -950,7 +2133,9 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let app_client_nonce_ranges = client_nonce_ranges.clone();
     let app_last_challenge = last_challenge.clone();
     let app_metrics = metrics_message_sender.clone();
-    tokio::spawn(async move {
+    let app_rpc_budget = rpc_budget.clone();
+    let latest_blockhash_cache = app_cache_latest_blockhash_cache.clone();
+    let pool_submission_system_handle = tokio::spawn(async move {
         pool_submission_system(
             app_proof,
             app_epoch_hashes,
@@ -968,6 +2153,9 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
             app_client_nonce_ranges,
             app_last_challenge,
             app_metrics,
+            app_rpc_budget,
+            latest_blockhash_cache,
+            submission_shutdown_receiver,
         )
         .await;
     });
@@ -976,17 +2164,28 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let app_app_database = app_database.clone();
     let app_config = config.clone();
     let app_wallet = wallet_extension.clone();
-    tokio::spawn(async move {
+    let last_challenge_rewards_cache = app_cache_last_challenge_rewards.clone();
+    let app_metrics = metrics_message_sender.clone();
+    let app_shutdown = shutdown_receiver.clone();
+    let mine_success_system_handle = tokio::spawn(async move {
         let app_database = app_app_database;
         pool_mine_success_system(
             app_shared_state,
             app_database,
             app_config,
             app_wallet,
+            last_challenge_rewards_cache,
             mine_success_receiver,
+            app_metrics,
+            app_shutdown,
         ).await;
     });
 
+    let announcements = Arc::new(Announcements {
+        all_clients_sender: all_clients_sender.clone(),
+        last_sent_at: Mutex::new(None),
+    });
+
     let app_shared_state = shared_state.clone();
     tokio::spawn(async move {
         message_text_all_clients_system(
@@ -995,6 +2194,55 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         ).await;
     });
 
+    let app_app_database = app_database.clone();
+    let app_config = config.clone();
+    let submission_compaction_system_handle = tokio::spawn(async move {
+        submission_compaction_system(app_app_database, app_config).await;
+    });
+
+    let app_app_database = app_database.clone();
+    let app_claims_queue = claims_queue.clone();
+    let app_config = config.clone();
+    let app_rpc_client = rpc_client.clone();
+    let app_rpc_budget = rpc_budget.clone();
+    let app_wallet = wallet_extension.clone();
+    let app_in_flight_claims = in_flight_claims.clone();
+    let app_claim_reservations = claim_reservations.clone();
+    let claim_scheduler_system_handle = tokio::spawn(async move {
+        claim_scheduler_system(app_app_database, app_claims_queue, app_rpc_client, app_rpc_budget, app_wallet.miner_wallet.clone(), app_in_flight_claims, app_claim_reservations, app_config).await;
+    });
+
+    let app_app_database = app_database.clone();
+    let app_rpc_client = rpc_client.clone();
+    let app_rpc_budget = rpc_budget.clone();
+    let app_wallet = wallet_extension.clone();
+    let app_metrics = metrics_message_sender.clone();
+    let proof_balance_watchdog_system_handle = tokio::spawn(async move {
+        proof_balance_watchdog_system(app_app_database, app_rpc_client, app_rpc_budget, program_ids, app_wallet.miner_wallet.pubkey(), app_metrics).await;
+    });
+
+    let app_shared_state = shared_state.clone();
+    let app_claims_queue = claims_queue.clone();
+    let app_config = config.clone();
+    let app_client_nonce_ranges = client_nonce_ranges.clone();
+    tokio::spawn(async move {
+        janitor_system(app_shared_state, app_claims_queue, app_client_nonce_ranges, app_config).await;
+    });
+
+    let app_rpc_budget = rpc_budget.clone();
+    let app_metrics = metrics_message_sender.clone();
+    tokio::spawn(async move {
+        rpc_call_metrics_system(app_rpc_budget, app_metrics).await;
+    });
+
+    let app_shared_state = shared_state.clone();
+    let app_metrics = metrics_message_sender.clone();
+    tokio::spawn(async move {
+        client_versions_metrics_system(app_shared_state, app_metrics).await;
+    });
+
+    let shutdown_metrics_sender = metrics_message_sender.clone();
+
     let cors = CorsLayer::new()
         .allow_methods([Method::GET])
         .allow_origin(tower_http::cors::Any);
@@ -1004,7 +2252,11 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
     let app = Router::new()
         .route("/", get(ws_handler))
         .route("/v2/ws", get(ws_handler_v2))
+        .route("/ws/challenges", get(ws_challenges_handler))
         .route("/pause", post(post_pause))
+        .route("/admin/announce", post(post_announce))
+        .route("/admin/miners/enabled", post(post_miners_enabled))
+        .route("/admin/miner/{pubkey}/adjust", post(post_adjust_miner_balance))
         .route("/latest-blockhash", get(get_latest_blockhash))
         .route("/pool/authority/pubkey", get(get_pool_authority_pubkey))
         .route("/pool/fee_payer/pubkey", get(get_pool_fee_payer_pubkey))
@@ -1025,6 +2277,11 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         .route("/unstake-boost", post(post_unstake_boost))
         .route("/v2/unstake-boost", post(post_unstake_boost_v2))
         .route("/active-miners", get(get_connected_miners))
+        .route("/status", get(get_status))
+        .route("/health", get(get_health))
+        .route("/metrics", get(get_metrics))
+        .route("/metrics/db", get(get_db_metrics))
+        .route("/pool/capacity", get(get_pool_capacity))
         .route("/timestamp", get(get_timestamp))
         .route("/miner/balance", get(get_miner_balance))
         .route("/v2/miner/balance", get(get_miner_balance_v2))
@@ -1032,15 +2289,26 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         .route("/miner/boost/stake", get(get_miner_boost_stake))
         .route("/v2/miner/boost/stake", get(get_miner_boost_stake_v2))
         .route("/v2/miner/boost/stake-accounts", get(get_miner_boost_stake_accounts_v2))
+        .route("/staker/{pubkey}/stake/{mint}", get(get_staker_stake_account))
+        .route("/staker/{pubkey}/rewards-by-mint", get(get_staker_rewards_by_mint))
         .route("/stake-multiplier", get(get_stake_multiplier))
         .route("/boost-multiplier", get(get_boost_multiplier))
+        .route("/busses", get(get_busses))
         // App RR Database routes
         .route(
             "/last-challenge-submissions",
             get(get_last_challenge_submissions),
         )
+        .route("/last-challenge-rewards", get(get_last_challenge_rewards))
+        .route("/miner/recent-submissions", get(get_miner_recent_submissions))
         .route("/miner/rewards", get(get_miner_rewards))
+        .route("/miner/rank", get(get_miner_rank))
         .route("/miner/submissions", get(get_miner_submissions))
+        .route("/miner/submissions/daily", get(get_miner_submissions_daily))
+        .route("/miner/{pubkey}/history", get(get_miner_earnings_history))
+        .route("/pool/history", get(get_pool_earnings_history))
+        .route("/pool/top-miners/difficulty", get(get_top_miners_by_difficulty))
+        .route("/pool/recent-claims", get(get_pool_recent_claims))
         .route("/miner/last-claim", get(get_miner_last_claim))
         .route("/challenges", get(get_challenges))
         .route("/pool", get(routes::get_pool))
@@ -1048,20 +2316,28 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         .route("/pool/balance", get(get_pool_balance))
         .route("/txns/latest-mine", get(get_latest_mine_txn))
         .with_state(app_shared_state)
-        .layer(Extension(app_database))
-        .layer(Extension(app_rr_database))
+        .layer(Extension(app_database.clone()))
+        .layer(Extension(app_rr_database.clone()))
         .layer(Extension(config))
         .layer(Extension(wallet_extension))
         .layer(Extension(client_channel))
         .layer(Extension(rpc_2_client))
         .layer(Extension(client_nonce_ranges))
+        .layer(Extension(ready_clients))
+        .layer(Extension(metrics_registry))
         .layer(Extension(claims_queue))
         .layer(Extension(submission_window))
         .layer(Extension(app_cache_boost_multiplier))
         .layer(Extension(app_cache_last_challenge_submissions))
         .layer(Extension(app_cache_challenges))
         .layer(Extension(app_cache_latest_blockhash_cache))
+        .layer(Extension(app_cache_last_challenge_rewards))
+        .layer(Extension(app_cache_busses))
+        .layer(Extension(app_rpc_health))
+        .layer(Extension(announcements))
         .layer(Extension(metrics_message_sender))
+        .layer(Extension(rpc_budget))
+        .layer(Extension(challenge_feed))
         // Logging
         .layer(
             TraceLayer::new_for_http()
@@ -1082,12 +2358,165 @@ async fn serve(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
+    .with_graceful_shutdown(shutdown_signal())
     .await
     .unwrap();
 
+    shutdown(
+        submission_shutdown_sender,
+        shutdown_sender,
+        pool_submission_system_handle,
+        mine_success_system_handle,
+        claim_system_handle,
+        epoch_hashes,
+        shutdown_metrics_sender,
+        vec![
+            ("cache update", cache_update_system_handle),
+            ("submission compaction", submission_compaction_system_handle),
+            ("claim scheduler", claim_scheduler_system_handle),
+            ("proof balance watchdog", proof_balance_watchdog_system_handle),
+        ],
+        app_database,
+        app_rr_database,
+    )
+    .await;
+
     Ok(())
 }
 
+/// Stops background work in dependency order so the database pools are only
+/// closed once nothing should issue queries against them anymore:
+/// 1. let the pool submission system finish its current mine cycle and stop,
+/// 2. drain the mine success and claim systems and flush the buffered submissions,
+/// 3. stop the periodic cache and maintenance tasks,
+/// 4. close the database pools.
+async fn shutdown(
+    submission_shutdown_sender: tokio::sync::watch::Sender<bool>,
+    shutdown_sender: tokio::sync::watch::Sender<bool>,
+    pool_submission_system_handle: tokio::task::JoinHandle<()>,
+    mine_success_system_handle: tokio::task::JoinHandle<()>,
+    claim_system_handle: tokio::task::JoinHandle<()>,
+    epoch_hashes: Arc<RwLock<EpochHashes>>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+    background_tasks: Vec<(&'static str, tokio::task::JoinHandle<()>)>,
+    app_database: Arc<AppDatabase>,
+    app_rr_database: Arc<AppRRDatabase>,
+) {
+    let drain_timeout = Duration::from_secs(SHUTDOWN_DRAIN_TIMEOUT_SECS);
+
+    info!(target: "server_log", "Server stopped. Waiting for the pool submission system to finish its mine cycle...");
+    let _ = submission_shutdown_sender.send(true);
+    if let Err(_) = tokio::time::timeout(drain_timeout, pool_submission_system_handle).await {
+        error!(target: "server_log", "Timed out waiting on the pool submission system after {}s.", SHUTDOWN_DRAIN_TIMEOUT_SECS);
+    }
+
+    info!(target: "server_log", "Draining mine success and claim systems...");
+    let _ = shutdown_sender.send(true);
+
+    if let Err(_) = tokio::time::timeout(drain_timeout, mine_success_system_handle).await {
+        error!(target: "server_log", "Timed out draining mine success system after {}s.", SHUTDOWN_DRAIN_TIMEOUT_SECS);
+    }
+    if let Err(_) = tokio::time::timeout(Duration::from_secs(CLAIM_DRAIN_TIMEOUT_SECS), claim_system_handle).await {
+        error!(target: "server_log", "Timed out waiting on pending claim confirmations after {}s.", CLAIM_DRAIN_TIMEOUT_SECS);
+    }
+
+    let buffered = epoch_hashes.write().await.drain_superseded();
+    let buffered_count: usize = buffered.iter().map(|(_, submissions)| submissions.len()).sum();
+    let flush = async {
+        let mut flushed = 0;
+        for (challenge, submissions) in buffered {
+            flushed += write_superseded_submissions(challenge, submissions, &app_database, &app_metrics_sender).await;
+        }
+        flushed
+    };
+    match tokio::time::timeout(drain_timeout, flush).await {
+        Ok(flushed) => info!(target: "server_log", "Flushed {} of {} buffered submissions.", flushed, buffered_count),
+        Err(_) => error!(target: "server_log", "Timed out flushing {} buffered submissions after {}s.", buffered_count, SHUTDOWN_DRAIN_TIMEOUT_SECS),
+    }
+
+    for (name, handle) in background_tasks {
+        handle.abort();
+        let _ = handle.await;
+        info!(target: "server_log", "Stopped {} system.", name);
+    }
+
+    // request handlers and websocket tasks can still hold clones, so close the pools
+    // explicitly rather than waiting on the last handle to drop
+    app_database.close();
+    app_rr_database.close();
+    info!(target: "server_log", "Shutdown complete.");
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(target: "server_log", "Failed to listen for ctrl-c. Error: {:?}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            },
+            Err(e) => {
+                error!(target: "server_log", "Failed to listen for SIGTERM. Error: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!(target: "server_log", "Received shutdown signal.");
+}
+
+
+/// Loads a keypair from exactly one of `{prefix}_PATH` (solana json keypair file),
+/// `{prefix}_BYTES` (json byte array) or `{prefix}_BASE58` (base58 secret key).
+pub fn load_keypair_from_env(prefix: &str) -> Result<Keypair, String> {
+    let path_var = format!("{}_PATH", prefix);
+    let bytes_var = format!("{}_BYTES", prefix);
+    let base58_var = format!("{}_BASE58", prefix);
+
+    let path = std::env::var(&path_var).ok();
+    let bytes = std::env::var(&bytes_var).ok();
+    let base58 = std::env::var(&base58_var).ok();
+
+    let set_count = [path.is_some(), bytes.is_some(), base58.is_some()].iter().filter(|s| **s).count();
+    if set_count == 0 {
+        return Err(format!("One of {}, {} or {} must be set.", path_var, bytes_var, base58_var));
+    }
+    if set_count > 1 {
+        return Err(format!("Only one of {}, {} or {} may be set.", path_var, bytes_var, base58_var));
+    }
+
+    if let Some(path_str) = path {
+        let path = Path::new(&path_str);
+        if !path.exists() {
+            return Err(format!("Failed to find keypair file at: {}", path_str));
+        }
+        return read_keypair_file(path)
+            .map_err(|e| format!("Failed to load keypair from file {}: {}", path_str, e));
+    }
+
+    if let Some(bytes_str) = bytes {
+        let bytes: Vec<u8> = serde_json::from_str(&bytes_str)
+            .map_err(|_| format!("{} must be a json byte array.", bytes_var))?;
+        return Keypair::from_bytes(&bytes)
+            .map_err(|_| format!("{} is not a valid keypair.", bytes_var));
+    }
+
+    let base58_str = base58.unwrap();
+    let bytes = solana_sdk::bs58::decode(base58_str.trim())
+        .into_vec()
+        .map_err(|_| format!("{} is not valid base58.", base58_var))?;
+    Keypair::from_bytes(&bytes)
+        .map_err(|_| format!("{} is not a valid keypair.", base58_var))
+}
 
 async fn get_pool_authority_pubkey(
     Extension(wallet): Extension<Arc<WalletExtension>>,
@@ -1175,6 +2604,191 @@ async fn post_pause(
         .unwrap();
 }
 
+#[derive(Deserialize)]
+struct MinersEnabledBody {
+    miner_ids: Vec<i32>,
+    enabled: bool,
+}
+
+async fn post_miners_enabled(
+    query_params: Query<PauseParams>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Json(body): Json<MinersEnabledBody>,
+) -> impl IntoResponse {
+    if !query_params.p.eq(app_config.password.as_str()) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("Content-Type", "text/text")
+            .body("Unauthorized".to_string())
+            .unwrap();
+    }
+
+    let requested = body.miner_ids.len();
+    match app_database.set_miners_enabled(body.miner_ids, body.enabled).await {
+        Ok(updated) => {
+            info!(target: "server_log", "Set enabled = {} for {} of {} requested miners", body.enabled, updated, requested);
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/text")
+                .body(updated.to_string())
+                .unwrap();
+        }
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("Content-Type", "text/text")
+                .body("Failed to update miners".to_string())
+                .unwrap();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AdjustBalanceBody {
+    delta: i64,
+    reason: String,
+}
+
+async fn post_adjust_miner_balance(
+    axum::extract::Path(pubkey): axum::extract::Path<String>,
+    query_params: Query<PauseParams>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Json(body): Json<AdjustBalanceBody>,
+) -> impl IntoResponse {
+    // adjustments need a named admin token so the audit trail records who made them
+    let Some(admin) = app_config.admin_tokens.get(&query_params.p).cloned() else {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("Content-Type", "text/text")
+            .body("Unauthorized".to_string())
+            .unwrap();
+    };
+
+    let Ok(miner_pubkey) = Pubkey::from_str(&pubkey) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "text/text")
+            .body("Invalid pubkey".to_string())
+            .unwrap();
+    };
+
+    let reason = body.reason.trim().to_string();
+    if body.delta == 0 || reason.is_empty() || reason.chars().count() > 255 {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "text/text")
+            .body("delta must be non-zero and reason 1-255 characters".to_string())
+            .unwrap();
+    }
+
+    let miner_id = match app_database.find_miner_rewards(miner_pubkey.to_string()).await {
+        Ok(Some(rewards)) => rewards.miner_id,
+        Ok(None) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("Content-Type", "text/text")
+                .body("miner has no rewards account".to_string())
+                .unwrap();
+        }
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("Content-Type", "text/text")
+                .body("failed to get miner rewards from database".to_string())
+                .unwrap();
+        }
+    };
+
+    match app_database.adjust_miner_balance(miner_id, body.delta, reason.clone(), admin.clone()).await {
+        Ok(new_balance) => {
+            info!(target: "server_log", "{} adjusted balance of {} by {} ({}), new balance: {}", admin, miner_pubkey, body.delta, reason, new_balance);
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/text")
+                .body(new_balance.to_string())
+                .unwrap();
+        }
+        Err(AppDatabaseError::InvalidInput(e)) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "text/text")
+                .body(e)
+                .unwrap();
+        }
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("Content-Type", "text/text")
+                .body("Failed to adjust balance".to_string())
+                .unwrap();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AnnounceBody {
+    message: String,
+}
+
+async fn post_announce(
+    query_params: Query<PauseParams>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(announcements): Extension<Arc<Announcements>>,
+    Json(body): Json<AnnounceBody>,
+) -> impl IntoResponse {
+    if !query_params.p.eq(app_config.password.as_str()) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("Content-Type", "text/text")
+            .body("Unauthorized".to_string())
+            .unwrap();
+    }
+
+    let message = body.message.trim();
+    if message.is_empty() || message.chars().count() > MAX_ANNOUNCEMENT_LEN {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "text/text")
+            .body(format!("Announcement must be between 1 and {} characters", MAX_ANNOUNCEMENT_LEN))
+            .unwrap();
+    }
+
+    let mut last_sent_at = announcements.last_sent_at.lock().await;
+    if let Some(at) = *last_sent_at {
+        if at.elapsed() < Duration::from_secs(ANNOUNCEMENT_MIN_INTERVAL_SECS) {
+            return Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("Content-Type", "text/text")
+                .body("Announcement rate limited".to_string())
+                .unwrap();
+        }
+    }
+
+    let text = serde_json::json!({
+        "type": "announcement",
+        "message": message,
+    })
+    .to_string();
+    if announcements.all_clients_sender.send(MessageInternalAllClients { text, channel: CHANNEL_ANNOUNCEMENTS }).is_err() {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header("Content-Type", "text/text")
+            .body("Failed to queue announcement".to_string())
+            .unwrap();
+    }
+    *last_sent_at = Some(Instant::now());
+    drop(last_sent_at);
+
+    info!(target: "server_log", "Sent announcement to connected miners: {}", message);
+    return Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/text")
+        .body("SUCCESS".to_string())
+        .unwrap();
+}
+
 #[derive(Deserialize)]
 struct SignupParams {
     pubkey: String,
@@ -1226,6 +2840,12 @@ async fn post_signup(
                     .body("SUCCESS".to_string())
                     .unwrap();
             },
+            Err(AppDatabaseError::PoolNotInitialized) => {
+                return Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body("Pool is not initialized".to_string())
+                    .unwrap();
+            },
             Err(_) => {
                 error!(target: "server_log", "Failed to add miner to database");
                 return Response::builder()
@@ -1295,6 +2915,12 @@ async fn post_signup_v2(
                     .body("SUCCESS".to_string())
                     .unwrap();
             },
+            Err(AppDatabaseError::PoolNotInitialized) => {
+                return Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body("Pool is not initialized".to_string())
+                    .unwrap();
+            },
             Err(_) => {
                 error!(target: "server_log", "Failed to add miner to database");
                 return Response::builder()
@@ -1323,9 +2949,11 @@ async fn get_signup_fee(
 
 async fn get_sol_balance(
     Extension(rpc_client): Extension<Arc<RpcClient>>,
+    Extension(rpc_budget): Extension<Arc<RpcBudget>>,
     query_params: Query<PubkeyParam>,
 ) -> impl IntoResponse {
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        rpc_budget.acquire("getBalance", RpcPriority::Stats).await;
         let res = rpc_client.get_balance(&user_pubkey).await;
 
         match res {
@@ -1433,9 +3061,47 @@ async fn get_miner_rewards(
             .as_millis();
 
         let metrics_data = MetricsRouteEventData {
-            route: "miner/rewards".to_string(),
+            route: "miner/rewards".to_string(),
+            method: "GET".to_string(),
+            status_code: 400,
+            request: metrics_start,
+            response: metrics_end,
+            latency: metrics_end - metrics_start,
+            ts_ns: metrics_end,
+
+        };
+        if let Err(_e) = app_metrics_channel.send(AppMetricsEvent::RouteEvent(metrics_data)) {
+            tracing::error!(target: "server_log", "Failed to send msg down app metrics channel.");
+        };
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    }
+}
+
+async fn get_last_challenge_submissions(
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_cache_last_challenge_submissions): Extension<Arc<RwLock<LastChallengeSubmissionsCache>>>,
+    Extension(app_metrics_channel): Extension<UnboundedSender<AppMetricsEvent>>,
+) -> Result<Json<Vec<SubmissionWithPubkey>>, String> {
+    if app_config.stats_enabled {
+        let metrics_start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis();
+        let reader = app_cache_last_challenge_submissions.read().await;
+        let cached_boost_multiplier = reader.clone();
+        drop(reader);
+        let metrics_end = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis();
+
+        let metrics_data = MetricsRouteEventData {
+            route: "last-challenge-submissions".to_string(),
             method: "GET".to_string(),
-            status_code: 400,
+            status_code: 200,
             request: metrics_start,
             response: metrics_end,
             latency: metrics_end - metrics_start,
@@ -1445,25 +3111,47 @@ async fn get_miner_rewards(
         if let Err(_e) = app_metrics_channel.send(AppMetricsEvent::RouteEvent(metrics_data)) {
             tracing::error!(target: "server_log", "Failed to send msg down app metrics channel.");
         };
-        return Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body("Invalid public key".to_string())
-            .unwrap();
+        return Ok(Json(cached_boost_multiplier.item));
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
     }
 }
 
-async fn get_last_challenge_submissions(
+async fn get_miner_recent_submissions(
+    query_params: Query<PubkeyParam>,
     Extension(app_config): Extension<Arc<Config>>,
     Extension(app_cache_last_challenge_submissions): Extension<Arc<RwLock<LastChallengeSubmissionsCache>>>,
+) -> Result<Json<Vec<RecentSubmission>>, String> {
+    if app_config.stats_enabled {
+        if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+            let reader = app_cache_last_challenge_submissions.read().await;
+            let recent = reader
+                .recent_by_miner
+                .get(&user_pubkey.to_string())
+                .cloned()
+                .unwrap_or_default();
+            drop(reader);
+            Ok(Json(recent))
+        } else {
+            Err("Invalid public key".to_string())
+        }
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+async fn get_last_challenge_rewards(
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_cache_last_challenge_rewards): Extension<Arc<RwLock<LastChallengeRewardsCache>>>,
     Extension(app_metrics_channel): Extension<UnboundedSender<AppMetricsEvent>>,
-) -> Result<Json<Vec<SubmissionWithPubkey>>, String> {
+) -> Result<Json<Option<LastChallengeRewards>>, String> {
     if app_config.stats_enabled {
         let metrics_start = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards")
             .as_millis();
-        let reader = app_cache_last_challenge_submissions.read().await;
-        let cached_boost_multiplier = reader.clone();
+        let reader = app_cache_last_challenge_rewards.read().await;
+        let cached_last_challenge_rewards = reader.clone();
         drop(reader);
         let metrics_end = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -1471,7 +3159,7 @@ async fn get_last_challenge_submissions(
             .as_millis();
 
         let metrics_data = MetricsRouteEventData {
-            route: "last-challenge-submissions".to_string(),
+            route: "last-challenge-rewards".to_string(),
             method: "GET".to_string(),
             status_code: 200,
             request: metrics_start,
@@ -1483,7 +3171,7 @@ async fn get_last_challenge_submissions(
         if let Err(_e) = app_metrics_channel.send(AppMetricsEvent::RouteEvent(metrics_data)) {
             tracing::error!(target: "server_log", "Failed to send msg down app metrics channel.");
         };
-        return Ok(Json(cached_boost_multiplier.item));
+        return Ok(Json(cached_last_challenge_rewards.item));
     } else {
         return Err("Stats not enabled for this server.".to_string());
     }
@@ -1605,6 +3293,186 @@ struct GetLastClaimParams {
     pubkey: String,
 }
 
+async fn get_miner_submissions_daily(
+    query_params: Query<GetSubmissionsParams>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Result<Json<Vec<SubmissionAggregate>>, String> {
+    if app_config.stats_enabled {
+        if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+            let res = app_rr_database
+                .get_miner_submission_aggregates(user_pubkey.to_string())
+                .await;
+
+            match res {
+                Ok(aggregates) => Ok(Json(aggregates)),
+                Err(_) => Err("Failed to get daily submissions for miner".to_string()),
+            }
+        } else {
+            Err("Invalid public key".to_string())
+        }
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+#[derive(Deserialize)]
+struct GetEarningsHistoryParams {
+    interval: Option<EarningsInterval>,
+    days: Option<u32>,
+}
+
+impl GetEarningsHistoryParams {
+    fn resolve(&self) -> (EarningsInterval, chrono::NaiveDateTime) {
+        let interval = self.interval.unwrap_or(EarningsInterval::Day);
+        let days = self.days.unwrap_or(30).clamp(1, interval.max_days());
+        let since = chrono::Utc::now().naive_utc() - chrono::Duration::days(days as i64);
+        (interval, since)
+    }
+}
+
+async fn get_miner_earnings_history(
+    axum::extract::Path(pubkey): axum::extract::Path<String>,
+    query_params: Query<GetEarningsHistoryParams>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Result<Json<Vec<models::EarningsBucket>>, String> {
+    if app_config.stats_enabled {
+        if let Ok(user_pubkey) = Pubkey::from_str(&pubkey) {
+            let (interval, since) = query_params.resolve();
+            let res = app_rr_database
+                .get_miner_earnings_history(user_pubkey.to_string(), interval, since)
+                .await;
+
+            match res {
+                Ok(buckets) => Ok(Json(buckets)),
+                Err(_) => Err("Failed to get earnings history for miner".to_string()),
+            }
+        } else {
+            Err("Invalid public key".to_string())
+        }
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+async fn get_pool_earnings_history(
+    query_params: Query<GetEarningsHistoryParams>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Result<Json<Vec<models::EarningsBucket>>, String> {
+    if app_config.stats_enabled {
+        let (interval, since) = query_params.resolve();
+        let res = app_rr_database
+            .get_pool_earnings_history(app_config.pool_id, interval, since)
+            .await;
+
+        match res {
+            Ok(buckets) => Ok(Json(buckets)),
+            Err(_) => Err("Failed to get pool earnings history".to_string()),
+        }
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+#[derive(Deserialize)]
+struct GetTopMinersByDifficultyParams {
+    hours: Option<u32>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct MinerDifficultyRank {
+    pubkey: String,
+    total_difficulty: u64,
+}
+
+async fn get_top_miners_by_difficulty(
+    query_params: Query<GetTopMinersByDifficultyParams>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Result<Json<Vec<MinerDifficultyRank>>, String> {
+    if app_config.stats_enabled {
+        let hours = query_params.hours.unwrap_or(1).clamp(1, 24);
+        let since = chrono::Utc::now().naive_utc() - chrono::Duration::hours(hours as i64);
+        let res = app_rr_database
+            .get_top_miners_by_difficulty(since, query_params.limit.unwrap_or(10))
+            .await;
+
+        match res {
+            Ok(miners) => Ok(Json(
+                miners
+                    .into_iter()
+                    .map(|(pubkey, total_difficulty)| MinerDifficultyRank { pubkey, total_difficulty })
+                    .collect(),
+            )),
+            Err(_) => Err("Failed to get top miners by difficulty".to_string()),
+        }
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+#[derive(Deserialize)]
+struct GetRecentClaimsParams {
+    limit: Option<u32>,
+}
+
+async fn get_pool_recent_claims(
+    query_params: Query<GetRecentClaimsParams>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Result<Json<Vec<models::ClaimWithMiner>>, String> {
+    if app_config.stats_enabled {
+        let res = app_rr_database
+            .get_recent_claims(app_config.pool_id, query_params.limit.unwrap_or(50))
+            .await;
+
+        match res {
+            Ok(claims) => Ok(Json(claims)),
+            Err(_) => Err("Failed to get recent claims".to_string()),
+        }
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+#[derive(Serialize)]
+struct MinerRankResponse {
+    pubkey: String,
+    rank: Option<i64>,
+}
+
+async fn get_miner_rank(
+    query_params: Query<PubkeyParam>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Result<Json<MinerRankResponse>, (StatusCode, String)> {
+    if app_config.stats_enabled {
+        if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+            // only a miner without rewards is unranked, a failed lookup is an error
+            let miner_id = match app_rr_database.find_miner_rewards(user_pubkey.to_string()).await {
+                Ok(Some(rewards)) => rewards.miner_id,
+                Ok(None) => {
+                    return Ok(Json(MinerRankResponse { pubkey: user_pubkey.to_string(), rank: None }));
+                }
+                Err(_) => {
+                    return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to get miner rewards".to_string()));
+                }
+            };
+            match app_rr_database.get_miner_rank(app_config.pool_id, miner_id).await {
+                Ok(rank) => Ok(Json(MinerRankResponse { pubkey: user_pubkey.to_string(), rank })),
+                Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to get miner rank".to_string())),
+            }
+        } else {
+            Err((StatusCode::BAD_REQUEST, "Invalid public key".to_string()))
+        }
+    } else {
+        return Err((StatusCode::BAD_REQUEST, "Stats not enabled for this server.".to_string()));
+    }
+}
+
 async fn get_miner_last_claim(
     query_params: Query<GetLastClaimParams>,
     Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
@@ -1706,10 +3574,11 @@ async fn get_miner_stake(
     query_params: Query<PubkeyParam>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
 ) -> impl IntoResponse {
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
         if let Ok(account) =
-            get_delegated_stake_account(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey())
+            get_delegated_stake_account(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey())
                 .await
         {
             let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
@@ -1729,6 +3598,7 @@ async fn get_miner_boost_stake(
     query_params: Query<PubkeyMintParam>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
 ) -> impl IntoResponse {
     let mint = match Pubkey::from_str(&query_params.mint) {
         Ok(pk) => {
@@ -1740,7 +3610,7 @@ async fn get_miner_boost_stake(
     };
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
         if let Ok(account) =
-            get_delegated_boost_account(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
+            get_delegated_boost_account(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
                 .await
         {
             let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
@@ -1758,6 +3628,7 @@ async fn get_miner_boost_stake_v2(
     query_params: Query<PubkeyMintParam>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
 ) -> impl IntoResponse {
     let mint = match Pubkey::from_str(&query_params.mint) {
         Ok(pk) => {
@@ -1768,15 +3639,20 @@ async fn get_miner_boost_stake_v2(
         }
     };
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
-        if let Ok(account) =
-            get_delegated_boost_account_v2(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
-                .await
+        match get_delegated_boost_account_v2(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
+            .await
         {
-            let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
-            let dec_amount = (account.amount as f64).div(decimals);
-            return Ok(dec_amount.to_string());
-        } else {
-            return Err("Failed to get delgated boost account v2 balance".to_string());
+            Ok(account) => {
+                let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+                let dec_amount = (account.amount as f64).div(decimals);
+                return Ok(dec_amount.to_string());
+            }
+            Err(DelegatedBoostError::UnknownBoostMint(_)) => {
+                return Err("Unknown boost mint".to_string());
+            }
+            Err(_) => {
+                return Err("Failed to get delgated boost account v2 balance".to_string());
+            }
         }
     } else {
         return Err("Invalid pubkey".to_string());
@@ -1801,6 +3677,75 @@ async fn get_miner_boost_stake_accounts_v2(
     }
 }
 
+async fn get_staker_rewards_by_mint(
+    axum::extract::Path(pubkey): axum::extract::Path<String>,
+    Extension(app_rr_database): Extension<Arc<AppRRDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Response<String> {
+    let Ok(staker_pubkey) = Pubkey::from_str(&pubkey) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid pubkey".to_string())
+            .unwrap();
+    };
+
+    match app_rr_database
+        .get_staker_rewards_by_mint(app_config.pool_id, staker_pubkey.to_string())
+        .await
+    {
+        Ok(rewards) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&rewards).unwrap())
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get staker rewards from db".to_string())
+            .unwrap(),
+    }
+}
+
+async fn get_staker_stake_account(
+    axum::extract::Path((pubkey, mint)): axum::extract::Path<(String, String)>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> Response<String> {
+    let (staker_pubkey, mint_pubkey) = match (Pubkey::from_str(&pubkey), Pubkey::from_str(&mint)) {
+        (Ok(staker_pubkey), Ok(mint_pubkey)) => (staker_pubkey, mint_pubkey),
+        (Err(_), _) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body("Invalid pubkey".to_string())
+                .unwrap();
+        }
+        (_, Err(_)) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body("Invalid mint".to_string())
+                .unwrap();
+        }
+    };
+
+    match app_database
+        .find_stake_account_for_staker(app_config.pool_id, staker_pubkey.to_string(), mint_pubkey.to_string())
+        .await
+    {
+        Ok(Some(stake_account)) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&stake_account).unwrap())
+            .unwrap(),
+        Ok(None) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("Stake account not found".to_string())
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get stake account from db".to_string())
+            .unwrap(),
+    }
+}
+
 async fn get_stake_multiplier(
     Extension(app_config): Extension<Arc<Config>>,
 ) -> impl IntoResponse {
@@ -1817,9 +3762,29 @@ pub struct BoostMultiplierData {
     boost_mint: String,
     staked_balance: f64,
     total_stake_balance: f64,
+    // exact base unit amounts; the f64 fields above are for display only
+    #[serde(with = "u64_string")]
+    staked_balance_raw: u64,
+    #[serde(with = "u64_string")]
+    total_stake_balance_raw: u64,
     multiplier: u64,
 }
 
+/// Serializes u64 amounts as strings so JSON consumers don't round them through a double.
+mod u64_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 async fn get_boost_multiplier(
     Extension(app_config): Extension<Arc<Config>>,
     Extension(app_cache_boost_multiplier): Extension<Arc<RwLock<BoostMultiplierCache>>>,
@@ -1834,9 +3799,161 @@ async fn get_boost_multiplier(
     }
 }
 
-#[derive(Deserialize)]
-struct ConnectedMinersParams {
-    pubkey: Option<String>,
+async fn get_busses(
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_cache_busses): Extension<Arc<RwLock<BussesCache>>>,
+) -> impl IntoResponse {
+    if app_config.stats_enabled {
+        let reader = app_cache_busses.read().await;
+        let cached_busses = reader.clone();
+        drop(reader);
+        return Ok(Json(cached_busses.item));
+    } else {
+        return Err("Stats not enabled for this server.".to_string());
+    }
+}
+
+#[derive(Deserialize)]
+struct ConnectedMinersParams {
+    pubkey: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ServerStatus {
+    rpc: RpcHealth,
+}
+
+async fn get_status(
+    Extension(app_rpc_health): Extension<Arc<RwLock<RpcHealth>>>,
+) -> Json<ServerStatus> {
+    let rpc = app_rpc_health.read().await.clone();
+
+    Json(ServerStatus { rpc })
+}
+
+#[derive(Serialize)]
+struct HealthReport {
+    healthy: bool,
+    unhealthy: Vec<&'static str>,
+}
+
+const HEALTH_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lists the components that are down or older than `max_age`. `rpc_age` is `None` until the
+/// first successful RPC call. The busses cache is only refreshed when stats are enabled.
+fn health_report(
+    db_ok: bool,
+    blockhash_age: Duration,
+    rpc_age: Option<Duration>,
+    busses_age: Duration,
+    stats_enabled: bool,
+    max_age: Duration,
+) -> HealthReport {
+    let mut unhealthy = Vec::new();
+    if !db_ok {
+        unhealthy.push("database");
+    }
+    if blockhash_age > max_age {
+        unhealthy.push("latest_blockhash_cache");
+    }
+    if !rpc_age.is_some_and(|age| age <= max_age) {
+        unhealthy.push("rpc");
+    }
+    if stats_enabled && busses_age > max_age {
+        unhealthy.push("busses_cache");
+    }
+    HealthReport { healthy: unhealthy.is_empty(), unhealthy }
+}
+
+async fn get_health(
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_rpc_health): Extension<Arc<RwLock<RpcHealth>>>,
+    Extension(app_latest_blockhash_cache): Extension<Arc<RwLock<LatestBlockhashCache>>>,
+    Extension(app_busses_cache): Extension<Arc<RwLock<BussesCache>>>,
+) -> impl IntoResponse {
+    let db_ok = matches!(tokio::time::timeout(HEALTH_DB_TIMEOUT, app_database.health_check()).await, Ok(Ok(())));
+    let blockhash_age = app_latest_blockhash_cache.read().await.last_updated_at.elapsed();
+    // the blockhash refresh records rpc health whether or not stats are enabled
+    let rpc_age = app_rpc_health.read().await.secs_since_success().map(Duration::from_secs);
+    let busses_age = app_busses_cache.read().await.last_updated_at.elapsed();
+
+    let report = health_report(
+        db_ok,
+        blockhash_age,
+        rpc_age,
+        busses_age,
+        app_config.stats_enabled,
+        Duration::from_secs(app_config.health_max_staleness_secs),
+    );
+    let status = if report.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+async fn get_db_metrics(
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+) -> impl IntoResponse {
+    Json(app_database.metrics_snapshot())
+}
+
+async fn get_metrics(
+    headers: HeaderMap,
+    State(app_state): State<Arc<RwLock<AppState>>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(metrics_registry): Extension<Arc<MetricsRegistry>>,
+    Extension(app_latest_blockhash_cache): Extension<Arc<RwLock<LatestBlockhashCache>>>,
+) -> impl IntoResponse {
+    // event driven metrics come from the registry, only live state is read here
+    let mut samples = metrics_registry.snapshot().await;
+    let reader = app_state.read().await;
+    samples.push(MetricSample::gauge("ore_pool_connected_miners", "Open miner websocket connections", reader.sockets.len() as f64));
+    samples.push(MetricSample::gauge("ore_pool_connected_cores", "Cores reported by connected miners", reader.total_cores() as f64));
+    samples.push(MetricSample::gauge("ore_pool_lingering_miners", "Disconnected miners still inside the linger window", reader.lingering_count() as f64));
+    samples.push(MetricSample::gauge("ore_pool_paused", "1 if mining is paused", if reader.paused { 1.0 } else { 0.0 }));
+    drop(reader);
+    let blockhash_age = app_latest_blockhash_cache.read().await.last_updated_at.elapsed();
+    samples.push(MetricSample::gauge("ore_pool_blockhash_age_seconds", "Age of the cached latest blockhash", blockhash_age.as_secs_f64()));
+    for query in app_database.metrics_snapshot() {
+        samples.push(MetricSample::counter("ore_pool_db_queries_total", "Calls per database query", query.count as f64).label("query", query.query));
+        samples.push(MetricSample::counter("ore_pool_db_query_ms_total", "Total milliseconds spent per database query", query.total_ms as f64).label("query", query.query));
+        samples.push(MetricSample::gauge("ore_pool_db_query_max_ms", "Slowest call per database query in milliseconds", query.max_ms as f64).label("query", query.query));
+    }
+
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("application/json"))
+        .unwrap_or(false);
+
+    let (content_type, body) = render_metrics(samples, wants_json);
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body)
+        .unwrap()
+}
+
+#[derive(Serialize)]
+struct PoolCapacity {
+    connections: usize,
+    cores: u64,
+}
+
+async fn get_pool_capacity(
+    State(app_state): State<Arc<RwLock<AppState>>>,
+) -> Json<PoolCapacity> {
+    let reader = app_state.read().await;
+    let capacity = PoolCapacity {
+        connections: reader.sockets.len(),
+        cores: reader.total_cores(),
+    };
+    drop(reader);
+
+    Json(capacity)
 }
 
 async fn get_connected_miners(
@@ -1845,6 +3962,7 @@ async fn get_connected_miners(
 ) -> impl IntoResponse {
     let reader = app_state.read().await;
     let socks = reader.sockets.clone();
+    let lingering = reader.lingering_count();
     drop(reader);
 
     if let Some(pubkey_str) = &query_params.pubkey {
@@ -1871,7 +3989,7 @@ async fn get_connected_miners(
     } else {
         return Response::builder()
             .status(StatusCode::OK)
-            .body(socks.len().to_string())
+            .body((socks.len() + lingering).to_string())
             .unwrap();
     }
 }
@@ -1897,6 +4015,8 @@ async fn post_claim(
     query_params: Query<ClaimParams>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
     Extension(claims_queue): Extension<Arc<ClaimsQueue>>,
+    Extension(rpc_client): Extension<Arc<RpcClient>>,
+    Extension(app_config): Extension<Arc<Config>>,
 ) -> impl IntoResponse {
     if let Ok(miner_pubkey) = Pubkey::from_str(&query_params.pubkey) {
         let reader = claims_queue.queue.read().await;
@@ -1916,8 +4036,7 @@ async fn post_claim(
         drop(reader);
 
         if let Some(item) =  claim_cd.get(&miner_pubkey) {
-            // seconds in a day 86_400
-            let cooldown_time = 86_400 * 2;
+            let cooldown_time = CLAIM_COOLDOWN_SECS;
             if item.last_processed.elapsed().as_secs() < cooldown_time {
                 return Response::builder()
                     .status(StatusCode::BAD_REQUEST)
@@ -1937,41 +4056,57 @@ async fn post_claim(
         }
 
 
-        if let Ok(miner_rewards) = app_database
-            .get_miner_rewards(miner_pubkey.to_string())
+        match app_database
+            .find_miner_rewards(miner_pubkey.to_string())
             .await
         {
-            if amount > miner_rewards.balance {
-                return Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body("claim amount exceeds miner rewards balance".to_string())
-                    .unwrap();
-            }
+            Ok(Some(miner_rewards)) => {
+                if amount > miner_rewards.balance {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body("claim amount exceeds miner rewards balance".to_string())
+                        .unwrap();
+                }
 
 
-            let mut writer = claims_queue.queue.write().await;
-            writer.insert((miner_pubkey, None), ClaimsQueueItem{
-                receiver_pubkey: miner_pubkey,
-                amount,
-                mint: None,
-            });
-            drop(writer);
+                if let Err((status, body)) = check_claim_beneficiary_ata(&app_config, &rpc_client, &miner_pubkey).await {
+                    return Response::builder()
+                        .status(status)
+                        .body(body)
+                        .unwrap();
+                }
 
-            let mut writer = claims_queue.claim_cooldown.write().await;
-            writer.insert(miner_pubkey, ClaimCooldownItem {
-                last_processed: Instant::now(),
-            });
-            drop(writer);
+                let mut writer = claims_queue.queue.write().await;
+                writer.insert((miner_pubkey, None), ClaimsQueueItem{
+                    receiver_pubkey: miner_pubkey,
+                    amount,
+                    mint: None,
+                });
+                drop(writer);
 
-            return Response::builder()
-                .status(StatusCode::OK)
-                .body("SUCCESS".to_string())
-                .unwrap();
-        } else {
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body("failed to get miner account from database".to_string())
-                .unwrap();
+                let mut writer = claims_queue.claim_cooldown.write().await;
+                writer.insert(miner_pubkey, ClaimCooldownItem {
+                    last_processed: Instant::now(),
+                });
+                drop(writer);
+
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .body("SUCCESS".to_string())
+                    .unwrap();
+            },
+            Ok(None) => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body("miner has no rewards account".to_string())
+                    .unwrap();
+            },
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("failed to get miner account from database".to_string())
+                    .unwrap();
+            }
         }
     } else {
         error!(target: "server_log", "Claim with invalid pubkey");
@@ -1982,6 +4117,22 @@ async fn post_claim(
     }
 }
 
+/// With the miner paying for token accounts, a claim to a wallet without an ORE token account
+/// can never be sent, so it is rejected before it is queued.
+async fn check_claim_beneficiary_ata(app_config: &Config, rpc_client: &RpcClient, receiver: &Pubkey) -> Result<(), (StatusCode, String)> {
+    if app_config.claim_ata_payer != ClaimAtaPayer::Miner {
+        return Ok(());
+    }
+    match beneficiary_ata_exists(rpc_client, receiver).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((StatusCode::BAD_REQUEST, "receiver has no ORE token account, create it before claiming".to_string())),
+        Err(e) => {
+            error!(target: "server_log", "Failed to check claim receiver token account: {:?}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to check receiver token account".to_string()))
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct ClaimParamsV2 {
     timestamp: u64,
@@ -1993,6 +4144,8 @@ async fn post_claim_v2(
     TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
     Extension(claims_queue): Extension<Arc<ClaimsQueue>>,
+    Extension(rpc_client): Extension<Arc<RpcClient>>,
+    Extension(app_config): Extension<Arc<Config>>,
     query_params: Query<ClaimParamsV2>,
 ) -> impl IntoResponse {
     let msg_timestamp = query_params.timestamp;
@@ -2048,50 +4201,58 @@ async fn post_claim_v2(
                 drop(reader);
 
                 if let Some(item) =  claim_cd.get(&miner_pubkey) {
-                    // seconds in a day 86_400
-                    let cooldown_time = 86_400 * 2;
+                    let cooldown_time = CLAIM_COOLDOWN_SECS;
                     if item.last_processed.elapsed().as_secs() < cooldown_time {
                         return Err((StatusCode::BAD_REQUEST, "claims only allowed once every 48 hours".to_string()));
                     }
                 }
 
-                if let Ok(miner_rewards) = app_database
-                    .get_miner_rewards(miner_pubkey.to_string())
+                match app_database
+                    .find_miner_rewards(miner_pubkey.to_string())
                     .await
                 {
-                    if amount > miner_rewards.balance {
-                        return Err((StatusCode::BAD_REQUEST, "claim amount exceeds miner rewards balance.".to_string()));
-                    }
+                    Ok(Some(miner_rewards)) => {
+                        if amount > miner_rewards.balance {
+                            return Err((StatusCode::BAD_REQUEST, "claim amount exceeds miner rewards balance.".to_string()));
+                        }
 
-                    if let Ok(last_claim) = app_database.get_last_claim(miner_rewards.miner_id).await {
-                        let last_claim_ts = last_claim.created_at.and_utc().timestamp();
-                        let now = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .expect("Time went backwards")
-                            .as_secs() as i64;
-                        let time_difference = now - last_claim_ts;
-                        if time_difference <= 1800 {
-                            return Err((StatusCode::TOO_MANY_REQUESTS, time_difference.to_string()));
+                        match app_database.get_last_claim(miner_rewards.miner_id).await {
+                            Ok(Some(last_claim)) => {
+                                let time_difference = last_claim.seconds_since();
+                                if time_difference <= 1800 {
+                                    return Err((StatusCode::TOO_MANY_REQUESTS, time_difference.to_string()));
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(_) => {
+                                return Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to get last claim from database".to_string()));
+                            }
                         }
-                    }
 
-                    let mut writer = claims_queue.queue.write().await;
-                    writer.insert((miner_pubkey, None), ClaimsQueueItem{
-                        receiver_pubkey,
-                        amount,
-                        mint: None,
-                    });
-                    drop(writer);
+                        check_claim_beneficiary_ata(&app_config, &rpc_client, &receiver_pubkey).await?;
 
-                    let mut writer = claims_queue.claim_cooldown.write().await;
-                    writer.insert(miner_pubkey, ClaimCooldownItem {
-                        last_processed: Instant::now(),
-                    });
-                    drop(writer);
+                        let mut writer = claims_queue.queue.write().await;
+                        writer.insert((miner_pubkey, None), ClaimsQueueItem{
+                            receiver_pubkey,
+                            amount,
+                            mint: None,
+                        });
+                        drop(writer);
 
-                    return Ok((StatusCode::OK, "SUCCESS"));
-                } else {
-                    return Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to get miner account from database".to_string()));
+                        let mut writer = claims_queue.claim_cooldown.write().await;
+                        writer.insert(miner_pubkey, ClaimCooldownItem {
+                            last_processed: Instant::now(),
+                        });
+                        drop(writer);
+
+                        return Ok((StatusCode::OK, "SUCCESS"));
+                    },
+                    Ok(None) => {
+                        return Err((StatusCode::BAD_REQUEST, "miner has no rewards account".to_string()));
+                    },
+                    Err(_) => {
+                        return Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to get miner account from database".to_string()));
+                    }
                 }
             } else {
                 return Err((StatusCode::UNAUTHORIZED, "Sig verification failed".to_string()));
@@ -2118,6 +4279,7 @@ async fn post_claim_stake_rewards_v2(
     Extension(app_database): Extension<Arc<AppDatabase>>,
     Extension(claims_queue): Extension<Arc<ClaimsQueue>>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
+    Extension(app_config): Extension<Arc<Config>>,
     query_params: Query<ClaimStakeRewardsParamsV2>,
 ) -> impl IntoResponse {
     let msg_timestamp = query_params.timestamp;
@@ -2152,13 +4314,7 @@ async fn post_claim_stake_rewards_v2(
         }
     };
 
-    let boost_mints = vec![
-        Pubkey::from_str("oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp").unwrap(),
-        Pubkey::from_str("DrSS5RM7zUd9qjUEdDaf31vnDUSbCrMto6mjqTrHFifN").unwrap(),
-        Pubkey::from_str("meUwDp23AaxhiNKaQCyJ2EAF2T4oe1gSkEkGXSRVdZb").unwrap()
-    ];
-
-    if !boost_mints.contains(&mint_pubkey) {
+    if !is_known_boost_mint(&mint_pubkey) {
         return Err((StatusCode::BAD_REQUEST, "Invalid mint provided.".to_string()))
     }
 
@@ -2185,8 +4341,7 @@ async fn post_claim_stake_rewards_v2(
                 drop(reader);
 
                 if let Some(item) =  claim_cd.get(&staker_pubkey) {
-                    // seconds in a day 86_400
-                    let cooldown_time = 86_400 * 2;
+                    let cooldown_time = CLAIM_COOLDOWN_SECS;
                     if item.last_processed.elapsed().as_secs() < cooldown_time {
                         return Err((StatusCode::BAD_REQUEST, "claims only allowed once every 48 hours".to_string()));
                     }
@@ -2214,29 +4369,37 @@ async fn post_claim_stake_rewards_v2(
                     return Err((StatusCode::BAD_REQUEST, "claim minimum is 0.00000005000".to_string()));
                 }
 
-                if let Ok(staker_rewards) = app_database
-                    .get_staker_rewards(staker_pubkey.to_string(), mint_pubkey.to_string())
+                match app_database
+                    .find_staker_rewards(app_config.pool_id, staker_pubkey.to_string(), mint_pubkey.to_string())
                     .await
                 {
-                    if amount > staker_rewards.rewards_balance {
-                        return Err((StatusCode::BAD_REQUEST, "claim amount exceeds staker rewards balance.".to_string()));
-                    }
+                    Ok(Some(staker_rewards)) => {
+                        if amount > staker_rewards.rewards_balance {
+                            return Err((StatusCode::BAD_REQUEST, "claim amount exceeds staker rewards balance.".to_string()));
+                        }
 
-                    let mut writer = claims_queue.queue.write().await;
-                    writer.insert((staker_pubkey, Some(mint_pubkey)), ClaimsQueueItem{
-                        receiver_pubkey,
-                        amount,
-                        mint: Some(mint_pubkey),
-                    });
-                    drop(writer);
-                    let mut writer = claims_queue.claim_cooldown.write().await;
-                    writer.insert(staker_pubkey, ClaimCooldownItem {
-                        last_processed: Instant::now(),
-                    });
-                    drop(writer);
-                    return Ok((StatusCode::OK, "SUCCESS".to_string()));
-                } else {
-                    return Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to get staker account from database".to_string()));
+                        check_claim_beneficiary_ata(&app_config, &rpc_client, &receiver_pubkey).await?;
+
+                        let mut writer = claims_queue.queue.write().await;
+                        writer.insert((staker_pubkey, Some(mint_pubkey)), ClaimsQueueItem{
+                            receiver_pubkey,
+                            amount,
+                            mint: Some(mint_pubkey),
+                        });
+                        drop(writer);
+                        let mut writer = claims_queue.claim_cooldown.write().await;
+                        writer.insert(staker_pubkey, ClaimCooldownItem {
+                            last_processed: Instant::now(),
+                        });
+                        drop(writer);
+                        return Ok((StatusCode::OK, "SUCCESS".to_string()));
+                    },
+                    Ok(None) => {
+                        return Err((StatusCode::BAD_REQUEST, "staker has no stake account for mint".to_string()));
+                    },
+                    Err(_) => {
+                        return Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to get staker account from database".to_string()));
+                    }
                 }
             } else {
                 return Err((StatusCode::UNAUTHORIZED, "Sig verification failed".to_string()));
@@ -2262,6 +4425,7 @@ async fn post_claim_stake_rewards_v3(
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
     Extension(claims_queue): Extension<Arc<ClaimsQueue>>,
+    Extension(app_config): Extension<Arc<Config>>,
 ) -> impl IntoResponse {
     let mint_pubkey = match Pubkey::from_str(&query_params.mint) {
         Ok(pk) => pk,
@@ -2289,8 +4453,7 @@ async fn post_claim_stake_rewards_v3(
         drop(reader);
 
         if let Some(item) =  claim_cd.get(&staker_pubkey) {
-            // seconds in a day 86_400
-            let cooldown_time = 86_400 * 2;
+            let cooldown_time = CLAIM_COOLDOWN_SECS;
             if item.last_processed.elapsed().as_secs() < cooldown_time {
                 return Response::builder()
                     .status(StatusCode::BAD_REQUEST)
@@ -2327,40 +4490,56 @@ async fn post_claim_stake_rewards_v3(
                 .unwrap();
         }
 
-        if let Ok(stake_account) = app_database
-            .get_staker_rewards(staker_pubkey.to_string(), mint_pubkey.to_string())
+        match app_database
+            .find_staker_rewards(app_config.pool_id, staker_pubkey.to_string(), mint_pubkey.to_string())
             .await
         {
-            if amount > stake_account.rewards_balance {
+            Ok(Some(stake_account)) => {
+                if amount > stake_account.rewards_balance {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body("claim amount exceeds staker rewards balance".to_string())
+                        .unwrap();
+                }
+
+                if let Err((status, body)) = check_claim_beneficiary_ata(&app_config, &rpc_client, &staker_pubkey).await {
+                    return Response::builder()
+                        .status(status)
+                        .body(body)
+                        .unwrap();
+                }
+
+                let mut writer = claims_queue.queue.write().await;
+                writer.insert((staker_pubkey, Some(mint_pubkey)), ClaimsQueueItem{
+                    receiver_pubkey: staker_pubkey,
+                    amount,
+                    mint: Some(mint_pubkey),
+                });
+                drop(writer);
+
+                let mut writer = claims_queue.claim_cooldown.write().await;
+                writer.insert(staker_pubkey, ClaimCooldownItem {
+                    last_processed: Instant::now(),
+                });
+                drop(writer);
+
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .body("SUCCESS".to_string())
+                    .unwrap();
+            },
+            Ok(None) => {
                 return Response::builder()
                     .status(StatusCode::BAD_REQUEST)
-                    .body("claim amount exceeds staker rewards balance".to_string())
+                    .body("staker has no stake account for mint".to_string())
+                    .unwrap();
+            },
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("failed to get staker account from database".to_string())
                     .unwrap();
             }
-
-            let mut writer = claims_queue.queue.write().await;
-            writer.insert((staker_pubkey, Some(mint_pubkey)), ClaimsQueueItem{
-                receiver_pubkey: staker_pubkey,
-                amount,
-                mint: Some(mint_pubkey),
-            });
-            drop(writer);
-
-            let mut writer = claims_queue.claim_cooldown.write().await;
-            writer.insert(staker_pubkey, ClaimCooldownItem {
-                last_processed: Instant::now(),
-            });
-            drop(writer);
-
-            return Response::builder()
-                .status(StatusCode::OK)
-                .body("SUCCESS".to_string())
-                .unwrap();
-        } else {
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body("failed to get staker account from database".to_string())
-                .unwrap();
         }
     } else {
         error!(target: "server_log", "Claim staker rewards with invalid pubkey");
@@ -2388,6 +4567,7 @@ async fn post_unstake(
     query_params: Query<UnstakeParams>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
     body: String,
 ) -> impl IntoResponse {
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
@@ -2413,7 +4593,7 @@ async fn post_unstake(
         }
 
         if let Err(_) =
-            get_delegated_stake_account(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey())
+            get_delegated_stake_account(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey())
                 .await
         {
             error!(target: "server_log", "Cannot unstake, no delegate stake account is created for {}", user_pubkey.to_string());
@@ -2425,11 +4605,17 @@ async fn post_unstake(
 
         let staker_ata = get_associated_token_address(&user_pubkey, &ore_api::consts::MINT_ADDRESS);
 
-        let base_ix = ore_miner_delegation::instruction::undelegate_stake(
-            user_pubkey,
+        let base_ix = retarget_ix(
+            ore_miner_delegation::instruction::undelegate_stake(
+                user_pubkey,
+                wallet.miner_wallet.pubkey(),
+                staker_ata,
+                query_params.amount,
+            ),
+            &app_config.program_ids,
             wallet.miner_wallet.pubkey(),
-            staker_ata,
-            query_params.amount,
+            Some(user_pubkey),
+            None,
         );
         let mut accts = Vec::new();
         for account_index in ixs[0].accounts.clone() {
@@ -2543,6 +4729,7 @@ async fn post_migrate_boost_v2(
     query_params: Query<MigrateBoostParams>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
     body: String,
 ) -> impl IntoResponse {
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
@@ -2571,13 +4758,19 @@ async fn post_migrate_boost_v2(
         };
 
         if query_params.init {
-            match get_delegated_boost_account_v2(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint).await {
+            match get_delegated_boost_account_v2(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint).await {
                 Ok(_) => {
                     return Response::builder()
                         .status(StatusCode::BAD_REQUEST)
                         .body("Account already initialized.".to_string())
                         .unwrap();
                 },
+                Err(DelegatedBoostError::UnknownBoostMint(_)) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body("Unknown boost mint".to_string())
+                        .unwrap();
+                },
                 Err(_) => {
                     // Account does not already exist
                     let ixs = tx.message.instructions.clone();
@@ -2592,11 +4785,17 @@ async fn post_migrate_boost_v2(
                     }
 
                     // First instruction is to init
-                    let server_ix = ore_miner_delegation::instruction::init_delegate_boost_v2(
-                        user_pubkey,
+                    let server_ix = retarget_ix(
+                        ore_miner_delegation::instruction::init_delegate_boost_v2(
+                            user_pubkey,
+                            wallet.miner_wallet.pubkey(),
+                            wallet.fee_wallet.pubkey(),
+                            mint,
+                        ),
+                        &app_config.program_ids,
                         wallet.miner_wallet.pubkey(),
-                        wallet.fee_wallet.pubkey(),
-                        mint,
+                        Some(user_pubkey),
+                        Some(mint),
                     );
                     let mut accts = Vec::new();
                     for account_index in ixs[0].accounts.clone() {
@@ -2612,10 +4811,16 @@ async fn post_migrate_boost_v2(
                     }
 
                     // Second instruction is to migrate to v2
-                    let server_ix = ore_miner_delegation::instruction::migrate_boost_to_v2(
-                        user_pubkey,
+                    let server_ix = retarget_ix(
+                        ore_miner_delegation::instruction::migrate_boost_to_v2(
+                            user_pubkey,
+                            wallet.miner_wallet.pubkey(),
+                            mint,
+                        ),
+                        &app_config.program_ids,
                         wallet.miner_wallet.pubkey(),
-                        mint,
+                        Some(user_pubkey),
+                        Some(mint),
                     );
                     let mut accts = Vec::new();
                     for account_index in ixs[1].accounts.clone() {
@@ -2670,7 +4875,7 @@ async fn post_migrate_boost_v2(
                 }
             }
         } else {
-            match get_delegated_boost_account_v2(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint).await {
+            match get_delegated_boost_account_v2(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint).await {
                 Ok(_) => {
                     // Account already exist
                     let ixs = tx.message.instructions.clone();
@@ -2684,10 +4889,16 @@ async fn post_migrate_boost_v2(
                             .unwrap();
                     }
 
-                    let base_ix = ore_miner_delegation::instruction::migrate_boost_to_v2(
-                        user_pubkey,
+                    let base_ix = retarget_ix(
+                        ore_miner_delegation::instruction::migrate_boost_to_v2(
+                            user_pubkey,
+                            wallet.miner_wallet.pubkey(),
+                            mint,
+                        ),
+                        &app_config.program_ids,
                         wallet.miner_wallet.pubkey(),
-                        mint,
+                        Some(user_pubkey),
+                        Some(mint),
                     );
                     let mut accts = Vec::new();
                     for account_index in ixs[0].accounts.clone() {
@@ -2741,6 +4952,12 @@ async fn post_migrate_boost_v2(
                         }
                     }
                 },
+                Err(DelegatedBoostError::UnknownBoostMint(_)) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body("Unknown boost mint".to_string())
+                        .unwrap();
+                },
                 Err(_) => {
                     return Response::builder()
                         .status(StatusCode::BAD_REQUEST)
@@ -2769,6 +4986,7 @@ async fn post_unstake_boost(
     query_params: Query<UnstakeBoostParams>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
     body: String,
 ) -> impl IntoResponse {
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
@@ -2808,7 +5026,7 @@ async fn post_unstake_boost(
         }
 
         if let Err(_) =
-            get_delegated_boost_account(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
+            get_delegated_boost_account(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
                 .await
         {
                 error!(target: "server_log", "unstake-boost error: invalid delegate boost account for user: {}", user_pubkey.to_string());
@@ -2818,11 +5036,17 @@ async fn post_unstake_boost(
                     .unwrap();
         }
 
-        let base_ix = ore_miner_delegation::instruction::undelegate_boost(
-            user_pubkey,
+        let base_ix = retarget_ix(
+            ore_miner_delegation::instruction::undelegate_boost(
+                user_pubkey,
+                wallet.miner_wallet.pubkey(),
+                mint,
+                query_params.amount,
+            ),
+            &app_config.program_ids,
             wallet.miner_wallet.pubkey(),
-            mint,
-            query_params.amount,
+            Some(user_pubkey),
+            Some(mint),
         );
         let mut accts = Vec::new();
         for account_index in ixs[0].accounts.clone() {
@@ -2890,6 +5114,7 @@ async fn post_unstake_boost_v2(
     query_params: Query<UnstakeBoostParams>,
     Extension(rpc_client): Extension<Arc<RpcClient>>,
     Extension(wallet): Extension<Arc<WalletExtension>>,
+    Extension(app_config): Extension<Arc<Config>>,
     body: String,
 ) -> impl IntoResponse {
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
@@ -2928,10 +5153,16 @@ async fn post_unstake_boost_v2(
                 .unwrap();
         }
 
-        if let Err(_) =
-            get_delegated_boost_account_v2(&rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
+        if let Err(e) =
+            get_delegated_boost_account_v2(&app_config.program_ids, &rpc_client, user_pubkey, wallet.miner_wallet.pubkey(), mint)
                 .await
         {
+                if let DelegatedBoostError::UnknownBoostMint(_) = e {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body("Unknown boost mint".to_string())
+                        .unwrap();
+                }
                 error!(target: "server_log", "unstake-boost error: invalid delegate boost account for user: {}", user_pubkey.to_string());
                 return Response::builder()
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -2939,11 +5170,17 @@ async fn post_unstake_boost_v2(
                     .unwrap();
         }
 
-        let base_ix = ore_miner_delegation::instruction::undelegate_boost_v2(
-            user_pubkey,
+        let base_ix = retarget_ix(
+            ore_miner_delegation::instruction::undelegate_boost_v2(
+                user_pubkey,
+                wallet.miner_wallet.pubkey(),
+                mint,
+                query_params.amount,
+            ),
+            &app_config.program_ids,
             wallet.miner_wallet.pubkey(),
-            mint,
-            query_params.amount,
+            Some(user_pubkey),
+            Some(mint),
         );
         let mut accts = Vec::new();
         for account_index in ixs[0].accounts.clone() {
@@ -3010,6 +5247,29 @@ async fn post_unstake_boost_v2(
 #[derive(Deserialize)]
 struct WsQueryParams {
     timestamp: u64,
+    #[serde(default)]
+    cores: Option<u32>,
+    #[serde(default)]
+    resume_token: Option<String>,
+    #[serde(default)]
+    miner_version: Option<String>,
+    #[serde(default)]
+    drillx_version: Option<String>,
+    #[serde(default)]
+    capabilities: Option<String>,
+}
+
+const MAX_REPORTED_CORES: u32 = 1024;
+
+fn clamp_reported_cores(cores: Option<u32>) -> u32 {
+    match cores {
+        Some(cores) if cores > MAX_REPORTED_CORES => {
+            info!(target: "server_log", "Clamping reported cores {} to {}", cores, MAX_REPORTED_CORES);
+            MAX_REPORTED_CORES
+        }
+        Some(cores) if cores > 0 => cores,
+        _ => 1,
+    }
 }
 
 async fn ws_handler(
@@ -3017,12 +5277,20 @@ async fn ws_handler(
     TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(app_state): State<Arc<RwLock<AppState>>>,
-    //Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_config): Extension<Arc<Config>>,
     Extension(client_channel): Extension<UnboundedSender<ClientMessage>>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(ready_clients): Extension<Arc<Mutex<HashSet<SocketAddr>>>>,
     query_params: Query<WsQueryParams>,
 ) -> impl IntoResponse {
     let msg_timestamp = query_params.timestamp;
+    let cores = clamp_reported_cores(query_params.cores);
+    let resume_token = query_params.resume_token.clone();
+    let capabilities = ClientCapabilities::from_handshake(
+        query_params.miner_version.as_deref(),
+        query_params.drillx_version.as_deref(),
+        query_params.capabilities.as_deref(),
+    );
 
     let pubkey = auth_header.username();
     let signed_msg = auth_header.password();
@@ -3078,18 +5346,28 @@ async fn ws_handler(
             let ts_msg = msg_timestamp.to_le_bytes();
 
             if signature.verify(&user_pubkey.to_bytes(), &ts_msg) {
-                info!(target: "server_log", "Client: {addr} connected with pubkey {pubkey}.");
-                return Ok(ws.on_upgrade(move |socket| {
-                    handle_socket(
-                        socket,
-                        addr,
-                        user_pubkey,
-                        miner.id,
-                        ClientVersion::V1,
-                        app_state,
-                        client_channel,
-                    )
-                }));
+                info!(target: "server_log", "Client: {addr} connected with pubkey {pubkey}. Miner version: {}, drillx version: {}", capabilities.miner_version, capabilities.drillx_version);
+                let max_message_size = app_config.max_ws_message_size;
+                let duplicate_connection_policy = app_config.duplicate_connection_policy;
+                return Ok(ws
+                    .max_message_size(max_message_size)
+                    .max_frame_size(max_message_size)
+                    .on_upgrade(move |socket| {
+                        handle_socket(
+                            socket,
+                            addr,
+                            user_pubkey,
+                            miner.id,
+                            ClientVersion::V1,
+                            cores,
+                            capabilities,
+                            resume_token,
+                            duplicate_connection_policy,
+                            app_state,
+                            client_channel,
+                            ready_clients,
+                        )
+                    }));
             } else {
                 return Err((StatusCode::UNAUTHORIZED, "Sig verification failed"));
             }
@@ -3101,17 +5379,82 @@ async fn ws_handler(
     }
 }
 
+#[derive(Deserialize)]
+struct ChallengeFeedParams {
+    token: Option<String>,
+}
+
+async fn ws_challenges_handler(
+    ws: WebSocketUpgrade,
+    query_params: Query<ChallengeFeedParams>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(challenge_feed): Extension<Arc<ChallengeFeed>>,
+) -> Result<impl IntoResponse, (StatusCode, &'static str)> {
+    if let Some(token) = &app_config.challenge_feed_token {
+        if query_params.token.as_ref() != Some(token) {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid token"));
+        }
+    }
+
+    let subscribers = challenge_feed.subscribers.fetch_add(1, Ordering::Relaxed);
+    let subscription = ChallengeFeedSubscription(challenge_feed.clone());
+    if subscribers >= app_config.challenge_feed_max_subscribers {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "Too many challenge feed subscribers"));
+    }
+
+    let receiver = challenge_feed.events.subscribe();
+    Ok(ws.on_upgrade(move |socket| handle_challenge_feed_socket(socket, receiver, subscription)))
+}
+
+async fn handle_challenge_feed_socket(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<ChallengeEvent>,
+    _subscription: ChallengeFeedSubscription,
+) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            msg = socket.recv() => {
+                match msg {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
 async fn ws_handler_v2(
     ws: WebSocketUpgrade,
     TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(app_state): State<Arc<RwLock<AppState>>>,
-    //Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_config): Extension<Arc<Config>>,
     Extension(client_channel): Extension<UnboundedSender<ClientMessage>>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(ready_clients): Extension<Arc<Mutex<HashSet<SocketAddr>>>>,
     query_params: Query<WsQueryParams>,
 ) -> impl IntoResponse {
     let msg_timestamp = query_params.timestamp;
+    let cores = clamp_reported_cores(query_params.cores);
+    let resume_token = query_params.resume_token.clone();
+    let capabilities = ClientCapabilities::from_handshake(
+        query_params.miner_version.as_deref(),
+        query_params.drillx_version.as_deref(),
+        query_params.capabilities.as_deref(),
+    );
 
     let pubkey = auth_header.username();
     let signed_msg = auth_header.password();
@@ -3167,18 +5510,28 @@ async fn ws_handler_v2(
             let ts_msg = msg_timestamp.to_le_bytes();
 
             if signature.verify(&user_pubkey.to_bytes(), &ts_msg) {
-                info!(target: "server_log", "Client: {addr} connected with pubkey {pubkey} on V2.");
-                return Ok(ws.on_upgrade(move |socket| {
-                    handle_socket(
-                        socket,
-                        addr,
-                        user_pubkey,
-                        miner.id,
-                        ClientVersion::V2,
-                        app_state,
-                        client_channel,
-                    )
-                }));
+                info!(target: "server_log", "Client: {addr} connected with pubkey {pubkey} on V2. Miner version: {}, drillx version: {}", capabilities.miner_version, capabilities.drillx_version);
+                let max_message_size = app_config.max_ws_message_size;
+                let duplicate_connection_policy = app_config.duplicate_connection_policy;
+                return Ok(ws
+                    .max_message_size(max_message_size)
+                    .max_frame_size(max_message_size)
+                    .on_upgrade(move |socket| {
+                        handle_socket(
+                            socket,
+                            addr,
+                            user_pubkey,
+                            miner.id,
+                            ClientVersion::V2,
+                            cores,
+                            capabilities,
+                            resume_token,
+                            duplicate_connection_policy,
+                            app_state,
+                            client_channel,
+                            ready_clients,
+                        )
+                    }));
             } else {
                 return Err((StatusCode::UNAUTHORIZED, "Sig verification failed"));
             }
@@ -3196,8 +5549,13 @@ async fn handle_socket(
     who_pubkey: Pubkey,
     who_miner_id: i32,
     client_version: ClientVersion,
+    cores: u32,
+    capabilities: ClientCapabilities,
+    resume_token: Option<String>,
+    duplicate_connection_policy: DuplicateConnectionPolicy,
     rw_app_state: Arc<RwLock<AppState>>,
     client_channel: UnboundedSender<ClientMessage>,
+    ready_clients: Arc<Mutex<HashSet<SocketAddr>>>,
 ) {
     if socket
         .send(axum::extract::ws::Message::Ping(vec![1, 2, 3].into()))
@@ -3212,46 +5570,214 @@ async fn handle_socket(
         return;
     }
 
-    let (sender, mut receiver) = socket.split();
+    let (mut sender, mut receiver) = socket.split();
+    let close_socket;
+    let valid_submissions;
+    let connected_at;
+    let resumed_ready;
+    let subscriptions;
+    let mut reward_receiver;
+    let reward_missed;
     let mut app_state = rw_app_state.write().await;
     if app_state.sockets.contains_key(&who) {
         info!(target: "server_log", "Socket addr: {who} already has an active connection");
         return;
     } else {
+        let oldest_existing = app_state
+            .sockets
+            .iter()
+            .filter(|(_, c)| c.pubkey == who_pubkey)
+            .min_by_key(|(_, c)| c.connected_at)
+            .map(|(addr, c)| (*addr, c.socket.clone()));
+        if let Some((existing_addr, existing_socket)) = oldest_existing {
+            match duplicate_connection_policy {
+                DuplicateConnectionPolicy::AllowMultiple => {}
+                DuplicateConnectionPolicy::RejectNew => {
+                    drop(app_state);
+                    info!(target: "server_log", "Rejecting {who}: {} already connected from {existing_addr}", who_pubkey.to_string());
+                    let _ = sender.send(Message::Close(Some(CloseFrame {
+                        code: close_code::POLICY,
+                        reason: "Pubkey already connected".into(),
+                    }))).await;
+                    return;
+                }
+                DuplicateConnectionPolicy::KickOldest => {
+                    info!(target: "server_log", "Kicking {existing_addr}: {} reconnected from {who}", who_pubkey.to_string());
+                    app_state.sockets.remove(&existing_addr);
+                    tokio::spawn(async move {
+                        let _ = existing_socket.lock().await.send(Message::Close(Some(CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "Replaced by a newer connection".into(),
+                        }))).await;
+                    });
+                }
+            }
+        }
+
+        let lingering = app_state.take_lingering_session(who_pubkey);
+        let resumed = resume_token
+            .as_deref()
+            .and_then(|token| app_state.take_retained_session(token, who_pubkey));
+        if resumed.is_some() {
+            info!(target: "server_log", "Client: {} resumed previous session.", who_pubkey.to_string());
+        } else if lingering.is_some() {
+            info!(target: "server_log", "Client: {} reconnected within the linger window.", who_pubkey.to_string());
+        }
+        let resumed = resumed.or(lingering);
+        resumed_ready = resumed.as_ref().map(|s| s.ready).unwrap_or(false);
+        connected_at = resumed.as_ref().map(|s| s.connected_at).unwrap_or_else(Instant::now);
+        valid_submissions = Arc::new(AtomicU64::new(resumed.map(|s| s.valid_submissions).unwrap_or(0)));
+        subscriptions = Arc::new(AtomicU8::new(capabilities.default_subscriptions()));
+        let (receiver, missed) = app_state.reward_router.subscribe(who_pubkey.to_string(), who);
+        reward_receiver = receiver;
+        reward_missed = missed;
         let new_app_client_connection = AppClientConnection {
             pubkey: who_pubkey,
             miner_id: who_miner_id,
             client_version,
+            cores,
+            connected_at,
+            valid_submissions: valid_submissions.clone(),
+            out_of_range_nonces: Arc::new(AtomicU64::new(0)),
+            subscriptions: subscriptions.clone(),
+            capabilities,
             socket: Arc::new(Mutex::new(sender)),
         };
+        close_socket = new_app_client_connection.socket.clone();
         app_state.sockets.insert(who, new_app_client_connection);
     }
     drop(app_state);
+    if resumed_ready {
+        ready_clients.lock().await.insert(who);
+    }
 
-    let _ = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
-            if process_message(msg, who, client_channel.clone()).is_break() {
+    let new_resume_token = uuid::Uuid::new_v4().to_string();
+    let session_message = serde_json::json!({
+        "type": "session",
+        "resume_token": new_resume_token,
+        "expires_in_secs": RESUME_TOKEN_TTL_SECS,
+    })
+    .to_string();
+    let _ = close_socket.lock().await.send(Message::Text(session_message.into())).await;
+
+    let reward_socket = close_socket.clone();
+    let reward_forwarder = tokio::spawn(async move {
+        while let Some(reward) = reward_receiver.recv().await {
+            let missed = reward_missed.swap(0, Ordering::Relaxed);
+            if subscriptions.load(Ordering::Relaxed) & CHANNEL_REWARDS == 0 {
+                continue;
+            }
+            if missed > 0 {
+                tracing::warn!(target: "server_log", "{} lagged {} reward notifications, sending resync hint", who, missed);
+                let resync = serde_json::json!({
+                    "type": "resync",
+                    "reason": "reward_notifications_lagged",
+                    "missed": missed,
+                });
+                if reward_socket.lock().await.send(Message::Text(resync.to_string().into())).await.is_err() {
+                    break;
+                }
+            }
+            let message = serde_json::json!({
+                "type": "staker_reward",
+                "challenge_id": reward.challenge_id,
+                "mint": reward.mint,
+                "amount": reward.amount,
+            });
+            if reward_socket.lock().await.send(Message::Text(message.to_string().into())).await.is_err() {
                 break;
             }
         }
+    });
+
+    let _ = tokio::spawn(async move {
+        let mut last_stats_request = None;
+        while let Some(res) = receiver.next().await {
+            match res {
+                Ok(msg) => {
+                    if process_message(msg, who, client_channel.clone(), &mut last_stats_request).is_break() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    if is_message_too_long(&e) {
+                        info!(target: "server_log", "Closing {} for exceeding max message size: {}", who, e);
+                        let _ = close_socket.lock().await.send(Message::Close(Some(CloseFrame {
+                            code: close_code::SIZE,
+                            reason: "Message too large".into(),
+                        }))).await;
+                    }
+                    break;
+                }
+            }
+        }
     })
     .await;
+    reward_forwarder.abort();
 
+    let ready = ready_clients.lock().await.remove(&who);
     let mut app_state = rw_app_state.write().await;
     app_state.sockets.remove(&who);
+    app_state.reward_router.unsubscribe(&who_pubkey.to_string(), &who);
+    let retained_at = Instant::now();
+    let session_valid_submissions = valid_submissions.load(Ordering::Relaxed);
+    app_state.retain_session(new_resume_token, RetainedSession {
+        pubkey: who_pubkey,
+        connected_at,
+        valid_submissions: session_valid_submissions,
+        ready,
+        retained_at,
+    });
+    if !app_state.sockets.values().any(|c| c.pubkey == who_pubkey) {
+        app_state.linger_session(RetainedSession {
+            pubkey: who_pubkey,
+            connected_at,
+            valid_submissions: session_valid_submissions,
+            ready,
+            retained_at,
+        });
+    }
     drop(app_state);
 
     info!(target: "server_log", "Client: {} disconnected!", who_pubkey.to_string());
 }
 
+/// Whether a websocket read failed because the client exceeded the configured message or frame size.
+fn is_message_too_long(e: &axum::Error) -> bool {
+    matches!(
+        std::error::Error::source(e).and_then(|inner| inner.downcast_ref::<tungstenite::Error>()),
+        Some(tungstenite::Error::Capacity(tungstenite::error::CapacityError::MessageTooLong { .. }))
+    )
+}
+
 fn process_message(
     msg: Message,
     who: SocketAddr,
     client_channel: UnboundedSender<ClientMessage>,
+    last_stats_request: &mut Option<Instant>,
 ) -> ControlFlow<(), ()> {
     match msg {
-        Message::Text(_t) => {
-            //println!(">>> {who} sent str: {t:?}");
+        Message::Text(t) => {
+            if let Ok(request) = serde_json::from_str::<ClientTextRequest>(t.as_str()) {
+                match request.r#type.as_str() {
+                    "get_stats" => {
+                        let rate_limited = last_stats_request
+                            .map(|at| at.elapsed() < Duration::from_secs(STATS_REQUEST_MIN_INTERVAL_SECS))
+                            .unwrap_or(false);
+                        if rate_limited {
+                            tracing::debug!(target: "server_log", "{} stats request rate limited", who);
+                        } else {
+                            *last_stats_request = Some(Instant::now());
+                            let _ = client_channel.send(ClientMessage::GetStats(who));
+                        }
+                    }
+                    "subscribe" => {
+                        let channels = parse_subscription_channels(&request.channels);
+                        let _ = client_channel.send(ClientMessage::Subscribe(who, channels));
+                    }
+                    _ => {}
+                }
+            }
         }
         Message::Binary(d) => {
             // first 8 bytes are message type
@@ -3389,15 +5915,16 @@ async fn ping_check_system(shared_state: &Arc<RwLock<AppState>>) {
 }
 
 async fn update_delegate_boost_stake_accounts(
+    ids: &ProgramIds,
     mining_pubkey: Pubkey,
     app_database: &Arc<AppDatabase>,
     rpc_client: &Arc<RpcClient>
 ) {
-    let managed_proof_authority_pda = managed_proof_pda(mining_pubkey);
+    let managed_proof_authority_pda = get_managed_proof_pda(ids, mining_pubkey);
     let program_accounts = match rpc_client.get_program_accounts_with_config(
-        &ore_miner_delegation::id(),
+        &ids.delegation,
         RpcProgramAccountsConfig {
-            filters: Some(vec![RpcFilterType::DataSize(56), RpcFilterType::Memcmp(Memcmp::new_raw_bytes(16, managed_proof_authority_pda.0.to_bytes().into()))]),
+            filters: Some(vec![RpcFilterType::DataSize(56), RpcFilterType::Memcmp(Memcmp::new_raw_bytes(16, managed_proof_authority_pda.to_bytes().into()))]),
             account_config: RpcAccountInfoConfig {
                 encoding: Some(UiAccountEncoding::Base64),
                 data_slice: None,
@@ -3502,5 +6029,246 @@ pub async fn confirm_txn(rpc_client: Arc<RpcClient>, sig: Signature) -> Result<S
     result
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reported_cores_are_clamped() {
+        assert_eq!(clamp_reported_cores(None), 1);
+        assert_eq!(clamp_reported_cores(Some(0)), 1);
+        assert_eq!(clamp_reported_cores(Some(16)), 16);
+        assert_eq!(clamp_reported_cores(Some(MAX_REPORTED_CORES + 1)), MAX_REPORTED_CORES);
+    }
+
+    #[test]
+    fn handshake_parses_known_capabilities_only() {
+        let caps = ClientCapabilities::from_handshake(Some("2.1.0"), Some("2.0.1"), Some(" boosts , compression,teleport"));
+        assert_eq!(caps.flags, CAPABILITY_BOOSTS | CAPABILITY_COMPRESSION);
+        assert_eq!(caps.miner_version, "2.1.0");
+        assert_eq!(caps.drillx_version, "2.0.1");
+
+        let none = ClientCapabilities::from_handshake(None, None, None);
+        assert_eq!(none.flags, 0);
+        assert_eq!(none.miner_version, "unknown");
+    }
+
+    #[test]
+    fn client_versions_are_sanitized_for_metrics_tags() {
+        assert_eq!(sanitize_client_version(Some("1.2.3-beta_4")), "1.2.3-beta_4");
+        assert_eq!(sanitize_client_version(Some("")), "unknown");
+        assert_eq!(sanitize_client_version(Some("1.0,host=x")), "unknown");
+        assert_eq!(sanitize_client_version(Some(&"1".repeat(MAX_CLIENT_VERSION_LEN + 1))), "unknown");
+        assert_eq!(sanitize_client_version(None), "unknown");
+    }
+
+    fn reward(staker_pubkey: &str) -> MessageInternalReward {
+        MessageInternalReward {
+            staker_pubkey: staker_pubkey.to_string(),
+            challenge_id: 1,
+            mint: ORE_BOOST_MINT.to_string(),
+            amount: 10,
+        }
+    }
+
+    fn epoch_hashes() -> EpochHashes {
+        EpochHashes {
+            challenge: [0; 32],
+            best_hash: BestHash {
+                solution: None,
+                difficulty: 0,
+            },
+            submissions: HashMap::new(),
+            superseded_submissions: HashMap::new(),
+            finalized: false,
+            previous: None,
+            challenge_feed: Arc::new(ChallengeFeed {
+                events: broadcast::channel(1).0,
+                subscribers: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    fn submission(nonce: u64) -> InternalMessageSubmission {
+        InternalMessageSubmission {
+            miner_id: 1,
+            supplied_diff: 10,
+            supplied_nonce: nonce,
+            supplied_digest: [0; 16],
+            hashpower: 1,
+        }
+    }
+
+    #[test]
+    fn superseded_buffer_flushes_early_at_the_cap() {
+        let mut epoch_hashes = epoch_hashes();
+        assert!(epoch_hashes.push_superseded(submission(1), 2).is_none());
+        let spilled = epoch_hashes.push_superseded(submission(2), 2).unwrap();
+        assert_eq!(spilled.len(), 2);
+        assert!(epoch_hashes.take_superseded_submissions([0; 32]).is_empty());
+    }
+
+    #[test]
+    fn shutdown_drains_buffered_submissions_of_both_epochs() {
+        let mut epoch_hashes = epoch_hashes();
+        assert!(epoch_hashes.push_superseded(submission(1), 10).is_none());
+        epoch_hashes.rotate([1; 32], 0, HashMap::new());
+        assert!(epoch_hashes.push_superseded(submission(2), 10).is_none());
+        assert!(epoch_hashes.push_superseded(submission(3), 10).is_none());
+
+        let drained = epoch_hashes.drain_superseded();
+        assert_eq!(drained.len(), 2);
+        assert_eq!(drained[0].0, [0; 32]);
+        assert_eq!(drained[0].1.len(), 1);
+        assert_eq!(drained[1].0, [1; 32]);
+        assert_eq!(drained[1].1.len(), 2);
+        assert!(epoch_hashes.drain_superseded().is_empty());
+    }
+
+    #[test]
+    fn superseded_buffer_stores_a_nonce_once() {
+        let mut epoch_hashes = epoch_hashes();
+        assert!(epoch_hashes.push_superseded(submission(1), 2).is_none());
+        assert!(epoch_hashes.push_superseded(submission(1), 2).is_none());
+        assert_eq!(epoch_hashes.take_superseded_submissions([0; 32]).len(), 1);
+    }
+
+    fn app_state(disconnect_linger: Duration) -> AppState {
+        AppState {
+            sockets: HashMap::new(),
+            paused: false,
+            retained_sessions: HashMap::new(),
+            lingering_sessions: HashMap::new(),
+            disconnect_linger,
+            reward_router: RewardRouter::new(4),
+        }
+    }
+
+    fn retained_session(pubkey: Pubkey) -> RetainedSession {
+        RetainedSession {
+            pubkey,
+            connected_at: Instant::now(),
+            valid_submissions: 7,
+            ready: true,
+            retained_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn reconnect_within_the_linger_window_keeps_the_session() {
+        let pubkey = Pubkey::new_unique();
+        let mut app_state = app_state(Duration::from_secs(60));
+        app_state.linger_session(retained_session(pubkey));
+        assert_eq!(app_state.lingering_count(), 1);
+        assert!(app_state.purge_lingering_sessions().is_empty());
+
+        let session = app_state.take_lingering_session(pubkey).unwrap();
+        assert_eq!(session.valid_submissions, 7);
+        assert!(session.ready);
+        assert_eq!(app_state.lingering_count(), 0);
+    }
+
+    #[test]
+    fn session_is_removed_after_the_linger_window() {
+        let pubkey = Pubkey::new_unique();
+        let mut app_state = app_state(Duration::from_millis(1));
+        app_state.linger_session(retained_session(pubkey));
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert_eq!(app_state.purge_lingering_sessions(), vec![pubkey]);
+        assert_eq!(app_state.lingering_count(), 0);
+        assert!(app_state.take_lingering_session(pubkey).is_none());
+    }
+
+    #[test]
+    fn oversize_reads_are_detected_by_error_type() {
+        let too_long = axum::Error::new(tungstenite::Error::Capacity(
+            tungstenite::error::CapacityError::MessageTooLong { size: 2048, max_size: 1024 },
+        ));
+        assert!(is_message_too_long(&too_long));
+
+        let closed = axum::Error::new(tungstenite::Error::ConnectionClosed);
+        assert!(!is_message_too_long(&closed));
+    }
+
+    #[test]
+    fn legacy_clients_keep_every_channel() {
+        let legacy = ClientCapabilities::from_handshake(Some("1.0.0"), None, Some("boosts"));
+        assert_eq!(legacy.default_subscriptions(), CHANNEL_ALL);
+    }
+
+    #[test]
+    fn subscribing_clients_start_with_work_only() {
+        let capable = ClientCapabilities::from_handshake(Some("2.0.0"), None, Some("boosts,subscriptions"));
+        let subscriptions = capable.default_subscriptions();
+        assert_eq!(subscriptions & CHANNEL_STATS, 0);
+        assert_eq!(subscriptions & CHANNEL_ANNOUNCEMENTS, 0);
+
+        let subscriptions = parse_subscription_channels(&["rewards".to_string(), "bogus".to_string()]);
+        assert_eq!(subscriptions, CHANNEL_WORK | CHANNEL_REWARDS);
+    }
+
+    #[test]
+    fn admin_tokens_map_each_token_to_its_name() {
+        let tokens = parse_admin_tokens("alice:t1, bob : t2,broken,:t3,carol:");
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens.get("t1").map(String::as_str), Some("alice"));
+        assert_eq!(tokens.get("t2").map(String::as_str), Some("bob"));
+        assert!(parse_admin_tokens("").is_empty());
+    }
+
+    #[test]
+    fn reward_router_only_delivers_to_the_staker() {
+        let mut router = RewardRouter::new(4);
+        let (mut alice, _) = router.subscribe("alice".to_string(), "127.0.0.1:1".parse().unwrap());
+        let (mut bob, _) = router.subscribe("bob".to_string(), "127.0.0.1:2".parse().unwrap());
+
+        router.route(&reward("alice"));
+
+        assert_eq!(alice.try_recv().unwrap().staker_pubkey, "alice");
+        assert!(bob.try_recv().is_err());
+    }
+
+    #[test]
+    fn reward_router_counts_rewards_dropped_on_a_full_queue() {
+        let mut router = RewardRouter::new(1);
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let (_receiver, missed) = router.subscribe("alice".to_string(), addr);
+
+        router.route(&reward("alice"));
+        router.route(&reward("alice"));
+        assert_eq!(missed.load(Ordering::Relaxed), 1);
 
+        router.unsubscribe("alice", &addr);
+        assert!(router.subscribers.is_empty());
+    }
+
+    const FRESH: Duration = Duration::from_secs(5);
+    const STALE: Duration = Duration::from_secs(120);
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn health_report_is_healthy_when_everything_is_fresh() {
+        let report = health_report(true, FRESH, Some(FRESH), FRESH, true, MAX_AGE);
+        assert!(report.healthy);
+        assert!(report.unhealthy.is_empty());
+    }
 
+    #[test]
+    fn health_report_names_each_culprit() {
+        assert_eq!(health_report(false, FRESH, Some(FRESH), FRESH, true, MAX_AGE).unhealthy, vec!["database"]);
+        assert_eq!(health_report(true, STALE, Some(FRESH), FRESH, true, MAX_AGE).unhealthy, vec!["latest_blockhash_cache"]);
+        assert_eq!(health_report(true, FRESH, Some(STALE), FRESH, true, MAX_AGE).unhealthy, vec!["rpc"]);
+        assert_eq!(health_report(true, FRESH, None, FRESH, true, MAX_AGE).unhealthy, vec!["rpc"]);
+        assert_eq!(health_report(true, FRESH, Some(FRESH), STALE, true, MAX_AGE).unhealthy, vec!["busses_cache"]);
+
+        let report = health_report(false, STALE, None, STALE, true, MAX_AGE);
+        assert!(!report.healthy);
+        assert_eq!(report.unhealthy, vec!["database", "latest_blockhash_cache", "rpc", "busses_cache"]);
+    }
+
+    #[test]
+    fn stale_busses_cache_is_ignored_without_stats() {
+        assert!(health_report(true, FRESH, Some(FRESH), STALE, false, MAX_AGE).healthy);
+    }
+}
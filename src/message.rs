@@ -3,6 +3,7 @@ pub struct ServerMessageStartMining {
     cutoff: i64,
     nonce_range_start: u64,
     nonce_range_end: u64,
+    challenge_end_at: i64,
 }
 
 impl ServerMessageStartMining {
@@ -11,12 +12,14 @@ impl ServerMessageStartMining {
         cutoff: i64,
         nonce_range_start: u64,
         nonce_range_end: u64,
+        challenge_end_at: i64,
     ) -> Self {
         ServerMessageStartMining {
             challenge,
             cutoff,
             nonce_range_start,
             nonce_range_end,
+            challenge_end_at,
         }
     }
 
@@ -27,6 +30,8 @@ impl ServerMessageStartMining {
         bin_data.extend_from_slice(&self.cutoff.to_le_bytes());
         bin_data.extend_from_slice(&self.nonce_range_start.to_le_bytes());
         bin_data.extend_from_slice(&self.nonce_range_end.to_le_bytes());
+        // appended after the original fields so older clients can ignore it
+        bin_data.extend_from_slice(&self.challenge_end_at.to_le_bytes());
 
         bin_data
     }
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamp, TinyInt, Unsigned};
 use serde::{Deserialize, Serialize};
@@ -42,6 +42,13 @@ pub struct UpdateChallengeRewards {
     pub rewards_earned: Option<u64>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::challenges)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct ChallengeRewardsDistributed {
+    pub rewards_distributed: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::claims)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -59,7 +66,14 @@ pub struct LastClaim {
     pub created_at: NaiveDateTime,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+impl LastClaim {
+    /// Seconds elapsed since the claim, treating `created_at` as UTC.
+    pub fn seconds_since(&self) -> i64 {
+        (chrono::Utc::now().naive_utc() - self.created_at).num_seconds()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Insertable, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::claims)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct InsertClaim {
@@ -78,7 +92,7 @@ pub struct Miner {
     pub enabled: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::pools)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct Pool {
@@ -130,6 +144,16 @@ pub struct InsertSubmission {
     pub difficulty: i8,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = crate::schema::earnings)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct InsertEarning {
+    pub miner_id: i32,
+    pub pool_id: i32,
+    pub challenge_id: i32,
+    pub amount: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::submissions_2)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -137,6 +161,95 @@ pub struct SubmissionWithId {
     pub id: i32,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::submission_aggregates)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct SubmissionAggregate {
+    pub miner_id: i32,
+    pub day: NaiveDate,
+    pub count: u32,
+    pub max_difficulty: i8,
+    pub sum_difficulty: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct EarningsBucket {
+    #[diesel(sql_type = Timestamp)]
+    pub bucket_start: NaiveDateTime,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub amount: u64,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct MaxSubmissionId {
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub id: Option<i32>,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct RowId {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct RowCount {
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct MinerSubmissionStats {
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+    #[diesel(sql_type = Nullable<TinyInt>)]
+    pub max_difficulty: Option<i8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct ClaimWithMiner {
+    #[diesel(sql_type = Text)]
+    pub pubkey: String,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub amount: u64,
+    #[diesel(sql_type = Text)]
+    pub signature: String,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct PoolUnpaidTotals {
+    #[diesel(sql_type = Integer)]
+    pub pool_id: i32,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub miner_unpaid: u64,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub staker_unpaid: u64,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct MinerRank {
+    #[diesel(sql_type = BigInt)]
+    pub miner_rank: i64,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct MinerDifficultyTotal {
+    #[diesel(sql_type = Text)]
+    pub pubkey: String,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub total_difficulty: u64,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct IdRange {
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub min_id: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub max_id: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::txns)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -180,6 +293,14 @@ pub struct UpdateReward {
     pub balance: u64,
 }
 
+#[derive(Debug, Clone, Copy, QueryableByName)]
+#[diesel(table_name = crate::schema::rewards)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct RewardCarryOver {
+    pub miner_id: i32,
+    pub carry_over: u64,
+}
+
 #[derive(Debug, Serialize, Clone, Copy, Deserialize, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::rewards)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -189,6 +310,18 @@ pub struct Reward {
     pub miner_id: i32,
 }
 
+#[derive(Debug, Clone, QueryableByName)]
+pub struct ClaimableMinerReward {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Integer)]
+    pub miner_id: i32,
+    #[diesel(sql_type = Text)]
+    pub pubkey: String,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub balance: u64,
+}
+
 #[derive(Debug, Clone, Deserialize, Insertable)]
 #[diesel(table_name = crate::schema::stake_accounts)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -226,5 +359,18 @@ pub struct UpdateStakeAccount {
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
 pub struct UpdateStakeAccountRewards {
     pub stake_pda: String,
+    pub mint_pubkey: String,
     pub rewards_balance: u64,
 }
+
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct StakerMintRewards {
+    #[diesel(sql_type = Text)]
+    pub mint_pubkey: String,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub staked_balance: u64,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub rewards_balance: u64,
+    #[diesel(sql_type = Unsigned<BigInt>)]
+    pub total_rewards_earned: u64,
+}
@@ -0,0 +1,95 @@
+use std::{
+    ops::Range,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const NONCE_RANGE_SIZE: u64 = 40_000_000;
+// clients reporting more cores than this get proportionally larger nonce ranges
+const CORES_PER_NONCE_RANGE: u32 = 32;
+// keeps a challenge's ranges far away from the end of the nonce space
+const MAX_NONCE_BASE: u64 = u64::MAX / 2;
+
+/// Source of the current unix timestamp used for challenge cutoffs.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+}
+
+/// Local clock, shifted by `offset_secs` to line up with chain time.
+pub struct SystemClock {
+    pub offset_secs: i64,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get time")
+            .as_secs() as i64
+            .saturating_add(self.offset_secs)
+    }
+}
+
+/// Hands out consecutive nonce ranges for the current challenge. Every challenge starts
+/// at a random base; passing a seed makes the sequence of bases reproducible.
+pub struct NonceAllocator {
+    rng: StdRng,
+    next: u64,
+}
+
+impl NonceAllocator {
+    pub fn new(seed: Option<u64>) -> Self {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let next = rng.gen_range(0..MAX_NONCE_BASE);
+        Self { rng, next }
+    }
+
+    /// Starts the nonce space over for a new challenge.
+    pub fn reset(&mut self) {
+        self.next = self.rng.gen_range(0..MAX_NONCE_BASE);
+    }
+
+    pub fn allocate(&mut self, cores: u32) -> Range<u64> {
+        let range_size = NONCE_RANGE_SIZE * cores.div_ceil(CORES_PER_NONCE_RANGE).max(1) as u64;
+        let start = self.next;
+        self.next += range_size;
+        // max hashes possible in 60s for a single client
+        start..start + range_size - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(seed: u64) -> Vec<Range<u64>> {
+        let mut allocator = NonceAllocator::new(Some(seed));
+        let mut ranges = Vec::new();
+        for _ in 0..3 {
+            for cores in [1, 32, 64, 8] {
+                ranges.push(allocator.allocate(cores));
+            }
+            allocator.reset();
+        }
+        ranges
+    }
+
+    #[test]
+    fn same_seed_gives_identical_allocations() {
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn ranges_within_a_challenge_do_not_overlap() {
+        let mut allocator = NonceAllocator::new(Some(1));
+        let a = allocator.allocate(16);
+        let b = allocator.allocate(64);
+        assert!(a.end < b.start);
+        assert_eq!(b.end - b.start + 1, 2 * NONCE_RANGE_SIZE);
+    }
+}
@@ -1,19 +1,144 @@
-use std::{str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+use std::{str::FromStr, time::Duration};
 
 use bytemuck::{Pod, Zeroable};
 use drillx::Solution;
 use ore_api::{
-    consts::{BUS_ADDRESSES, CONFIG_ADDRESS, MINT_ADDRESS, PROOF, TOKEN_DECIMALS}, state::{Config, Proof}, ID as ORE_ID
+    consts::{BUS, BUS_ADDRESSES, CONFIG, CONFIG_ADDRESS, MINT_ADDRESS, PROOF, TOKEN_DECIMALS, TREASURY, TREASURY_ADDRESS, TREASURY_TOKENS_ADDRESS}, state::{Bus, Config, Proof}, ID as ORE_ID
 };
 use ore_boost_api::state::{boost_pda, stake_pda};
-use ore_miner_delegation::{instruction, state::{DelegatedBoost, DelegatedBoostV2, DelegatedStake}, utils::AccountDeserializeV1, pda::managed_proof_pda};
+use ore_miner_delegation::{instruction, state::{DelegatedBoost, DelegatedBoostV2, DelegatedStake}, utils::AccountDeserializeV1};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::{account::ReadableAccount, instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::{account::ReadableAccount, commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey, signature::Signature, transaction::TransactionError};
+use solana_transaction_status::TransactionStatus;
 use spl_associated_token_account::get_associated_token_address;
 use steel::event;
 
+use crate::nonce_allocator::{Clock, SystemClock};
+
 pub const ORE_TOKEN_DECIMALS: u8 = TOKEN_DECIMALS;
 
+/// Converts a UI amount to base units, rejecting amounts with more precision than `decimals`.
+pub fn from_ui_amount(amount: f64, decimals: u8) -> Result<u64, String> {
+    if !amount.is_finite() || amount < 0.0 {
+        return Err(format!("invalid amount {}", amount));
+    }
+    let scaled = amount * 10f64.powi(decimals as i32);
+    if scaled >= u64::MAX as f64 {
+        return Err(format!("amount {} is too large", amount));
+    }
+    let base_units = scaled.round();
+    let tolerance = (scaled * f64::EPSILON * 4.0).max(1e-3);
+    if (scaled - base_units).abs() > tolerance {
+        return Err(format!("amount {} has more than {} decimal places", amount, decimals));
+    }
+    Ok(base_units as u64)
+}
+
+/// Program ids used for PDA derivation and instructions. Defaults to the compiled-in mainnet ids.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgramIds {
+    pub ore: Pubkey,
+    pub delegation: Pubkey,
+}
+
+impl Default for ProgramIds {
+    fn default() -> Self {
+        ProgramIds {
+            ore: ORE_ID,
+            delegation: ore_miner_delegation::id(),
+        }
+    }
+}
+
+impl ProgramIds {
+    fn is_default(&self) -> bool {
+        *self == ProgramIds::default()
+    }
+
+    fn ore_pda(&self, seeds: &[&[u8]]) -> Pubkey {
+        Pubkey::find_program_address(seeds, &self.ore).0
+    }
+
+    pub fn config_address(&self) -> Pubkey {
+        if self.is_default() {
+            return CONFIG_ADDRESS;
+        }
+        self.ore_pda(&[CONFIG])
+    }
+
+    pub fn bus_address(&self, bus: usize) -> Pubkey {
+        if self.is_default() {
+            return BUS_ADDRESSES[bus];
+        }
+        self.ore_pda(&[BUS, &[bus as u8]])
+    }
+
+    fn treasury_address(&self) -> Pubkey {
+        if self.is_default() {
+            return TREASURY_ADDRESS;
+        }
+        self.ore_pda(&[TREASURY])
+    }
+
+    fn treasury_tokens_address(&self) -> Pubkey {
+        if self.is_default() {
+            return TREASURY_TOKENS_ADDRESS;
+        }
+        get_associated_token_address(&self.treasury_address(), &MINT_ADDRESS)
+    }
+}
+
+/// Points an instruction built by ore-api or ore-miner-delegation, which always use the
+/// compiled-in program ids, at `ids`. Rewrites the program id and every account this crate
+/// derives for `miner`, plus the delegated stake of `staker` and its `mint` boosts when given.
+pub fn retarget_ix(mut ix: Instruction, ids: &ProgramIds, miner: Pubkey, staker: Option<Pubkey>, mint: Option<Pubkey>) -> Instruction {
+    if ids.is_default() {
+        return ix;
+    }
+    let defaults = ProgramIds::default();
+    let mut addresses = vec![
+        (defaults.ore, ids.ore),
+        (defaults.delegation, ids.delegation),
+        (get_managed_proof_pda(&defaults, miner), get_managed_proof_pda(ids, miner)),
+        (get_managed_proof_token_ata(&defaults, miner), get_managed_proof_token_ata(ids, miner)),
+        (get_proof_pda(&defaults, miner), get_proof_pda(ids, miner)),
+        (proof_pubkey(&defaults, miner), proof_pubkey(ids, miner)),
+        (defaults.config_address(), ids.config_address()),
+        (defaults.treasury_address(), ids.treasury_address()),
+        (defaults.treasury_tokens_address(), ids.treasury_tokens_address()),
+    ];
+    for bus in 0..BUS_ADDRESSES.len() {
+        addresses.push((defaults.bus_address(bus), ids.bus_address(bus)));
+    }
+    if let Some(staker) = staker {
+        let mints: Vec<Pubkey> = mint.into_iter().collect();
+        let from = derive_staker_pdas(&defaults, staker, miner, &mints).all();
+        let to = derive_staker_pdas(ids, staker, miner, &mints).all();
+        addresses.extend(from.into_iter().zip(to));
+    }
+
+    let retarget = |address: Pubkey| {
+        addresses
+            .iter()
+            .find(|(from, _)| *from == address)
+            .map(|(_, to)| *to)
+            .unwrap_or(address)
+    };
+    ix.program_id = retarget(ix.program_id);
+    for account in ix.accounts.iter_mut() {
+        account.pubkey = retarget(account.pubkey);
+    }
+    ix
+}
+
+pub fn get_managed_proof_pda(ids: &ProgramIds, miner: Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"managed-proof-account", miner.as_ref()],
+        &ids.delegation,
+    )
+    .0
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
 pub struct MineEventWithBoosts {
@@ -44,65 +169,202 @@ pub struct MineEventWithGlobalBoosts {
 
 event!(MineEventWithGlobalBoosts);
 
-pub fn get_auth_ix(signer: Pubkey) -> Instruction {
-    let proof = get_proof_pda(signer);
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum MineEventLayout {
+    Boosts,
+    GlobalBoosts,
+    Auto,
+}
 
-    ore_api::prelude::auth(proof)
+#[derive(Debug, Clone, Copy)]
+pub enum ParsedMineEvent {
+    Boosts(MineEventWithBoosts),
+    GlobalBoosts(MineEventWithGlobalBoosts),
 }
 
-pub fn get_mine_with_global_boost_ix(signer: Pubkey, solution: Solution, bus: usize) -> Instruction {
-    instruction::mine_with_boost(signer, BUS_ADDRESSES[bus], solution)
+impl ParsedMineEvent {
+    /// Rewards earned by the pool's miners, before commissions and staker splits.
+    pub fn miner_rewards(&self) -> Option<u64> {
+        match self {
+            ParsedMineEvent::Boosts(e) => Some(e.reward),
+            ParsedMineEvent::GlobalBoosts(e) => e.net_base_reward.checked_add(e.net_miner_boost_reward),
+        }
+    }
+
+    pub fn global_boosts_active(&self) -> bool {
+        matches!(self, ParsedMineEvent::GlobalBoosts(_))
+    }
 }
 
-pub fn get_rotate_ix(signer: Pubkey) -> Instruction {
-    instruction::rotate_global_boost(signer)
+/// Both layouts are the same size, so `Auto` treats the data as global boosts only when
+/// the net reward equals the sum of its parts.
+pub fn parse_mine_event(bytes: &[u8], layout: MineEventLayout) -> Option<ParsedMineEvent> {
+    let boosts = || bytemuck::try_from_bytes::<MineEventWithBoosts>(bytes).ok().map(|e| ParsedMineEvent::Boosts(*e));
+    let global_boosts = || bytemuck::try_from_bytes::<MineEventWithGlobalBoosts>(bytes).ok().copied();
+    match layout {
+        MineEventLayout::Boosts => boosts(),
+        MineEventLayout::GlobalBoosts => global_boosts().map(ParsedMineEvent::GlobalBoosts),
+        MineEventLayout::Auto => match global_boosts() {
+            Some(e) if e
+                .net_base_reward
+                .checked_add(e.net_miner_boost_reward)
+                .and_then(|r| r.checked_add(e.net_staker_boost_reward))
+                == Some(e.net_reward) =>
+            {
+                Some(ParsedMineEvent::GlobalBoosts(e))
+            }
+            _ => boosts(),
+        },
+    }
 }
 
-pub fn get_register_ix(signer: Pubkey) -> Instruction {
-    instruction::open_managed_proof(signer)
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ParsePolicy {
+    SkipAndLog,
+    FailFast,
+    SkipSilently,
 }
 
-pub fn get_reset_ix(signer: Pubkey) -> Instruction {
-    ore_api::prelude::reset(signer)
+impl ParsePolicy {
+    /// Called for each account in a batch that fails to parse. `Err` means the batch should be abandoned.
+    pub fn on_parse_failure(&self, label: &str) -> Result<(), ()> {
+        match self {
+            ParsePolicy::SkipAndLog => {
+                tracing::error!(target: "server_log", "Failed to parse {} account, skipping", label);
+                Ok(())
+            }
+            ParsePolicy::SkipSilently => Ok(()),
+            ParsePolicy::FailFast => {
+                tracing::error!(target: "server_log", "Failed to parse {} account, abandoning batch", label);
+                Err(())
+            }
+        }
+    }
+
+    /// Parses every account in a batch, applying the policy to the ones `parse` rejects.
+    /// Under `FailFast` the address of the first unparseable account is returned.
+    pub fn parse_batch<'a, T>(
+        &self,
+        accounts: impl IntoIterator<Item = (&'a Pubkey, &'a [u8])>,
+        parse: impl Fn(&[u8]) -> Option<T>,
+    ) -> Result<Vec<(Pubkey, T)>, Pubkey> {
+        let mut parsed = vec![];
+        for (address, data) in accounts {
+            match parse(data) {
+                Some(account) => parsed.push((*address, account)),
+                None => self.on_parse_failure(&address.to_string()).map_err(|_| *address)?,
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum BusSelectionPolicy {
+    MaxCurrent,
+    MaxTheoretical,
+    RoundRobin,
+}
+
+pub fn best_bus(busses: &[Result<Bus, ()>]) -> Option<usize> {
+    max_bus_by(busses, |bus| bus.rewards)
+}
+
+pub fn select_bus(
+    busses: &[Result<Bus, ()>],
+    policy: BusSelectionPolicy,
+    last_bus: &mut usize,
+) -> Option<usize> {
+    let selected = match policy {
+        BusSelectionPolicy::MaxCurrent => best_bus(busses),
+        BusSelectionPolicy::MaxTheoretical => max_bus_by(busses, |bus| bus.theoretical_rewards),
+        BusSelectionPolicy::RoundRobin => (1..=busses.len())
+            .map(|offset| (*last_bus + offset) % busses.len())
+            .find(|i| busses[*i].is_ok()),
+    };
+    if let Some(i) = selected {
+        *last_bus = i;
+    }
+    selected
+}
+
+fn max_bus_by(busses: &[Result<Bus, ()>], rewards: impl Fn(&Bus) -> u64) -> Option<usize> {
+    let mut best = None;
+    let mut best_rewards = 0;
+    for (i, bus) in busses.iter().enumerate() {
+        if let Ok(bus) = bus {
+            if best.is_none() || rewards(bus) > best_rewards {
+                best = Some(i);
+                best_rewards = rewards(bus);
+            }
+        }
+    }
+    best
+}
+
+pub fn get_auth_ix(ids: &ProgramIds, signer: Pubkey) -> Instruction {
+    let proof = get_proof_pda(ids, signer);
+
+    retarget_ix(ore_api::prelude::auth(proof), ids, signer, None, None)
+}
+
+pub fn get_mine_with_global_boost_ix(ids: &ProgramIds, signer: Pubkey, solution: Solution, bus: usize) -> Instruction {
+    retarget_ix(instruction::mine_with_boost(signer, BUS_ADDRESSES[bus], solution), ids, signer, None, None)
 }
 
-pub fn get_claim_ix(signer: Pubkey, beneficiary: Pubkey, claim_amount: u64) -> Instruction {
-    instruction::undelegate_stake(signer, signer, beneficiary, claim_amount)
+pub fn get_mine_ix(ids: &ProgramIds, signer: Pubkey, solution: Solution, bus: usize) -> Instruction {
+    retarget_ix(instruction::mine(signer, BUS_ADDRESSES[bus], solution), ids, signer, None, None)
 }
 
-pub fn get_stake_ix(signer: Pubkey, sender: Pubkey, stake_amount: u64) -> Instruction {
-    instruction::delegate_stake(sender, signer, stake_amount)
+pub fn get_rotate_ix(ids: &ProgramIds, signer: Pubkey) -> Instruction {
+    retarget_ix(instruction::rotate_global_boost(signer), ids, signer, None, None)
+}
+
+pub fn get_register_ix(ids: &ProgramIds, signer: Pubkey) -> Instruction {
+    retarget_ix(instruction::open_managed_proof(signer), ids, signer, None, None)
+}
+
+pub fn get_reset_ix(ids: &ProgramIds, signer: Pubkey) -> Instruction {
+    retarget_ix(ore_api::prelude::reset(signer), ids, signer, None, None)
+}
+
+pub fn get_claim_ix(ids: &ProgramIds, signer: Pubkey, beneficiary: Pubkey, claim_amount: u64) -> Instruction {
+    retarget_ix(instruction::undelegate_stake(signer, signer, beneficiary, claim_amount), ids, signer, Some(signer), None)
+}
+
+/// Claims from the signer's own ORE proof rather than the managed proof.
+pub fn get_original_claim_ix(ids: &ProgramIds, signer: Pubkey, beneficiary: Pubkey, claim_amount: u64) -> Instruction {
+    retarget_ix(ore_api::prelude::claim(signer, beneficiary, claim_amount), ids, signer, None, None)
+}
+
+pub fn get_stake_ix(ids: &ProgramIds, signer: Pubkey, sender: Pubkey, stake_amount: u64) -> Instruction {
+    retarget_ix(instruction::delegate_stake(sender, signer, stake_amount), ids, signer, Some(sender), None)
 }
 
 pub fn get_ore_mint() -> Pubkey {
     MINT_ADDRESS
 }
 
-pub fn get_managed_proof_token_ata(miner: Pubkey) -> Pubkey {
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", miner.as_ref()],
-        &ore_miner_delegation::id(),
-    );
+pub fn get_managed_proof_token_ata(ids: &ProgramIds, miner: Pubkey) -> Pubkey {
+    let managed_proof = get_managed_proof_pda(ids, miner);
 
-    get_associated_token_address(&managed_proof.0, &ore_api::consts::MINT_ADDRESS)
+    get_associated_token_address(&managed_proof, &ore_api::consts::MINT_ADDRESS)
 }
 
-pub fn get_proof_pda(miner: Pubkey) -> Pubkey {
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", miner.as_ref()],
-        &ore_miner_delegation::id(),
-    );
+pub fn get_proof_pda(ids: &ProgramIds, miner: Pubkey) -> Pubkey {
+    let managed_proof = get_managed_proof_pda(ids, miner);
 
-    proof_pubkey(managed_proof.0)
+    proof_pubkey(ids, managed_proof)
 }
 
 pub async fn get_delegated_stake_account(
+    ids: &ProgramIds,
     client: &RpcClient,
     staker: Pubkey,
     miner: Pubkey,
 ) -> Result<ore_miner_delegation::state::DelegatedStake, String> {
     let data = client
-        .get_account_data(&get_delegated_stake_pda(staker, miner))
+        .get_account_data(&get_delegated_stake_pda(ids, staker, miner))
         .await;
     match data {
         Ok(data) => {
@@ -118,13 +380,14 @@ pub async fn get_delegated_stake_account(
 }
 
 pub async fn get_delegated_boost_account(
+    ids: &ProgramIds,
     client: &RpcClient,
     staker: Pubkey,
     miner: Pubkey,
     mint: Pubkey,
 ) -> Result<ore_miner_delegation::state::DelegatedBoost, String> {
     let data = client
-        .get_account_data(&get_delegated_boost_pda(staker, miner, mint))
+        .get_account_data(&get_delegated_boost_pda(ids, staker, miner, mint))
         .await;
     match data {
         Ok(data) => {
@@ -139,14 +402,46 @@ pub async fn get_delegated_boost_account(
     }
 }
 
+pub fn boost_mints() -> Vec<Pubkey> {
+    [crate::ORE_BOOST_MINT, crate::ORE_SOL_BOOST_MINT, crate::ORE_ISC_BOOST_MINT]
+        .iter()
+        .map(|mint| Pubkey::from_str(mint).unwrap())
+        .collect()
+}
+
+pub fn is_known_boost_mint(mint: &Pubkey) -> bool {
+    boost_mints().contains(mint)
+}
+
+#[derive(Debug)]
+pub enum DelegatedBoostError {
+    UnknownBoostMint(Pubkey),
+    AccountNotFound,
+    ParseFailed,
+}
+
+impl std::fmt::Display for DelegatedBoostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DelegatedBoostError::UnknownBoostMint(mint) => write!(f, "{} is not a known boost mint", mint),
+            DelegatedBoostError::AccountNotFound => write!(f, "Failed to get delegated boost v2 account"),
+            DelegatedBoostError::ParseFailed => write!(f, "Failed to parse delegated boost v2 account"),
+        }
+    }
+}
+
 pub async fn get_delegated_boost_account_v2(
+    ids: &ProgramIds,
     client: &RpcClient,
     staker: Pubkey,
     miner: Pubkey,
     mint: Pubkey,
-) -> Result<ore_miner_delegation::state::DelegatedBoostV2, String> {
+) -> Result<ore_miner_delegation::state::DelegatedBoostV2, DelegatedBoostError> {
+    if !is_known_boost_mint(&mint) {
+        return Err(DelegatedBoostError::UnknownBoostMint(mint));
+    }
     let data = client
-        .get_account_data(&get_delegated_boost_v2_pda(staker, miner, mint))
+        .get_account_data(&get_delegated_boost_v2_pda(ids, staker, miner, mint))
         .await;
     match data {
         Ok(data) => {
@@ -154,87 +449,237 @@ pub async fn get_delegated_boost_account_v2(
             if let Ok(delegated_boost) = delegated_boost {
                 return Ok(*delegated_boost);
             } else {
-                return Err("Failed to parse delegated boost v2 account".to_string());
+                return Err(DelegatedBoostError::ParseFailed);
             }
         }
-        Err(_) => return Err("Failed to get delegated boost v2 account".to_string()),
+        Err(_) => return Err(DelegatedBoostError::AccountNotFound),
     }
 }
 
-pub fn get_delegated_stake_pda(staker: Pubkey, miner: Pubkey) -> Pubkey {
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", miner.as_ref()],
-        &ore_miner_delegation::id(),
-    );
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq)]
+pub enum ConfirmOutcome {
+    Confirmed,
+    Failed(TransactionError),
+    /// The blockhash expired without the transaction landing, so it is safe to rebuild and resubmit.
+    Expired,
+}
+
+#[derive(Debug)]
+pub enum OreUtilError {
+    Rpc(String),
+    TimedOut,
+}
+
+impl std::fmt::Display for OreUtilError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OreUtilError::Rpc(e) => write!(f, "RPC error: {}", e),
+            OreUtilError::TimedOut => write!(f, "Timed out waiting for confirmation"),
+        }
+    }
+}
+
+/// Polls the signature until it reaches `commitment`, its blockhash expires, or `timeout`
+/// elapses. A timeout means the outcome is still unknown and the transaction may yet land.
+pub async fn confirm_with_timeout(
+    client: &RpcClient,
+    signature: &Signature,
+    commitment: CommitmentConfig,
+    timeout: Duration,
+    last_valid_block_height: u64,
+) -> Result<ConfirmOutcome, OreUtilError> {
+    let started = tokio::time::Instant::now();
+    loop {
+        let mut last_error = None;
+        match client.get_signature_statuses(&[*signature]).await {
+            Ok(response) => match response.value.into_iter().next().flatten() {
+                Some(status) => {
+                    if let Some(outcome) = poll_outcome(Some(&status), commitment, None, last_valid_block_height) {
+                        return Ok(outcome);
+                    }
+                }
+                None => match client.get_block_height_with_commitment(commitment).await {
+                    Ok(block_height) => {
+                        if let Some(outcome) = poll_outcome(None, commitment, Some(block_height), last_valid_block_height) {
+                            return Ok(outcome);
+                        }
+                    }
+                    Err(e) => last_error = Some(e.to_string()),
+                },
+            },
+            Err(e) => last_error = Some(e.to_string()),
+        }
+
+        let elapsed = started.elapsed();
+        if elapsed >= timeout {
+            return Err(match last_error {
+                Some(e) => OreUtilError::Rpc(e),
+                None => OreUtilError::TimedOut,
+            });
+        }
+        tokio::time::sleep(CONFIRM_POLL_INTERVAL.min(timeout - elapsed)).await;
+    }
+}
+
+/// Outcome of one confirmation poll, from the signature status if there is one and
+/// otherwise from the current block height. `None` means keep polling.
+fn poll_outcome(
+    status: Option<&TransactionStatus>,
+    commitment: CommitmentConfig,
+    block_height: Option<u64>,
+    last_valid_block_height: u64,
+) -> Option<ConfirmOutcome> {
+    match status {
+        Some(status) if status.satisfies_commitment(commitment) => Some(match &status.err {
+            Some(e) => ConfirmOutcome::Failed(e.clone()),
+            None => ConfirmOutcome::Confirmed,
+        }),
+        Some(_) => None,
+        None => match block_height {
+            Some(block_height) if block_height > last_valid_block_height => Some(ConfirmOutcome::Expired),
+            _ => None,
+        },
+    }
+}
+
+pub fn get_delegated_stake_pda(ids: &ProgramIds, staker: Pubkey, miner: Pubkey) -> Pubkey {
+    let managed_proof = get_managed_proof_pda(ids, miner);
 
     Pubkey::find_program_address(
         &[
             b"delegated-stake",
             staker.as_ref(),
-            managed_proof.0.as_ref(),
+            managed_proof.as_ref(),
         ],
-        &ore_miner_delegation::id(),
+        &ids.delegation,
     )
     .0
 }
 
-pub fn get_delegated_boost_pda(staker: Pubkey, miner: Pubkey, mint: Pubkey) -> Pubkey {
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", miner.as_ref()],
-        &ore_miner_delegation::id(),
-    );
+pub fn get_delegated_boost_pda(ids: &ProgramIds, staker: Pubkey, miner: Pubkey, mint: Pubkey) -> Pubkey {
+    let managed_proof = get_managed_proof_pda(ids, miner);
 
     Pubkey::find_program_address(
         &[
             ore_miner_delegation::consts::DELEGATED_BOOST,
             staker.as_ref(),
             mint.as_ref(),
-            managed_proof.0.as_ref(),
+            managed_proof.as_ref(),
         ],
-        &ore_miner_delegation::id(),
+        &ids.delegation,
     )
     .0
 }
 
-pub fn get_delegated_boost_v2_pda(staker: Pubkey, miner: Pubkey, mint: Pubkey) -> Pubkey {
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", miner.as_ref()],
-        &ore_miner_delegation::id(),
-    );
+pub fn get_delegated_boost_v2_pda(ids: &ProgramIds, staker: Pubkey, miner: Pubkey, mint: Pubkey) -> Pubkey {
+    let managed_proof = get_managed_proof_pda(ids, miner);
 
     Pubkey::find_program_address(
         &[
             ore_miner_delegation::consts::DELEGATED_BOOST_V2,
             staker.as_ref(),
             mint.as_ref(),
-            managed_proof.0.as_ref(),
+            managed_proof.as_ref(),
         ],
-        &ore_miner_delegation::id(),
+        &ids.delegation,
     )
     .0
 }
 
+#[derive(Debug, Clone)]
+pub struct PoolPda {
+    pub label: String,
+    pub address: Pubkey,
+    pub exists: bool,
+}
+
+/// Everything the pool needs on chain for `authority`, proof first.
+pub fn derive_pool_pdas(ids: &ProgramIds, authority: Pubkey) -> Vec<(String, Pubkey)> {
+    let managed_proof = get_managed_proof_pda(ids, authority);
+    let mut pdas = vec![
+        ("proof".to_string(), get_proof_pda(ids, authority)),
+        ("managed proof".to_string(), managed_proof),
+        ("managed proof ata".to_string(), get_managed_proof_token_ata(ids, authority)),
+    ];
+    for mint in boost_mints() {
+        let boost = boost_pda(mint).0;
+        pdas.push((format!("boost stake {}", mint), stake_pda(managed_proof, boost).0));
+    }
+    pdas
+}
+
+pub async fn verify_pool_pdas(ids: &ProgramIds, client: &RpcClient, authority: Pubkey) -> Result<Vec<PoolPda>, String> {
+    let pdas = derive_pool_pdas(ids, authority);
+    let addresses: Vec<Pubkey> = pdas.iter().map(|(_, address)| *address).collect();
+    let accounts = client
+        .get_multiple_accounts(&addresses)
+        .await
+        .map_err(|e| format!("Failed to fetch pool accounts: {:?}", e))?;
+    Ok(pdas
+        .into_iter()
+        .zip(accounts)
+        .map(|((label, address), account)| PoolPda {
+            label,
+            address,
+            exists: account.is_some(),
+        })
+        .collect())
+}
 
+pub struct StakerBoostPdas {
+    pub mint: Pubkey,
+    pub boost: Pubkey,
+    pub boost_v2: Pubkey,
+}
 
+pub struct StakerPdas {
+    pub stake: Pubkey,
+    pub boosts: Vec<StakerBoostPdas>,
+}
+
+impl StakerPdas {
+    /// Stake PDA followed by each mint's V1 and V2 boost PDAs, in `get_multiple_accounts` order.
+    pub fn all(&self) -> Vec<Pubkey> {
+        let mut pdas = Vec::with_capacity(1 + self.boosts.len() * 2);
+        pdas.push(self.stake);
+        for b in self.boosts.iter() {
+            pdas.push(b.boost);
+            pdas.push(b.boost_v2);
+        }
+        pdas
+    }
+}
+
+pub fn derive_staker_pdas(ids: &ProgramIds, staker: Pubkey, miner: Pubkey, mints: &[Pubkey]) -> StakerPdas {
+    StakerPdas {
+        stake: get_delegated_stake_pda(ids, staker, miner),
+        boosts: mints
+            .iter()
+            .map(|mint| StakerBoostPdas {
+                mint: *mint,
+                boost: get_delegated_boost_pda(ids, staker, miner, *mint),
+                boost_v2: get_delegated_boost_v2_pda(ids, staker, miner, *mint),
+            })
+            .collect(),
+    }
+}
 
-pub async fn get_pool_boost_stake(rpc_client: &RpcClient, authority: Pubkey) -> Vec<ore_boost_api::state::Stake> {
-    let managed_proof = Pubkey::find_program_address(
-        &[b"managed-proof-account", authority.as_ref()],
-        &ore_miner_delegation::id(),
-    );
 
-    let boost_mints = vec![
-        Pubkey::from_str("oreoU2P8bN6jkk3jbaiVxYnG1dCXcYxwhwyK9jSybcp").unwrap(),
-        Pubkey::from_str("DrSS5RM7zUd9qjUEdDaf31vnDUSbCrMto6mjqTrHFifN").unwrap(),
-        Pubkey::from_str("meUwDp23AaxhiNKaQCyJ2EAF2T4oe1gSkEkGXSRVdZb").unwrap()
-    ];
+
+
+pub async fn get_pool_boost_stake(ids: &ProgramIds, rpc_client: &RpcClient, authority: Pubkey) -> Vec<ore_boost_api::state::Stake> {
+    let managed_proof = get_managed_proof_pda(ids, authority);
+
+    let boost_mints = boost_mints();
 
     // Get pools boost stake accounts
     let mut boost_stake_acct_pdas = vec![];
 
     for boost_mint in boost_mints {
         let boost_account_pda = boost_pda(boost_mint);
-        let boost_stake_pda = stake_pda(managed_proof.0, boost_account_pda.0);
+        let boost_stake_pda = stake_pda(managed_proof, boost_account_pda.0);
         boost_stake_acct_pdas.push(boost_stake_pda.0);
     }
 
@@ -250,18 +695,256 @@ pub async fn get_pool_boost_stake(rpc_client: &RpcClient, authority: Pubkey) ->
 }
 
 
-pub fn proof_pubkey(authority: Pubkey) -> Pubkey {
-    Pubkey::find_program_address(&[PROOF, authority.as_ref()], &ORE_ID).0
+pub fn proof_pubkey(ids: &ProgramIds, authority: Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[PROOF, authority.as_ref()], &ids.ore).0
 }
 
-pub fn get_cutoff(proof: Proof, buffer_time: u64) -> i64 {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Failed to get time")
-        .as_secs() as i64;
-    proof
-        .last_hash_at
-        .saturating_add(60)
+/// `clock_offset_secs` is added to the local clock so cutoffs line up with chain time.
+pub fn get_cutoff(proof: Proof, buffer_time: u64, clock_offset_secs: i64) -> i64 {
+    get_cutoff_at(proof, buffer_time, SystemClock { offset_secs: clock_offset_secs }.now())
+}
+
+/// Same as `get_cutoff`, but against a caller supplied unix timestamp instead of the system clock.
+pub fn get_cutoff_at(proof: Proof, buffer_time: u64, now: i64) -> i64 {
+    get_challenge_end_at(proof)
         .saturating_sub(buffer_time as i64)
         .saturating_sub(now)
 }
+
+/// Unix timestamp at which the current challenge's epoch ends.
+pub fn get_challenge_end_at(proof: Proof) -> i64 {
+    proof.last_hash_at.saturating_add(60)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WorkCutoff {
+    pub challenge_end_at: i64,
+    pub cutoff: i64,
+}
+
+pub fn get_work_cutoff_at(proof: Proof, buffer_time: u64, now: i64) -> WorkCutoff {
+    WorkCutoff {
+        challenge_end_at: get_challenge_end_at(proof),
+        cutoff: get_cutoff_at(proof, buffer_time, now),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_transaction_status::TransactionConfirmationStatus;
+
+    use super::*;
+
+    fn bus(rewards: u64, theoretical_rewards: u64) -> Result<Bus, ()> {
+        let mut bus = Bus::zeroed();
+        bus.rewards = rewards;
+        bus.theoretical_rewards = theoretical_rewards;
+        Ok(bus)
+    }
+
+    #[test]
+    fn bus_selection_follows_the_policy() {
+        let busses = vec![bus(10, 50), Err(()), bus(30, 20), bus(20, 40)];
+        let mut last_bus = 0;
+        assert_eq!(select_bus(&busses, BusSelectionPolicy::MaxCurrent, &mut last_bus), Some(2));
+        assert_eq!(select_bus(&busses, BusSelectionPolicy::MaxTheoretical, &mut last_bus), Some(0));
+        assert_eq!(last_bus, 0);
+    }
+
+    #[test]
+    fn round_robin_skips_failed_busses_and_wraps() {
+        let busses = vec![bus(10, 50), Err(()), bus(30, 20)];
+        let mut last_bus = 0;
+        assert_eq!(select_bus(&busses, BusSelectionPolicy::RoundRobin, &mut last_bus), Some(2));
+        assert_eq!(select_bus(&busses, BusSelectionPolicy::RoundRobin, &mut last_bus), Some(0));
+        assert_eq!(select_bus(&[Err(()), Err(())], BusSelectionPolicy::RoundRobin, &mut last_bus), None);
+        assert_eq!(last_bus, 0);
+    }
+
+    #[test]
+    fn ui_amounts_convert_to_base_units() {
+        assert_eq!(from_ui_amount(1.5, 11), Ok(150_000_000_000));
+        assert_eq!(from_ui_amount(0.0, 11), Ok(0));
+        assert_eq!(from_ui_amount(0.00000000001, 11), Ok(1));
+    }
+
+    #[test]
+    fn ui_amounts_with_too_much_precision_are_rejected() {
+        assert!(from_ui_amount(0.000000000001, 11).is_err());
+        assert!(from_ui_amount(1.25, 1).is_err());
+    }
+
+    #[test]
+    fn invalid_ui_amounts_are_rejected() {
+        assert!(from_ui_amount(-1.0, 11).is_err());
+        assert!(from_ui_amount(f64::NAN, 11).is_err());
+        assert!(from_ui_amount(f64::INFINITY, 11).is_err());
+        assert!(from_ui_amount(1e12, 11).is_err());
+    }
+
+    fn global_boosts_event(net_base_reward: u64, net_miner_boost_reward: u64, net_staker_boost_reward: u64, net_reward: u64) -> MineEventWithGlobalBoosts {
+        MineEventWithGlobalBoosts {
+            balance: 1,
+            difficulty: 20,
+            last_hash_at: 0,
+            timing: 0,
+            net_reward,
+            net_base_reward,
+            net_miner_boost_reward,
+            net_staker_boost_reward,
+        }
+    }
+
+    #[test]
+    fn auto_layout_detects_global_boosts_from_the_reward_sum() {
+        let event = global_boosts_event(100, 20, 5, 125);
+        let event = parse_mine_event(bytemuck::bytes_of(&event), MineEventLayout::Auto).unwrap();
+        assert!(event.global_boosts_active());
+        assert_eq!(event.miner_rewards(), Some(120));
+    }
+
+    #[test]
+    fn auto_layout_falls_back_to_boosts() {
+        let event = MineEventWithBoosts {
+            balance: 1,
+            difficulty: 20,
+            last_hash_at: 0,
+            timing: 0,
+            reward: 300,
+            boost_1: 1,
+            boost_2: 2,
+            boost_3: 3,
+        };
+        let event = parse_mine_event(bytemuck::bytes_of(&event), MineEventLayout::Auto).unwrap();
+        assert!(!event.global_boosts_active());
+        assert_eq!(event.miner_rewards(), Some(300));
+    }
+
+    #[test]
+    fn explicit_layouts_are_not_second_guessed() {
+        let event = global_boosts_event(100, 20, 5, 0);
+        let bytes = bytemuck::bytes_of(&event);
+        assert!(parse_mine_event(bytes, MineEventLayout::GlobalBoosts).unwrap().global_boosts_active());
+        assert!(!parse_mine_event(bytes, MineEventLayout::Boosts).unwrap().global_boosts_active());
+        assert!(parse_mine_event(&bytes[..bytes.len() - 8], MineEventLayout::Boosts).is_none());
+    }
+
+    fn status(confirmation_status: TransactionConfirmationStatus, err: Option<TransactionError>) -> TransactionStatus {
+        TransactionStatus {
+            slot: 1,
+            confirmations: None,
+            status: match &err {
+                Some(e) => Err(e.clone()),
+                None => Ok(()),
+            },
+            err,
+            confirmation_status: Some(confirmation_status),
+        }
+    }
+
+    #[test]
+    fn landed_transactions_map_to_confirmed_or_failed() {
+        let finalized = CommitmentConfig::finalized();
+        let ok = status(TransactionConfirmationStatus::Finalized, None);
+        let failed = status(TransactionConfirmationStatus::Finalized, Some(TransactionError::AccountNotFound));
+        assert_eq!(poll_outcome(Some(&ok), finalized, None, 10), Some(ConfirmOutcome::Confirmed));
+        assert_eq!(
+            poll_outcome(Some(&failed), finalized, None, 10),
+            Some(ConfirmOutcome::Failed(TransactionError::AccountNotFound))
+        );
+    }
+
+    #[test]
+    fn unconfirmed_transactions_keep_polling_until_the_blockhash_expires() {
+        let finalized = CommitmentConfig::finalized();
+        let mut processed = status(TransactionConfirmationStatus::Processed, None);
+        processed.confirmations = Some(0);
+        assert_eq!(poll_outcome(Some(&processed), finalized, None, 10), None);
+        assert_eq!(poll_outcome(None, finalized, Some(10), 10), None);
+        assert_eq!(poll_outcome(None, finalized, None, 10), None);
+        assert_eq!(poll_outcome(None, finalized, Some(11), 10), Some(ConfirmOutcome::Expired));
+    }
+
+    fn batch() -> Vec<(Pubkey, Vec<u8>)> {
+        vec![
+            (Pubkey::new_unique(), vec![1]),
+            (Pubkey::new_unique(), vec![]),
+            (Pubkey::new_unique(), vec![3]),
+        ]
+    }
+
+    fn parse(policy: ParsePolicy, accounts: &[(Pubkey, Vec<u8>)]) -> Result<Vec<u8>, Pubkey> {
+        policy
+            .parse_batch(accounts.iter().map(|(address, data)| (address, data.as_slice())), |data| data.first().copied())
+            .map(|parsed| parsed.into_iter().map(|(_, value)| value).collect())
+    }
+
+    #[test]
+    fn skip_policies_keep_the_parseable_accounts() {
+        let accounts = batch();
+        assert_eq!(parse(ParsePolicy::SkipAndLog, &accounts), Ok(vec![1, 3]));
+        assert_eq!(parse(ParsePolicy::SkipSilently, &accounts), Ok(vec![1, 3]));
+    }
+
+    fn devnet_ids() -> ProgramIds {
+        ProgramIds {
+            ore: Pubkey::new_unique(),
+            delegation: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn custom_program_ids_change_derived_pdas() {
+        let defaults = ProgramIds::default();
+        let devnet = devnet_ids();
+        let miner = Pubkey::new_unique();
+        let staker = Pubkey::new_unique();
+
+        assert_ne!(get_managed_proof_pda(&defaults, miner), get_managed_proof_pda(&devnet, miner));
+        assert_ne!(get_proof_pda(&defaults, miner), get_proof_pda(&devnet, miner));
+        assert_ne!(get_delegated_stake_pda(&defaults, staker, miner), get_delegated_stake_pda(&devnet, staker, miner));
+        assert_ne!(defaults.bus_address(0), devnet.bus_address(0));
+        assert_eq!(defaults.config_address(), CONFIG_ADDRESS);
+    }
+
+    #[test]
+    fn instruction_builders_use_the_given_program_ids() {
+        let devnet = devnet_ids();
+        let miner = Pubkey::new_unique();
+        let beneficiary = Pubkey::new_unique();
+
+        let ix = get_claim_ix(&devnet, miner, beneficiary, 1);
+        assert_eq!(ix.program_id, devnet.delegation);
+        let accounts: Vec<Pubkey> = ix.accounts.iter().map(|a| a.pubkey).collect();
+        assert!(accounts.contains(&get_managed_proof_pda(&devnet, miner)));
+        assert!(!accounts.contains(&get_managed_proof_pda(&ProgramIds::default(), miner)));
+        assert!(accounts.contains(&beneficiary));
+
+        let default_ix = get_claim_ix(&ProgramIds::default(), miner, beneficiary, 1);
+        assert_eq!(default_ix, instruction::undelegate_stake(miner, miner, beneficiary, 1));
+    }
+
+    #[test]
+    fn staker_pdas_match_the_individual_derivations() {
+        let ids = devnet_ids();
+        let staker = Pubkey::new_unique();
+        let miner = Pubkey::new_unique();
+        let mints = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let pdas = derive_staker_pdas(&ids, staker, miner, &mints);
+        assert_eq!(pdas.stake, get_delegated_stake_pda(&ids, staker, miner));
+        for (b, mint) in pdas.boosts.iter().zip(mints) {
+            assert_eq!(b.mint, mint);
+            assert_eq!(b.boost, get_delegated_boost_pda(&ids, staker, miner, mint));
+            assert_eq!(b.boost_v2, get_delegated_boost_v2_pda(&ids, staker, miner, mint));
+        }
+        assert_eq!(pdas.all().len(), 5);
+    }
+
+    #[test]
+    fn fail_fast_abandons_the_batch() {
+        let accounts = batch();
+        assert_eq!(parse(ParsePolicy::FailFast, &accounts), Err(accounts[1].0));
+        assert_eq!(parse(ParsePolicy::FailFast, &accounts[..1]), Ok(vec![1]));
+    }
+}
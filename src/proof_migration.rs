@@ -9,7 +9,10 @@ use solana_sdk::{
 use spl_associated_token_account::get_associated_token_address;
 use tracing::{error, info};
 
+use crate::ore_utils::{get_original_claim_ix, get_stake_ix, ProgramIds};
+
 pub async fn migrate(
+    ids: &ProgramIds,
     rpc_client: &RpcClient,
     wallet: &Keypair,
     original_proof_balance: u64,
@@ -22,7 +25,8 @@ pub async fn migrate(
     let mut ixs = Vec::new();
     let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(20_000);
     ixs.push(prio_fee_ix);
-    let claim_ix = ore_api::prelude::claim(
+    let claim_ix = get_original_claim_ix(
+        ids,
         wallet.pubkey(),
         miner_ore_token_account_addr,
         original_proof_balance,
@@ -61,7 +65,8 @@ pub async fn migrate(
     let mut ixs = Vec::new();
     let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(20_000);
     ixs.push(prio_fee_ix);
-    let stake_ix = crate::ore_utils::get_stake_ix(
+    let stake_ix = get_stake_ix(
+        ids,
         wallet.pubkey(),
         wallet.pubkey(),
         original_proof_balance.saturating_add(ore_token_account_balance),
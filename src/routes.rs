@@ -180,7 +180,7 @@ pub async fn get_pool_staked(
         .as_millis();
     if app_config.stats_enabled {
         let pubkey = Pubkey::from_str("mineXqpDeBeMR8bPQCyy9UneJZbjFywraS3koWZ8SSH").unwrap();
-        let proof = if let Ok(loaded_proof) = get_proof(&rpc_client, pubkey).await {
+        let proof = if let Ok(loaded_proof) = get_proof(&app_config.program_ids, &rpc_client, pubkey).await {
             loaded_proof
         } else {
             error!("get_pool_staked: Failed to load proof.");
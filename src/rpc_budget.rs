@@ -0,0 +1,123 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+// stats calls are deferred once this share of the per second budget is used
+const STATS_BUDGET_PERCENT: u32 = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpcPriority {
+    Critical,
+    Stats,
+}
+
+struct RpcBudgetWindow {
+    started_at: Instant,
+    calls: u32,
+}
+
+/// Shared counter for outgoing RPC calls. Critical calls are always let through and
+/// counted; stats calls wait for the next window once the soft limit is reached.
+///
+/// Every call made by the background systems goes through here. Not counted:
+/// - calls made while `serve` starts up, before the budget exists
+/// - the CLI scripts and proof migration, which run outside the server
+/// - HTTP handlers other than /sol-balance, which make a bounded number of calls per request
+/// - the status polling inside `confirm_with_timeout`
+/// - the proof account websocket subscription
+pub struct RpcBudget {
+    max_calls_per_sec: u32,
+    window: Mutex<RpcBudgetWindow>,
+    calls_by_method: Mutex<HashMap<&'static str, u64>>,
+}
+
+impl RpcBudget {
+    /// A budget of 0 disables deferral but still counts calls.
+    pub fn new(max_calls_per_sec: u32) -> Self {
+        RpcBudget {
+            max_calls_per_sec,
+            window: Mutex::new(RpcBudgetWindow {
+                started_at: Instant::now(),
+                calls: 0,
+            }),
+            calls_by_method: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn acquire(&self, method: &'static str, priority: RpcPriority) {
+        loop {
+            match self.try_acquire(priority) {
+                Ok(()) => break,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+        *self.calls_by_method.lock().unwrap().entry(method).or_insert(0) += 1;
+    }
+
+    /// Counts the call if allowed, otherwise returns how long until the current window ends.
+    fn try_acquire(&self, priority: RpcPriority) -> Result<(), Duration> {
+        let mut window = self.window.lock().unwrap();
+        let elapsed = window.started_at.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            window.started_at = Instant::now();
+            window.calls = 0;
+        }
+
+        let soft_limit = self.max_calls_per_sec.saturating_mul(STATS_BUDGET_PERCENT) / 100;
+        if priority == RpcPriority::Stats && self.max_calls_per_sec > 0 && window.calls >= soft_limit {
+            return Err(Duration::from_secs(1).saturating_sub(window.started_at.elapsed()));
+        }
+
+        window.calls = window.calls.saturating_add(1);
+        Ok(())
+    }
+
+    /// Returns and resets the per method call counts since the last drain.
+    pub fn drain_call_counts(&self) -> HashMap<&'static str, u64> {
+        std::mem::take(&mut *self.calls_by_method.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_calls_are_deferred_past_the_soft_limit() {
+        let budget = RpcBudget::new(10);
+        for _ in 0..8 {
+            assert!(budget.try_acquire(RpcPriority::Stats).is_ok());
+        }
+        assert!(budget.try_acquire(RpcPriority::Stats).is_err());
+        assert!(budget.try_acquire(RpcPriority::Critical).is_ok());
+    }
+
+    #[test]
+    fn critical_calls_are_never_deferred() {
+        let budget = RpcBudget::new(1);
+        for _ in 0..5 {
+            assert!(budget.try_acquire(RpcPriority::Critical).is_ok());
+        }
+    }
+
+    #[test]
+    fn zero_budget_only_counts() {
+        let budget = RpcBudget::new(0);
+        for _ in 0..100 {
+            assert!(budget.try_acquire(RpcPriority::Stats).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn call_counts_are_drained_per_method() {
+        let budget = RpcBudget::new(0);
+        budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+        budget.acquire("getAccountInfo", RpcPriority::Stats).await;
+        budget.acquire("getSlot", RpcPriority::Stats).await;
+
+        let counts = budget.drain_call_counts();
+        assert_eq!(counts.get("getAccountInfo"), Some(&2));
+        assert_eq!(counts.get("getSlot"), Some(&1));
+        assert!(budget.drain_call_counts().is_empty());
+    }
+}
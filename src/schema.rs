@@ -1,5 +1,18 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    balance_adjustments (id) {
+        id -> Integer,
+        miner_id -> Integer,
+        delta -> Bigint,
+        #[max_length = 255]
+        reason -> Varchar,
+        #[max_length = 64]
+        admin -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     challenges (id) {
         id -> Integer,
@@ -8,8 +21,10 @@ diesel::table! {
         #[max_length = 32]
         challenge -> Binary,
         rewards_earned -> Nullable<Unsigned<Bigint>>,
+        rewards_distributed -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        needs_review -> Bool,
     }
 }
 
@@ -25,6 +40,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    earnings (id) {
+        id -> Bigint,
+        miner_id -> Integer,
+        pool_id -> Integer,
+        challenge_id -> Integer,
+        amount -> Unsigned<Bigint>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     miners (id) {
         id -> Integer,
@@ -58,6 +85,7 @@ diesel::table! {
         balance -> Unsigned<Bigint>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        carry_over -> Unsigned<Bigint>,
     }
 }
 
@@ -76,6 +104,20 @@ diesel::table! {
         total_rewards_earned -> Unsigned<Bigint>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        is_active -> Bool,
+    }
+}
+
+diesel::table! {
+    submission_aggregates (id) {
+        id -> Integer,
+        miner_id -> Integer,
+        day -> Date,
+        count -> Unsigned<Integer>,
+        max_difficulty -> Tinyint,
+        sum_difficulty -> Unsigned<Bigint>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -105,12 +147,15 @@ diesel::table! {
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    balance_adjustments,
     challenges,
     claims,
+    earnings,
     miners,
     pools,
     rewards,
     stake_accounts,
+    submission_aggregates,
     submissions_2,
     txns,
 );
@@ -1,32 +1,29 @@
 use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 
-use ore_miner_delegation::{pda::{delegated_boost_pda, managed_proof_pda}, state::DelegatedBoostV2, utils::AccountDeserializeV1};
+use ore_miner_delegation::{pda::{delegated_boost_pda, managed_proof_pda}, state::{DelegatedBoost, DelegatedBoostV2, DelegatedStake}, utils::AccountDeserializeV1};
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig}, rpc_filter::{Memcmp, RpcFilterType}};
 use solana_sdk::{
-    commitment_config::{CommitmentConfig, CommitmentLevel}, pubkey::Pubkey, signature::read_keypair_file, signer::Signer
+    commitment_config::{CommitmentConfig, CommitmentLevel}, pubkey::Pubkey, signer::Signer
 };
 use tokio::time::Instant;
 
-use crate::{app_database::AppDatabase, InsertStakeAccount, UpdateStakeAccount};
+use crate::{app_database::AppDatabase, load_keypair_from_env, ore_utils::ParsePolicy, InsertStakeAccount, UpdateStakeAccount};
 
-pub async fn update_stake_accounts() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn update_stake_accounts(parse_policy: ParsePolicy) -> Result<(), Box<dyn std::error::Error>> {
     println!("Updating stake accounts from on-chain data");
 
     // load envs
-    let wallet_path_str = std::env::var("WALLET_PATH").expect("WALLET_PATH must be set.");
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
     let rpc_url = std::env::var("RPC_URL").expect("RPC_URL must be set.");
 
-    let wallet_path = std::path::Path::new(&wallet_path_str);
-
-    if !wallet_path.exists() {
-        println!("Failed to load wallet at: {}", wallet_path_str);
-        return Err("Failed to find wallet path.".into());
-    }
-
-    let wallet = read_keypair_file(wallet_path)
-        .expect("Failed to load keypair from file: {wallet_path_str}");
+    let wallet = match load_keypair_from_env("WALLET") {
+        Ok(kp) => kp,
+        Err(e) => {
+            println!("Failed to load wallet: {}", e);
+            return Err(e.into());
+        }
+    };
     println!("loaded wallet {}", wallet.pubkey().to_string());
 
     let app_database = Arc::new(AppDatabase::new(database_url));
@@ -155,12 +152,17 @@ pub async fn update_stake_accounts() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Found {} program accounts", program_accounts.len());
 
-    let mut delegated_boosts = HashMap::new();
-    for program_account in program_accounts.iter() {
-        if let Ok(delegate_boost_acct) = DelegatedBoostV2::try_from_bytes(&program_account.1.data) {
-            delegated_boosts.insert(program_account.0, delegate_boost_acct);
+    let parsed = parse_policy.parse_batch(
+        program_accounts.iter().map(|(address, account)| (address, account.data.as_slice())),
+        |data| DelegatedBoostV2::try_from_bytes(data).ok().copied(),
+    );
+    let delegated_boosts: HashMap<Pubkey, DelegatedBoostV2> = match parsed {
+        Ok(parsed) => parsed.into_iter().collect(),
+        Err(address) => {
+            println!("Aborting, delegated boost account {} failed to parse.", address);
+            return Err(format!("failed to parse delegated boost account {}", address).into());
         }
-    }
+    };
 
     println!("Found {} delegated_boosts.", delegated_boosts.len());
     let mut updated_stake_accounts = vec![];
@@ -191,6 +193,27 @@ pub async fn update_stake_accounts() -> Result<(), Box<dyn std::error::Error>> {
                 tokio::time::sleep(Duration::from_millis(500)).await;
             }
             println!("Updated staked_account batch {} in {}ms", i, instant.elapsed().as_millis());
+
+            let pdas = batch.iter().map(|a| a.stake_pda.clone()).collect();
+            match app_database.get_stake_accounts_by_pdas(pdas).await {
+                Ok(stored) => {
+                    let stored: HashMap<String, u64> = stored.into_iter().map(|a| (a.stake_pda, a.staked_balance)).collect();
+                    for expected in batch.iter() {
+                        match stored.get(&expected.stake_pda) {
+                            Some(balance) if *balance == expected.staked_balance => {},
+                            Some(balance) => {
+                                println!("Stake account {} has staked_balance {} after update, expected {}.", expected.stake_pda, balance, expected.staked_balance);
+                            },
+                            None => {
+                                println!("Stake account {} is not in the db.", expected.stake_pda);
+                            }
+                        }
+                    }
+                },
+                Err(e) => {
+                    println!("Failed to verify staked_account batch {}.\nError: {:?}", i, e);
+                }
+            }
             tokio::time::sleep(Duration::from_millis(200)).await;
         }
         println!("Successfully updated stake_accounts");
@@ -228,5 +251,249 @@ pub async fn db_submissions_cleanup() -> Result<(), Box<dyn std::error::Error>>
     }
 }
 
+pub async fn migrate_legacy_submissions() -> Result<(), Box<dyn std::error::Error>> {
+    println!("Migrating legacy submissions into submissions_2.");
+
+    // load envs
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
+
+    let app_database = Arc::new(AppDatabase::new(database_url));
+
+    match app_database.legacy_submissions_table_exists().await {
+        Ok(true) => {},
+        Ok(false) => {
+            println!("Legacy submissions table does not exist. Nothing to migrate.");
+            return Ok(());
+        },
+        Err(e) => {
+            return Err(format!("Failed to check for legacy submissions table: {:?}", e).into());
+        }
+    }
+
+    let (min_id, max_id) = match app_database.get_legacy_submissions_id_range().await {
+        Ok(range) => range,
+        Err(e) => {
+            return Err(format!("Failed to get legacy submissions id range: {:?}", e).into());
+        }
+    };
+
+    if let (Some(min_id), Some(max_id)) = (min_id, max_id) {
+        let batch_size = 10_000;
+        let mut from_id = min_id;
+        let mut total_copied = 0;
+        while from_id <= max_id {
+            let to_id = from_id + batch_size - 1;
+            let instant = Instant::now();
+            match app_database.copy_legacy_submissions(from_id, to_id).await {
+                Ok(copied) => {
+                    total_copied += copied;
+                    println!("Copied {} submissions with ids {}..={} in {}ms", copied, from_id, to_id, instant.elapsed().as_millis());
+                },
+                Err(e) => {
+                    return Err(format!("Failed to copy legacy submissions with ids {}..={}, re-run to resume: {:?}", from_id, to_id, e).into());
+                }
+            }
+            from_id = to_id + 1;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        println!("Copied {} legacy submissions.", total_copied);
+    }
+
+    match app_database.count_uncopied_legacy_submissions().await {
+        Ok(0) => {
+            println!("Verified all legacy submissions exist in submissions_2.");
+        },
+        Ok(missing) => {
+            return Err(format!("{} legacy submissions are missing from submissions_2. Keeping legacy table, re-run to retry.", missing).into());
+        },
+        Err(e) => {
+            return Err(format!("Failed to verify legacy submissions: {:?}", e).into());
+        }
+    }
+
+    match app_database.drop_legacy_submissions_table().await {
+        Ok(_) => {
+            println!("Dropped legacy submissions table.");
+        },
+        Err(e) => {
+            return Err(format!("Failed to drop legacy submissions table: {:?}", e).into());
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn reconcile_stake_accounts(parse_policy: ParsePolicy) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Reconciling stake accounts against on-chain delegated balances.");
 
+    // load envs
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
+    let rpc_url = std::env::var("RPC_URL").expect("RPC_URL must be set.");
+
+    let wallet = match load_keypair_from_env("WALLET") {
+        Ok(kp) => kp,
+        Err(e) => {
+            println!("Failed to load wallet: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    let app_database = Arc::new(AppDatabase::new(database_url));
+    let rpc_client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+
+    let pool = match app_database.get_pool_by_authority_pubkey(wallet.pubkey().to_string()).await {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Failed to get pool data from database.\nError: {:?}", e);
+            return Ok(());
+        }
+    };
 
+    let mut stake_accounts = vec![];
+    let mut last_id: i32 = 0;
+    loop {
+        match app_database.get_active_stake_accounts(pool.id, last_id).await {
+            Ok(d) => {
+                for ac in d.iter() {
+                    last_id = ac.id;
+                    stake_accounts.push(ac.clone());
+                }
+
+                if d.len() < 500 {
+                    break;
+                }
+            },
+            Err(e) => {
+                println!("Failed to get stake accounts. Retrying...\nError: {:?}", e);
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    println!("Checking {} active stake accounts.", stake_accounts.len());
+
+    let mut empty_stake_pdas = vec![];
+    for batch in stake_accounts.chunks(100) {
+        let mut pubkeys = vec![];
+        let mut batch_pdas = vec![];
+        for stake_account in batch {
+            match Pubkey::from_str(&stake_account.stake_pda) {
+                Ok(pk) => {
+                    pubkeys.push(pk);
+                    batch_pdas.push(stake_account.stake_pda.clone());
+                },
+                Err(_) => {
+                    println!("Skipping stake account {} with invalid stake_pda.", stake_account.id);
+                }
+            }
+        }
+
+        let accounts = loop {
+            match rpc_client.get_multiple_accounts(&pubkeys).await {
+                Ok(accounts) => break accounts,
+                Err(e) => {
+                    println!("Failed to get stake accounts from rpc. Retrying...\nError: {:?}", e);
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+        };
+
+        for (stake_pda, account) in batch_pdas.into_iter().zip(accounts.into_iter()) {
+            match stake_account_is_empty(account.as_ref().map(|a| a.data.as_slice()), delegated_amount) {
+                Some(true) => empty_stake_pdas.push(stake_pda),
+                Some(false) => {},
+                None => {
+                    if parse_policy.on_parse_failure(&stake_pda).is_err() {
+                        println!("Aborting, failed to parse on-chain account for {}.", stake_pda);
+                        return Err(format!("failed to parse on-chain account for {}", stake_pda).into());
+                    }
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    println!("Found {} stake accounts with no delegated balance.", empty_stake_pdas.len());
+    let mut total_deactivated = 0;
+    for batch in empty_stake_pdas.chunks(200) {
+        loop {
+            match app_database.deactivate_stake_accounts(batch.to_vec()).await {
+                Ok(count) => {
+                    total_deactivated += count;
+                    break;
+                },
+                Err(_) => {
+                    println!("Failed to deactivate stake accounts batch. Retrying...");
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            }
+        }
+    }
+    println!("Marked {} stake accounts inactive.", total_deactivated);
+
+    Ok(())
+}
+
+pub async fn unpaid_by_pool() -> Result<(), Box<dyn std::error::Error>> {
+    // load envs
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
+
+    let app_database = Arc::new(AppDatabase::new(database_url));
+
+    let totals = match app_database.get_unpaid_by_pool().await {
+        Ok(t) => t,
+        Err(e) => {
+            println!("Failed to get unpaid rewards by pool.\nError: {:?}", e);
+            return Ok(());
+        }
+    };
+
+    println!("pool_id,miner_unpaid,staker_unpaid");
+    for (pool_id, miner_unpaid, staker_unpaid) in totals {
+        println!("{},{},{}", pool_id, miner_unpaid, staker_unpaid);
+    }
+
+    Ok(())
+}
+
+/// Whether a stake account should be deactivated: it was closed on-chain or nothing is
+/// delegated to it anymore. `None` means the account data could not be parsed.
+fn stake_account_is_empty(data: Option<&[u8]>, parse_amount: impl Fn(&[u8]) -> Option<u64>) -> Option<bool> {
+    match data {
+        None => Some(true),
+        Some(data) => parse_amount(data).map(|amount| amount == 0),
+    }
+}
+
+fn delegated_amount(data: &[u8]) -> Option<u64> {
+    if let Ok(acct) = DelegatedBoostV2::try_from_bytes(data) {
+        return Some(acct.amount);
+    }
+    if let Ok(acct) = DelegatedBoost::try_from_bytes(data) {
+        return Some(acct.amount);
+    }
+    if let Ok(acct) = DelegatedStake::try_from_bytes(data) {
+        return Some(acct.amount);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_byte(data: &[u8]) -> Option<u64> {
+        data.first().map(|b| *b as u64)
+    }
+
+    #[test]
+    fn closed_or_drained_stake_accounts_are_empty() {
+        assert_eq!(stake_account_is_empty(None, first_byte), Some(true));
+        assert_eq!(stake_account_is_empty(Some(&[0u8][..]), first_byte), Some(true));
+        assert_eq!(stake_account_is_empty(Some(&[5u8][..]), first_byte), Some(false));
+    }
+
+    #[test]
+    fn unparseable_stake_accounts_are_left_to_the_parse_policy() {
+        assert_eq!(stake_account_is_empty(Some(&[][..]), first_byte), None);
+    }
+}
@@ -1,9 +1,24 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 
 use sysinfo::{CpuRefreshKind, Disks, MemoryRefreshKind, RefreshKind, System};
-use tokio::{sync::mpsc::UnboundedReceiver, time::Instant};
+use tokio::{sync::mpsc::{UnboundedReceiver, UnboundedSender}, time::Instant};
 
-use crate::app_metrics::{AppMetrics, AppMetricsEvent};
+use crate::app_metrics::{AppMetrics, AppMetricsEvent, MetricsRegistry};
+
+/// Records every event into the registry served by `/metrics`, then hands it on to the
+/// influxdb metrics system when one is configured.
+pub async fn metrics_registry_system(
+    mut metrics_event: UnboundedReceiver<AppMetricsEvent>,
+    registry: Arc<MetricsRegistry>,
+    influx_sender: Option<UnboundedSender<AppMetricsEvent>>,
+) {
+    while let Some(event) = metrics_event.recv().await {
+        registry.record_event(&event).await;
+        if let Some(sender) = influx_sender.as_ref() {
+            let _ = sender.send(event);
+        }
+    }
+}
 
 pub async fn metrics_system(
     url: String,
@@ -106,9 +121,10 @@ pub async fn metrics_system(
                             continue;
                         }
                     };
-                    let formatted_data = format!("claim_system_event,host={} queue_length={}u {}",
+                    let formatted_data = format!("claim_system_event,host={} queue_length={}u,in_flight={}u {}",
                         app_metrics.hostname,
                         data.claims_queue_length,
+                        data.in_flight_claims,
                         ts_ns
                     );
                     match app_metrics.send_data_to_influxdb(formatted_data).await {
@@ -146,6 +162,232 @@ pub async fn metrics_system(
                             tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
                         }
                     }
+                },
+                AppMetricsEvent::MineCycleEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let (status, failed_phase) = match data.failed_phase {
+                        Some(phase) => ("failed", phase.as_str()),
+                        None => ("success", "none"),
+                    };
+                    let formatted_data = format!("mine_cycle,host={},status={},failed_phase={} observe_to_build={}u,build_to_send={}u,send_to_confirm={}u,total={}u {}",
+                        app_metrics.hostname,
+                        status,
+                        failed_phase,
+                        data.observe_to_build_ms,
+                        data.build_to_send_ms,
+                        data.send_to_confirm_ms,
+                        data.observe_to_build_ms + data.build_to_send_ms + data.send_to_confirm_ms,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                },
+                AppMetricsEvent::RpcFetchFailureEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("rpc_fetch_failure,host={},kind={} proof_ok={},config_ok={},busses_ok={},missing_busses={}u {}",
+                        app_metrics.hostname,
+                        if data.total_failure { "total" } else { "partial" },
+                        data.proof_ok,
+                        data.config_ok,
+                        data.busses_ok,
+                        data.missing_busses,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                },
+                AppMetricsEvent::RpcHealthEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let mut fields = format!("consecutive_failures={}u", data.consecutive_failures);
+                    if let Some(secs) = data.secs_since_success {
+                        fields.push_str(&format!(",secs_since_success={}u", secs));
+                    }
+                    let formatted_data = format!("rpc_health,host={} {} {}",
+                        app_metrics.hostname,
+                        fields,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                },
+                AppMetricsEvent::ClaimAmountMismatchEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("claim_amount_mismatch,host={},kind={} expected={}u,transferred={}u {}",
+                        app_metrics.hostname,
+                        data.claim_kind,
+                        data.expected_amount,
+                        data.transferred_amount,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                },
+                AppMetricsEvent::ProofBalanceDecreaseEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("proof_balance_decrease,host={} unexplained={}u,balance={}u {}",
+                        app_metrics.hostname,
+                        data.unexplained_decrease,
+                        data.balance,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                }
+                AppMetricsEvent::RpcCallsEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("rpc_calls,host={},method={} calls_per_sec={} {}",
+                        app_metrics.hostname,
+                        data.method,
+                        data.calls_per_sec,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                }
+                AppMetricsEvent::RewardRemainderEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("reward_remainder,host={} challenge_id={}i,remainder={}i,recipients={}i {}",
+                        app_metrics.hostname,
+                        data.challenge_id,
+                        data.remainder,
+                        data.recipients,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                }
+                AppMetricsEvent::SubmissionBufferFlushEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("submission_buffer_flush,host={} challenge_id={}i,forced_flushes={}i,submissions={}i {}",
+                        app_metrics.hostname,
+                        data.challenge_id,
+                        data.forced_flushes,
+                        data.submissions,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
+                }
+                AppMetricsEvent::ClientVersionsEvent(data) => {
+                    let ts_ns = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                        Ok(d) => {
+                            d.as_nanos()
+                        },
+                        Err(_d) => {
+                            tracing::error!(target: "server_log", "Time went backwards...");
+                            continue;
+                        }
+                    };
+                    let formatted_data = format!("client_versions,host={},miner_version={},drillx_version={},capabilities={} connections={}i {}",
+                        app_metrics.hostname,
+                        data.miner_version,
+                        data.drillx_version,
+                        data.capabilities,
+                        data.connections,
+                        ts_ns
+                    );
+                    match app_metrics.send_data_to_influxdb(formatted_data).await {
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::error!(target: "server_log", "Failed to send metrics data to influxdb.\nError: {:?}", e);
+                        }
+                    }
                 }
             }
         }
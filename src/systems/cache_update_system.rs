@@ -1,43 +1,59 @@
-use std::{ops::Div, str::FromStr as _, sync::Arc, time::Duration};
+use std::{collections::HashMap, ops::Div, str::FromStr as _, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 
 use ore_boost_api::state::{boost_pda, stake_pda};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::{commitment_config::{CommitmentConfig, CommitmentLevel}, signer::Signer};
 use steel::{AccountDeserialize as _, Pubkey};
-use tokio::{sync::RwLock, time::Instant};
+use tokio::{sync::{mpsc::UnboundedSender, RwLock}, time::Instant};
 use base64::{prelude::BASE64_STANDARD, Engine};
 
-use crate::{app_rr_database::AppRRDatabase, ore_utils::ORE_TOKEN_DECIMALS, BoostMultiplierCache, BoostMultiplierData, ChallengesCache, Config, LastChallengeSubmissionsCache, LatestBlockhashCache, WalletExtension};
+use crate::{app_metrics::{AppMetricsEvent, MetricsRpcHealthEventData}, app_rr_database::{AppRRDatabase, MAX_RECENT_SUBMISSIONS_PER_MINER}, rpc_budget::{RpcBudget, RpcPriority}, global_boost_util::get_proof_and_config_with_busses, ore_utils::{best_bus, ORE_TOKEN_DECIMALS}, BoostMultiplierCache, BoostMultiplierData, BusBalance, BussesCache, BussesData, ChallengeWithDifficulty, ChallengesCache, Config, LastChallengeSubmissionsCache, LatestBlockhashCache, RecentSubmission, RpcHealth, WalletExtension};
 
 const CACHED_BOOST_MULTIPLIER_UPDATE_INTERVAL: u64 = 15;
 const CACHED_LAST_CHALLENGE_SUBMISSIONS_UPDATE_INTERVAL: u64 = 15;
 const CACHED_CHALLENGES_UPDATE_INTERVAL: u64 = 15;
 const CACHED_LATEST_BLOCKHASH_UPDATE_INTERVAL: u64 = 5;
+const CACHED_BUSSES_UPDATE_INTERVAL: u64 = 5;
+const CACHED_CHALLENGES_LIMIT: usize = 1440;
 
 
 pub async fn cache_update_system(
     app_config: Arc<Config>,
     rpc_client: Arc<RpcClient>,
     app_rr_database: Arc<AppRRDatabase>,
+    app_wallet: Arc<WalletExtension>,
     boost_multiplier_cache: Arc<RwLock<BoostMultiplierCache>>,
     last_challenge_submission_cache: Arc<RwLock<LastChallengeSubmissionsCache>>,
     challenges_cache: Arc<RwLock<ChallengesCache>>,
     latest_blockhash_cache: Arc<RwLock<LatestBlockhashCache>>,
+    busses_cache: Arc<RwLock<BussesCache>>,
+    rpc_health: Arc<RwLock<RpcHealth>>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+    rpc_budget: Arc<RpcBudget>,
 ) {
     // Cached LatestBlockhash
     let cached_item = latest_blockhash_cache.clone();
     let app_rpc_client = rpc_client.clone();
+    let app_rpc_health = rpc_health.clone();
+    let app_metrics = app_metrics_sender.clone();
+    let app_rpc_budget = rpc_budget.clone();
     tokio::spawn(async move {
         let latest_blockhash_cache = cached_item;
         let rpc_client = app_rpc_client;
+        let rpc_health = app_rpc_health;
+        let app_metrics_sender = app_metrics;
+        let rpc_budget = app_rpc_budget;
         loop {
             let lbhash = loop {
+                rpc_budget.acquire("getLatestBlockhash", RpcPriority::Critical).await;
                 match rpc_client.get_latest_blockhash_with_commitment(CommitmentConfig { commitment: CommitmentLevel::Finalized }).await {
                         Ok(lb) => {
                             //tracing::info!(target: "server_log", "Successfully updated latest blockhash");
+                            record_rpc_health(&rpc_health, &app_metrics_sender, true).await;
                             break lb
                         },
                         Err(e) => {
+                            record_rpc_health(&rpc_health, &app_metrics_sender, false).await;
                             tracing::error!(target: "server_log", "Failed to get latest blockhash in cache system. E: {:?}\n Retrying in 2 secs...", e);
                             tokio::time::sleep(Duration::from_secs(2000)).await;
                         }
@@ -75,6 +91,7 @@ pub async fn cache_update_system(
         // Cached Last Challenge Submissions
         let cached_item = last_challenge_submission_cache.clone();
         let app_rr_db = app_rr_database.clone();
+        let recent_per_miner = app_config.recent_submissions_per_miner.min(MAX_RECENT_SUBMISSIONS_PER_MINER as usize) as u32;
         tokio::spawn(async move {
             let last_challenge_submission_cache = cached_item;
             let app_rr_database = app_rr_db;
@@ -91,6 +108,33 @@ pub async fn cache_update_system(
                     Err(_) => {},
                 }
 
+                if recent_per_miner > 0 {
+                    let mut recent_by_miner: HashMap<String, Vec<RecentSubmission>> = HashMap::new();
+                    let mut after_miner_id = 0;
+                    let complete = loop {
+                        match app_rr_database.get_recent_submissions_per_miner(recent_per_miner, after_miner_id).await {
+                            Ok(submissions) => {
+                                let Some(last) = submissions.last() else {
+                                    break true;
+                                };
+                                after_miner_id = last.miner_id;
+                                for submission in submissions {
+                                    recent_by_miner.entry(submission.pubkey).or_default().push(RecentSubmission {
+                                        challenge_id: submission.challenge_id,
+                                        nonce: submission.nonce,
+                                        difficulty: submission.difficulty,
+                                    });
+                                }
+                            }
+                            Err(_) => break false,
+                        }
+                    };
+                    // keep the previous snapshot rather than serving a partial one
+                    if complete {
+                        last_challenge_submission_cache.write().await.recent_by_miner = recent_by_miner;
+                    }
+                }
+
                 tokio::time::sleep(Duration::from_secs(CACHED_LAST_CHALLENGE_SUBMISSIONS_UPDATE_INTERVAL)).await;
             }
         });
@@ -102,12 +146,19 @@ pub async fn cache_update_system(
             let challenges_cache = cached_item;
             let app_rr_database = app_rr_db;
             loop {
-                let res = app_rr_database.get_challenges().await;
+                let last_updated_at = challenges_cache.read().await.item.iter().map(|c| c.updated_at).max();
+                let res = match last_updated_at {
+                    Some(since) => app_rr_database.get_challenges_since(since).await,
+                    None => app_rr_database.get_challenges().await,
+                };
 
                 match res {
                     Ok(challenges) => {
                         let mut writer = challenges_cache.write().await;
-                        writer.item = challenges.clone();
+                        if !challenges.is_empty() {
+                            let cached = std::mem::take(&mut writer.item);
+                            writer.item = merge_challenges(cached, challenges, CACHED_CHALLENGES_LIMIT);
+                        }
                         writer.last_updated_at = Instant::now();
                         drop(writer);
                     }
@@ -117,5 +168,121 @@ pub async fn cache_update_system(
                 tokio::time::sleep(Duration::from_secs(CACHED_CHALLENGES_UPDATE_INTERVAL)).await;
             }
         });
+
+        // Cached Busses
+        let cached_item = busses_cache.clone();
+        let app_rpc_client = rpc_client.clone();
+        let parse_policy = app_config.account_parse_policy;
+        let program_ids = app_config.program_ids;
+        tokio::spawn(async move {
+            let busses_cache = cached_item;
+            let rpc_client = app_rpc_client;
+            let rpc_health = rpc_health;
+            let app_metrics_sender = app_metrics_sender;
+            let pool_authority = app_wallet.miner_wallet.pubkey();
+            loop {
+                rpc_budget.acquire("getMultipleAccounts", RpcPriority::Stats).await;
+                match get_proof_and_config_with_busses(&program_ids, &rpc_client, pool_authority, parse_policy).await {
+                    (_, _, Ok(busses)) => {
+                        record_rpc_health(&rpc_health, &app_metrics_sender, true).await;
+                        let mut bus_balances = vec![];
+                        for (i, bus) in busses.iter().enumerate() {
+                            if let Ok(bus) = bus {
+                                bus_balances.push(BusBalance {
+                                    index: i,
+                                    rewards: bus.rewards,
+                                    theoretical_rewards: bus.theoretical_rewards,
+                                });
+                            }
+                        }
+                        let mut writer = busses_cache.write().await;
+                        writer.item = BussesData {
+                            busses: bus_balances,
+                            best_bus: best_bus(&busses),
+                        };
+                        writer.last_updated_at = Instant::now();
+                        drop(writer);
+                    }
+                    _ => {
+                        record_rpc_health(&rpc_health, &app_metrics_sender, false).await;
+                        tracing::error!(target: "server_log", "Failed to get busses in cache system.");
+                    },
+                }
+
+                tokio::time::sleep(Duration::from_secs(CACHED_BUSSES_UPDATE_INTERVAL)).await;
+            }
+        });
+    }
+}
+
+async fn record_rpc_health(
+    rpc_health: &Arc<RwLock<RpcHealth>>,
+    app_metrics_sender: &UnboundedSender<AppMetricsEvent>,
+    success: bool,
+) {
+    let mut writer = rpc_health.write().await;
+    if success {
+        writer.record_success();
+    } else {
+        writer.record_failure();
+    }
+    let consecutive_failures = writer.consecutive_failures;
+    let last_success_at = writer.last_success_at;
+    drop(writer);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    let data = MetricsRpcHealthEventData {
+        consecutive_failures,
+        secs_since_success: last_success_at.map(|ts| now.saturating_sub(ts)),
+    };
+    if let Err(_) = app_metrics_sender.send(AppMetricsEvent::RpcHealthEvent(data)) {
+        tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
+    }
+}
+
+/// Replaces cached challenges with their updated rows, adds new ones and keeps the newest `limit` by id.
+pub fn merge_challenges(cached: Vec<ChallengeWithDifficulty>, updated: Vec<ChallengeWithDifficulty>, limit: usize) -> Vec<ChallengeWithDifficulty> {
+    let mut by_id: HashMap<i32, ChallengeWithDifficulty> = cached.into_iter().map(|c| (c.id, c)).collect();
+    for challenge in updated {
+        by_id.insert(challenge.id, challenge);
+    }
+    let mut merged: Vec<ChallengeWithDifficulty> = by_id.into_values().collect();
+    merged.sort_by(|a, b| b.id.cmp(&a.id));
+    merged.truncate(limit);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenge(id: i32, rewards_earned: Option<u64>, updated_secs: i64) -> ChallengeWithDifficulty {
+        ChallengeWithDifficulty {
+            id,
+            rewards_earned,
+            difficulty: 20,
+            updated_at: chrono::DateTime::from_timestamp(updated_secs, 0).unwrap().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn late_rewards_replace_the_cached_challenge() {
+        let cached = vec![challenge(3, None, 30), challenge(2, Some(5), 20)];
+        let updated = vec![challenge(3, Some(7), 40), challenge(4, None, 40)];
+        let merged = merge_challenges(cached, updated, 10);
+        let ids: Vec<i32> = merged.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert_eq!(merged[1].rewards_earned, Some(7));
+    }
+
+    #[test]
+    fn merge_keeps_the_newest_challenges() {
+        let cached = vec![challenge(2, None, 20), challenge(1, None, 10)];
+        let merged = merge_challenges(cached, vec![challenge(3, None, 30)], 2);
+        let ids: Vec<i32> = merged.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![3, 2]);
     }
 }
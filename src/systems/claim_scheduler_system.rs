@@ -0,0 +1,430 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+use spl_associated_token_account::get_associated_token_address;
+use steel::Pubkey;
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use crate::{
+    app_database::AppDatabase,
+    rpc_budget::{RpcBudget, RpcPriority},
+    ore_utils::{confirm_with_timeout, get_claim_ix, get_delegated_stake_account, get_ore_mint, ConfirmOutcome},
+    systems::claim_system::{claim_can_no_longer_land, lock_in_flight, lock_reservations, reserve_claim, restore_miner_reward, ClaimReservations, InFlightClaim, InFlightClaims, CLAIM_CONFIRM_TIMEOUT_SECS},
+    ClaimCooldownItem, ClaimsQueue, Config, InsertClaim, InsertTxn,
+};
+
+/// A miner considered for a scheduled claim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimCandidate {
+    pub miner_id: i32,
+    pub pubkey: Pubkey,
+    pub balance: u64,
+    pub seconds_since_last_claim: Option<i64>,
+}
+
+pub async fn claim_scheduler_system(
+    app_database: Arc<AppDatabase>,
+    claims_queue: Arc<ClaimsQueue>,
+    rpc_client: Arc<RpcClient>,
+    rpc_budget: Arc<RpcBudget>,
+    wallet: Arc<Keypair>,
+    in_flight_claims: InFlightClaims,
+    claim_reservations: ClaimReservations,
+    app_config: Arc<Config>,
+) {
+    if app_config.scheduled_claims_interval_secs == 0 {
+        return;
+    }
+    let batch_size = app_config.scheduled_claims_batch_size.max(1);
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(app_config.scheduled_claims_interval_secs)).await;
+
+        let instant = Instant::now();
+        let mut claimed = 0;
+        let mut last_id: i32 = 0;
+
+        // with a reserve configured, only schedule what the on-chain balance can pay above it
+        let mut claimable_above_reserve = if app_config.reserve_amount > 0 {
+            rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+            match get_delegated_stake_account(&app_config.program_ids, &rpc_client, wallet.pubkey(), wallet.pubkey()).await {
+                Ok(delegated_stake) => {
+                    let queued_amount: u64 = claims_queue.queue.read().await.values().map(|item| item.amount).sum();
+                    let in_flight_amount = *lock_reservations(&claim_reservations);
+                    Some(delegated_stake.amount.saturating_sub(app_config.reserve_amount).saturating_sub(queued_amount).saturating_sub(in_flight_amount))
+                }
+                Err(e) => {
+                    error!(target: "claim_log", "Failed to get pool delegated stake account, skipping scheduled claims. Error: {}", e);
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        loop {
+            let rewards = match app_database
+                .get_claimable_miner_rewards(app_config.scheduled_claims_threshold, last_id)
+                .await
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    error!(target: "claim_log", "Failed to get claimable miner rewards. Error: {:?}", e);
+                    break;
+                }
+            };
+
+            let mut candidates = Vec::new();
+            for reward in rewards.iter() {
+                last_id = reward.id;
+
+                if reward.miner_id == app_config.commissions_miner_id {
+                    continue;
+                }
+
+                let miner_pubkey = match Pubkey::from_str(&reward.pubkey) {
+                    Ok(pk) => pk,
+                    Err(_) => continue,
+                };
+
+                // miners with a manual claim pending are settled by the claim system
+                if claims_queue.queue.read().await.contains_key(&(miner_pubkey, None)) {
+                    continue;
+                }
+
+                let seconds_since_last_claim = match app_database.get_last_claim(reward.miner_id).await {
+                    Ok(last_claim) => last_claim.map(|c| c.seconds_since()),
+                    Err(_) => {
+                        error!(target: "claim_log", "Failed to get last claim for miner id {}", reward.miner_id);
+                        continue;
+                    }
+                };
+
+                candidates.push(ClaimCandidate {
+                    miner_id: reward.miner_id,
+                    pubkey: miner_pubkey,
+                    balance: reward.balance,
+                    seconds_since_last_claim,
+                });
+            }
+
+            let eligible = select_eligible_claims(
+                candidates,
+                app_config.scheduled_claims_threshold,
+                app_config.scheduled_claims_cooldown_secs,
+                &mut claimable_above_reserve,
+            );
+
+            for batch in eligible.chunks(batch_size) {
+                claimed += process_claim_batch(batch, &app_database, &claims_queue, &rpc_client, &rpc_budget, &wallet, &in_flight_claims, &claim_reservations, &app_config).await;
+            }
+
+            if rewards.len() < 500 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        info!(target: "claim_log", "Scheduled claims debited {} miners in {}ms", claimed, instant.elapsed().as_millis());
+    }
+}
+
+/// Picks the candidates at or above `threshold` whose last claim is older than `cooldown_secs`.
+/// With a reserve, `claimable_above_reserve` is drawn down and candidates that no longer fit are left out.
+pub fn select_eligible_claims(
+    candidates: Vec<ClaimCandidate>,
+    threshold: u64,
+    cooldown_secs: i64,
+    claimable_above_reserve: &mut Option<u64>,
+) -> Vec<ClaimCandidate> {
+    let mut eligible = Vec::new();
+    for candidate in candidates {
+        if candidate.balance == 0 || candidate.balance < threshold {
+            continue;
+        }
+        if let Some(seconds) = candidate.seconds_since_last_claim {
+            if seconds <= cooldown_secs {
+                continue;
+            }
+        }
+        if let Some(remaining) = claimable_above_reserve.as_mut() {
+            if candidate.balance > *remaining {
+                continue;
+            }
+            *remaining -= candidate.balance;
+        }
+        eligible.push(candidate);
+    }
+    eligible
+}
+
+/// Pays a batch of miners in one transaction. Balances are debited before sending and only
+/// credited back once the transaction definitely failed or expired, so a claim whose outcome
+/// is unknown can never be paid twice. Returns how many miners were left debited.
+async fn process_claim_batch(
+    batch: &[ClaimCandidate],
+    app_database: &AppDatabase,
+    claims_queue: &ClaimsQueue,
+    rpc_client: &RpcClient,
+    rpc_budget: &RpcBudget,
+    wallet: &Keypair,
+    in_flight_claims: &InFlightClaims,
+    claim_reservations: &ClaimReservations,
+    app_config: &Config,
+) -> usize {
+    // skip miners the claim system is already paying, and hold the rest until this batch settles
+    let batch: Vec<&ClaimCandidate> = {
+        let in_flight = lock_in_flight(in_flight_claims);
+        batch
+            .iter()
+            .filter(|candidate| !in_flight.contains(&(candidate.pubkey, None)))
+            .collect()
+    };
+    if batch.is_empty() {
+        return 0;
+    }
+    let _in_flight_guards: Vec<InFlightClaim> = batch
+        .iter()
+        .map(|candidate| InFlightClaim::insert(in_flight_claims.clone(), (candidate.pubkey, None)))
+        .collect();
+
+    // keeps manual claims for these miners out while the batch is in flight
+    {
+        let mut writer = claims_queue.claim_cooldown.write().await;
+        for candidate in batch.iter() {
+            writer.insert(candidate.pubkey, ClaimCooldownItem {
+                last_processed: Instant::now(),
+            });
+        }
+    }
+
+    // scheduled claims don't create token accounts, miners without one claim manually
+    let ore_mint = get_ore_mint();
+    let token_accounts: Vec<Pubkey> = batch
+        .iter()
+        .map(|candidate| get_associated_token_address(&candidate.pubkey, &ore_mint))
+        .collect();
+    rpc_budget.acquire("getMultipleAccounts", RpcPriority::Critical).await;
+    let accounts = match rpc_client.get_multiple_accounts(&token_accounts).await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!(target: "claim_log", "Failed to check scheduled claim token accounts: {:?}. Will retry on next run.", e);
+            return 0;
+        }
+    };
+    let batch: Vec<(&ClaimCandidate, Pubkey)> = batch
+        .into_iter()
+        .zip(token_accounts)
+        .zip(accounts)
+        .filter_map(|((candidate, token_account), account)| account.map(|_| (candidate, token_account)))
+        .collect();
+    if batch.is_empty() {
+        return 0;
+    }
+    let total: u64 = batch.iter().map(|(candidate, _)| candidate.balance).sum();
+
+    // held until the batch settles, so manual claims can't spend the same on-chain balance
+    rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+    let _reservation = match reserve_claim(&app_config.program_ids, rpc_client, wallet.pubkey(), total, app_config.reserve_amount, claim_reservations).await {
+        Ok(Some(reservation)) => reservation,
+        Ok(None) => {
+            error!(target: "claim_log", "Scheduled claims batch of {} exceeds on-chain claimable balance. Will retry on next run.", total);
+            return 0;
+        }
+        Err(e) => {
+            error!(target: "claim_log", "{} Will retry on next run.", e);
+            return 0;
+        }
+    };
+
+    let prio_fee: u32 = 100_000;
+    let mut ixs = vec![ComputeBudgetInstruction::set_compute_unit_price(prio_fee as u64)];
+    for (candidate, token_account) in batch.iter() {
+        ixs.push(get_claim_ix(&app_config.program_ids, wallet.pubkey(), *token_account, candidate.balance));
+    }
+
+    rpc_budget.acquire("getLatestBlockhash", RpcPriority::Critical).await;
+    let (hash, last_valid_block_height) = match rpc_client
+        .get_latest_blockhash_with_commitment(rpc_client.commitment())
+        .await
+    {
+        Ok(blockhash) => blockhash,
+        Err(e) => {
+            error!(target: "claim_log", "Failed to get blockhash for scheduled claims: {:?}. Will retry on next run.", e);
+            return 0;
+        }
+    };
+    let expired_timer = Instant::now();
+    let mut tx = Transaction::new_with_payer(&ixs, Some(&wallet.pubkey()));
+    tx.sign(&[wallet], hash);
+
+    let mut debited = Vec::new();
+    for (candidate, _) in batch.iter() {
+        if let Err(e) = app_database.decrease_miner_reward(candidate.miner_id, candidate.balance).await {
+            error!(target: "claim_log", "Failed to debit miner {} before sending scheduled claims: {:?}. Will retry on next run.", candidate.miner_id, e);
+            for (miner_id, amount) in debited {
+                restore_miner_reward(app_database, miner_id, amount).await;
+            }
+            return 0;
+        }
+        debited.push((candidate.miner_id, candidate.balance));
+    }
+
+    let rpc_config = RpcSendTransactionConfig {
+        preflight_commitment: Some(rpc_client.commitment().commitment),
+        ..RpcSendTransactionConfig::default()
+    };
+
+    let signature;
+    let mut send_attempts = 1;
+    loop {
+        rpc_budget.acquire("sendTransaction", RpcPriority::Critical).await;
+        match rpc_client.send_transaction_with_config(&tx, rpc_config).await {
+            Ok(sig) => {
+                signature = sig;
+                break;
+            }
+            Err(e) => {
+                if send_attempts > 10 {
+                    error!(target: "claim_log", "Failed to send scheduled claims transaction.\nError: {:?}\nRetry limit reached, will retry on next run.", e);
+                    // a send error does not prove the transaction never reached a leader
+                    let signature = tx.signatures[0];
+                    if claim_can_no_longer_land(rpc_client, signature, last_valid_block_height).await {
+                        for (miner_id, amount) in debited {
+                            restore_miner_reward(app_database, miner_id, amount).await;
+                        }
+                        return 0;
+                    }
+                    error!(target: "claim_log", "Scheduled claims {} may have landed, leaving {} miners debited. Check manually.", signature, batch.len());
+                    return batch.len();
+                }
+                send_attempts += 1;
+                error!(target: "claim_log", "Failed to send scheduled claims transaction.\nError: {:?}.\nRetrying in 2 seconds...", e);
+                tokio::time::sleep(Duration::from_millis(2000)).await;
+            }
+        }
+    }
+
+    // the bool is true only when the transaction can no longer land
+    let result: Result<Signature, (String, bool)> = loop {
+        let elapsed = expired_timer.elapsed().as_secs();
+        if elapsed >= CLAIM_CONFIRM_TIMEOUT_SECS {
+            break Err(("Transaction confirmation timed out".to_string(), false));
+        }
+        match confirm_with_timeout(rpc_client, &signature, CommitmentConfig::finalized(), Duration::from_secs(5), last_valid_block_height).await {
+            Ok(ConfirmOutcome::Confirmed) => break Ok(signature),
+            Ok(ConfirmOutcome::Failed(e)) => break Err((format!("Transaction Failed: {:?}", e), true)),
+            Ok(ConfirmOutcome::Expired) => break Err(("Transaction blockhash expired".to_string(), true)),
+            Err(e) => {
+                info!(target: "claim_log", "Scheduled claims -- elapsed: {} -- status: {}", elapsed, e);
+            }
+        }
+    };
+
+    let sig = match result {
+        Ok(sig) => sig,
+        Err((e, true)) => {
+            error!(target: "claim_log", "Scheduled claims transaction {} did not land: {}", signature, e);
+            for (miner_id, amount) in debited {
+                restore_miner_reward(app_database, miner_id, amount).await;
+            }
+            return 0;
+        }
+        Err((e, false)) => {
+            error!(target: "claim_log", "Scheduled claims transaction {}: {}. It may still land, leaving {} miners debited. Check manually.", signature, e, batch.len());
+            return batch.len();
+        }
+    };
+
+    info!(target: "claim_log", "Scheduled claims paid {} miners {} in total.\nSig: {}", batch.len(), total, sig.to_string());
+    while let Err(_) = app_database
+        .update_pool_claimed(wallet.pubkey().to_string(), total)
+        .await
+    {
+        error!(target: "claim_log", "Failed to increase pool claimed amount! Retrying...");
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+    }
+
+    let itxn = InsertTxn {
+        txn_type: "claim".to_string(),
+        signature: sig.to_string(),
+        priority_fee: prio_fee,
+    };
+    while let Err(_) = app_database.add_new_txn(itxn.clone()).await {
+        error!(target: "claim_log", "Failed to add new claim txn! Retrying...");
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+    }
+
+    let txn_id;
+    loop {
+        if let Ok(ntxn) = app_database.get_txn_by_sig(sig.to_string()).await {
+            txn_id = ntxn.id;
+            break;
+        } else {
+            error!(target: "claim_log", "Failed to get tx by sig! Retrying...");
+            tokio::time::sleep(Duration::from_millis(2000)).await;
+        }
+    }
+
+    let iclaims: Vec<InsertClaim> = batch
+        .iter()
+        .map(|(candidate, _)| InsertClaim {
+            miner_id: candidate.miner_id,
+            pool_id: app_config.pool_id,
+            txn_id,
+            amount: candidate.balance,
+        })
+        .collect();
+    while let Err(_) = app_database.add_new_claims_batch(iclaims.clone()).await {
+        error!(target: "claim_log", "Failed to add scheduled claims to db! Retrying...");
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+    }
+
+    batch.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(miner_id: i32, balance: u64, seconds_since_last_claim: Option<i64>) -> ClaimCandidate {
+        ClaimCandidate {
+            miner_id,
+            pubkey: Pubkey::new_unique(),
+            balance,
+            seconds_since_last_claim,
+        }
+    }
+
+    #[test]
+    fn selects_miners_above_threshold_and_past_cooldown() {
+        let seeded = vec![
+            candidate(1, 500, None),
+            candidate(2, 99, None),
+            candidate(3, 500, Some(60)),
+            candidate(4, 100, Some(3_600)),
+            candidate(5, 800, Some(1_800)),
+        ];
+        let eligible = select_eligible_claims(seeded, 100, 1_800, &mut None);
+        let ids: Vec<i32> = eligible.iter().map(|c| c.miner_id).collect();
+        assert_eq!(ids, vec![1, 4]);
+    }
+
+    #[test]
+    fn reserve_limits_the_selected_total() {
+        let seeded = vec![candidate(1, 500, None), candidate(2, 400, None), candidate(3, 300, None)];
+        let mut remaining = Some(850);
+        let eligible = select_eligible_claims(seeded, 100, 1_800, &mut remaining);
+        let ids: Vec<i32> = eligible.iter().map(|c| c.miner_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(remaining, Some(50));
+    }
+}
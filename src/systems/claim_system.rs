@@ -1,33 +1,62 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient, rpc_config::{RpcSendTransactionConfig, RpcTransactionConfig}};
 use solana_sdk::{
+    commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
     signature::{Keypair, Signature},
     signer::Signer,
     transaction::Transaction,
 };
-use solana_transaction_status::TransactionConfirmationStatus;
+use solana_transaction_status::{option_serializer::OptionSerializer, UiTransactionEncoding, UiTransactionStatusMeta, UiTransactionTokenBalance};
 use spl_associated_token_account::get_associated_token_address;
 use steel::Pubkey;
-use tokio::{sync::mpsc::UnboundedSender, time::Instant};
+use tokio::{sync::{mpsc::UnboundedSender, watch, Semaphore}, task::JoinHandle, time::Instant};
 use tracing::{error, info};
 
 use crate::{
-    app_database::AppDatabase, app_metrics::{AppMetricsEvent, MetricsProcessingClaimsEventData}, ore_utils::{get_ore_mint, ORE_TOKEN_DECIMALS}, ClaimsQueue, ClaimsQueueItem, InsertClaim, InsertTxn
+    app_database::AppDatabase, app_metrics::{AppMetricsEvent, MetricsClaimAmountMismatchEventData, MetricsProcessingClaimsEventData}, rpc_budget::{RpcBudget, RpcPriority}, ore_utils::{confirm_with_timeout, get_claim_ix, get_delegated_stake_account, get_ore_mint, ConfirmOutcome, ProgramIds, ORE_TOKEN_DECIMALS}, ClaimAtaPayer, ClaimDecrementOrder, ClaimsQueue, ClaimsQueueItem, Config, InsertClaim, InsertTxn, UpdateReward
 };
 
-const CLAIMS_PROCESSING_AMOUNT: usize = 10;
+/// How long a sent claim is polled before giving up on confirmation.
+pub const CLAIM_CONFIRM_TIMEOUT_SECS: u64 = 600;
 
 pub async fn claim_system(
     claims_queue: Arc<ClaimsQueue>,
     rpc_client: Arc<RpcClient>,
+    rpc_budget: Arc<RpcBudget>,
     wallet: Arc<Keypair>,
     app_database: Arc<AppDatabase>,
     app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+    in_flight_claims: InFlightClaims,
+    claim_reservations: ClaimReservations,
+    shutdown: watch::Receiver<bool>,
+    app_config: Arc<Config>,
 ) {
+    let max_in_flight = app_config.max_in_flight_claims.max(1);
+    let in_flight_permits = Arc::new(Semaphore::new(max_in_flight));
+    let mut handles: Vec<JoinHandle<()>> = Vec::new();
+
     loop {
-        let mut handles = Vec::new();
+        if *shutdown.borrow() {
+            handles.retain(|handle| !handle.is_finished());
+            let in_flight = handles.len();
+            let mut drained = 0;
+            for handle in handles {
+                // wait for spawned tasks to finish
+                match handle.await {
+                    Ok(()) => drained += 1,
+                    Err(e) => error!(target: "claim_log", "In-flight claim task failed during shutdown: {:?}", e),
+                }
+            }
+            let still_queued = claims_queue.queue.read().await.len();
+            info!(target: "claim_log", "Drained {} of {} in-flight claims during shutdown, {} claims left queued.", drained, in_flight, still_queued);
+            info!(target: "claim_log", "Shutdown requested, claim system stopped.");
+            break;
+        }
+
+        handles.retain(|handle| !handle.is_finished());
 
         let reader = claims_queue.queue.read().await;
         let claims_queue_len = reader.len();
@@ -36,6 +65,7 @@ pub async fn claim_system(
         if claims_queue_len > 0 {
             let processing_claims_event_data = MetricsProcessingClaimsEventData {
                 claims_queue_length: claims_queue_len,
+                in_flight_claims: max_in_flight - in_flight_permits.available_permits(),
             };
             match app_metrics_sender.send(AppMetricsEvent::ProcessingClaimsEvent(processing_claims_event_data)) {
                 Ok(_) => {}
@@ -44,43 +74,117 @@ pub async fn claim_system(
                 }
             }
 
-            let mut selected_claims = Vec::with_capacity(CLAIMS_PROCESSING_AMOUNT);
-            for (i, item) in reader.iter().enumerate() {
-                if i >= CLAIMS_PROCESSING_AMOUNT {
-                    break;
-                }
+            let selected_claims = {
+                let in_flight = lock_in_flight(&in_flight_claims);
+                let mut selected_claims = Vec::new();
+                for item in reader.iter() {
+                    if selected_claims.len() >= in_flight_permits.available_permits() {
+                        break;
+                    }
+                    if in_flight.contains(item.0) {
+                        continue;
+                    }
 
-               selected_claims.push((item.0.clone(), item.1.clone()));
-            }
+                   selected_claims.push((item.0.clone(), item.1.clone()));
+                }
+                selected_claims
+            };
             drop(reader);
 
+            for ((user_pubkey, mint_pubkey), claim_queue_item) in selected_claims {
+                // remaining claims stay queued until a permit frees up
+                let permit = match in_flight_permits.clone().try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                let in_flight_guard = InFlightClaim::insert(in_flight_claims.clone(), (user_pubkey, mint_pubkey));
 
-            for ((user_pubkey, _mint_pubkey), claim_queue_item) in selected_claims {
                 let cq = claims_queue.clone();
                 let rpc = rpc_client.clone();
+                let budget = rpc_budget.clone();
                 let w = wallet.clone();
                 let adb = app_database.clone();
+                let ams = app_metrics_sender.clone();
+                let pool_id = app_config.pool_id;
+                let decrement_order = app_config.claim_decrement_order;
+                let ata_payer = app_config.claim_ata_payer;
+                let reserve_amount = app_config.reserve_amount;
+                let program_ids = app_config.program_ids;
+                let reservations = claim_reservations.clone();
                 handles.push(tokio::spawn(async move {
                     let claims_queue = cq;
                     let rpc_client = rpc;
+                    let rpc_budget = budget;
                     let wallet = w;
                     let app_database = adb;
-                        process_claim(user_pubkey, claim_queue_item, rpc_client, wallet, app_database, claims_queue).await;
+                    let app_metrics_sender = ams;
+                    // dropped even if process_claim panics, so the user's claims are not blocked
+                    let _in_flight_guard = in_flight_guard;
+                    let _permit = permit;
+                    process_claim(user_pubkey, claim_queue_item, pool_id, decrement_order, ata_payer, reserve_amount, reservations, program_ids, rpc_client, rpc_budget, wallet, app_database, claims_queue, app_metrics_sender).await;
                 }));
                 tokio::time::sleep(Duration::from_millis(500)).await;
             }
-
-            for handle in handles {
-                // wait for spawned tasks to finish
-                let _ = handle.await;
-            }
         }
 
         tokio::time::sleep(Duration::from_secs(2)).await;
     }
 }
 
-async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, rpc_client: Arc<RpcClient>, wallet: Arc<Keypair>, app_database: Arc<AppDatabase>, claims_queue: Arc<ClaimsQueue>) {
+/// Claims currently being sent or confirmed, shared with the claim scheduler.
+pub type InFlightClaims = Arc<std::sync::Mutex<HashSet<(Pubkey, Option<Pubkey>)>>>;
+
+pub fn lock_in_flight(claims: &InFlightClaims) -> std::sync::MutexGuard<'_, HashSet<(Pubkey, Option<Pubkey>)>> {
+    claims.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Marks a claim as in flight for as long as it is held.
+pub struct InFlightClaim {
+    claims: InFlightClaims,
+    key: (Pubkey, Option<Pubkey>),
+}
+
+impl InFlightClaim {
+    pub fn insert(claims: InFlightClaims, key: (Pubkey, Option<Pubkey>)) -> Self {
+        lock_in_flight(&claims).insert(key);
+        InFlightClaim { claims, key }
+    }
+}
+
+impl Drop for InFlightClaim {
+    fn drop(&mut self) {
+        lock_in_flight(&self.claims).remove(&self.key);
+    }
+}
+
+/// Whether the claim beneficiary already has an ORE token account. An RPC error is returned as
+/// is so callers can retry instead of treating the account as missing.
+pub async fn beneficiary_ata_exists(rpc_client: &RpcClient, receiver: &Pubkey) -> Result<bool, ClientError> {
+    let receiver_token_account = get_associated_token_address(receiver, &get_ore_mint());
+    rpc_client
+        .get_account_with_commitment(&receiver_token_account, rpc_client.commitment())
+        .await
+        .map(|response| response.value.is_some())
+}
+
+/// Builds the instruction creating the claim beneficiary's ORE token account. The claim
+/// transaction is only signed by the pool wallet, so `None` is returned when the pool
+/// is not the one paying for the account. Claim routes reject those claims up front.
+fn create_beneficiary_ata_ix(ata_payer: ClaimAtaPayer, pool_wallet: &Pubkey, receiver: &Pubkey) -> Option<Instruction> {
+    match ata_payer {
+        ClaimAtaPayer::Pool => Some(
+            spl_associated_token_account::instruction::create_associated_token_account(
+                pool_wallet,
+                receiver,
+                &ore_api::consts::MINT_ADDRESS,
+                &spl_token::id(),
+            ),
+        ),
+        ClaimAtaPayer::Miner => None,
+    }
+}
+
+async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, pool_id: i32, decrement_order: ClaimDecrementOrder, ata_payer: ClaimAtaPayer, reserve_amount: u64, claim_reservations: ClaimReservations, program_ids: ProgramIds, rpc_client: Arc<RpcClient>, rpc_budget: Arc<RpcBudget>, wallet: Arc<Keypair>, app_database: Arc<AppDatabase>, claims_queue: Arc<ClaimsQueue>, app_metrics_sender: UnboundedSender<AppMetricsEvent>) {
     if let Some(mint_pubkey) = claim_queue_item.mint {
         info!(target: "claim_log", "Processing stakers claim");
         let staker_pubkey = user_pubkey;
@@ -91,57 +195,79 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
 
         let mut claim_amount = amount;
 
-        if let Ok(stake_account) = app_database
-            .get_staker_rewards(staker_pubkey.to_string(), mint_pubkey.to_string())
+        match app_database
+            .find_staker_rewards(pool_id, staker_pubkey.to_string(), mint_pubkey.to_string())
             .await
         {
-            if amount > stake_account.rewards_balance {
+            Ok(Some(stake_account)) => {
+                if amount > stake_account.rewards_balance {
+                    let mut writer = claims_queue.queue.write().await;
+                    writer.remove(&(staker_pubkey, Some(mint_pubkey)));
+                    drop(writer);
+                    return;
+                }
+            }
+            Ok(None) => {
+                error!(target: "claim_log", "Staker {} has no rewards account. Removing claim from queue.", staker_pubkey.to_string());
                 let mut writer = claims_queue.queue.write().await;
                 writer.remove(&(staker_pubkey, Some(mint_pubkey)));
                 drop(writer);
                 return;
             }
-        } else {
-            let mut writer = claims_queue.queue.write().await;
-            writer.remove(&(staker_pubkey, Some(mint_pubkey)));
-            drop(writer);
-            return;
+            Err(_) => {
+                error!(target: "claim_log", "Failed to get staker rewards from database, will retry on next iteration.");
+                return;
+            }
         }
 
+        // held until this claim is settled, so parallel claims can't spend the same balance
+        rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+        let _reservation = match reserve_claim(&program_ids, &rpc_client, wallet.pubkey(), amount, reserve_amount, &claim_reservations).await {
+            Ok(Some(reservation)) => reservation,
+            Ok(None) => {
+                error!(target: "claim_log", "Staker {} claim of {} exceeds on-chain claimable balance. Removing claim from queue.", staker_pubkey.to_string(), amount);
+                let mut writer = claims_queue.queue.write().await;
+                writer.remove(&(staker_pubkey, Some(mint_pubkey)));
+                drop(writer);
+                return;
+            }
+            Err(e) => {
+                error!(target: "claim_log", "{} Will retry on next iteration.", e);
+                return;
+            }
+        };
+
         let prio_fee: u32 = 100_000;
 
         let mut is_creating_ata = false;
         let mut ixs = Vec::new();
         let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(prio_fee as u64);
         ixs.push(prio_fee_ix);
-        if let Ok(response) = rpc_client
-            .get_token_account_balance(&receiver_token_account)
-            .await
-        {
-            if let Some(_amount) = response.ui_amount {
+        rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+        match beneficiary_ata_exists(&rpc_client, &receiver_pubkey).await {
+            Ok(true) => {
                 info!(target: "claim_log", "staker claim beneficiary has valid token account.");
-            } else {
-                info!(target: "claim_log", "will create token account for staker claim beneficiary");
-                ixs.push(
-                    spl_associated_token_account::instruction::create_associated_token_account(
-                        &wallet.pubkey(),
-                        &receiver_pubkey,
-                        &ore_api::consts::MINT_ADDRESS,
-                        &spl_token::id(),
-                    ),
-                )
             }
-        } else {
-            info!(target: "claim_log", "Adding create ata ix for staker claim");
-            is_creating_ata = true;
-            ixs.push(
-                spl_associated_token_account::instruction::create_associated_token_account(
-                    &wallet.pubkey(),
-                    &receiver_pubkey,
-                    &ore_api::consts::MINT_ADDRESS,
-                    &spl_token::id(),
-                ),
-            )
+            Ok(false) => {
+                info!(target: "claim_log", "Adding create ata ix for staker claim");
+                is_creating_ata = true;
+            }
+            Err(e) => {
+                error!(target: "claim_log", "Failed to check staker claim beneficiary token account: {:?}. Will retry on next iteration.", e);
+                return;
+            }
+        }
+        if is_creating_ata {
+            match create_beneficiary_ata_ix(ata_payer, &wallet.pubkey(), &receiver_pubkey) {
+                Some(ix) => ixs.push(ix),
+                None => {
+                    error!(target: "claim_log", "Staker {} claim beneficiary {} has no ORE token account and the pool does not pay for it. Removing claim from queue.", staker_pubkey.to_string(), receiver_pubkey.to_string());
+                    let mut writer = claims_queue.queue.write().await;
+                    writer.remove(&(staker_pubkey, Some(mint_pubkey)));
+                    drop(writer);
+                    return;
+                }
+            }
         }
 
         // 0.00_000_001_000
@@ -149,10 +275,11 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
             claim_amount = amount - 1_000
         }
         let ix =
-            crate::ore_utils::get_claim_ix(wallet.pubkey(), receiver_token_account, claim_amount);
+            get_claim_ix(&program_ids, wallet.pubkey(), receiver_token_account, claim_amount);
         ixs.push(ix);
 
-        if let Ok((hash, _slot)) = rpc_client
+        rpc_budget.acquire("getLatestBlockhash", RpcPriority::Critical).await;
+        if let Ok((hash, last_valid_block_height)) = rpc_client
             .get_latest_blockhash_with_commitment(rpc_client.commitment())
             .await
         {
@@ -169,6 +296,7 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
             let signature;
             let mut send_attempts = 1;
             loop {
+                rpc_budget.acquire("sendTransaction", RpcPriority::Critical).await;
                 match rpc_client
                     .send_transaction_with_config(&tx, rpc_config)
                     .await 
@@ -195,32 +323,20 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
 
             let result: Result<Signature, String> = loop {
                 let elapsed = expired_timer.elapsed().as_secs();
-                if elapsed >= 600 {
+                if elapsed >= CLAIM_CONFIRM_TIMEOUT_SECS {
                     break Err("Transaction Expired".to_string());
                 }
-                let results = rpc_client.get_signature_statuses(&[signature]).await;
-                if let Ok(response) = results {
-                    let statuses = response.value;
-                    if let Some(status) = &statuses[0] {
-                        info!(target: "claim_log", "Staker claim for {} -- elapsed: {}  -- status: {:?}", user_pubkey.to_string(), elapsed, status);
-                        if status.confirmation_status()
-                            == TransactionConfirmationStatus::Finalized
-                        {
-                            if status.err.is_some() {
-                                let e_str = format!("Stake Claim Transaction Failed: {:?}", status.err);
-                                break Err(e_str);
-                            }
-                            break Ok(signature);
-                        }
-                    } else {
-                        info!(target: "claim_log", "Staker claim for {} -- elapsed: {}  -- status: None", user_pubkey.to_string(), elapsed);
+                match confirm_with_timeout(&rpc_client, &signature, CommitmentConfig::finalized(), Duration::from_secs(5), last_valid_block_height).await {
+                    Ok(ConfirmOutcome::Confirmed) => break Ok(signature),
+                    Ok(ConfirmOutcome::Failed(e)) => break Err(format!("Stake Claim Transaction Failed: {:?}", e)),
+                    Ok(ConfirmOutcome::Expired) => break Err("Transaction blockhash expired".to_string()),
+                    Err(e) => {
+                        info!(target: "claim_log", "Staker claim for {} -- elapsed: {}  -- status: {}", user_pubkey.to_string(), elapsed, e);
                     }
-                } else {
-                    info!(target: "claim_log", "Staker claim for {} -- elapsed: {}  -- status: GET SIG RPC ERROR", user_pubkey.to_string(), elapsed);
                 }
                 // Send the txn again
+                rpc_budget.acquire("sendTransaction", RpcPriority::Critical).await;
                 let _ = rpc_client.send_transaction_with_config(&tx, rpc_config).await;
-                tokio::time::sleep(Duration::from_millis(5000)).await;
             };
 
             match result {
@@ -261,6 +377,9 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
                         tokio::time::sleep(Duration::from_millis(2000)).await;
                     }
 
+                    rpc_budget.acquire("getTransaction", RpcPriority::Critical).await;
+                    verify_claim_transfer(&rpc_client, sig, receiver_pubkey, claim_amount, "staker", &app_metrics_sender).await;
+
                     // TODO: InsertStakerClaim
                     let mut writer = claims_queue.queue.write().await;
                     writer.remove(&(staker_pubkey, Some(mint_pubkey)));
@@ -285,22 +404,48 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
 
         let mut claim_amount = amount;
 
-        if let Ok(miner_rewards) = app_database
-            .get_miner_rewards(miner_pubkey.to_string())
+        let miner_id = match app_database
+            .find_miner_rewards(miner_pubkey.to_string())
             .await
         {
-            if amount > miner_rewards.balance {
+            Ok(Some(miner_rewards)) => {
+                if amount > miner_rewards.balance {
+                    let mut writer = claims_queue.queue.write().await;
+                    writer.remove(&(miner_pubkey, None));
+                    drop(writer);
+                    return;
+                }
+                miner_rewards.miner_id
+            }
+            Ok(None) => {
+                error!(target: "claim_log", "Miner {} has no rewards account. Removing claim from queue.", miner_pubkey.to_string());
                 let mut writer = claims_queue.queue.write().await;
                 writer.remove(&(miner_pubkey, None));
                 drop(writer);
                 return;
             }
-        } else {
-            let mut writer = claims_queue.queue.write().await;
-            writer.remove(&(miner_pubkey, None));
-            drop(writer);
-            return;
-        }
+            Err(_) => {
+                error!(target: "claim_log", "Failed to get miner rewards from database, will retry on next iteration.");
+                return;
+            }
+        };
+
+        // held until this claim is settled, so parallel claims can't spend the same balance
+        rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+        let _reservation = match reserve_claim(&program_ids, &rpc_client, wallet.pubkey(), amount, reserve_amount, &claim_reservations).await {
+            Ok(Some(reservation)) => reservation,
+            Ok(None) => {
+                error!(target: "claim_log", "Miner {} claim of {} exceeds on-chain claimable balance. Removing claim from queue.", miner_pubkey.to_string(), amount);
+                let mut writer = claims_queue.queue.write().await;
+                writer.remove(&(miner_pubkey, None));
+                drop(writer);
+                return;
+            }
+            Err(e) => {
+                error!(target: "claim_log", "{} Will retry on next iteration.", e);
+                return;
+            }
+        };
 
         let prio_fee: u32 = 100_000;
 
@@ -308,34 +453,31 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
         let mut ixs = Vec::new();
         let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(prio_fee as u64);
         ixs.push(prio_fee_ix);
-        if let Ok(response) = rpc_client
-            .get_token_account_balance(&receiver_token_account)
-            .await
-        {
-            if let Some(_amount) = response.ui_amount {
+        rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
+        match beneficiary_ata_exists(&rpc_client, &receiver_pubkey).await {
+            Ok(true) => {
                 info!(target: "claim_log", "miner has valid token account.");
-            } else {
-                info!(target: "claim_log", "will create token account for miner");
-                ixs.push(
-                    spl_associated_token_account::instruction::create_associated_token_account(
-                        &wallet.pubkey(),
-                        &receiver_pubkey,
-                        &ore_api::consts::MINT_ADDRESS,
-                        &spl_token::id(),
-                    ),
-                )
             }
-        } else {
-            info!(target: "claim_log", "Adding create ata ix for miner claim");
-            is_creating_ata = true;
-            ixs.push(
-                spl_associated_token_account::instruction::create_associated_token_account(
-                    &wallet.pubkey(),
-                    &receiver_pubkey,
-                    &ore_api::consts::MINT_ADDRESS,
-                    &spl_token::id(),
-                ),
-            )
+            Ok(false) => {
+                info!(target: "claim_log", "Adding create ata ix for miner claim");
+                is_creating_ata = true;
+            }
+            Err(e) => {
+                error!(target: "claim_log", "Failed to check miner claim beneficiary token account: {:?}. Will retry on next iteration.", e);
+                return;
+            }
+        }
+        if is_creating_ata {
+            match create_beneficiary_ata_ix(ata_payer, &wallet.pubkey(), &receiver_pubkey) {
+                Some(ix) => ixs.push(ix),
+                None => {
+                    error!(target: "claim_log", "Miner {} claim beneficiary {} has no ORE token account and the pool does not pay for it. Removing claim from queue.", miner_pubkey.to_string(), receiver_pubkey.to_string());
+                    let mut writer = claims_queue.queue.write().await;
+                    writer.remove(&(miner_pubkey, None));
+                    drop(writer);
+                    return;
+                }
+            }
         }
 
         // 0.02_000_000_000
@@ -343,10 +485,11 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
             claim_amount = amount - 2_000_000_000
         }
         let ix =
-            crate::ore_utils::get_claim_ix(wallet.pubkey(), receiver_token_account, claim_amount);
+            get_claim_ix(&program_ids, wallet.pubkey(), receiver_token_account, claim_amount);
         ixs.push(ix);
 
-        if let Ok((hash, _slot)) = rpc_client
+        rpc_budget.acquire("getLatestBlockhash", RpcPriority::Critical).await;
+        if let Ok((hash, last_valid_block_height)) = rpc_client
             .get_latest_blockhash_with_commitment(rpc_client.commitment())
             .await
         {
@@ -360,9 +503,18 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
                 ..RpcSendTransactionConfig::default()
             };
 
+            let debited_before_send = decrement_order == ClaimDecrementOrder::DecrementBeforeSend;
+            if debited_before_send {
+                if let Err(e) = app_database.decrease_miner_reward(miner_id, amount).await {
+                    error!(target: "claim_log", "Failed to debit miner {} before sending claim: {:?}. Will retry on next iteration.", miner_pubkey.to_string(), e);
+                    return;
+                }
+            }
+
             let signature;
             let mut send_attempts = 1;
             loop {
+                rpc_budget.acquire("sendTransaction", RpcPriority::Critical).await;
                 match rpc_client
                     .send_transaction_with_config(&tx, rpc_config)
                     .await 
@@ -374,6 +526,15 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
                         Err(e) => {
                             if send_attempts > 10 {
                                 error!(target: "claim_log", "Failed to send claim transaction.\nError: {:?}\nRetry limit reached. Removing claim from queue.", e);
+                                if debited_before_send {
+                                    // a send error does not prove the transaction never reached a leader
+                                    let signature = tx.signatures[0];
+                                    if claim_can_no_longer_land(&rpc_client, signature, last_valid_block_height).await {
+                                        restore_miner_reward(&app_database, miner_id, amount).await;
+                                    } else {
+                                        error!(target: "claim_log", "Claim {} for miner {} may have landed, leaving {} debited. Check manually.", signature, miner_pubkey.to_string(), amount);
+                                    }
+                                }
                                 let mut writer = claims_queue.queue.write().await;
                                 writer.remove(&(miner_pubkey, None));
                                 drop(writer);
@@ -387,28 +548,20 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
                 }
             }
 
-            let result: Result<Signature, String> = loop {
+            // the bool is true only when the transaction can no longer land
+            let result: Result<Signature, (String, bool)> = loop {
                 let elapsed = expired_timer.elapsed().as_secs();
-                if elapsed >= 600 {
-                    break Err("Transaction Expired".to_string());
+                if elapsed >= CLAIM_CONFIRM_TIMEOUT_SECS {
+                    break Err(("Transaction confirmation timed out".to_string(), false));
                 }
-                let results = rpc_client.get_signature_statuses(&[signature]).await;
-                if let Ok(response) = results {
-                    let statuses = response.value;
-                    if let Some(status) = &statuses[0] {
-                        info!(target: "claim_log", "Miner claim for {}  -- elapsed: {} -- status: {:?}", user_pubkey.to_string(), elapsed, status);
-                        if status.confirmation_status()
-                            == TransactionConfirmationStatus::Finalized
-                        {
-                            if status.err.is_some() {
-                                let e_str = format!("Transaction Failed: {:?}", status.err);
-                                break Err(e_str);
-                            }
-                            break Ok(signature);
-                        }
+                match confirm_with_timeout(&rpc_client, &signature, CommitmentConfig::finalized(), Duration::from_secs(5), last_valid_block_height).await {
+                    Ok(ConfirmOutcome::Confirmed) => break Ok(signature),
+                    Ok(ConfirmOutcome::Failed(e)) => break Err((format!("Transaction Failed: {:?}", e), true)),
+                    Ok(ConfirmOutcome::Expired) => break Err(("Transaction blockhash expired".to_string(), true)),
+                    Err(e) => {
+                        info!(target: "claim_log", "Miner claim for {}  -- elapsed: {} -- status: {}", user_pubkey.to_string(), elapsed, e);
                     }
                 }
-                tokio::time::sleep(Duration::from_millis(5000)).await;
             };
 
             match result {
@@ -425,11 +578,13 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
                         .get_pool_by_authority_pubkey(wallet.pubkey().to_string())
                         .await
                         .unwrap();
-                    while let Err(_) =
-                        app_database.decrease_miner_reward(miner.id, amount).await
-                    {
-                        error!(target: "claim_log", "Failed to decrease stakers rewards! Retrying...");
-                        tokio::time::sleep(Duration::from_millis(2000)).await;
+                    if !debited_before_send {
+                        while let Err(_) =
+                            app_database.decrease_miner_reward(miner.id, amount).await
+                        {
+                            error!(target: "claim_log", "Failed to decrease miner rewards! Retrying...");
+                            tokio::time::sleep(Duration::from_millis(2000)).await;
+                        }
                     }
                     while let Err(_) = app_database
                         .update_pool_claimed(wallet.pubkey().to_string(), amount)
@@ -471,14 +626,24 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
                         tokio::time::sleep(Duration::from_millis(2000)).await;
                     }
 
+                    rpc_budget.acquire("getTransaction", RpcPriority::Critical).await;
+                    verify_claim_transfer(&rpc_client, sig, receiver_pubkey, claim_amount, "miner", &app_metrics_sender).await;
+
                     let mut writer = claims_queue.queue.write().await;
                     writer.remove(&(miner_pubkey, None));
                     drop(writer);
 
                     info!(target: "claim_log", "Claim successfully processed!");
                 }
-                Err(e) => {
+                Err((e, definitive)) => {
                     error!(target: "claim_log", "ERROR: {:?}", e);
+                    if debited_before_send {
+                        if definitive {
+                            restore_miner_reward(&app_database, miner_id, amount).await;
+                        } else {
+                            error!(target: "claim_log", "Claim {} for miner {} may still land, leaving {} debited. Check manually.", signature, miner_pubkey.to_string(), amount);
+                        }
+                    }
                 }
             }
         } else {
@@ -486,3 +651,199 @@ async fn process_claim(user_pubkey: Pubkey, claim_queue_item: ClaimsQueueItem, r
         }
     }
 }
+
+/// Polls until the claim transaction either failed or its blockhash expired. Returns false if it
+/// landed or the outcome is still unknown after the confirmation timeout.
+pub async fn claim_can_no_longer_land(rpc_client: &RpcClient, signature: Signature, last_valid_block_height: u64) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(CLAIM_CONFIRM_TIMEOUT_SECS) {
+        match confirm_with_timeout(rpc_client, &signature, CommitmentConfig::finalized(), Duration::from_secs(5), last_valid_block_height).await {
+            Ok(ConfirmOutcome::Confirmed) => return false,
+            Ok(ConfirmOutcome::Failed(_)) | Ok(ConfirmOutcome::Expired) => return true,
+            Err(_) => {}
+        }
+    }
+    false
+}
+
+pub async fn restore_miner_reward(app_database: &AppDatabase, miner_id: i32, amount: u64) {
+    info!(target: "claim_log", "Crediting {} back to miner {} after failed claim.", amount, miner_id);
+    while let Err(_) = app_database
+        .update_rewards(vec![UpdateReward { miner_id, balance: amount }])
+        .await
+    {
+        error!(target: "claim_log", "Failed to credit back miner rewards! Retrying...");
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+    }
+}
+
+// The recorded claim amount includes any ATA creation fee, so callers pass the
+// amount that was actually sent in the claim instruction.
+async fn verify_claim_transfer(
+    rpc_client: &RpcClient,
+    signature: Signature,
+    receiver_pubkey: Pubkey,
+    expected_amount: u64,
+    claim_kind: &'static str,
+    app_metrics_sender: &UnboundedSender<AppMetricsEvent>,
+) {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(CommitmentConfig::finalized()),
+        max_supported_transaction_version: Some(0),
+    };
+    let txn = match rpc_client.get_transaction_with_config(&signature, config).await {
+        Ok(txn) => txn,
+        Err(e) => {
+            error!(target: "claim_log", "Failed to fetch claim transaction {} for amount verification. Error: {:?}", signature, e);
+            return;
+        }
+    };
+
+    let transferred_amount = match txn.transaction.meta.as_ref().and_then(|meta| token_balance_delta(meta, receiver_pubkey, get_ore_mint())) {
+        Some(amount) => amount,
+        None => {
+            error!(target: "claim_log", "Could not determine transferred amount for claim transaction {}.", signature);
+            return;
+        }
+    };
+
+    if transferred_amount != expected_amount {
+        error!(target: "claim_log", "Claim amount mismatch for {} claim {}: expected {}, transferred {}.", claim_kind, signature, expected_amount, transferred_amount);
+        let _ = app_metrics_sender.send(AppMetricsEvent::ClaimAmountMismatchEvent(MetricsClaimAmountMismatchEventData {
+            claim_kind,
+            expected_amount,
+            transferred_amount,
+        }));
+    }
+}
+
+fn token_balance_delta(meta: &UiTransactionStatusMeta, owner: Pubkey, mint: Pubkey) -> Option<u64> {
+    let owner = owner.to_string();
+    let mint = mint.to_string();
+    let find_balance = |balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>| -> Option<u64> {
+        let balances: Option<&Vec<UiTransactionTokenBalance>> = balances.as_ref().into();
+        balances?
+            .iter()
+            .find(|b| b.mint == mint && Option::<&String>::from(b.owner.as_ref()) == Some(&owner))
+            .and_then(|b| b.ui_token_amount.amount.parse::<u64>().ok())
+    };
+
+    let post = find_balance(&meta.post_token_balances)?;
+    // the receiver ATA may have been created by the claim itself
+    let pre = find_balance(&meta.pre_token_balances).unwrap_or(0);
+    post.checked_sub(pre)
+}
+
+/// Amounts of the claims currently in flight, which the on-chain balance does not reflect yet.
+pub type ClaimReservations = Arc<std::sync::Mutex<u64>>;
+
+pub fn lock_reservations(reservations: &ClaimReservations) -> std::sync::MutexGuard<'_, u64> {
+    reservations.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Holds a claim's amount against the on-chain balance until it is dropped.
+pub struct ClaimReservation {
+    reservations: ClaimReservations,
+    amount: u64,
+}
+
+impl Drop for ClaimReservation {
+    fn drop(&mut self) {
+        let mut reserved = lock_reservations(&self.reservations);
+        *reserved = reserved.saturating_sub(self.amount);
+    }
+}
+
+/// Whether a claim of `amount` keeps `balance` at or above `reserve` once the `queued` amounts of
+/// the other in-flight claims are paid out too.
+pub fn fits_above_reserve(balance: u64, queued: u64, reserve: u64, amount: u64) -> bool {
+    amount
+        .checked_add(queued)
+        .and_then(|total| total.checked_add(reserve))
+        .is_some_and(|total| total <= balance)
+}
+
+// Claims are paid out of the pool's own delegated stake account, which can drift
+// from the db rewards balances after a failed sync.
+pub async fn reserve_claim(program_ids: &ProgramIds, rpc_client: &RpcClient, pool_authority: Pubkey, amount: u64, reserve_amount: u64, reservations: &ClaimReservations) -> Result<Option<ClaimReservation>, String> {
+    match get_delegated_stake_account(program_ids, rpc_client, pool_authority, pool_authority).await {
+        Ok(delegated_stake) => {
+            let mut reserved = lock_reservations(reservations);
+            if !fits_above_reserve(delegated_stake.amount, *reserved, reserve_amount, amount) {
+                error!(target: "claim_log", "Claim amount {} with {} already in flight would take on-chain delegated stake balance {} below the reserve of {}", amount, *reserved, delegated_stake.amount, reserve_amount);
+                return Ok(None);
+            }
+            *reserved += amount;
+            Ok(Some(ClaimReservation {
+                reservations: reservations.clone(),
+                amount,
+            }))
+        },
+        Err(e) => {
+            Err(format!("Failed to get pool delegated stake account for claim check. Error: {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_flight_claim_is_released_when_the_task_panics() {
+        let claims: InFlightClaims = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let key = (Pubkey::new_unique(), None);
+
+        let task_claims = claims.clone();
+        let result = std::panic::catch_unwind(move || {
+            let _guard = InFlightClaim::insert(task_claims, key);
+            panic!("claim processing failed");
+        });
+
+        assert!(result.is_err());
+        assert!(!lock_in_flight(&claims).contains(&key));
+    }
+
+    #[test]
+    fn pool_payer_funds_and_signs_the_beneficiary_ata() {
+        let pool_wallet = Pubkey::new_unique();
+        let receiver = Pubkey::new_unique();
+        let ix = create_beneficiary_ata_ix(ClaimAtaPayer::Pool, &pool_wallet, &receiver).unwrap();
+
+        let signers: Vec<Pubkey> = ix.accounts.iter().filter(|a| a.is_signer).map(|a| a.pubkey).collect();
+        assert_eq!(signers, vec![pool_wallet]);
+        assert_eq!(ix.accounts[0].pubkey, pool_wallet);
+        assert_eq!(ix.accounts[2].pubkey, receiver);
+    }
+
+    #[test]
+    fn miner_payer_leaves_the_ata_out_of_the_pool_transaction() {
+        let pool_wallet = Pubkey::new_unique();
+        let receiver = Pubkey::new_unique();
+        assert!(create_beneficiary_ata_ix(ClaimAtaPayer::Miner, &pool_wallet, &receiver).is_none());
+    }
+
+    #[test]
+    fn claim_within_the_reserve_is_accepted() {
+        assert!(fits_above_reserve(1_000, 0, 100, 900));
+    }
+
+    #[test]
+    fn claim_breaching_the_reserve_is_rejected() {
+        assert!(!fits_above_reserve(1_000, 0, 100, 901));
+    }
+
+    #[test]
+    fn in_flight_claims_count_against_the_reserve() {
+        assert!(!fits_above_reserve(1_000, 500, 100, 500));
+        assert!(!fits_above_reserve(u64::MAX, u64::MAX, 0, 1));
+    }
+
+    #[test]
+    fn dropped_reservation_frees_its_amount() {
+        let reservations: ClaimReservations = Arc::new(std::sync::Mutex::new(300));
+        drop(ClaimReservation { reservations: reservations.clone(), amount: 200 });
+        assert_eq!(*lock_reservations(&reservations), 100);
+    }
+}
@@ -2,19 +2,21 @@ use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
     ops::Range,
-    sync::Arc,
+    sync::{atomic::Ordering, Arc},
 };
 
 
+use axum::extract::ws::Message;
+use futures::SinkExt;
 use ore_api::state::Proof;
 use solana_sdk::pubkey::Pubkey;
 use tokio::{
-    sync::{mpsc::UnboundedReceiver, Mutex, RwLock},
+    sync::{mpsc::{UnboundedReceiver, UnboundedSender}, Mutex, RwLock},
     time::Instant,
 };
 
 use crate::{
-    AppState, ClientMessage, EpochHashes, LastPong, SubmissionWindow,
+    app_database::AppDatabase, app_metrics::AppMetricsEvent, app_rr_database::AppRRDatabase, AppState, ClientMessage, ClientStatsReply, Config, EpochHashes, LastPong, SubmissionWindow,
 };
 
 use super::client_submissions_handler::{client_submissions_handler, ClientBestSolution};
@@ -28,6 +30,10 @@ pub async fn client_message_handler_system(
     app_state: Arc<RwLock<AppState>>,
     app_pongs: Arc<RwLock<LastPong>>,
     app_submission_window: Arc<RwLock<SubmissionWindow>>,
+    app_config: Arc<Config>,
+    app_rr_database: Arc<AppRRDatabase>,
+    app_database: Arc<AppDatabase>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
 ) {
     let (s, r) = tokio::sync::mpsc::unbounded_channel::<ClientBestSolution>();
 
@@ -43,7 +49,10 @@ pub async fn client_message_handler_system(
             app_epoch_hashes,
             app_client_nonce_ranges,
             app_app_state,
-            app_app_submission_window
+            app_app_submission_window,
+            app_config,
+            app_database,
+            app_metrics_sender,
         ).await;
     });
 
@@ -68,6 +77,42 @@ pub async fn client_message_handler_system(
                     data: (addr, solution, pubkey)
                 });
             }
+            ClientMessage::GetStats(addr) => {
+                let reader = app_state.read().await;
+                let client = reader.sockets.get(&addr).cloned();
+                drop(reader);
+
+                if let Some(client) = client {
+                    let app_rr_database = app_rr_database.clone();
+                    tokio::spawn(async move {
+                        let (submissions_24h, max_difficulty_24h) = match app_rr_database.get_miner_submission_stats(client.miner_id).await {
+                            Ok(stats) => (stats.count, stats.max_difficulty),
+                            Err(e) => {
+                                tracing::error!(target: "server_log", "Failed to get submission stats for {}: {:?}", client.pubkey, e);
+                                (0, None)
+                            }
+                        };
+                        let reply = ClientStatsReply {
+                            r#type: "stats",
+                            connected_secs: client.connected_at.elapsed().as_secs(),
+                            session_valid_submissions: client.valid_submissions.load(Ordering::Relaxed),
+                            submissions_24h,
+                            max_difficulty_24h,
+                        };
+                        if let Ok(reply) = serde_json::to_string(&reply) {
+                            let _ = client.socket.lock().await.send(Message::Text(reply.into())).await;
+                        }
+                    });
+                }
+            }
+            ClientMessage::Subscribe(addr, channels) => {
+                let reader = app_state.read().await;
+                // only clients that advertised subscriptions in the handshake can narrow what they get
+                if let Some(client) = reader.sockets.get(&addr).filter(|c| c.capabilities.supports_subscriptions()) {
+                    client.subscriptions.store(channels, Ordering::Relaxed);
+                }
+                drop(reader);
+            }
         }
     }
 }
@@ -2,7 +2,8 @@ use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
     ops::Range,
-    sync::Arc,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
 
 use drillx::Solution;
@@ -12,10 +13,10 @@ use axum::extract::ws::Message;
 use futures::SinkExt;
 use ore_api::state::Proof;
 use solana_sdk::pubkey::Pubkey;
-use tokio::sync::{mpsc::UnboundedReceiver, Mutex, RwLock};
+use tokio::sync::{mpsc::{UnboundedReceiver, UnboundedSender}, Mutex, RwLock};
 
 use crate::{
-    AppState, EpochHashes, InternalMessageSubmission, SubmissionWindow, MAX_CALCULATED_HASHPOWER, MIN_DIFF, MIN_HASHPOWER
+    app_database::AppDatabase, app_metrics::{AppMetricsEvent, MetricsSubmissionBufferFlushEventData}, AppState, Config, EpochHashes, InsertSubmission, InternalMessageSubmission, SubmissionWindow, MAX_CALCULATED_HASHPOWER, MIN_DIFF, MIN_HASHPOWER
 };
 
 pub struct ClientBestSolution {
@@ -29,7 +30,11 @@ pub async fn client_submissions_handler(
     client_nonce_ranges: Arc<RwLock<HashMap<Pubkey, Vec<Range<u64>>>>>,
     app_state: Arc<RwLock<AppState>>,
     app_submission_window: Arc<RwLock<SubmissionWindow>>,
+    app_config: Arc<Config>,
+    app_database: Arc<AppDatabase>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
 ) {
+    let submission_grace = Duration::from_millis(app_config.submission_grace_ms);
 
     loop {
         let mut msgs = vec![];
@@ -38,6 +43,22 @@ pub async fn client_submissions_handler(
             let (addr, solution, pubkey) = msg.data;
             let diff = solution.to_hash().difficulty();
             if diff >= MIN_DIFF {
+                if let Some(credited) = handle_previous_challenge_submission(&epoch_hashes, &app_state, submission_grace, addr, pubkey, &solution, diff).await {
+                    if !credited {
+                        let reader = app_state.read().await;
+                        if let Some(app_client_socket) = reader.sockets.get(&addr) {
+                            let _ = app_client_socket
+                                .socket
+                                .lock()
+                                .await
+                                .send(Message::Text("Stale submission for previous challenge.".to_string().into()))
+                                .await;
+                        }
+                        drop(reader);
+                    }
+                    continue;
+                }
+
                 let reader = app_submission_window.read().await;
                 let submission_windows_closed = reader.closed;
                 drop(reader);
@@ -85,14 +106,31 @@ pub async fn client_submissions_handler(
                 }
 
                 if !in_range {
-                    //tracing::error!(target: "server_log", "Client submitted nonce out of assigned range");
-                    continue;
+                    let reader = app_state.read().await;
+                    if let Some(app_client_socket) = reader.sockets.get(&addr) {
+                        let count = app_client_socket.out_of_range_nonces.fetch_add(1, Ordering::Relaxed) + 1;
+                        tracing::warn!(target: "server_log", "{} submitted nonce {} outside assigned range ({} this session)", pubkey, nonce, count);
+                        if !app_config.nonce_range_warn_only {
+                            let _ = app_client_socket
+                                .socket
+                                .lock()
+                                .await
+                                .send(Message::Text("Rejected submission: nonce outside assigned range.".to_string().into()))
+                                .await;
+                        }
+                    }
+                    drop(reader);
+                    if !app_config.nonce_range_warn_only {
+                        continue;
+                    }
                 }
 
                 let reader = app_state.read().await;
                 let miner_id;
+                let valid_submissions;
                 if let Some(app_client_socket) = reader.sockets.get(&addr) {
                     miner_id = app_client_socket.miner_id;
+                    valid_submissions = app_client_socket.valid_submissions.clone();
                 } else {
                     //tracing::error!(target: "server_log", "Failed to get client socket for addr: {}", addr);
                     continue;
@@ -104,6 +142,7 @@ pub async fn client_submissions_handler(
                 drop(lock);
 
                 if solution.is_valid(&challenge) {
+                    valid_submissions.fetch_add(1, Ordering::Relaxed);
                     let submission_uuid = Uuid::new_v4();
                     //tracing::info!(target: "submission_log", "{} - {} found diff: {}", submission_uuid, pubkey_str, diff);
                     // calculate rewards
@@ -117,22 +156,33 @@ pub async fn client_submissions_handler(
                         drop(reader);
 
                         if let Some(old_sub) = subs.get(&pubkey) {
+                            let new_sub = InternalMessageSubmission {
+                                miner_id,
+                                supplied_nonce: nonce,
+                                supplied_digest: solution.d,
+                                supplied_diff: diff,
+                                hashpower,
+                            };
                             if diff > old_sub.supplied_diff {
                                 let mut epoch_hashes = epoch_hashes.write().await;
-                                epoch_hashes.submissions.insert(
-                                    pubkey,
-                                    InternalMessageSubmission {
-                                        miner_id,
-                                        supplied_nonce: nonce,
-                                        supplied_diff: diff,
-                                        hashpower,
-                                    },
-                                );
-                                if diff > epoch_hashes.best_hash.difficulty {
+                                if let Some(replaced) = epoch_hashes.submissions.insert(pubkey, new_sub) {
+                                    if app_config.store_all_submissions {
+                                        if let Some(spill) = epoch_hashes.push_superseded(replaced, app_config.submission_buffer_max) {
+                                            spill_superseded_submissions(epoch_hashes.challenge, spill, app_database.clone(), app_metrics_sender.clone());
+                                        }
+                                    }
+                                }
+                                if epoch_hashes.offer_best(solution, diff) {
                                     tracing::info!(target: "server_log", "{} - New best diff: {}", submission_uuid, diff);
                                     //tracing::info!(target: "submission_log", "{} - New best diff: {}", submission_uuid, diff);
-                                    epoch_hashes.best_hash.difficulty = diff;
-                                    epoch_hashes.best_hash.solution = Some(solution);
+                                }
+                                drop(epoch_hashes);
+                            } else if app_config.store_all_submissions && new_sub.supplied_nonce != old_sub.supplied_nonce {
+                                let mut epoch_hashes = epoch_hashes.write().await;
+                                if epoch_hashes.challenge == challenge {
+                                    if let Some(spill) = epoch_hashes.push_superseded(new_sub, app_config.submission_buffer_max) {
+                                        spill_superseded_submissions(challenge, spill, app_database.clone(), app_metrics_sender.clone());
+                                    }
                                 }
                                 drop(epoch_hashes);
                             }
@@ -144,15 +194,14 @@ pub async fn client_submissions_handler(
                                 InternalMessageSubmission {
                                     miner_id,
                                     supplied_nonce: nonce,
+                                    supplied_digest: solution.d,
                                     supplied_diff: diff,
                                     hashpower,
                                 },
                             );
-                            if diff > epoch_hashes.best_hash.difficulty {
+                            if epoch_hashes.offer_best(solution, diff) {
                                 tracing::info!(target: "server_log", "{} - New best diff: {}", submission_uuid, diff);
                                 //tracing::info!(target: "submission_log", "{} - New best diff: {}", submission_uuid, diff);
-                                epoch_hashes.best_hash.difficulty = diff;
-                                epoch_hashes.best_hash.solution = Some(solution);
                             }
                             drop(epoch_hashes);
                             //tracing::info!(target: "submission_log", "{} - Added {} submission diff: {} to epoch_hashes submissions.", submission_uuid, pubkey_str, diff);
@@ -174,3 +223,124 @@ pub async fn client_submissions_handler(
         }
     }
 }
+
+/// Writes a full buffer of non-best shares to the database ahead of distribution so the
+/// buffer stays bounded. These shares earn nothing, so a later distribution never rewrites them.
+fn spill_superseded_submissions(
+    challenge: [u8; 32],
+    submissions: Vec<InternalMessageSubmission>,
+    app_database: Arc<AppDatabase>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+) {
+    tracing::info!(target: "server_log", "Submission buffer reached {}, flushing early", submissions.len());
+    tokio::spawn(async move {
+        write_superseded_submissions(challenge, submissions, &app_database, &app_metrics_sender).await;
+    });
+}
+
+/// Writes buffered non-best shares for a challenge, retrying until they land. Returns how many
+/// were written.
+pub async fn write_superseded_submissions(
+    challenge: [u8; 32],
+    submissions: Vec<InternalMessageSubmission>,
+    app_database: &AppDatabase,
+    app_metrics_sender: &UnboundedSender<AppMetricsEvent>,
+) -> usize {
+    let challenge_id = loop {
+        match app_database.get_challenge_by_challenge(challenge.to_vec()).await {
+            Ok(c) => break c.id,
+            Err(_) => {
+                tracing::error!(target: "server_log", "Failed to get challenge for buffered submission flush. Retrying...");
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+            }
+        }
+    };
+    let rows: Vec<InsertSubmission> = submissions
+        .iter()
+        .map(|s| InsertSubmission {
+            miner_id: s.miner_id,
+            challenge_id,
+            nonce: s.supplied_nonce,
+            difficulty: s.supplied_diff as i8,
+        })
+        .collect();
+    for batch in rows.chunks(1000) {
+        while let Err(_) = app_database.add_new_submissions_batch(batch.to_vec()).await {
+            tracing::error!(target: "server_log", "Failed to flush buffered submissions for challenge_id: {}. Retrying...", challenge_id);
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+    if let Err(_) = app_metrics_sender.send(AppMetricsEvent::SubmissionBufferFlushEvent(MetricsSubmissionBufferFlushEventData {
+        challenge_id,
+        forced_flushes: 1,
+        submissions: rows.len(),
+    })) {
+        tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
+    }
+    rows.len()
+}
+
+/// Credits a solution for the previous challenge while its grace window is open.
+/// Returns None when the solution is not for the previous challenge, otherwise
+/// whether it was credited.
+async fn handle_previous_challenge_submission(
+    epoch_hashes: &Arc<RwLock<EpochHashes>>,
+    app_state: &Arc<RwLock<AppState>>,
+    submission_grace: Duration,
+    addr: SocketAddr,
+    pubkey: Pubkey,
+    solution: &Solution,
+    diff: u32,
+) -> Option<bool> {
+    let reader = epoch_hashes.read().await;
+    let previous = reader.previous.as_ref()?;
+    if solution.is_valid(&reader.challenge) || !solution.is_valid(&previous.challenge) {
+        return None;
+    }
+    if previous.finalized || previous.rotated_at.elapsed() > submission_grace {
+        return Some(false);
+    }
+    let nonce = u64::from_le_bytes(solution.n);
+    let in_range = previous
+        .nonce_ranges
+        .get(&pubkey)
+        .map(|ranges| ranges.iter().any(|r| r.contains(&nonce)))
+        .unwrap_or(false);
+    drop(reader);
+    if !in_range {
+        return Some(false);
+    }
+
+    let miner_id = app_state.read().await.sockets.get(&addr)?.miner_id;
+
+    let mut hashpower = MIN_HASHPOWER * 2u64.pow(diff - MIN_DIFF);
+    if hashpower > MAX_CALCULATED_HASHPOWER {
+        hashpower = MAX_CALCULATED_HASHPOWER;
+    }
+
+    let mut writer = epoch_hashes.write().await;
+    let previous = match writer.previous.as_mut() {
+        Some(p) if !p.finalized => p,
+        _ => return Some(false),
+    };
+    let should_insert = match previous.submissions.get(&pubkey) {
+        Some(old_sub) => diff > old_sub.supplied_diff,
+        None => true,
+    };
+    if should_insert {
+        previous.submissions.insert(
+            pubkey,
+            InternalMessageSubmission {
+                miner_id,
+                supplied_nonce: nonce,
+                supplied_digest: solution.d,
+                supplied_diff: diff,
+                hashpower,
+            },
+        );
+        tracing::info!(target: "server_log", "Credited {} with diff {} for previous challenge during grace window", pubkey, diff);
+    }
+    drop(writer);
+
+    Some(true)
+}
@@ -0,0 +1,38 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::sync::{mpsc::UnboundedSender, RwLock};
+
+use crate::{app_metrics::{AppMetricsEvent, MetricsClientVersionsEventData}, AppState};
+
+const CLIENT_VERSIONS_METRICS_INTERVAL_SECS: u64 = 60;
+
+pub async fn client_versions_metrics_system(
+    app_state: Arc<RwLock<AppState>>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(CLIENT_VERSIONS_METRICS_INTERVAL_SECS)).await;
+
+        let mut counts: HashMap<(String, String, u8), u64> = HashMap::new();
+        let reader = app_state.read().await;
+        for client in reader.sockets.values() {
+            let capabilities = &client.capabilities;
+            *counts
+                .entry((capabilities.miner_version.clone(), capabilities.drillx_version.clone(), capabilities.flags))
+                .or_default() += 1;
+        }
+        drop(reader);
+
+        for ((miner_version, drillx_version, capabilities), connections) in counts {
+            let data = MetricsClientVersionsEventData {
+                miner_version,
+                drillx_version,
+                capabilities,
+                connections,
+            };
+            if let Err(_) = app_metrics_sender.send(AppMetricsEvent::ClientVersionsEvent(data)) {
+                tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
+            }
+        }
+    }
+}
@@ -12,18 +12,22 @@ use ore_api::state::Proof;
 use solana_sdk::pubkey::Pubkey;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::{message::ServerMessageStartMining, ore_utils::get_cutoff, AppState, EpochHashes, SubmissionWindow};
-
-const NONCE_RANGE_SIZE: u64 = 40_000_000;
+use crate::{
+    message::ServerMessageStartMining,
+    nonce_allocator::{Clock, NonceAllocator},
+    ore_utils::get_work_cutoff_at,
+    AppState, EpochHashes, SubmissionWindow,
+};
 
 pub async fn handle_ready_clients_system(
     app_state: Arc<RwLock<AppState>>,
     app_proof: Arc<Mutex<Proof>>,
     app_epoch_hashes: Arc<RwLock<EpochHashes>>,
     ready_clients: Arc<Mutex<HashSet<SocketAddr>>>,
-    app_nonce: Arc<Mutex<u64>>,
+    app_nonce: Arc<Mutex<NonceAllocator>>,
     app_client_nonce_ranges: Arc<RwLock<HashMap<Pubkey, Vec<Range<u64>>>>>,
     app_submission_window: Arc<RwLock<SubmissionWindow>>,
+    clock: Arc<dyn Clock>,
 ) {
     tracing::info!(target: "server_log", "handle ready clients system started!");
     loop {
@@ -46,7 +50,8 @@ pub async fn handle_ready_clients_system(
                 let latest_proof = lock.clone();
                 drop(lock);
 
-                let cutoff = get_cutoff(latest_proof, 4);
+                let work_cutoff = get_work_cutoff_at(latest_proof, 4, clock.now());
+                let cutoff = work_cutoff.cutoff;
                 let mut should_mine = true;
 
                 // only distribute challenge if 10 seconds or more is left
@@ -81,23 +86,14 @@ pub async fn handle_ready_clients_system(
                     for client in clients {
                         let app_client_nonce_ranges = app_client_nonce_ranges.clone();
                         if let Some(sender) = sockets.get(&client) {
-                            let nonce_range = {
-                                let mut nonce = app_nonce.lock().await;
-                                let start = *nonce;
-                                *nonce += NONCE_RANGE_SIZE;
-                                drop(nonce);
-                                // max hashes possible in 60s for a single client
-                                //
-                                let nonce_end = start + NONCE_RANGE_SIZE - 1;
-                                let end = nonce_end;
-                                start..end
-                            };
+                            let nonce_range = app_nonce.lock().await.allocate(sender.cores);
 
                             let start_mining_message = ServerMessageStartMining::new(
                                 challenge,
                                 cutoff,
                                 nonce_range.start,
                                 nonce_range.end,
+                                work_cutoff.challenge_end_at,
                             );
                             let sender = sender.clone();
                             tokio::spawn(async move {
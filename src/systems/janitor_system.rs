@@ -0,0 +1,45 @@
+use std::{collections::HashMap, ops::Range, sync::Arc, time::Duration};
+
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::RwLock;
+
+use crate::{AppState, ClaimsQueue, Config, CLAIM_COOLDOWN_SECS};
+
+pub async fn janitor_system(
+    app_state: Arc<RwLock<AppState>>,
+    claims_queue: Arc<ClaimsQueue>,
+    client_nonce_ranges: Arc<RwLock<HashMap<Pubkey, Vec<Range<u64>>>>>,
+    app_config: Arc<Config>,
+) {
+    let interval = Duration::from_secs(app_config.janitor_interval_secs.max(1));
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let mut writer = app_state.write().await;
+        let sessions_before = writer.retained_sessions.len();
+        writer.purge_expired_sessions();
+        let sessions_purged = sessions_before - writer.retained_sessions.len();
+        let lingering_purged = writer.purge_lingering_sessions();
+        drop(writer);
+
+        // a miner that did not come back within the linger window gives up its nonce ranges
+        if !lingering_purged.is_empty() {
+            let mut writer = client_nonce_ranges.write().await;
+            for pubkey in lingering_purged.iter() {
+                writer.remove(pubkey);
+            }
+            drop(writer);
+        }
+
+        let cooldown = Duration::from_secs(CLAIM_COOLDOWN_SECS);
+        let mut writer = claims_queue.claim_cooldown.write().await;
+        let cooldowns_before = writer.len();
+        writer.retain(|_, item| item.last_processed.elapsed() < cooldown);
+        let cooldowns_purged = cooldowns_before - writer.len();
+        drop(writer);
+
+        if sessions_purged > 0 || !lingering_purged.is_empty() || cooldowns_purged > 0 {
+            tracing::info!(target: "server_log", "Janitor purged {} resume sessions, {} lingering sessions and {} claim cooldowns", sessions_purged, lingering_purged.len(), cooldowns_purged);
+        }
+    }
+}
@@ -17,6 +17,9 @@ pub async fn message_text_all_clients_system(
                 let socks = shared_state.sockets.clone();
                 drop(shared_state);
                 for (_socket_addr, socket_sender) in socks.iter() {
+                    if !socket_sender.is_subscribed(msg.channel) {
+                        continue;
+                    }
                     let text = msg.text.clone();
                     let socket = socket_sender.clone();
                     tokio::spawn(async move {
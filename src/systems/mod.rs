@@ -9,3 +9,9 @@ pub mod message_text_all_clients_system;
 pub mod app_metrics_system;
 pub mod cache_update_system;
 pub mod client_submissions_handler;
+pub mod submission_compaction_system;
+pub mod claim_scheduler_system;
+pub mod proof_balance_watchdog_system;
+pub mod janitor_system;
+pub mod rpc_call_metrics_system;
+pub mod client_versions_metrics_system;
@@ -1,5 +1,6 @@
 use axum::extract::ws::Message;
 use base64::{prelude::BASE64_STANDARD, Engine};
+use drillx::Solution;
 use futures::SinkExt;
 use std::{
     ops::Div,
@@ -11,279 +12,430 @@ use solana_sdk::
     signer::Signer
 ;
 use tokio::{
-    sync::{mpsc::UnboundedReceiver, RwLock}, time::Instant}
+    sync::{mpsc::{Receiver, UnboundedSender}, watch, RwLock}, time::Instant}
 ;
 use tracing::info;
 
 use crate::{
-    app_database::AppDatabase, message::ServerMessagePoolSubmissionResult, ore_utils::
-        ORE_TOKEN_DECIMALS, AppState, ClientVersion, Config, InsertSubmission, MessageInternalMineSuccess, UpdateReward, UpdateStakeAccountRewards, WalletExtension
+    app_database::{AppDatabase, AppDatabaseError, DistributionOutcome}, app_metrics::{AppMetricsEvent, MetricsRewardRemainderEventData}, message::ServerMessagePoolSubmissionResult, ore_utils::
+        ORE_TOKEN_DECIMALS, AppState, ChallengeTopEarner, ClientVersion, Config, InsertSubmission, InternalMessageSubmission, LastChallengeRewards, LastChallengeRewardsCache, MessageInternalMineSuccess, MessageInternalReward, CHANNEL_REWARDS, UpdateReward, UpdateStakeAccountRewards, WalletExtension
 };
 
 pub const ORE_STAKE_PERCENTAGE: u64 = 20;
 pub const ORE_SOL_STAKE_PERCENTAGE: u64 = 10;
 pub const ORE_ISC_STAKE_PERCENTAGE: u64 = 14;
 pub const TOTAL_STAKER_PERCENTAGE: u64 = ORE_STAKE_PERCENTAGE + ORE_SOL_STAKE_PERCENTAGE + ORE_ISC_STAKE_PERCENTAGE;
+const LAST_CHALLENGE_TOP_EARNERS: usize = 10;
+
+/// Retry allowance for the DB writes a distribution cycle makes before it commits any rewards.
+/// Once `distribute_challenge_rewards` has committed, the remaining writes retry until they land
+/// so a cycle is never left half-written.
+pub struct RetryBudget {
+    remaining: u32,
+    deadline: Instant,
+}
+
+impl RetryBudget {
+    pub fn new(max_retries: u32, max_duration: Duration) -> Self {
+        RetryBudget {
+            remaining: max_retries,
+            deadline: Instant::now() + max_duration,
+        }
+    }
+
+    /// Takes one retry, returning false once either the count or the time allowance is spent.
+    pub fn try_consume(&mut self) -> bool {
+        if self.remaining == 0 || Instant::now() >= self.deadline {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+}
 
 pub async fn pool_mine_success_system(
     app_shared_state: Arc<RwLock<AppState>>,
     app_database: Arc<AppDatabase>,
     app_config: Arc<Config>,
     app_wallet: Arc<WalletExtension>,
-    mut mine_success_receiver: UnboundedReceiver<MessageInternalMineSuccess>
+    app_cache_last_challenge_rewards: Arc<RwLock<LastChallengeRewardsCache>>,
+    mut mine_success_receiver: Receiver<MessageInternalMineSuccess>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+    mut shutdown: watch::Receiver<bool>,
 ) {
     loop {
-        while let Some(msg) = mine_success_receiver.recv().await {
-            let id = uuid::Uuid::new_v4();
-            let c = BASE64_STANDARD.encode(msg.challenge);
-            info!(target: "server_log", "{} - Processing internal mine success for challenge: {}", id, c);
-            {
-                let instant = Instant::now();
-                info!(target: "server_log", "{} - Getting sockets.", id);
-                let shared_state = app_shared_state.read().await;
-                let len = shared_state.sockets.len();
-                let socks = shared_state.sockets.clone();
-                drop(shared_state);
-                info!(target: "server_log", "{} - Got sockets in {}.", id, instant.elapsed().as_millis());
-
-                let mut i_rewards = Vec::new();
-                let mut i_submissions = Vec::new();
-
-                let instant = Instant::now();
-                info!(target: "server_log", "{} - Processing submission results for challenge: {}.", id, c);
-                let staker_rewards = if !msg.global_boosts_active {
-                    (msg.rewards as u128).saturating_mul(TOTAL_STAKER_PERCENTAGE as u128).saturating_div(100) as u64
-                } else {
-                    info!(target: "server_log", "{} - Global Boosts Active, Staking rewards are 0", id);
-                    0
-                };
-
-                let total_rewards = msg.rewards - msg.commissions - staker_rewards;
-                info!(target: "server_log", "{} - Miners Rewards: {}", id, total_rewards);
-                info!(target: "server_log", "{} - Commission: {}", id, msg.commissions);
-                info!(target: "server_log", "{} - Staker Rewards: {}", id, staker_rewards);
-                let mut total_miners_earned_rewards = 0;
-                for (miner_pubkey, msg_submission) in msg.submissions.iter() {
-                    let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
-                    let earned_rewards = (total_rewards as u128).saturating_mul(msg_submission.hashpower as u128).saturating_div(msg.total_hashpower as u128) as u64;
-                    total_miners_earned_rewards += earned_rewards;
-
-                    let new_submission = InsertSubmission {
-                        miner_id: msg_submission.miner_id,
-                        challenge_id: msg.challenge_id,
-                        nonce: msg_submission.supplied_nonce,
-                        difficulty: msg_submission.supplied_diff as i8,
-                    };
-
-                    let new_reward = UpdateReward {
-                        miner_id: msg_submission.miner_id,
-                        balance: earned_rewards,
-                    };
-
-                    i_rewards.push(new_reward);
-                    i_submissions.push(new_submission);
-                    //let _ = app_database.add_new_earning(new_earning).await.unwrap();
-
-                    let earned_rewards_dec = (earned_rewards as f64).div(decimals);
-                    let pool_rewards_dec = (msg.rewards as f64).div(decimals);
-
-                    let percentage = if pool_rewards_dec != 0.0 {
-                        (earned_rewards_dec / pool_rewards_dec) * 100.0
-                    } else {
-                        0.0 // Handle the case where pool_rewards_dec is 0 to avoid division by zero
-                    };
-
-                    let top_stake = 1.0f64;
-
-                    for (_addr, client_connection) in socks.iter() {
-                        if client_connection.pubkey.eq(&miner_pubkey) {
-                            let socket_sender = client_connection.socket.clone();
-
-                            match client_connection.client_version {
-                                ClientVersion::V1 => {
-                                    let message = format!(
-                                        "Pool Submitted Difficulty: {}\nPool Earned:  {:.11} ORE\nPool Balance: {:.11} ORE\nTop Stake:    {:.11} ORE\nPool Multiplier: {:.2}x\n----------------------\nActive Miners: {}\n----------------------\nMiner Submitted Difficulty: {}\nMiner Earned: {:.11} ORE\n{:.2}% of total pool reward",
-                                        msg.difficulty,
-                                        pool_rewards_dec,
-                                        msg.total_balance,
-                                        top_stake,
-                                        msg.multiplier,
-                                        len,
-                                        msg_submission.supplied_diff,
-                                        earned_rewards_dec,
-                                        percentage
-                                    );
-                                    tokio::spawn(async move {
-                                        if let Ok(_) = socket_sender
-                                            .lock()
-                                            .await
-                                            .send(Message::Text(message.into()))
-                                            .await
-                                        {
-                                        } else {
-                                            tracing::error!(target: "server_log", "Failed to send client text");
-                                        }
-                                    });
-                                }
-                                ClientVersion::V2 => {
-                                    let server_message = ServerMessagePoolSubmissionResult::new(
-                                        msg.difficulty,
-                                        msg.total_balance,
-                                        pool_rewards_dec,
-                                        top_stake,
-                                        msg.multiplier,
-                                        len as u32,
-                                        msg.challenge,
-                                        msg.best_nonce,
-                                        msg_submission.supplied_diff as u32,
-                                        earned_rewards_dec,
-                                        percentage,
-                                    );
-                                    tokio::spawn(async move {
-                                        if let Ok(_) = socket_sender
-                                            .lock()
-                                            .await
-                                            .send(Message::Binary(
-                                                server_message.to_message_binary().into(),
-                                            ))
-                                            .await
-                                        {
-                                        } else {
-                                            tracing::error!(target: "server_log", "Failed to send client pool submission result binary message");
-                                        }
-                                    });
-                                }
-                            }
-                        }
+        tokio::select! {
+            msg = mine_success_receiver.recv() => {
+                match msg {
+                    Some(msg) => {
+                        process_mine_success(msg, &app_shared_state, &app_database, &app_config, &app_wallet, &app_cache_last_challenge_rewards, &app_metrics_sender).await;
+                    },
+                    None => {
+                        break;
                     }
                 }
+            }
+            _ = shutdown.changed() => {
+                info!(target: "server_log", "Shutdown requested, draining pending mine success messages.");
+                mine_success_receiver.close();
+                let mut drained = 0;
+                while let Some(msg) = mine_success_receiver.recv().await {
+                    process_mine_success(msg, &app_shared_state, &app_database, &app_config, &app_wallet, &app_cache_last_challenge_rewards, &app_metrics_sender).await;
+                    drained += 1;
+                }
+                info!(target: "server_log", "Drained {} pending mine success messages.", drained);
+                break;
+            }
+        }
+    }
+}
 
-                info!(target: "server_log", "{} - Finished processing submission results in {}ms for challenge: {}.", id, instant.elapsed().as_millis(), c);
-
-                let batch_size = 1000;
-                let instant = Instant::now();
-                info!(target: "server_log", "{} - Adding submissions", id);
-                if i_submissions.len() > 0 {
-                    for batch in i_submissions.chunks(batch_size) {
-                        info!(target: "server_log", "{} - Submissions batch size: {}", id, batch.len());
-                        while let Err(_) =
-                            app_database.add_new_submissions_batch(batch.to_vec()).await
-                        {
-                            tracing::error!(target: "server_log", "{} - Failed to add new submissions batch. Retrying...", id);
-                            tokio::time::sleep(Duration::from_millis(500)).await;
-                        }
-                        tokio::time::sleep(Duration::from_millis(200)).await;
-                    }
+async fn process_mine_success(
+    msg: MessageInternalMineSuccess,
+    app_shared_state: &Arc<RwLock<AppState>>,
+    app_database: &Arc<AppDatabase>,
+    app_config: &Arc<Config>,
+    app_wallet: &Arc<WalletExtension>,
+    app_cache_last_challenge_rewards: &Arc<RwLock<LastChallengeRewardsCache>>,
+    app_metrics_sender: &UnboundedSender<AppMetricsEvent>,
+) {
+    let id = uuid::Uuid::new_v4();
+    let c = BASE64_STANDARD.encode(msg.challenge);
+    info!(target: "server_log", "{} - Processing internal mine success for challenge: {}", id, c);
+    let mut retry_budget = RetryBudget::new(
+        app_config.distribution_retry_max,
+        Duration::from_secs(app_config.distribution_retry_max_secs),
+    );
 
-                    info!(target: "server_log", "{} - Successfully added submissions batch", id);
+    loop {
+        match app_database.is_challenge_rewards_distributed(msg.challenge_id).await {
+            Ok(true) => {
+                tracing::error!(target: "server_log", "{} - Rewards already distributed for challenge_id: {}. Skipping.", id, msg.challenge_id);
+                return;
+            },
+            Ok(false) => break,
+            Err(_) => {
+                if !retry_budget.try_consume() {
+                    tracing::error!(target: "server_log", "{} - CRITICAL: Could not check if rewards were distributed for challenge_id: {}. Aborting distribution.", id, msg.challenge_id);
+                    return;
                 }
-                info!(target: "server_log", "{} - Added {} submissions in {}ms", id, i_submissions.len(), instant.elapsed().as_millis());
-
+                tracing::error!(target: "server_log", "{} - Failed to check if rewards were distributed for challenge_id: {}. Retrying...", id, msg.challenge_id);
                 tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        }
+    }
 
-                let batch_size = 400;
-                let instant = Instant::now();
-                info!(target: "server_log", "{} - Updating rewards", id);
-                if i_rewards.len() > 0 {
-                    let mut batch_num = 1;
-                    for batch in i_rewards.chunks(batch_size) {
-                        let instant = Instant::now();
-                        info!(target: "server_log", "{} - Updating reward batch {}", id, batch_num);
-                        while let Err(_) = app_database.update_rewards(batch.to_vec()).await {
-                            tracing::error!(target: "server_log", "{} - Failed to update rewards in db. Retrying...", id);
-                            tokio::time::sleep(Duration::from_millis(500)).await;
-                        }
-                        info!(target: "server_log", "{} - Updated reward batch {} in {}ms", id, batch_num, instant.elapsed().as_millis());
-                        batch_num += 1;
-                        tokio::time::sleep(Duration::from_millis(200)).await;
-                    }
-                    info!(target: "server_log", "{} - Successfully updated rewards", id);
-                }
-                info!(target: "server_log", "{} - Updated rewards in {}ms", id, instant.elapsed().as_millis());
+    let instant = Instant::now();
+    info!(target: "server_log", "{} - Getting sockets.", id);
+    let shared_state = app_shared_state.read().await;
+    let len = shared_state.sockets.len();
+    let socks = shared_state.sockets.clone();
+    drop(shared_state);
+    info!(target: "server_log", "{} - Got sockets in {}.", id, instant.elapsed().as_millis());
 
-                tokio::time::sleep(Duration::from_millis(500)).await;
+    let mut i_rewards = Vec::new();
+    // lines up with i_rewards, top_earners is trimmed as it fills so it can't be indexed
+    let mut reward_pubkeys = Vec::new();
+    let mut i_submissions = Vec::new();
 
-                let instant = Instant::now();
-                info!(target: "server_log", "{} - Updating pool rewards", id);
-                while let Err(_) = app_database
-                    .update_pool_rewards(
-                        app_wallet.miner_wallet.pubkey().to_string(),
-                        msg.rewards,
-                    )
-                    .await
-                {
-                    tracing::error!(target: "server_log",
-                        "{} - Failed to update pool rewards! Retrying...", id
-                    );
-                    tokio::time::sleep(Duration::from_millis(1000)).await;
-                }
-                info!(target: "server_log", "{} - Updated pool rewards in {}ms", id, instant.elapsed().as_millis());
-
-                tokio::time::sleep(Duration::from_millis(200)).await;
-
-                let instant = Instant::now();
-                info!(target: "server_log", "{} - Updating challenge rewards", id);
-                if let Ok(s) = app_database
-                    .get_submission_id_with_nonce(msg.best_nonce)
-                    .await
-                {
-                    if let Err(_) = app_database
-                        .update_challenge_rewards(msg.challenge.to_vec(), s, msg.rewards)
-                        .await
-                    {
-                        tracing::error!(target: "server_log", "{} - Failed to update challenge rewards! Skipping! Devs check!", id);
-                        let err_str = format!("{} - Challenge UPDATE FAILED - Challenge: {:?}\nSubmission ID: {}\nRewards: {}\n", id, msg.challenge.to_vec(), s, msg.rewards);
-                        tracing::error!(target: "server_log", err_str);
-                    }
-                    info!(target: "server_log", "{} - Updated challenge rewards in {}ms", id, instant.elapsed().as_millis());
-                } else {
-                    tracing::error!(target: "server_log", "{} - Failed to get submission id with nonce: {} for challenge_id: {}", id, msg.best_nonce, msg.challenge_id);
-                    tracing::error!(target: "server_log", "{} - Failed update challenge rewards!", id);
-                    let mut found_best_nonce = false;
-                    for submission in i_submissions {
-                        if submission.nonce == msg.best_nonce {
-                            found_best_nonce = true;
-                            break;
-                        }
-                    }
+    let instant = Instant::now();
+    info!(target: "server_log", "{} - Processing submission results for challenge: {}.", id, c);
+    let staker_rewards = if !msg.global_boosts_active {
+        staker_reward_share(msg.rewards, msg.commissions, app_config.staker_reward_share_bps)
+    } else {
+        info!(target: "server_log", "{} - Global Boosts Active, Staking rewards are 0", id);
+        0
+    };
+
+    let total_rewards = msg.rewards.saturating_sub(msg.commissions).saturating_sub(staker_rewards);
+    info!(target: "server_log", "{} - Miners Rewards: {}", id, total_rewards);
+    info!(target: "server_log", "{} - Commission: {}", id, msg.commissions);
+    info!(target: "server_log", "{} - Staker Rewards: {}", id, staker_rewards);
+    let mut total_submissions = 0;
+    let mut best_nonce_buffered = false;
+    let mut total_miners_earned_rewards = 0;
+    let mut accepted_hashpower: u64 = 0;
+    let mut top_earners = Vec::new();
+
+    // rejected shares come out of the total so their hashpower does not dilute everyone else
+    let mut rejected_hashpower: u64 = 0;
+    let verified_submissions: Vec<_> = msg.submissions.iter().filter(|(miner_pubkey, msg_submission)| {
+        if submission_is_valid(&msg.challenge, msg_submission) {
+            return true;
+        }
+        tracing::error!(target: "server_log", "{} - Rejecting submission from {}: nonce {} with difficulty {} does not verify against the challenge", id, miner_pubkey, msg_submission.supplied_nonce, msg_submission.supplied_diff);
+        rejected_hashpower = rejected_hashpower.saturating_add(msg_submission.hashpower);
+        false
+    }).collect();
+    let total_hashpower = msg.total_hashpower.saturating_sub(rejected_hashpower);
+
+    for (miner_pubkey, msg_submission) in verified_submissions {
+        let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+        let earned_rewards = hashpower_share(total_rewards, msg_submission.hashpower, total_hashpower);
+        total_miners_earned_rewards += earned_rewards;
+        accepted_hashpower += msg_submission.hashpower;
+
+        let new_submission = InsertSubmission {
+            miner_id: msg_submission.miner_id,
+            challenge_id: msg.challenge_id,
+            nonce: msg_submission.supplied_nonce,
+            difficulty: msg_submission.supplied_diff as i8,
+        };
 
-                    if found_best_nonce {
-                        info!(target: "server_log", "{} - Found best nonce in i_submissions", id);
-                    } else {
-                        info!(target: "server_log", "{} - Failed to find best nonce in i_submissions", id);
+        let new_reward = UpdateReward {
+            miner_id: msg_submission.miner_id,
+            balance: earned_rewards,
+        };
+
+        if new_submission.nonce == msg.best_nonce {
+            best_nonce_buffered = true;
+        }
+        i_rewards.push(new_reward);
+        reward_pubkeys.push(miner_pubkey.to_string());
+        i_submissions.push(new_submission);
+        total_submissions += 1;
+        top_earners.push(ChallengeTopEarner {
+            pubkey: miner_pubkey.to_string(),
+            earned: earned_rewards,
+        });
+        if top_earners.len() >= LAST_CHALLENGE_TOP_EARNERS * 2 {
+            top_earners.sort_by(|a, b| b.earned.cmp(&a.earned));
+            top_earners.truncate(LAST_CHALLENGE_TOP_EARNERS);
+        }
+
+        let earned_rewards_dec = (earned_rewards as f64).div(decimals);
+        let pool_rewards_dec = (msg.rewards as f64).div(decimals);
+
+        let percentage = if pool_rewards_dec != 0.0 {
+            (earned_rewards_dec / pool_rewards_dec) * 100.0
+        } else {
+            0.0 // Handle the case where pool_rewards_dec is 0 to avoid division by zero
+        };
+
+        let top_stake = 1.0f64;
+
+        for (_addr, client_connection) in socks.iter() {
+            if client_connection.pubkey.eq(&miner_pubkey) && client_connection.is_subscribed(CHANNEL_REWARDS) {
+                let socket_sender = client_connection.socket.clone();
+
+                match client_connection.client_version {
+                    ClientVersion::V1 => {
+                        let message = format!(
+                            "Pool Submitted Difficulty: {}\nPool Earned:  {:.11} ORE\nPool Balance: {:.11} ORE\nTop Stake:    {:.11} ORE\nPool Multiplier: {:.2}x\n----------------------\nActive Miners: {}\n----------------------\nMiner Submitted Difficulty: {}\nMiner Earned: {:.11} ORE\n{:.2}% of total pool reward",
+                            msg.difficulty,
+                            pool_rewards_dec,
+                            msg.total_balance,
+                            top_stake,
+                            msg.multiplier,
+                            len,
+                            msg_submission.supplied_diff,
+                            earned_rewards_dec,
+                            percentage
+                        );
+                        tokio::spawn(async move {
+                            if let Ok(_) = socket_sender
+                                .lock()
+                                .await
+                                .send(Message::Text(message.into()))
+                                .await
+                            {
+                            } else {
+                                tracing::error!(target: "server_log", "Failed to send client text");
+                            }
+                        });
+                    }
+                    ClientVersion::V2 => {
+                        let server_message = ServerMessagePoolSubmissionResult::new(
+                            msg.difficulty,
+                            msg.total_balance,
+                            pool_rewards_dec,
+                            top_stake,
+                            msg.multiplier,
+                            len as u32,
+                            msg.challenge,
+                            msg.best_nonce,
+                            msg_submission.supplied_diff as u32,
+                            earned_rewards_dec,
+                            percentage,
+                        );
+                        tokio::spawn(async move {
+                            if let Ok(_) = socket_sender
+                                .lock()
+                                .await
+                                .send(Message::Binary(
+                                    server_message.to_message_binary().into(),
+                                ))
+                                .await
+                            {
+                            } else {
+                                tracing::error!(target: "server_log", "Failed to send client pool submission result binary message");
+                            }
+                        });
                     }
                 }
+            }
+        }
+    }
 
-                if msg.global_boosts_active {
-                    info!(target: "server_log", "{} - Global Boosts Active, skipping processing of staker rewards.", id);
-                    info!(target: "server_log", "{} - Skipping unclaimed rewards bonuses.", id);
-                } else {
-                    info!(target: "server_log", "{} - Processing stakers rewards", id);
-                    process_stakers_rewards(msg.rewards, staker_rewards, &app_database, &app_config).await;
-                    info!(target: "server_log", "{} - Total Distributed For Miners: {}", id, total_miners_earned_rewards);
-                }
+    info!(target: "server_log", "{} - Finished processing submission results in {}ms for challenge: {}.", id, instant.elapsed().as_millis(), c);
+
+    // Non-best shares are only recorded, they never earn rewards.
+    for superseded in msg.superseded_submissions.iter() {
+        i_submissions.push(InsertSubmission {
+            miner_id: superseded.miner_id,
+            challenge_id: msg.challenge_id,
+            nonce: superseded.supplied_nonce,
+            difficulty: superseded.supplied_diff as i8,
+        });
+        total_submissions += 1;
+    }
 
-                info!(target: "server_log", "{} - Finished processing internal mine success for challenge: {}", id, c);
+    // Flooring each share leaves up to one unit per miner unassigned. Hand those out one
+    // unit each, starting at an offset that rotates with the challenge so no miner is favored.
+    let apportioned = hashpower_share(total_rewards, accepted_hashpower, total_hashpower);
+    let remainder = apportioned.saturating_sub(total_miners_earned_rewards);
+    if remainder > 0 && !i_rewards.is_empty() {
+        let start = (msg.challenge_id.unsigned_abs() as usize) % i_rewards.len();
+        let recipients = (remainder as usize).min(i_rewards.len());
+        let mut recipient_ids = Vec::with_capacity(recipients);
+        for i in 0..recipients {
+            let index = (start + i) % i_rewards.len();
+            i_rewards[index].balance += 1;
+            if let Some(earner) = top_earners.iter_mut().find(|e| e.pubkey == reward_pubkeys[index]) {
+                earner.earned += 1;
             }
+            recipient_ids.push(i_rewards[index].miner_id);
+        }
+        total_miners_earned_rewards += recipients as u64;
+        info!(target: "server_log", "{} - Reward remainder of {} for challenge_id: {} assigned to miner ids: {:?}", id, remainder, msg.challenge_id, recipient_ids);
+        if let Err(_) = app_metrics_sender.send(AppMetricsEvent::RewardRemainderEvent(MetricsRewardRemainderEventData {
+            challenge_id: msg.challenge_id,
+            remainder,
+            recipients,
+        })) {
+            tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
         }
     }
-}
 
-pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, app_database: &Arc<AppDatabase>, app_config: &Arc<Config>) {
-    let ore_rewards = (total_rewards as u128).saturating_mul(ORE_STAKE_PERCENTAGE as u128).saturating_div(100) as u64;
-    let ore_sol_rewards = (total_rewards as u128).saturating_mul(ORE_SOL_STAKE_PERCENTAGE as u128).saturating_div(100) as u64;
-    let ore_isc_rewards = (total_rewards as u128).saturating_mul(ORE_ISC_STAKE_PERCENTAGE as u128).saturating_div(100) as u64;
+    top_earners.sort_by(|a, b| b.earned.cmp(&a.earned));
+    top_earners.truncate(LAST_CHALLENGE_TOP_EARNERS);
+    let mut writer = app_cache_last_challenge_rewards.write().await;
+    writer.item = Some(LastChallengeRewards {
+        challenge_id: msg.challenge_id,
+        total_rewards: total_miners_earned_rewards,
+        miner_count: msg.submissions.len(),
+        top_earners,
+    });
+    writer.last_updated_at = Instant::now();
+    drop(writer);
 
-    info!(target: "server_log", "Total Rewards: {}", total_rewards);
-    info!(target: "server_log", "ore Rewards ({}%): {}", ORE_STAKE_PERCENTAGE, ore_rewards);
-    info!(target: "server_log", "ore-sol Rewards ({}%): {}", ORE_SOL_STAKE_PERCENTAGE, ore_sol_rewards);
-    info!(target: "server_log", "ore-isc Rewards ({}%): {}", ORE_ISC_STAKE_PERCENTAGE, ore_isc_rewards);
+    let instant = Instant::now();
+    info!(target: "server_log", "{} - Updating rewards", id);
+    let outcome = loop {
+        match app_database.distribute_challenge_rewards(
+            app_config.pool_id,
+            msg.challenge_id,
+            i_rewards.clone(),
+            i_submissions.clone(),
+            msg.best_nonce,
+            msg.rewards,
+            app_config.min_reward_credit,
+        ).await {
+            Ok(outcome) => break outcome,
+            Err(_) => {
+                if !retry_budget.try_consume() {
+                    tracing::error!(target: "server_log", "{} - Retry budget exhausted distributing rewards for challenge_id: {}. Aborting distribution.", id, msg.challenge_id);
+                    return;
+                }
+                tracing::error!(target: "server_log", "{} - Failed to update rewards in db. Retrying...", id);
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        }
+    };
+    let best_submission_id = match outcome {
+        DistributionOutcome::AlreadyDistributed => {
+            tracing::error!(target: "server_log", "{} - Rewards already distributed for challenge_id: {}. Skipping remaining distribution.", id, msg.challenge_id);
+            return;
+        }
+        DistributionOutcome::Distributed { best_submission_id } => best_submission_id,
+    };
+    info!(target: "server_log", "{} - Successfully updated rewards", id);
+    info!(target: "server_log", "{} - Added {} submissions and updated rewards in {}ms", id, total_submissions, instant.elapsed().as_millis());
+    if best_submission_id.is_none() {
+        tracing::error!(target: "server_log", "{} - No submission found with nonce: {} for challenge_id: {}", id, msg.best_nonce, msg.challenge_id);
+        if best_nonce_buffered {
+            info!(target: "server_log", "{} - Found best nonce in i_submissions", id);
+        } else {
+            info!(target: "server_log", "{} - Failed to find best nonce in i_submissions", id);
+        }
+    }
 
-    if ore_rewards + ore_sol_rewards + ore_isc_rewards > staker_rewards {
-        tracing::error!(target: "server_log", "Calculations exceeded max staker rewards of 40%!!!");
-        return;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let instant = Instant::now();
+    info!(target: "server_log", "{} - Updating pool rewards", id);
+    while let Err(_) = app_database
+        .update_pool_rewards(
+            app_wallet.miner_wallet.pubkey().to_string(),
+            msg.rewards,
+        )
+        .await
+    {
+        tracing::error!(target: "server_log",
+            "{} - Failed to update pool rewards for challenge_id: {}! Retrying...", id, msg.challenge_id
+        );
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+    }
+    info!(target: "server_log", "{} - Updated pool rewards in {}ms", id, instant.elapsed().as_millis());
+
+    if msg.global_boosts_active {
+        info!(target: "server_log", "{} - Global Boosts Active, skipping processing of staker rewards.", id);
+        info!(target: "server_log", "{} - Skipping unclaimed rewards bonuses.", id);
+    } else {
+        info!(target: "server_log", "{} - Processing stakers rewards", id);
+        process_stakers_rewards(msg.rewards, staker_rewards, msg.challenge_id, app_shared_state, app_database, app_config).await;
+        info!(target: "server_log", "{} - Total Distributed For Miners: {}", id, total_miners_earned_rewards);
     }
 
+    info!(target: "server_log", "{} - Finished processing internal mine success for challenge: {}", id, c);
+}
+
+/// Runs the equix check on a buffered share against the challenge it was submitted for, and
+/// confirms the difficulty it was recorded with.
+pub fn submission_is_valid(challenge: &[u8; 32], submission: &InternalMessageSubmission) -> bool {
+    let solution = Solution::new(submission.supplied_digest, submission.supplied_nonce.to_le_bytes());
+    solution.is_valid(challenge) && solution.to_hash().difficulty() == submission.supplied_diff
+}
+
+/// The part of `rewards` earned by `hashpower` out of `total_hashpower`, or nothing if no
+/// hashpower was accepted.
+pub fn hashpower_share(rewards: u64, hashpower: u64, total_hashpower: u64) -> u64 {
+    (rewards as u128)
+        .saturating_mul(hashpower as u128)
+        .checked_div(total_hashpower as u128)
+        .unwrap_or(0) as u64
+}
+
+/// Stakers get `share_bps` of the challenge reward, capped so that commissions,
+/// stakers and miners always add up to exactly `rewards`.
+pub fn staker_reward_share(rewards: u64, commissions: u64, share_bps: u16) -> u64 {
+    let share = (rewards as u128).saturating_mul(share_bps as u128).saturating_div(10_000) as u64;
+    share.min(rewards.saturating_sub(commissions))
+}
+
+/// Runs after the miner rewards have committed, so every write here retries until it lands.
+pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, challenge_id: i32, app_shared_state: &Arc<RwLock<AppState>>, app_database: &Arc<AppDatabase>, app_config: &Arc<Config>) {
+    // split the staker share between mints in the ratio of their stake percentages
+    let ore_rewards = (staker_rewards as u128).saturating_mul(ORE_STAKE_PERCENTAGE as u128).saturating_div(TOTAL_STAKER_PERCENTAGE as u128) as u64;
+    let ore_sol_rewards = (staker_rewards as u128).saturating_mul(ORE_SOL_STAKE_PERCENTAGE as u128).saturating_div(TOTAL_STAKER_PERCENTAGE as u128) as u64;
+    let ore_isc_rewards = staker_rewards - ore_rewards - ore_sol_rewards;
+
+    info!(target: "server_log", "Total Rewards: {}", total_rewards);
+    info!(target: "server_log", "Staker Rewards ({} bps): {}", app_config.staker_reward_share_bps, staker_rewards);
+    info!(target: "server_log", "ore Rewards: {}", ore_rewards);
+    info!(target: "server_log", "ore-sol Rewards: {}", ore_sol_rewards);
+    info!(target: "server_log", "ore-isc Rewards: {}", ore_isc_rewards);
+
     // get all the stake accounts for ore mint
     let mut ore_stake_accounts = vec![]; 
     let mut total_ore_boosted = 0;
@@ -375,14 +527,21 @@ pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, ap
     tracing::info!(target: "server_log", "Total {} ore-isc boosted.", total_ore_isc_boosted as f64 / 10f64.powf(ORE_TOKEN_DECIMALS as f64));
 
     let mut update_stake_rewards = vec![];
+    let mut staker_reward_events = vec![];
     let mut total_distributed_for_ore = 0;
     if total_ore_boosted > 0 {
         for ore_stake_account in ore_stake_accounts.iter() {
             let rewards_balance = (ore_rewards as u128 * ore_stake_account.staked_balance as u128 / total_ore_boosted as u128) as u64;
             let stake_rewards = UpdateStakeAccountRewards {
                 stake_pda: ore_stake_account.stake_pda.clone(),
+                mint_pubkey: ore_stake_account.mint_pubkey.clone(),
                 rewards_balance,
             };
+            staker_reward_events.push(StakerRewardEvent {
+                staker_pubkey: ore_stake_account.staker_pubkey.clone(),
+                mint: ore_stake_account.mint_pubkey.clone(),
+                amount: rewards_balance,
+            });
             total_distributed_for_ore += rewards_balance;
             update_stake_rewards.push(stake_rewards);
         }
@@ -394,8 +553,14 @@ pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, ap
             let rewards_balance = (ore_sol_rewards as u128 * ore_sol_stake_account.staked_balance as u128 / total_ore_sol_boosted as u128) as u64;
             let stake_rewards = UpdateStakeAccountRewards {
                 stake_pda: ore_sol_stake_account.stake_pda.clone(),
+                mint_pubkey: ore_sol_stake_account.mint_pubkey.clone(),
                 rewards_balance,
             };
+            staker_reward_events.push(StakerRewardEvent {
+                staker_pubkey: ore_sol_stake_account.staker_pubkey.clone(),
+                mint: ore_sol_stake_account.mint_pubkey.clone(),
+                amount: rewards_balance,
+            });
             total_distributed_for_ore_sol += rewards_balance;
             update_stake_rewards.push(stake_rewards);
         }
@@ -407,8 +572,14 @@ pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, ap
             let rewards_balance = (ore_isc_rewards as u128 * ore_isc_stake_account.staked_balance as u128 / total_ore_isc_boosted as u128) as u64;
             let stake_rewards = UpdateStakeAccountRewards {
                 stake_pda: ore_isc_stake_account.stake_pda.clone(),
+                mint_pubkey: ore_isc_stake_account.mint_pubkey.clone(),
                 rewards_balance,
             };
+            staker_reward_events.push(StakerRewardEvent {
+                staker_pubkey: ore_isc_stake_account.staker_pubkey.clone(),
+                mint: ore_isc_stake_account.mint_pubkey.clone(),
+                amount: rewards_balance,
+            });
             total_distributed_for_ore_isc += rewards_balance;
             update_stake_rewards.push(stake_rewards);
         }
@@ -426,12 +597,24 @@ pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, ap
      info!(target: "server_log", "Updating staking rewards");
      if update_stake_rewards.len() > 0 {
          let mut batch_num = 1;
-         for batch in update_stake_rewards.chunks(batch_size) {
+         for (batch, batch_events) in update_stake_rewards.chunks(batch_size).zip(staker_reward_events.chunks(batch_size)) {
              let instant = Instant::now();
              info!(target: "server_log", "Updating stake reward batch {}", batch_num);
-             while let Err(_) = app_database.update_stake_accounts_rewards(batch.to_vec()).await {
-                 tracing::error!(target: "server_log", "Failed to update rewards in db. Retrying...");
-                 tokio::time::sleep(Duration::from_millis(500)).await;
+             loop {
+                 match app_database.update_stake_accounts_rewards(batch.to_vec()).await {
+                     Ok(_) => {
+                         notify_staker_rewards(challenge_id, batch_events, app_shared_state).await;
+                         break;
+                     },
+                     Err(AppDatabaseError::InvalidInput(e)) => {
+                         tracing::error!(target: "server_log", "Skipping invalid stake reward batch {}: {}", batch_num, e);
+                         break;
+                     }
+                     Err(_) => {
+                         tracing::error!(target: "server_log", "Failed to update stake reward batch {} for challenge_id: {}. Retrying...", batch_num, challenge_id);
+                         tokio::time::sleep(Duration::from_millis(500)).await;
+                     }
+                 }
              }
              info!(target: "server_log", "Updated reward batch {} in {}ms", batch_num, instant.elapsed().as_millis());
              batch_num += 1;
@@ -443,6 +626,24 @@ pub async fn process_stakers_rewards(total_rewards: u64, staker_rewards: u64, ap
 }
 
 
+struct StakerRewardEvent {
+    staker_pubkey: String,
+    mint: String,
+    amount: u64,
+}
+
+async fn notify_staker_rewards(challenge_id: i32, events: &[StakerRewardEvent], app_shared_state: &Arc<RwLock<AppState>>) {
+    let shared_state = app_shared_state.read().await;
+    for event in events.iter().filter(|e| e.amount > 0) {
+        shared_state.reward_router.route(&MessageInternalReward {
+            staker_pubkey: event.staker_pubkey.clone(),
+            challenge_id,
+            mint: event.mint.clone(),
+            amount: event.amount,
+        });
+    }
+}
+
 pub async fn process_unclaimed_bonus_rewards(total_rewards: u64, bonus_rewards: u64, app_database: &Arc<AppDatabase>, app_config: &Arc<Config>) {
 
     // get all the rewards accounts for miners
@@ -509,6 +710,7 @@ pub async fn process_unclaimed_bonus_rewards(total_rewards: u64, bonus_rewards:
             let rewards_balance = (bonus_rewards as u128 * stake_account.rewards_balance as u128 / total_unclaimed_amount as u128) as u64;
             let stake_rewards = UpdateStakeAccountRewards {
                 stake_pda: stake_account.stake_pda.clone(),
+                mint_pubkey: stake_account.mint_pubkey.clone(),
                 rewards_balance,
             };
             total_distributed_for_stakers += rewards_balance;
@@ -542,9 +744,18 @@ pub async fn process_unclaimed_bonus_rewards(total_rewards: u64, bonus_rewards:
          for batch in update_stake_rewards.chunks(batch_size) {
              let instant = Instant::now();
              info!(target: "server_log", "Updating stake reward batch {}", batch_num);
-             while let Err(_) = app_database.update_stake_accounts_rewards(batch.to_vec()).await {
-                 tracing::error!(target: "server_log", "Failed to update rewards in db. Retrying...");
-                 tokio::time::sleep(Duration::from_millis(500)).await;
+             loop {
+                 match app_database.update_stake_accounts_rewards(batch.to_vec()).await {
+                     Ok(_) => break,
+                     Err(AppDatabaseError::InvalidInput(e)) => {
+                         tracing::error!(target: "server_log", "Skipping invalid stake reward batch {}: {}", batch_num, e);
+                         break;
+                     }
+                     Err(_) => {
+                         tracing::error!(target: "server_log", "Failed to update rewards in db. Retrying...");
+                         tokio::time::sleep(Duration::from_millis(500)).await;
+                     }
+                 }
              }
              info!(target: "server_log", "Updated reward batch {} in {}ms", batch_num, instant.elapsed().as_millis());
              batch_num += 1;
@@ -555,8 +766,57 @@ pub async fn process_unclaimed_bonus_rewards(total_rewards: u64, bonus_rewards:
     info!(target: "server_log", "Updated rewards in {}ms", instant.elapsed().as_millis());
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn retry_budget_runs_out_after_max_retries() {
+        let mut budget = RetryBudget::new(2, Duration::from_secs(60));
+        assert!(budget.try_consume());
+        assert!(budget.try_consume());
+        assert!(!budget.try_consume());
+    }
 
+    #[test]
+    fn retry_budget_runs_out_at_the_deadline() {
+        let mut budget = RetryBudget::new(10, Duration::ZERO);
+        assert!(!budget.try_consume());
+    }
 
+    #[test]
+    fn staker_share_is_taken_in_basis_points() {
+        assert_eq!(staker_reward_share(10_000, 500, 1_000), 1_000);
+        assert_eq!(staker_reward_share(10_000, 500, 0), 0);
+        assert_eq!(staker_reward_share(u64::MAX, 0, 10_000), u64::MAX);
+    }
 
+    #[test]
+    fn staker_share_never_eats_into_commissions() {
+        assert_eq!(staker_reward_share(10_000, 9_500, 1_000), 500);
+        assert_eq!(staker_reward_share(10_000, 12_000, 1_000), 0);
+    }
 
+    #[test]
+    fn submission_that_fails_equix_is_rejected() {
+        let submission = InternalMessageSubmission {
+            miner_id: 1,
+            supplied_diff: 0,
+            supplied_nonce: 7,
+            supplied_digest: [0; 16],
+            hashpower: 1,
+        };
+        assert!(!submission_is_valid(&[1; 32], &submission));
+    }
+
+    #[test]
+    fn hashpower_share_splits_by_accepted_hashpower() {
+        assert_eq!(hashpower_share(1_000, 1, 4), 250);
+        assert_eq!(hashpower_share(1_000, 3, 3), 1_000);
+    }
+
+    #[test]
+    fn hashpower_share_is_zero_without_accepted_hashpower() {
+        assert_eq!(hashpower_share(1_000, 0, 0), 0);
+    }
+}
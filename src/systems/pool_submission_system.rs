@@ -7,7 +7,7 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use ore_miner_delegation::{pda::{delegated_boost_pda, managed_proof_pda}, state::DelegatedBoost, utils::AccountDeserializeV1};
-use crate::{app_metrics::{AppMetricsEvent, AppMetricsMineEvent}, global_boost_util::{get_proof_and_config_with_busses}, ore_utils::{get_proof_pda, get_rotate_ix}};
+use crate::{app_metrics::{AppMetricsEvent, AppMetricsMineEvent, MetricsMineCycleEventData, MetricsRpcFetchFailureEventData, MineCyclePhase}, global_boost_util::{get_proof_and_config_with_busses}, ore_utils::{get_challenge_end_at, get_proof_pda, get_rotate_ix}};
 
 use base64::{prelude::BASE64_STANDARD, Engine};
 use ore_api::{consts::BUS_COUNT, event::MineEvent, state::{Proof, proof_pda}};
@@ -18,6 +18,7 @@ use solana_client::{
 };
 use solana_sdk::{
     compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
     instruction::InstructionError,
     native_token::lamports_to_sol,
     pubkey::Pubkey,
@@ -28,24 +29,27 @@ use solana_sdk::{
 };
 use solana_transaction_status::{TransactionConfirmationStatus, UiTransactionEncoding};
 use tokio::{
-    sync::{mpsc::UnboundedSender, Mutex, RwLock},
+    sync::{mpsc::{Sender, UnboundedSender}, watch, Mutex, RwLock},
+    task::JoinSet,
     time::Instant,
 };
 use tracing::info;
 
 use crate::{
-    app_database::AppDatabase, ore_utils::{
-        get_auth_ix, get_cutoff, get_mine_with_global_boost_ix, get_reset_ix, MineEventWithBoosts, MineEventWithGlobalBoosts, ORE_TOKEN_DECIMALS
-    }, Config, EpochHashes, InsertChallenge, InsertTxn, MessageInternalAllClients, MessageInternalMineSuccess, SubmissionWindow, UpdateReward, WalletExtension
+    app_database::AppDatabase, nonce_allocator::NonceAllocator, rpc_budget::{RpcBudget, RpcPriority}, ore_utils::{
+        get_auth_ix, get_cutoff, get_mine_ix, get_mine_with_global_boost_ix, get_reset_ix, select_bus, parse_mine_event, ParsedMineEvent, ORE_TOKEN_DECIMALS
+    }, Config, EpochHashes, InsertChallenge, InsertSubmission, InsertTxn, MessageInternalAllClients, CHANNEL_STATUS, LatestBlockhashCache, MessageInternalMineSuccess, SubmissionWindow, UpdateReward, WalletExtension
 };
 
 
 
+const FETCH_FAILURE_BACKOFF_MS: u64 = 500;
+
 pub async fn pool_submission_system(
     app_proof: Arc<Mutex<Proof>>,
     app_epoch_hashes: Arc<RwLock<EpochHashes>>,
     app_wallet: Arc<WalletExtension>,
-    app_nonce: Arc<Mutex<u64>>,
+    app_nonce: Arc<Mutex<NonceAllocator>>,
     app_prio_fee: Arc<u64>,
     app_jito_tip: Arc<u64>,
     rpc_client: Arc<RpcClient>,
@@ -53,18 +57,32 @@ pub async fn pool_submission_system(
     config: Arc<Config>,
     app_database: Arc<AppDatabase>,
     app_all_clients_sender: UnboundedSender<MessageInternalAllClients>,
-    mine_success_sender: UnboundedSender<MessageInternalMineSuccess>,
+    mine_success_sender: Sender<MessageInternalMineSuccess>,
     app_submission_window: Arc<RwLock<SubmissionWindow>>,
     app_client_nonce_ranges: Arc<RwLock<HashMap<Pubkey, Vec<Range<u64>>>>>,
     app_last_challenge: Arc<Mutex<[u8; 32]>>,
     app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+    rpc_budget: Arc<RpcBudget>,
+    latest_blockhash_cache: Arc<RwLock<LatestBlockhashCache>>,
+    shutdown: watch::Receiver<bool>,
 ) {
+    let mut last_bus = 0;
+    let mut fetch_failures: u32 = 0;
+    // tasks that read the MineEvent of a landed transaction and queue its distribution
+    let mut mine_success_reporters = JoinSet::new();
     loop {
+        while mine_success_reporters.try_join_next().is_some() {}
+        // only stop between mine cycles, never with a transaction in flight
+        if *shutdown.borrow() {
+            info!(target: "server_log", "Shutdown requested, stopping pool submission system.");
+            break;
+        }
+
         let lock = app_proof.lock().await;
         let old_proof = lock.clone();
         drop(lock);
 
-        let cutoff = get_cutoff(old_proof, 3);
+        let cutoff = get_cutoff(old_proof, 3, config.clock_offset_secs);
         if cutoff <= 0 {
             // process solutions
             let reader = app_epoch_hashes.read().await;
@@ -76,10 +94,11 @@ pub async fn pool_submission_system(
                 let mut writer = app_submission_window.write().await;
                 writer.closed = true;
                 drop(writer);
+                let observed_at = Instant::now();
 
                 let signer = app_wallet.clone().miner_wallet.clone();
 
-                let bus = rand::thread_rng().gen_range(0..BUS_COUNT);
+                let mut bus = rand::thread_rng().gen_range(0..BUS_COUNT);
 
                 let mut success = false;
                 let reader = app_epoch_hashes.read().await;
@@ -99,20 +118,53 @@ pub async fn pool_submission_system(
                         let mut loaded_config = None;
                         info!(target: "server_log", "Getting latest config and busses data.");
                         tokio::time::sleep(Duration::from_millis(1000)).await;
-                        if let (Ok(p), Ok(config), Ok(_busses)) =
-                            get_proof_and_config_with_busses(&rpc_client, signer.pubkey()).await
-                        {
-                            loaded_config = Some(config);
-
-                            info!(target: "server_log", "Latest Challenge: {}", BASE64_STANDARD.encode(p.challenge));
-
-                            if !best_solution.is_valid(&p.challenge) {
-                                tracing::error!(target: "server_log", "SOLUTION IS NOT VALID ANYMORE!");
-                                info!(target: "server_log", "Updating to latest proof.");
-                                let mut lock = app_proof.lock().await;
-                                *lock = p;
-                                drop(lock);
-                                break;
+                        rpc_budget.acquire("getMultipleAccounts", RpcPriority::Critical).await;
+                        match get_proof_and_config_with_busses(&config.program_ids, &rpc_client, signer.pubkey(), config.account_parse_policy).await {
+                            (Ok(p), Ok(ore_config), Ok(busses)) => {
+                                fetch_failures = 0;
+                                let missing_busses = busses.iter().filter(|b| b.is_err()).count();
+                                if missing_busses > 0 {
+                                    report_fetch_failure(&app_metrics_sender, true, true, true, missing_busses);
+                                }
+
+                                loaded_config = Some(ore_config);
+                                if let Some(b) = select_bus(&busses, config.bus_selection_policy, &mut last_bus) {
+                                    bus = b;
+                                }
+
+                                info!(target: "server_log", "Latest Challenge: {}", BASE64_STANDARD.encode(p.challenge));
+
+                                if !best_solution.is_valid(&p.challenge) {
+                                    tracing::error!(target: "server_log", "SOLUTION IS NOT VALID ANYMORE!");
+                                    send_mine_cycle_event(&app_metrics_sender, observed_at, None, None, Some(MineCyclePhase::Observe));
+                                    info!(target: "server_log", "Updating to latest proof.");
+                                    let mut lock = app_proof.lock().await;
+                                    *lock = p;
+                                    drop(lock);
+                                    break;
+                                }
+                            }
+                            (p, ore_config, busses) => {
+                                let missing_busses = match &busses {
+                                    Ok(busses) => busses.iter().filter(|b| b.is_err()).count(),
+                                    Err(_) => BUS_COUNT,
+                                };
+                                report_fetch_failure(&app_metrics_sender, p.is_ok(), ore_config.is_ok(), busses.is_ok(), missing_busses);
+                                if let Ok(ore_config) = ore_config {
+                                    loaded_config = Some(ore_config);
+                                }
+                                if let Ok(busses) = &busses {
+                                    if let Some(b) = select_bus(busses, config.bus_selection_policy, &mut last_bus) {
+                                        bus = b;
+                                    }
+                                }
+
+                                let backoff = FETCH_FAILURE_BACKOFF_MS.saturating_mul(1 << fetch_failures.min(4));
+                                fetch_failures += 1;
+                                tokio::time::sleep(Duration::from_millis(backoff)).await;
+                                if p.is_err() && loaded_config.is_none() && busses.is_err() {
+                                    continue;
+                                }
                             }
                         }
 
@@ -125,6 +177,7 @@ pub async fn pool_submission_system(
 
                         let _ = app_all_clients_sender.send(MessageInternalAllClients {
                             text: String::from("Server is sending mine transaction..."),
+                            channel: CHANNEL_STATUS,
                         });
 
                         let mut cu_limit = 565_000;
@@ -179,25 +232,58 @@ pub async fn pool_submission_system(
                             info!(target: "server_log", "Jito tip: {} SOL", lamports_to_sol(jito_tip));
                         }
 
-                        let noop_ix = get_auth_ix(signer.pubkey());
+                        let noop_ix = get_auth_ix(&config.program_ids, signer.pubkey());
                         ixs.push(noop_ix);
 
                         if should_add_reset_ix {
-                            let reset_ix = get_reset_ix(signer.pubkey());
+                            let reset_ix = get_reset_ix(&config.program_ids, signer.pubkey());
                             ixs.push(reset_ix);
                         }
 
-                        let ix_mine = get_mine_with_global_boost_ix(signer.pubkey(), best_solution, bus);
+                        let ix_mine = get_mine_with_global_boost_ix(&config.program_ids, signer.pubkey(), best_solution, bus);
                         ixs.push(ix_mine);
-
-                        if let Ok((hash, _slot)) = rpc_client
-                            .get_latest_blockhash_with_commitment(rpc_client.commitment())
-                            .await
-                        {
+                        let mine_ix_index = ixs.len() - 1;
+
+                        let latest_blockhash = if config.max_blockhash_age_secs > 0 {
+                            // sign with the cached blockhash so the staleness check guards the hash
+                            // the transaction actually uses. Wait at most max_age for it to refresh,
+                            // then give up on this attempt.
+                            let max_age = Duration::from_secs(config.max_blockhash_age_secs);
+                            let wait_started = Instant::now();
+                            loop {
+                                let cache = latest_blockhash_cache.read().await.clone();
+                                match stale_blockhash_age(&cache, max_age) {
+                                    Some(blockhash_age) => {
+                                        if *shutdown.borrow() || wait_started.elapsed() >= max_age {
+                                            tracing::warn!(target: "server_log",
+                                                "Giving up on mine submission attempt {}: cached blockhash is still {}s old (max {}s).",
+                                                i, blockhash_age.as_secs(), config.max_blockhash_age_secs
+                                            );
+                                            break None;
+                                        }
+                                        tracing::warn!(target: "server_log",
+                                            "Holding mine submission attempt {}: cached blockhash is {}s old (max {}s).",
+                                            i, blockhash_age.as_secs(), config.max_blockhash_age_secs
+                                        );
+                                        tokio::time::sleep(Duration::from_millis(1000)).await;
+                                    }
+                                    None => break decode_cached_blockhash(&cache.item),
+                                }
+                            }
+                        } else {
+                            rpc_budget.acquire("getLatestBlockhash", RpcPriority::Critical).await;
+                            rpc_client
+                                .get_latest_blockhash_with_commitment(rpc_client.commitment())
+                                .await
+                                .ok()
+                                .map(|(hash, _last_valid_block_height)| hash)
+                        };
+                        if let Some(hash) = latest_blockhash {
                             let mut tx = Transaction::new_with_payer(&ixs, Some(&signer.pubkey()));
 
                             let expired_timer = Instant::now();
                             tx.sign(&[&signer], hash);
+                            let built_at = Instant::now();
                             info!(target: "server_log", "Sending signed tx...");
                             info!(target: "server_log", "attempt: {}", i + 1);
                             let send_client = if jito_tip > 0 {
@@ -219,8 +305,9 @@ pub async fn pool_submission_system(
 
                             let sim_tx = tx.clone();
 
+                            rpc_budget.acquire("simulateTransaction", RpcPriority::Critical).await;
                             if let Ok(result) = rpc_client
-                                .simulate_transaction_with_config(&sim_tx, rpc_sim_config)
+                                .simulate_transaction_with_config(&sim_tx, rpc_sim_config.clone())
                                 .await
                             {
                                 if let Some(tx_error) = result.value.err {
@@ -236,13 +323,39 @@ pub async fn pool_submission_system(
                                             )
                                     {
                                         tracing::error!(target: "server_log", "Custom program error: Invalid Hash");
+                                        send_mine_cycle_event(&app_metrics_sender, observed_at, Some(built_at), None, Some(MineCyclePhase::Build));
                                         break;
                                     }
+
+                                    if config.boost_fallback && is_boost_account_error(&tx_error, mine_ix_index) {
+                                        tracing::error!(target: "server_log", "Boosted mine instruction failed simulation on a boost account: {:?}. Trying plain mine.", tx_error);
+                                        let mut plain_ixs = ixs.clone();
+                                        plain_ixs[mine_ix_index] = get_mine_ix(&config.program_ids, signer.pubkey(), best_solution, bus);
+                                        let mut plain_tx = Transaction::new_with_payer(&plain_ixs, Some(&signer.pubkey()));
+                                        plain_tx.sign(&[&signer], hash);
+                                        rpc_budget.acquire("simulateTransaction", RpcPriority::Critical).await;
+                                        match rpc_client
+                                            .simulate_transaction_with_config(&plain_tx, rpc_sim_config)
+                                            .await
+                                        {
+                                            Ok(plain_result) if plain_result.value.err.is_none() => {
+                                                tracing::warn!(target: "server_log", "Dropped boosts for this mine transaction, sending plain mine.");
+                                                tx = plain_tx;
+                                            }
+                                            Ok(plain_result) => {
+                                                tracing::error!(target: "server_log", "Plain mine instruction also failed simulation: {:?}. Keeping boosted mine.", plain_result.value.err);
+                                            }
+                                            Err(e) => {
+                                                tracing::error!(target: "server_log", "Failed to simulate plain mine instruction: {:?}. Keeping boosted mine.", e);
+                                            }
+                                        }
+                                    }
                                 }
                             }
 
                             let mut rpc_send_attempts = 1;
                             let signature = loop {
+                                rpc_budget.acquire("sendTransaction", RpcPriority::Critical).await;
                                 match send_client
                                     .send_transaction_with_config(&tx, rpc_config)
                                     .await
@@ -264,10 +377,12 @@ pub async fn pool_submission_system(
                             };
 
                             let signature = if signature.is_err() {
+                                send_mine_cycle_event(&app_metrics_sender, observed_at, Some(built_at), None, Some(MineCyclePhase::Send));
                                 break;
                             } else {
                                 signature.unwrap()
                             };
+                            let sent_at = Instant::now();
                             let (tx_message_sender, tx_message_receiver) =
                                 tokio::sync::oneshot::channel::<u8>();
                             let app_app_nonce = app_nonce.clone();
@@ -281,8 +396,10 @@ pub async fn pool_submission_system(
                             let app_app_submission_window = app_submission_window.clone();
                             let app_app_client_nonce_ranges = app_client_nonce_ranges.clone();
                             let app_app_last_challenge = app_last_challenge.clone();
+                            let app_rpc_budget = rpc_budget.clone();
                             tokio::spawn(async move {
                                 let mut stop_reciever = tx_message_receiver;
+                                let rpc_budget = app_rpc_budget;
                                 let app_nonce = app_app_nonce;
                                 let app_database = app_app_database;
                                 let app_config = app_app_config;
@@ -300,6 +417,7 @@ pub async fn pool_submission_system(
                                         break;
                                     } else {
                                         info!(target: "server_log", "Resending signed tx...");
+                                        rpc_budget.acquire("sendTransaction", RpcPriority::Critical).await;
                                         let _ = app_send_client
                                             .send_transaction_with_config(&tx, rpc_config)
                                             .await;
@@ -314,7 +432,9 @@ pub async fn pool_submission_system(
 
                                         if old_proof.challenge.eq(&latest_proof.challenge) {
                                             info!(target: "server_log", "Proof challenge not updated yet..");
+                                            rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
                                             if let Ok(p) = crate::global_boost_util::get_proof(
+                                                &app_config.program_ids,
                                                 &app_rpc_client,
                                                 app_wallet.miner_wallet.pubkey(),
                                             )
@@ -337,26 +457,19 @@ pub async fn pool_submission_system(
 
                                                     // reset nonce
                                                     {
-                                                        let mut nonce = app_nonce.lock().await;
-                                                        *nonce = 0;
+                                                        app_nonce.lock().await.reset();
                                                     }
-                                                    // reset client nonce ranges
+                                                    // reset client nonce ranges and rotate epoch hashes,
+                                                    // keeping the previous epoch around for the grace window
                                                     {
                                                         let mut writer =
                                                             app_client_nonce_ranges.write().await;
-                                                        *writer = HashMap::new();
+                                                        let previous_nonce_ranges = std::mem::take(&mut *writer);
                                                         drop(writer);
-                                                    }
-                                                    // reset epoch hashes
-                                                    {
                                                         info!(target: "server_log", "reset epoch hashes");
                                                         let mut mut_epoch_hashes =
                                                             app_epoch_hashes.write().await;
-                                                        mut_epoch_hashes.challenge = p.challenge;
-                                                        mut_epoch_hashes.best_hash.solution = None;
-                                                        mut_epoch_hashes.best_hash.difficulty = 0;
-                                                        mut_epoch_hashes.submissions =
-                                                            HashMap::new();
+                                                        mut_epoch_hashes.rotate(p.challenge, get_challenge_end_at(p), previous_nonce_ranges);
                                                     }
                                                     // Open submission window
                                                     info!(target: "server_log", "openning submission window.");
@@ -449,25 +562,19 @@ pub async fn pool_submission_system(
                                             // }
                                             // reset nonce
                                             {
-                                                let mut nonce = app_nonce.lock().await;
-                                                *nonce = 0;
+                                                app_nonce.lock().await.reset();
                                             }
-                                            // reset client nonce ranges
+                                            // reset client nonce ranges and rotate epoch hashes,
+                                            // keeping the previous epoch around for the grace window
                                             {
                                                 let mut writer =
                                                     app_client_nonce_ranges.write().await;
-                                                *writer = HashMap::new();
+                                                let previous_nonce_ranges = std::mem::take(&mut *writer);
                                                 drop(writer);
-                                            }
-                                            // reset epoch hashes
-                                            {
                                                 info!(target: "server_log", "reset epoch hashes");
                                                 let mut mut_epoch_hashes =
                                                     app_epoch_hashes.write().await;
-                                                mut_epoch_hashes.challenge = latest_proof.challenge;
-                                                mut_epoch_hashes.best_hash.solution = None;
-                                                mut_epoch_hashes.best_hash.difficulty = 0;
-                                                mut_epoch_hashes.submissions = HashMap::new();
+                                                mut_epoch_hashes.rotate(latest_proof.challenge, get_challenge_end_at(latest_proof), previous_nonce_ranges);
                                             }
                                             // Open submission window
                                             info!(target: "server_log", "openning submission window.");
@@ -488,6 +595,7 @@ pub async fn pool_submission_system(
                                 if expired_timer.elapsed().as_secs() >= 200 {
                                     break Err("Transaction Expired".to_string());
                                 }
+                                rpc_budget.acquire("getSignatureStatuses", RpcPriority::Critical).await;
                                 let results = rpc_client.get_signature_statuses(&[signature]).await;
                                 if let Ok(response) = results {
                                     let statuses = response.value;
@@ -515,6 +623,7 @@ pub async fn pool_submission_system(
                                 Ok(sig) => {
                                     // success
                                     success = true;
+                                    send_mine_cycle_event(&app_metrics_sender, observed_at, Some(built_at), Some(sent_at), None);
                                     info!(target: "server_log", "Success!!");
                                     info!(target: "server_log", "Sig: {}", sig);
                                     let itxn = InsertTxn {
@@ -540,8 +649,10 @@ pub async fn pool_submission_system(
                                     let app_app_config = config.clone();
                                     let app_app_wallet = app_wallet.clone();
                                     let app_metrics = app_metrics_sender.clone();
-                                    tokio::spawn(async move {
+                                    let app_rpc_budget = rpc_budget.clone();
+                                    mine_success_reporters.spawn(async move {
                                         let rpc_client = app_rpc_client;
+                                        let rpc_budget = app_rpc_budget;
                                         let app_database = app_app_database;
                                         let mine_success_sender = app_mine_success_sender;
                                         let app_proof = app_app_proof;
@@ -549,6 +660,7 @@ pub async fn pool_submission_system(
                                         //let app_wallet = app_app_wallet;
                                         let app_metrics_sender = app_metrics;
                                         loop {
+                                            rpc_budget.acquire("getTransaction", RpcPriority::Critical).await;
                                             if let Ok(txn_result) = rpc_client
                                                 .get_transaction_with_config(
                                                     &sig,
@@ -570,7 +682,7 @@ pub async fn pool_submission_system(
 
                                                 match data {
                                                     solana_transaction_status::option_serializer::OptionSerializer::Some(data) => {
-                                                    let prefix = format!("Program return: {} ", ore_miner_delegation::ID.to_string());
+                                                    let prefix = format!("Program return: {} ", app_config.program_ids.delegation);
                                                     let mut mine_event_str = "";
                                                     for log_message in data.iter().rev() {
                                                         if log_message.starts_with(&prefix) {
@@ -585,22 +697,34 @@ pub async fn pool_submission_system(
                                                     // Parse return data 
                                                     let bytes = BASE64_STANDARD.decode(mine_event_str).unwrap();
 
-                                                        if let Ok(mine_event) = bytemuck::try_from_bytes::<MineEventWithGlobalBoosts>(&bytes) {
-                                                            info!(target: "server_log", "MineEvent Global Boosts: {:?}", mine_event);
+                                                        if let Some(mine_event) = parse_mine_event(&bytes, app_config.mine_event_layout) {
+                                                            info!(target: "server_log", "MineEvent: {:?}", mine_event);
                                                             //info!(target: "submission_log", "MineEvent: {:?}", mine_event);
                                                             let encoded_challenge = BASE64_STANDARD.encode(old_proof.challenge);
                                                             info!(target: "server_log", "For Challenge: {:?}", encoded_challenge);
-                                                            match app_metrics_sender.send(AppMetricsEvent::MineEvent(AppMetricsMineEvent::V2(*mine_event))) {
+                                                            match app_metrics_sender.send(AppMetricsEvent::MineEvent(match mine_event {
+                                                                ParsedMineEvent::Boosts(e) => AppMetricsMineEvent::V1(e),
+                                                                ParsedMineEvent::GlobalBoosts(e) => AppMetricsMineEvent::V2(e),
+                                                            })) {
                                                                 Ok(_) => {}
                                                                 Err(_) => {
                                                                     tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
                                                                 }
                                                             }
                                                             //info!(target: "submission_log", "For Challenge: {:?}", BASE64_STANDARD.encode(old_proof.challenge));
-                                                            let full_rewards = mine_event.net_base_reward.checked_add(mine_event.net_miner_boost_reward).unwrap();
+                                                            let full_rewards = mine_event.miner_rewards().unwrap();
                                                             let commissions = full_rewards.mul(5).saturating_div(100);
 
                                                             // handle sending mine success message
+                                                            let submissions = app_epoch_hashes
+                                                                .write()
+                                                                .await
+                                                                .finalize_submissions(old_proof.challenge)
+                                                                .unwrap_or_else(|| submissions.clone());
+                                                            let superseded_submissions = app_epoch_hashes
+                                                                .write()
+                                                                .await
+                                                                .take_superseded_submissions(old_proof.challenge);
                                                             let mut total_hashpower: u64 = 0;
                                                             for submission in submissions.iter() {
                                                                 total_hashpower += submission.1.hashpower
@@ -643,6 +767,26 @@ pub async fn pool_submission_system(
                                                                 }
                                                             }
 
+                                                            if exceeds_reward_ceiling(full_rewards, app_config.max_plausible_challenge_reward) {
+                                                                tracing::error!(target: "server_log", "CRITICAL: challenge {} reported reward {} above ceiling {}. Skipping distribution and flagging for review.", challenge.id, full_rewards, app_config.max_plausible_challenge_reward);
+                                                                // keep the shares with the flag so the challenge can be redistributed after review
+                                                                let held_submissions: Vec<InsertSubmission> = submissions
+                                                                    .values()
+                                                                    .chain(superseded_submissions.iter())
+                                                                    .map(|s| InsertSubmission {
+                                                                        miner_id: s.miner_id,
+                                                                        challenge_id: challenge.id,
+                                                                        nonce: s.supplied_nonce,
+                                                                        difficulty: s.supplied_diff as i8,
+                                                                    })
+                                                                    .collect();
+                                                                while let Err(e) = app_database.flag_challenge_for_review(challenge.id, full_rewards, held_submissions.clone()).await {
+                                                                    tracing::error!(target: "server_log", "Failed to flag challenge {} for review: {:?}. Retrying...", challenge.id, e);
+                                                                    tokio::time::sleep(Duration::from_millis(1000)).await;
+                                                                }
+                                                                break;
+                                                            }
+
                                                             let new_commission_rewards = vec![UpdateReward {
                                                                 miner_id: app_config.commissions_miner_id,
                                                                 balance: commissions,
@@ -664,7 +808,11 @@ pub async fn pool_submission_system(
                                                             let multiplier = 0.0f64;
 
                                                             info!(target: "server_log", "Sending internal mine success for challenge: {}", BASE64_STANDARD.encode(old_proof.challenge));
-                                                            let _ = mine_success_sender.send(
+                                                            if mine_success_sender.capacity() == 0 {
+                                                                tracing::warn!(target: "server_log", "Reward distribution queue is full, waiting to queue challenge {}.", challenge.id);
+                                                            }
+                                                            // wait for room rather than dropping, commissions are already credited
+                                                            let send_result = mine_success_sender.send(
                                                                 MessageInternalMineSuccess {
                                                                     difficulty,
                                                                     total_balance: balance,
@@ -677,9 +825,14 @@ pub async fn pool_submission_system(
                                                                     ore_config: loaded_config,
                                                                     multiplier,
                                                                     submissions,
-                                                                    global_boosts_active: true,
+                                                                    superseded_submissions,
+                                                                    global_boosts_active: mine_event.global_boosts_active(),
                                                                 },
-                                                            );
+                                                            ).await;
+                                                            if let Err(e) = send_result {
+                                                                let msg = e.0;
+                                                                tracing::error!(target: "server_log", "Reward distribution queue is closed, challenge {} was not distributed (rewards: {}, commissions: {}, submissions: {}).", msg.challenge_id, msg.rewards, msg.commissions, msg.submissions.len());
+                                                            }
                                                             tokio::time::sleep(Duration::from_millis(200)).await;
                                                         } else {
                                                             tracing::error!(target: "server_log", "Failed get MineEvent data from transaction... wtf...");
@@ -715,7 +868,9 @@ pub async fn pool_submission_system(
 
                                         if old_proof.challenge.eq(&latest_proof.challenge) {
                                             info!(target: "server_log", "Proof challenge not updated yet..");
+                                            rpc_budget.acquire("getAccountInfo", RpcPriority::Critical).await;
                                             if let Ok(p) = crate::global_boost_util::get_proof(
+                                                &config.program_ids,
                                                 &rpc_client,
                                                 app_wallet.miner_wallet.pubkey(),
                                             )
@@ -734,26 +889,19 @@ pub async fn pool_submission_system(
 
                                                     // reset nonce
                                                     {
-                                                        let mut nonce = app_nonce.lock().await;
-                                                        *nonce = 0;
+                                                        app_nonce.lock().await.reset();
                                                     }
-                                                    // reset client nonce ranges
+                                                    // reset client nonce ranges and rotate epoch hashes,
+                                                    // keeping the previous epoch around for the grace window
                                                     {
                                                         let mut writer =
                                                             app_client_nonce_ranges.write().await;
-                                                        *writer = HashMap::new();
+                                                        let previous_nonce_ranges = std::mem::take(&mut *writer);
                                                         drop(writer);
-                                                    }
-                                                    // reset epoch hashes
-                                                    {
                                                         info!(target: "server_log", "reset epoch hashes");
                                                         let mut mut_epoch_hashes =
                                                             app_epoch_hashes.write().await;
-                                                        mut_epoch_hashes.challenge = p.challenge;
-                                                        mut_epoch_hashes.best_hash.solution = None;
-                                                        mut_epoch_hashes.best_hash.difficulty = 0;
-                                                        mut_epoch_hashes.submissions =
-                                                            HashMap::new();
+                                                        mut_epoch_hashes.rotate(p.challenge, get_challenge_end_at(p), previous_nonce_ranges);
                                                     }
                                                     // Open submission window
                                                     info!(target: "server_log", "openning submission window.");
@@ -825,26 +973,19 @@ pub async fn pool_submission_system(
                                                 // }
                                                 // reset nonce
                                                 {
-                                                    let mut nonce = app_nonce.lock().await;
-                                                    *nonce = 0;
+                                                    app_nonce.lock().await.reset();
                                                 }
-                                                // reset client nonce ranges
+                                                // reset client nonce ranges and rotate epoch hashes,
+                                                // keeping the previous epoch around for the grace window
                                                 {
                                                     let mut writer =
                                                         app_client_nonce_ranges.write().await;
-                                                    *writer = HashMap::new();
+                                                    let previous_nonce_ranges = std::mem::take(&mut *writer);
                                                     drop(writer);
-                                                }
-                                                // reset epoch hashes
-                                                {
                                                     info!(target: "server_log", "reset epoch hashes");
                                                     let mut mut_epoch_hashes =
                                                         app_epoch_hashes.write().await;
-                                                    mut_epoch_hashes.challenge =
-                                                        latest_proof.challenge;
-                                                    mut_epoch_hashes.best_hash.solution = None;
-                                                    mut_epoch_hashes.best_hash.difficulty = 0;
-                                                    mut_epoch_hashes.submissions = HashMap::new();
+                                                    mut_epoch_hashes.rotate(latest_proof.challenge, get_challenge_end_at(latest_proof), previous_nonce_ranges);
                                                 }
                                                 // Open submission window
                                                 info!(target: "server_log", "openning submission window.");
@@ -886,6 +1027,7 @@ pub async fn pool_submission_system(
                                     break;
                                 }
                                 Err(e) => {
+                                    send_mine_cycle_event(&app_metrics_sender, observed_at, Some(built_at), Some(sent_at), Some(MineCyclePhase::Confirm));
                                     tracing::error!(target: "server_log", "Failed to send and confirm txn");
                                     tracing::error!(target: "server_log", "Error: {:?}", e);
                                     println!("Error: {:?}", e);
@@ -912,8 +1054,7 @@ pub async fn pool_submission_system(
                     info!(target: "server_log", "Failed to send tx. Discarding and refreshing data.");
                     // reset nonce
                     {
-                        let mut nonce = app_nonce.lock().await;
-                        *nonce = 0;
+                        app_nonce.lock().await.reset();
                     }
                     // reset client nonce ranges
                     {
@@ -928,6 +1069,7 @@ pub async fn pool_submission_system(
                         mut_epoch_hashes.best_hash.solution = None;
                         mut_epoch_hashes.best_hash.difficulty = 0;
                         mut_epoch_hashes.submissions = HashMap::new();
+                        mut_epoch_hashes.superseded_submissions = HashMap::new();
                     }
                     // Open submission window
                     info!(target: "server_log", "openning submission window.");
@@ -944,5 +1086,164 @@ pub async fn pool_submission_system(
             tokio::time::sleep(Duration::from_millis(1000)).await;
         };
     }
+
+    if !mine_success_reporters.is_empty() {
+        info!(target: "server_log", "Waiting on {} landed mine transactions to be queued for distribution.", mine_success_reporters.len());
+    }
+    while mine_success_reporters.join_next().await.is_some() {}
+}
+
+/// True when `rewards` is above `ceiling`. A ceiling of 0 disables the check.
+pub fn exceeds_reward_ceiling(rewards: u64, ceiling: u64) -> bool {
+    ceiling > 0 && rewards > ceiling
+}
+
+/// Whether a simulation error is the mine instruction rejecting one of its boost accounts, which
+/// a plain mine without boosts can avoid. Program errors such as an invalid hash are not.
+pub fn is_boost_account_error(tx_error: &TransactionError, mine_ix_index: usize) -> bool {
+    match tx_error {
+        TransactionError::InstructionError(index, error) if *index as usize == mine_ix_index => matches!(
+            error,
+            InstructionError::InvalidAccountData
+                | InstructionError::InvalidAccountOwner
+                | InstructionError::UninitializedAccount
+                | InstructionError::IncorrectProgramId
+                | InstructionError::InvalidSeeds
+                | InstructionError::NotEnoughAccountKeys
+        ),
+        _ => false,
+    }
+}
+
+/// Returns how old the cached blockhash is when it is older than `max_age`.
+pub fn stale_blockhash_age(cache: &LatestBlockhashCache, max_age: Duration) -> Option<Duration> {
+    let age = cache.last_updated_at.elapsed();
+    if age > max_age {
+        Some(age)
+    } else {
+        None
+    }
+}
+
+/// Decodes the base64 bincode `(Hash, last_valid_block_height)` stored in the blockhash cache.
+pub fn decode_cached_blockhash(item: &str) -> Option<Hash> {
+    let bytes = BASE64_STANDARD.decode(item).ok()?;
+    bincode::deserialize::<(Hash, u64)>(&bytes)
+        .ok()
+        .map(|(hash, _last_valid_block_height)| hash)
+}
+
+fn send_mine_cycle_event(
+    app_metrics_sender: &UnboundedSender<AppMetricsEvent>,
+    observed_at: Instant,
+    built_at: Option<Instant>,
+    sent_at: Option<Instant>,
+    failed_phase: Option<MineCyclePhase>,
+) {
+    let now = Instant::now();
+    let observe_to_build_ms = built_at.unwrap_or(now).duration_since(observed_at).as_millis();
+    let build_to_send_ms = match built_at {
+        Some(built_at) => sent_at.unwrap_or(now).duration_since(built_at).as_millis(),
+        None => 0,
+    };
+    let send_to_confirm_ms = match sent_at {
+        Some(sent_at) => now.duration_since(sent_at).as_millis(),
+        None => 0,
+    };
+
+    let data = MetricsMineCycleEventData {
+        observe_to_build_ms,
+        build_to_send_ms,
+        send_to_confirm_ms,
+        failed_phase,
+    };
+    if let Err(_) = app_metrics_sender.send(AppMetricsEvent::MineCycleEvent(data)) {
+        tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
+    }
 }
 
+fn report_fetch_failure(
+    app_metrics_sender: &UnboundedSender<AppMetricsEvent>,
+    proof_ok: bool,
+    config_ok: bool,
+    busses_ok: bool,
+    missing_busses: usize,
+) {
+    let total_failure = !proof_ok && !config_ok && !busses_ok;
+    tracing::error!(target: "server_log",
+        "Proof/config/busses fetch failed: kind={} proof_ok={} config_ok={} busses_ok={} missing_busses={}",
+        if total_failure { "total" } else { "partial" },
+        proof_ok,
+        config_ok,
+        busses_ok,
+        missing_busses
+    );
+    let data = MetricsRpcFetchFailureEventData {
+        total_failure,
+        proof_ok,
+        config_ok,
+        busses_ok,
+        missing_busses,
+    };
+    if let Err(_) = app_metrics_sender.send(AppMetricsEvent::RpcFetchFailureEvent(data)) {
+        tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_with_age(item: String, age: Duration) -> LatestBlockhashCache {
+        LatestBlockhashCache {
+            item,
+            last_updated_at: Instant::now() - age,
+        }
+    }
+
+    #[test]
+    fn stale_blockhash_cache_skips_submission() {
+        let cache = cache_with_age(String::new(), Duration::from_secs(45));
+        let age = stale_blockhash_age(&cache, Duration::from_secs(30));
+        assert!(age.unwrap() >= Duration::from_secs(45));
+    }
+
+    #[test]
+    fn fresh_blockhash_cache_is_used() {
+        let hash = Hash::new_unique();
+        let item = BASE64_STANDARD.encode(bincode::serialize(&(hash, 42u64)).unwrap());
+        let cache = cache_with_age(item, Duration::from_secs(5));
+        assert_eq!(stale_blockhash_age(&cache, Duration::from_secs(30)), None);
+        assert_eq!(decode_cached_blockhash(&cache.item), Some(hash));
+    }
+
+    #[test]
+    fn over_ceiling_reward_is_rejected() {
+        assert!(exceeds_reward_ceiling(1_000_001, 1_000_000));
+    }
+
+    #[test]
+    fn reward_within_ceiling_proceeds() {
+        assert!(!exceeds_reward_ceiling(1_000_000, 1_000_000));
+        assert!(!exceeds_reward_ceiling(u64::MAX, 0));
+    }
+
+    #[test]
+    fn boost_account_error_falls_back_to_plain_mine() {
+        let error = TransactionError::InstructionError(3, InstructionError::InvalidAccountData);
+        assert!(is_boost_account_error(&error, 3));
+    }
+
+    #[test]
+    fn other_mine_errors_keep_the_boosted_mine() {
+        let invalid_hash = TransactionError::InstructionError(3, InstructionError::Custom(1));
+        assert!(!is_boost_account_error(&invalid_hash, 3));
+        let other_ix = TransactionError::InstructionError(1, InstructionError::InvalidAccountData);
+        assert!(!is_boost_account_error(&other_ix, 3));
+    }
+
+    #[test]
+    fn undecodable_blockhash_is_rejected() {
+        assert_eq!(decode_cached_blockhash("not base64!"), None);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{sync::Arc, time::Duration};
+
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::mpsc::UnboundedSender, time::Instant};
+use tracing::error;
+
+use crate::{
+    app_database::AppDatabase,
+    app_metrics::{AppMetricsEvent, MetricsProofBalanceDecreaseEventData},
+    global_boost_util::get_proof,
+    ore_utils::ProgramIds,
+    rpc_budget::{RpcBudget, RpcPriority},
+    systems::claim_system::CLAIM_CONFIRM_TIMEOUT_SECS,
+};
+
+const PROOF_BALANCE_WATCHDOG_INTERVAL: u64 = 30;
+
+// a claim is only recorded once it is confirmed, so a decrease is unexplained only after this long
+const UNEXPLAINED_DECREASE_GRACE: Duration = Duration::from_secs(CLAIM_CONFIRM_TIMEOUT_SECS + PROOF_BALANCE_WATCHDOG_INTERVAL);
+
+pub async fn proof_balance_watchdog_system(
+    app_database: Arc<AppDatabase>,
+    rpc_client: Arc<RpcClient>,
+    rpc_budget: Arc<RpcBudget>,
+    program_ids: ProgramIds,
+    pool_authority: Pubkey,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+) {
+    let mut watchdog = BalanceWatchdog::default();
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(PROOF_BALANCE_WATCHDOG_INTERVAL)).await;
+
+        // read the chain rather than the cached proof, which only moves with the mine loop
+        rpc_budget.acquire("getAccountInfo", RpcPriority::Stats).await;
+        let balance = match get_proof(&program_ids, &rpc_client, pool_authority).await {
+            Ok(proof) => proof.balance,
+            Err(e) => {
+                error!(target: "server_log", "Proof balance watchdog failed to get proof from chain. Error: {}", e);
+                continue;
+            }
+        };
+        let claimed = match app_database.get_pool_by_authority_pubkey(pool_authority.to_string()).await {
+            Ok(pool) => pool.claimed_rewards,
+            Err(e) => {
+                error!(target: "server_log", "Proof balance watchdog failed to get pool from db. Error: {:?}", e);
+                continue;
+            }
+        };
+
+        if let Some(unexplained) = watchdog.observe(balance, claimed, Instant::now(), UNEXPLAINED_DECREASE_GRACE) {
+            error!(target: "server_log", "CRITICAL: pool proof balance decreased by {} more than recorded claims for over {}s. Current balance: {}.", unexplained, UNEXPLAINED_DECREASE_GRACE.as_secs(), balance);
+            let _ = app_metrics_sender.send(AppMetricsEvent::ProofBalanceDecreaseEvent(MetricsProofBalanceDecreaseEventData {
+                unexplained_decrease: unexplained,
+                balance,
+            }));
+        }
+    }
+}
+
+/// Tracks proof balance decreases that recorded claims have not accounted for yet.
+#[derive(Default)]
+pub struct BalanceWatchdog {
+    last: Option<(u64, u64)>,
+    pending: u64,
+    pending_since: Option<Instant>,
+}
+
+impl BalanceWatchdog {
+    /// Records a balance and total claimed reading. Returns the unexplained decrease once it has
+    /// gone unmatched by recorded claims for longer than `grace`.
+    pub fn observe(&mut self, balance: u64, claimed: u64, now: Instant, grace: Duration) -> Option<u64> {
+        let mut alert = None;
+        if let Some((last_balance, last_claimed)) = self.last {
+            let decrease = last_balance.saturating_sub(balance);
+            let claimed_delta = claimed.saturating_sub(last_claimed);
+            self.pending = self.pending.saturating_add(decrease).saturating_sub(claimed_delta);
+
+            if self.pending == 0 {
+                self.pending_since = None;
+            } else {
+                let since = *self.pending_since.get_or_insert(now);
+                if now.duration_since(since) >= grace {
+                    alert = Some(self.pending);
+                    self.pending = 0;
+                    self.pending_since = None;
+                }
+            }
+        }
+        self.last = Some((balance, claimed));
+        alert
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn decrease_without_claims_alerts_after_the_grace_window() {
+        let start = Instant::now();
+        let mut watchdog = BalanceWatchdog::default();
+        assert_eq!(watchdog.observe(1_000, 0, start, GRACE), None);
+        assert_eq!(watchdog.observe(600, 0, start + Duration::from_secs(30), GRACE), None);
+        assert_eq!(watchdog.observe(600, 0, start + Duration::from_secs(90), GRACE), Some(400));
+        assert_eq!(watchdog.observe(600, 0, start + Duration::from_secs(120), GRACE), None);
+    }
+
+    #[test]
+    fn claim_recorded_within_the_window_explains_the_decrease() {
+        let start = Instant::now();
+        let mut watchdog = BalanceWatchdog::default();
+        watchdog.observe(1_000, 0, start, GRACE);
+        watchdog.observe(600, 0, start + Duration::from_secs(30), GRACE);
+        assert_eq!(watchdog.observe(600, 400, start + Duration::from_secs(60), GRACE), None);
+        assert_eq!(watchdog.observe(600, 400, start + Duration::from_secs(200), GRACE), None);
+    }
+}
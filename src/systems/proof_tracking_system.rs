@@ -9,10 +9,11 @@ use solana_client::{nonblocking::pubsub_client::PubsubClient, rpc_config::RpcAcc
 use solana_sdk::{commitment_config::CommitmentConfig, signature::Keypair, signer::Signer};
 use tokio::sync::Mutex;
 
-use crate::ore_utils::get_proof_pda;
+use crate::ore_utils::{get_proof_pda, ProgramIds};
 
 pub async fn proof_tracking_system(
     ws_url: String,
+    program_ids: ProgramIds,
     wallet: Arc<Keypair>,
     proof: Arc<Mutex<Proof>>,
     app_last_challenge: Arc<Mutex<[u8; 32]>>,
@@ -33,7 +34,7 @@ pub async fn proof_tracking_system(
         let app_wallet = wallet.clone();
         if let Ok(ps_client) = ps_client {
             let ps_client = Arc::new(ps_client);
-            let account_pubkey = get_proof_pda(app_wallet.pubkey());
+            let account_pubkey = get_proof_pda(&program_ids, app_wallet.pubkey());
             let pubsub = ps_client
                 .account_subscribe(
                     &account_pubkey,
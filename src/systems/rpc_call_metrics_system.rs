@@ -0,0 +1,26 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{app_metrics::{AppMetricsEvent, MetricsRpcCallsEventData}, rpc_budget::RpcBudget};
+
+const RPC_CALL_METRICS_INTERVAL_SECS: u64 = 10;
+
+pub async fn rpc_call_metrics_system(
+    rpc_budget: Arc<RpcBudget>,
+    app_metrics_sender: UnboundedSender<AppMetricsEvent>,
+) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(RPC_CALL_METRICS_INTERVAL_SECS)).await;
+
+        for (method, calls) in rpc_budget.drain_call_counts() {
+            let data = MetricsRpcCallsEventData {
+                method: method.to_string(),
+                calls_per_sec: calls as f64 / RPC_CALL_METRICS_INTERVAL_SECS as f64,
+            };
+            if let Err(_) = app_metrics_sender.send(AppMetricsEvent::RpcCallsEvent(data)) {
+                tracing::error!(target: "server_log", "Failed to send AppMetricsEvent down app_metrics_sender mpsc channel.");
+            }
+        }
+    }
+}
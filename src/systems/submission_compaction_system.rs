@@ -0,0 +1,43 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use crate::{app_database::AppDatabase, Config};
+
+const SUBMISSION_COMPACTION_INTERVAL: u64 = 60;
+const SUBMISSION_COMPACTION_BATCH_SIZE: u32 = 50_000;
+
+pub async fn submission_compaction_system(
+    app_database: Arc<AppDatabase>,
+    app_config: Arc<Config>,
+) {
+    loop {
+        let instant = Instant::now();
+        let mut total_compacted = 0;
+        loop {
+            match app_database
+                .compact_old_submissions(app_config.submissions_compaction_age_days, SUBMISSION_COMPACTION_BATCH_SIZE)
+                .await
+            {
+                Ok(compacted) => {
+                    total_compacted += compacted;
+                    if compacted < SUBMISSION_COMPACTION_BATCH_SIZE as usize {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!(target: "server_log", "Failed to compact old submissions. Error: {:?}", e);
+                    break;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+
+        if total_compacted > 0 {
+            info!(target: "server_log", "Compacted {} submissions into aggregates in {}ms", total_compacted, instant.elapsed().as_millis());
+        }
+
+        tokio::time::sleep(Duration::from_secs(SUBMISSION_COMPACTION_INTERVAL)).await;
+    }
+}